@@ -0,0 +1,225 @@
+//! Build succinct structures from files and query them
+//
+//     succinct-tool build bits INPUT OUTPUT
+//     succinct-tool build wavelet INPUT OUTPUT
+//     succinct-tool stats FILE
+//     succinct-tool access FILE POS
+//     succinct-tool rank FILE SYMBOL POS
+//     succinct-tool select FILE SYMBOL N
+//     succinct-tool build-fm TEXT OUTPUT
+//     succinct-tool fm-stats INDEX
+//     succinct-tool count INDEX PATTERN...
+//     succinct-tool locate INDEX PATTERN...
+//
+// `build bits` reads the characters `0` and `1` of INPUT as the bits
+// of a `Rank9`, ignoring any others, and `build wavelet` the bytes of
+// INPUT as the symbols of a wavelet tree. Both write the structure to
+// OUTPUT in the versioned format, from which the queries load it.
+// Symbols are bits or bytes as the file holds, and `select` gives the
+// position after the `N`th occurrence as `Select` does. `build-fm`
+// writes the FM-index of the bytes of TEXT to OUTPUT in the same
+// format, and the FM-index queries load it from INDEX rather than
+// indexing the text again.
+
+extern crate succinct;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write, Cursor};
+use std::process;
+use std::str::FromStr;
+use succinct::build::Builder;
+use succinct::collection::Collection;
+use succinct::dictionary::{Access, Rank, Select};
+use succinct::fm::FmIndex;
+use succinct::format::Format;
+use succinct::rank9::{self, Rank9};
+use succinct::space::SpaceUsage;
+use succinct::wavelet::Wavelet;
+
+/// A structure loaded from a file
+enum Loaded {
+    Bits(Rank9),
+    Symbols(Wavelet<Rank9, u8>),
+}
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(), "usage: succinct-tool build (bits|wavelet) INPUT OUTPUT
+       succinct-tool stats FILE
+       succinct-tool access FILE POS
+       succinct-tool rank FILE SYMBOL POS
+       succinct-tool select FILE SYMBOL N
+       succinct-tool build-fm TEXT OUTPUT
+       succinct-tool fm-stats INDEX
+       succinct-tool count INDEX PATTERN...
+       succinct-tool locate INDEX PATTERN...");
+    process::exit(2)
+}
+
+fn fail<E: std::fmt::Display>(msg: E) -> ! {
+    let _ = writeln!(io::stderr(), "succinct-tool: {}", msg);
+    process::exit(1)
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match File::open(path).and_then(|mut f| f.read_to_end(&mut buf)) {
+        Ok(_) => buf,
+        Err(e) => fail(format!("{}: {}", path, e)),
+    }
+}
+
+fn write_file<T: Format>(path: &str, x: &T) {
+    match File::create(path).and_then(|mut f| x.write_to(&mut f)) {
+        Ok(()) => {},
+        Err(e) => fail(format!("{}: {}", path, e)),
+    }
+}
+
+fn parse<T: FromStr>(s: &str) -> T {
+    match s.parse() {
+        Ok(x) => x,
+        Err(_) => fail(format!("invalid number {}", s)),
+    }
+}
+
+fn parse_bit(s: &str) -> bool {
+    match s {
+        "0" => false,
+        "1" => true,
+        _ => fail(format!("invalid bit {}", s)),
+    }
+}
+
+/// Load a file written by `build`, telling bits and symbols apart by
+/// its type tag
+fn load(path: &str) -> Loaded {
+    let bytes = read_file(path);
+    if bytes.len() < 24 {
+        fail(format!("{}: not a succinct index file", path));
+    }
+    let tag = (0..8).fold(0u64, |t, i| t | (bytes[16 + i] as u64) << (8 * i));
+    let mut r = Cursor::new(bytes);
+    let loaded = if tag == <Rank9 as Format>::tag() {
+        Format::read_from(&mut r).map(Loaded::Bits)
+    } else if tag == <Wavelet<Rank9, u8> as Format>::tag() {
+        Format::read_from(&mut r).map(Loaded::Symbols)
+    } else {
+        fail(format!("{}: holds neither bits nor a wavelet tree", path))
+    };
+    match loaded {
+        Ok(x) => x,
+        Err(e) => fail(format!("{}: {}", path, e)),
+    }
+}
+
+fn build(kind: &str, input: &str, output: &str) {
+    let bytes = read_file(input);
+    match kind {
+        "bits" => {
+            let bits = bytes.iter().filter(|b| **b == b'0' || **b == b'1').map(|b| *b == b'1');
+            let bv: Rank9 = rank9::Builder::with_capacity(bytes.len()).from_iter(bits);
+            write_file(output, &bv);
+        }
+        "wavelet" => {
+            let wavelet = Wavelet::from_slice(bytes.as_slice(), || rank9::Builder::with_capacity(128));
+            write_file(output, &wavelet);
+        }
+        _ => usage(),
+    }
+}
+
+/// Print the length and space of a structure
+fn report(len: usize, bytes: usize) {
+    println!("length: {}", len);
+    println!("bytes: {}", bytes);
+    if len > 0 {
+        println!("bits per element: {:.3}", 8.0 * bytes as f64 / len as f64);
+    }
+}
+
+/// Load an FM-index written by `build-fm`
+fn fm_index(path: &str) -> FmIndex {
+    match File::open(path).and_then(|f| Format::read_from(&mut io::BufReader::new(f))) {
+        Ok(index) => index,
+        Err(e) => fail(format!("{}: {}", path, e)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match args.as_slice() {
+        ["build", kind, input, output] => build(kind, input, output),
+        ["build-fm", text, output] => write_file(output, &FmIndex::new(read_file(text).as_slice())),
+        ["stats", file] => match load(file) {
+            Loaded::Bits(bv) => {
+                report(bv.len(), bv.total_bytes());
+                println!("ones: {}", bv.rank(true, bv.len()));
+            }
+            Loaded::Symbols(wavelet) => {
+                let stats = wavelet.stats();
+                report(wavelet.len(), stats.bytes);
+                println!("depth: {}", stats.depth);
+                println!("nodes: {}", stats.nodes.len());
+            }
+        },
+        ["access", file, pos] => {
+            let pos: usize = parse(pos);
+            match load(file) {
+                Loaded::Bits(bv) if pos < bv.len() => println!("{}", bv.get(pos) as u8),
+                Loaded::Symbols(ref w) if pos < w.len() => println!("{}", w.get(pos)),
+                _ => fail(format!("position {} out of bounds", pos)),
+            }
+        }
+        ["rank", file, sym, pos] => {
+            let pos: usize = parse(pos);
+            match load(file) {
+                Loaded::Bits(bv) if pos <= bv.len() => println!("{}", bv.rank(parse_bit(sym), pos)),
+                Loaded::Symbols(ref w) if pos <= w.len() => println!("{}", w.rank(parse::<u8>(sym), pos)),
+                _ => fail(format!("position {} out of bounds", pos)),
+            }
+        }
+        ["select", file, sym, n] => {
+            let n: usize = parse(n);
+            let answer = match load(file) {
+                Loaded::Bits(bv) => {
+                    let bit = parse_bit(sym);
+                    match n <= bv.rank(bit, bv.len()) {
+                        true => Some(bv.select(bit, n)),
+                        false => None,
+                    }
+                }
+                Loaded::Symbols(w) => {
+                    let sym: u8 = parse(sym);
+                    match n <= w.rank(sym, w.len()) {
+                        true => Some(w.select(sym, n)),
+                        false => None,
+                    }
+                }
+            };
+            match answer {
+                Some(pos) => println!("{}", pos),
+                None => fail(format!("fewer than {} occurrences of {}", n, sym)),
+            }
+        }
+        ["fm-stats", file] => {
+            let index = fm_index(file);
+            report(index.len(), index.total_bytes());
+        }
+        ["count", file, patterns @ ..] if !patterns.is_empty() => {
+            let index = fm_index(file);
+            for p in patterns.iter() {
+                println!("{}\t{}", p, index.count(p.as_bytes()));
+            }
+        }
+        ["locate", file, patterns @ ..] if !patterns.is_empty() => {
+            let index = fm_index(file);
+            for p in patterns.iter() {
+                let positions: Vec<String> = index.locate(p.as_bytes()).iter().map(|x| x.to_string()).collect();
+                println!("{}\t{}", p, positions.join(" "));
+            }
+        }
+        _ => usage(),
+    }
+}
@@ -2,94 +2,298 @@
 
 use super::dictionary::{Access, Rank, BitRank, Select};
 use super::collection::Collection;
+use super::error::{self, check_len};
+use super::bits::{Bits, pack_bools, pack_bytes};
+use super::build::{BitOrder, VecBuilder, push_words};
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint};
+use super::utils::{div_ceil, same_bits};
+use super::digest::{ContentDigest, bits_digest};
+use std::hash::{Hash, Hasher};
+use super::space::{SpaceUsage, vec_bytes};
+use super::view::{View, take_uint, take_words};
+use super::frozen::{self, Freeze, Thaw, Region};
+use std::mem::size_of;
 use std::cmp::min;
-use std::num::Int;
+use std::io::{self, Read, Write};
+use std::convert::From;
+use std::ops::Index;
 
-pub use bit_vector::build::Builder;
+pub use crate::bit_vector::build::{Builder, StreamBuilder};
 
 /// A simple bit vector
 ///
 /// The first bit in the vector is the least-significant bit of the
 /// first broadword
-#[derive(Show)]
+#[derive(Debug, Clone)]
 pub struct BitVector {
     /// length in bits
-    bits: int,
+    bits: usize,
     /// the bits
-    buffer: Vec<u64>
+    buffer: Vec<u64>,
+    /// the content digest of the bits
+    digest: u64,
 }
 
 impl BitVector {
-    pub fn zero(length_in_bits: int) -> BitVector {
-        let len = if length_in_bits % 64 == 0 {
-            length_in_bits / 64
-        } else {
-            length_in_bits / 64 + 1
-        };
-        BitVector {
-            bits: length_in_bits,
-            buffer: Vec::with_capacity(len as uint),
-        }
+    pub fn zero(length_in_bits: usize) -> BitVector {
+        BitVector::from_words(vec![0; div_ceil(length_in_bits, 64)], length_in_bits)
     }
 
-    pub fn from_vec(vec: &Vec<u64>, length_in_bits: int) -> BitVector {
-        BitVector {
-            bits: length_in_bits,
-            buffer: vec.clone()
-        }
+    /// The first `bits` bits of `buffer`, taking ownership of it
+    fn from_words(buffer: Vec<u64>, bits: usize) -> BitVector {
+        let digest = bits_digest(buffer.as_slice(), bits);
+        BitVector { bits, buffer, digest }
+    }
+
+    /// The first `length_in_bits` bits of `vec`, which must hold
+    /// them
+    pub fn from_vec(vec: &[u64], length_in_bits: usize) -> error::Result<BitVector> {
+        check_len(length_in_bits, vec.len())?;
+        Ok(BitVector::from_words(vec.to_vec(), length_in_bits))
+    }
+
+    /// The first `bits` bits of `bytes`, the `i`th bit being bit
+    /// `i % 8` of byte `i / 8` as in the words of the vector, which
+    /// must hold them
+    pub fn from_bytes(bytes: &[u8], bits: usize) -> error::Result<BitVector> {
+        Ok(BitVector::from_words(pack_bytes(bytes, bits)?, bits))
+    }
+
+    /// The bitvector of `len` bits whose `i`th bit is `f(i)`
+    pub fn from_fn<F: Fn(usize) -> bool>(len: usize, f: F) -> BitVector {
+        use super::build::Builder;
+        build::Builder::with_capacity(len).from_iter((0..len).map(f))
+    }
+
+    /// The bits of the bytes read from `r` to its end, taken in
+    /// `order`, without buffering the input
+    pub fn from_reader<R: Read>(r: &mut R, order: BitOrder) -> io::Result<BitVector> {
+        use super::build::Builder;
+        let mut builder = VecBuilder::with_capacity(0);
+        let bits = push_words(r, order, &mut builder)?;
+        Ok(BitVector::from_words(builder.finish(), bits))
+    }
+}
+
+/// The `i`th bit of the vector is the `i`th of the slice
+impl<'a> From<&'a [bool]> for BitVector {
+    fn from(bits: &'a [bool]) -> BitVector {
+        BitVector::from_words(pack_bools(bits), bits.len())
+    }
+}
+
+impl From<Vec<bool>> for BitVector {
+    fn from(bits: Vec<bool>) -> BitVector {
+        BitVector::from(bits.as_slice())
+    }
+}
+
+/// A `BitVector` borrowing its words, as viewed in a serialization
+/// held in memory
+#[derive(Copy, Clone, Debug)]
+pub struct BitVectorRef<'a> {
+    bits: usize,
+    buffer: &'a [u64],
+}
+
+impl BitVector {
+    /// Borrow the bitvector
+    pub fn view(&self) -> BitVectorRef<'_> {
+        BitVectorRef { bits: self.bits, buffer: self.buffer.as_slice() }
+    }
+
+    /// The words holding the bits, least significant bit first
+    pub fn words(&self) -> &[u64] {
+        self.buffer.as_slice()
+    }
+
+    /// An iterator over the bits
+    pub fn iter(&self) -> Bits<'_> {
+        Bits::new(self.buffer.as_slice(), self.bits)
+    }
+}
+
+/// The empty bitvector
+impl Default for BitVector {
+    fn default() -> BitVector {
+        BitVector::zero(0)
+    }
+}
+
+/// The `i`th bit, as given by `get`
+impl Index<usize> for BitVector {
+    type Output = bool;
+    fn index(&self, i: usize) -> &bool {
+        if self.get(i) { &true } else { &false }
+    }
+}
+
+impl<'a> IntoIterator for &'a BitVector {
+    type Item = bool;
+    type IntoIter = Bits<'a>;
+    fn into_iter(self) -> Bits<'a> {
+        self.iter()
     }
 }
 
 impl Collection for BitVector {
-    fn len(&self) -> uint {
-        self.bits as uint
+    fn len(&self) -> usize {
+        self.bits
+    }
+}
+
+impl<'a> Collection for BitVectorRef<'a> {
+    fn len(&self) -> usize {
+        self.bits
     }
 }
 
 impl Access<bool> for BitVector {
-    fn get(&self, n: uint) -> bool {
+    fn get(&self, n: usize) -> bool {
+        self.view().get(n)
+    }
+}
+
+impl<'a> Access<bool> for BitVectorRef<'a> {
+    fn get(&self, n: usize) -> bool {
         let word = self.buffer[n / 64];
         (word >> (n % 64)) & 1 == 1
     }
 }
 
 impl Rank<bool> for BitVector {
-    fn rank(&self, el: bool, n: int) -> int {
+    fn rank(&self, el: bool, n: usize) -> usize {
+        if el {self.rank1(n)} else {self.rank0(n)}
+    }
+}
+
+impl<'a> Rank<bool> for BitVectorRef<'a> {
+    fn rank(&self, el: bool, n: usize) -> usize {
         if el {self.rank1(n)} else {self.rank0(n)}
     }
 }
 
 impl BitRank for BitVector {
-    fn rank0(&self, n: int) -> int {
+    fn rank0(&self, n: usize) -> usize {
+        n - self.rank1(n)
+    }
+
+    fn rank1(&self, n: usize) -> usize {
+        self.view().rank1(n)
+    }
+}
+
+impl<'a> BitRank for BitVectorRef<'a> {
+    fn rank0(&self, n: usize) -> usize {
         n - self.rank1(n)
     }
 
-    fn rank1(&self, n: int) -> int {
+    fn rank1(&self, n: usize) -> usize {
         assert!(n <= self.bits);
         let mut rank = 0;
         let n = min(self.bits, n);
-        for i in self.buffer.iter().take(n as uint / 64) {
+        for i in self.buffer.iter().take(n / 64) {
             rank += i.rank1(64);
         }
-        rank += self.buffer[n as uint / 64].rank1(n % 64);
+        if !n.is_multiple_of(64) {
+            rank += self.buffer[n / 64].rank1(n % 64);
+        }
         rank
     }
 }
 
+impl Serialize for BitVector {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.bits)?;
+        self.buffer.serialize(w)
+    }
+}
+
+impl Deserialize for BitVector {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BitVector> {
+        let bits = read_uint(r)?;
+        let buffer: Vec<u64> = Deserialize::deserialize(r)?;
+        check_len(bits, buffer.len())?;
+        Ok(BitVector::from_words(buffer, bits))
+    }
+}
+
+impl<'a> View<'a> for BitVectorRef<'a> {
+    fn view(words: &mut &'a [u64]) -> io::Result<BitVectorRef<'a>> {
+        let bits = take_uint(words)?;
+        let buffer = take_words(words)?;
+        check_len(bits, buffer.len())?;
+        Ok(BitVectorRef { bits, buffer })
+    }
+}
+
+/// The number of bits, then the words
+impl Freeze for BitVector {
+    fn freeze(&self, out: &mut Vec<u64>) {
+        let mut region = Region::new(out, 3);
+        region.set(0, self.bits as u64);
+        region.array(1, self.buffer.as_slice());
+        region.finish()
+    }
+}
+
+impl<'a> Thaw<'a> for BitVectorRef<'a> {
+    fn thaw(region: &'a [u64]) -> io::Result<BitVectorRef<'a>> {
+        let bits = frozen::uint_field(region, 0)?;
+        let buffer = frozen::array(region, 1)?;
+        check_len(bits, buffer.len())?;
+        Ok(BitVectorRef { bits, buffer })
+    }
+}
+
+/// Bitvectors are equal when they hold the same bits
+impl PartialEq for BitVector {
+    fn eq(&self, other: &BitVector) -> bool {
+        self.bits == other.bits && self.digest == other.digest
+            && same_bits(self.buffer.as_slice(), other.buffer.as_slice(), self.bits)
+    }
+}
+
+impl Eq for BitVector {}
+
+impl Hash for BitVector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.digest.hash(state);
+    }
+}
+
+/// The digest computed when the bitvector was built
+impl ContentDigest for BitVector {
+    fn content_digest(&self) -> u64 {
+        self.digest
+    }
+}
+
+impl SpaceUsage for BitVector {
+    fn total_bytes(&self) -> usize {
+        size_of::<usize>() + vec_bytes(&self.buffer) + size_of::<u64>()
+    }
+}
+
 impl Select<bool> for BitVector {
+    fn select(&self, bit: bool, n: usize) -> usize {
+        self.view().select(bit, n)
+    }
+}
+
+impl<'a> Select<bool> for BitVectorRef<'a> {
     #[inline(always)]
-    fn select(&self, bit: bool, n: int) -> int {
-        debug_assert!(n >= 0);
+    fn select(&self, bit: bool, n: usize) -> usize {
         if n == 0 {
             return 0;
         }
 
         let mut cur: u64 = 0;
-        let mut remain: int = n; // counting down from n
-        let mut idx: int = 0;
+        let mut remain: usize = n; // counting down from n
+        let mut idx: usize = 0;
         for word in self.buffer.iter() {
             cur = *word;
-            let matches = if bit { word.count_ones() } else { word.count_zeros() } as int;
+            let matches = if bit { word.count_ones() } else { word.count_zeros() } as usize;
             if remain > matches {
                 remain -= matches;
                 idx += 64;
@@ -102,24 +306,100 @@ impl Select<bool> for BitVector {
 }
 
 mod build {
+    use std::io::{self, Read, Write};
     use super::super::build;
+    use super::super::collection::Collection;
+    use super::super::serialize::{Serialize, Deserialize, write_uint, invalid_data};
     use super::super::utils::div_ceil;
     use super::BitVector;
 
     /// Build a `BitVector` from bits
-    #[derive(Show)]
+    #[derive(Debug)]
     pub struct Builder {
         builder: build::BitBuilder<build::VecBuilder<u64>>,
     }
 
     impl Builder {
         /// Build a bitvector with capacity for `cap` bits
-        pub fn with_capacity(cap: uint) -> Builder {
+        pub fn with_capacity(cap: usize) -> Builder {
             let words = div_ceil(cap, 64);
             Builder {
                 builder: build::BitBuilder::new(build::VecBuilder::with_capacity(words)),
             }
         }
+
+        /// Build a bitvector starting with `len` zeros
+        pub fn with_len(len: usize) -> Builder {
+            let mut builder = Builder::with_capacity(len);
+            for _ in 0..len / 64 {
+                builder.push_word(0);
+            }
+            builder.push_bits(0, len % 64);
+            builder
+        }
+
+        /// The number of bits pushed
+        pub fn len(&self) -> usize {
+            self.builder.len()
+        }
+
+        /// Whether no bits have been pushed
+        pub fn is_empty(&self) -> bool {
+            self.builder.is_empty()
+        }
+
+        /// The number of bits that can be held without reallocating
+        pub fn capacity(&self) -> usize {
+            64 * self.builder.get_ref().capacity()
+        }
+
+        /// Make room for at least `additional` more bits
+        pub fn reserve(&mut self, additional: usize) {
+            let words = div_ceil(self.len() + additional, 64) - self.len() / 64;
+            self.builder.get_mut().reserve(words)
+        }
+    }
+
+    impl Builder {
+        /// Push the `width` least significant bits of `value`, least
+        /// significant first
+        pub fn push_bits(&mut self, value: u64, width: usize) {
+            self.builder.push_bits(value, width)
+        }
+
+        /// Push the 64 bits of `word`, least significant first
+        pub fn push_word(&mut self, word: u64) {
+            self.builder.push_word(word)
+        }
+    }
+
+    impl build::Buildable<bool> for BitVector {
+        type Builder = Builder;
+        fn new_builder() -> Builder {
+            Builder::with_capacity(64)
+        }
+    }
+
+    /// A checkpoint of the bits pushed so far, from which the build
+    /// can be resumed
+    impl Serialize for Builder {
+        fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            self.builder.serialize(w)
+        }
+    }
+
+    impl Deserialize for Builder {
+        fn deserialize<R: Read>(r: &mut R) -> io::Result<Builder> {
+            let builder = Deserialize::deserialize(r)?;
+            Ok(Builder { builder })
+        }
+    }
+
+    /// The bits pushed so far
+    impl Collection for Builder {
+        fn len(&self) -> usize {
+            self.builder.len()
+        }
     }
 
     impl build::Builder<bool, BitVector> for Builder {
@@ -127,8 +407,44 @@ mod build {
             self.builder.push(bit)
         }
         fn finish(self) -> BitVector {
+            let (vec, bits) = self.builder.finish();
+            BitVector::from_words(vec, bits)
+        }
+    }
+
+    /// Write the serialization of a `BitVector` of `len` bits to a
+    /// stream as its bits are pushed, giving back the stream
+    pub struct StreamBuilder<W> {
+        builder: build::BitBuilder<build::WriteBuilder<W>>,
+        len: usize,
+    }
+
+    impl<W: Write> StreamBuilder<W> {
+        /// Start the serialization of a bitvector of `len` bits
+        pub fn new(mut w: W, len: usize) -> io::Result<StreamBuilder<W>> {
+            write_uint(&mut w, len)?;
+            write_uint(&mut w, div_ceil(len, 64))?;
+            Ok(StreamBuilder {
+                builder: build::BitBuilder::new(build::WriteBuilder::new(w)),
+                len,
+            })
+        }
+
+        /// Push the `width` least significant bits of `value`, least
+        /// significant first
+        pub fn push_bits(&mut self, value: u64, width: usize) {
+            self.builder.push_bits(value, width)
+        }
+    }
+
+    impl<W: Write> build::Builder<bool, io::Result<W>> for StreamBuilder<W> {
+        fn push(&mut self, bit: bool) {
+            self.builder.push(bit)
+        }
+        fn finish(self) -> io::Result<W> {
             match self.builder.finish() {
-                (vec, bits) => BitVector { bits: bits as int, buffer: vec }
+                (w, bits) if bits == self.len => w,
+                _ => Err(invalid_data("StreamBuilder: bits pushed differ from its length")),
             }
         }
     }
@@ -136,31 +452,31 @@ mod build {
 
 #[cfg(test)]
 mod test {
-    use std::num::Int;
     use quickcheck::TestResult;
 
     use super::BitVector;
     use super::super::dictionary::{BitRank, Select, Access};
+    use super::super::collection::Collection;
     use super::super::naive;
 
     #[test]
     pub fn test_select0() {
-        super::super::dictionary::test::test_select0(&BitVector::from_vec)
+        super::super::dictionary::test::test_select0(&|v, n| BitVector::from_vec(v, n).unwrap())
     }
 
     #[test]
     pub fn test_select1() {
-        super::super::dictionary::test::test_select1(&BitVector::from_vec)
+        super::super::dictionary::test::test_select1(&|v, n| BitVector::from_vec(v, n).unwrap())
     }
 
     #[test]
     pub fn test_rank0() {
-        super::super::dictionary::test::test_rank0(&BitVector::from_vec)
+        super::super::dictionary::test::test_rank0(&|v, n| BitVector::from_vec(v, n).unwrap())
     }
 
     #[test]
     pub fn test_rank1() {
-        super::super::dictionary::test::test_rank1(&BitVector::from_vec)
+        super::super::dictionary::test::test_rank1(&|v, n| BitVector::from_vec(v, n).unwrap())
     }
 
     #[quickcheck]
@@ -175,41 +491,133 @@ mod test {
         true
     }
 
+    #[quickcheck]
+    fn std_traits_agree(bits: Vec<bool>) -> bool {
+        let bv = BitVector::from(bits.as_slice());
+        let empty = BitVector::default();
+        (0..bits.len()).all(|i| bv[i] == bits[i])
+            && bv.iter().collect::<Vec<bool>>() == bits
+            && (&bv).into_iter().len() == bits.len()
+            && bv.clone() == bv
+            && empty.len() == 0 && empty.rank1(0) == 0 && empty.iter().next().is_none()
+    }
+
+    #[quickcheck]
+    fn from_vec_checks_length(v: Vec<u64>, bits: u16) -> bool {
+        let bits = bits as usize;
+        BitVector::from_vec(&v, bits).is_ok() == (bits <= 64 * v.len())
+    }
+
+    #[quickcheck]
+    fn from_bytes_checks_length(bytes: Vec<u8>, bits: u16) -> bool {
+        let bits = bits as usize;
+        BitVector::from_bytes(&bytes, bits).is_ok() == (bits <= 8 * bytes.len())
+            && super::super::rank9::Rank9::from_bytes(&bytes, bits).is_ok() == (bits <= 8 * bytes.len())
+    }
+
+    #[test]
+    fn test_deserialize_checks_length() {
+        use std::io::Cursor;
+        use super::super::serialize::{Serialize, Deserialize, write_uint};
+        let mut buf = Vec::new();
+        write_uint(&mut buf, 129).unwrap();
+        vec!(1u64, 2).serialize(&mut buf).unwrap();
+        assert!(<BitVector as Deserialize>::deserialize(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let bv = BitVector::from_vec(&v, 64 * v.len()).unwrap();
+        let bv2 = round_trip(&bv);
+        (0..64 * v.len()).all(|i| bv.get(i) == bv2.get(i))
+    }
+
+    #[quickcheck]
+    fn constructors_agree(bits: Vec<bool>) -> bool {
+        use std::convert::From;
+        use super::super::build::Buildable;
+        let from_slice = BitVector::from(bits.as_slice());
+        let mut bytes: Vec<u8> = (0..bits.len().div_ceil(8)).map(|_| 0xff).collect();
+        for (i, bit) in bits.iter().enumerate() {
+            if !*bit {
+                bytes[i / 8] &= !(1 << (i % 8));
+            }
+        }
+        let from_bytes = BitVector::from_bytes(bytes.as_slice(), bits.len()).unwrap();
+        (0..bits.len()).all(|i| from_slice.get(i) == bits[i])
+            && from_bytes == from_slice && BitVector::from(bits.clone()) == from_slice
+            && <BitVector as Buildable<bool>>::build_from_iter(bits.into_iter()) == from_slice
+    }
+
+    #[quickcheck]
+    fn builder_sizes_are_correct(len: u16, bits: Vec<bool>) -> bool {
+        use super::super::build::Builder;
+        let len = len as usize;
+        let mut builder = super::Builder::with_len(len);
+        let zeros = builder.len() == len && builder.capacity() >= len;
+        builder.reserve(bits.len());
+        let reserved = builder.capacity() >= len + bits.len();
+        for bit in bits.iter() {
+            builder.push(*bit);
+        }
+        let pushed = builder.len() == len + bits.len();
+        let bv = builder.finish();
+        zeros && reserved && pushed
+            && (0..len).all(|i| !bv.get(i)) && bits.iter().enumerate().all(|(i, b)| bv.get(len + i) == *b)
+    }
+
+    #[quickcheck]
+    fn from_fn_is_correct(bits: Vec<bool>) -> bool {
+        use std::convert::From;
+        BitVector::from_fn(bits.len(), |i| bits[i]) == BitVector::from(bits.as_slice())
+    }
+
+    #[quickcheck]
+    fn from_reader_is_correct(bytes: Vec<u8>) -> bool {
+        use std::io::Cursor;
+        use super::super::build::BitOrder;
+        use super::super::collection::Collection;
+        let lsb = BitVector::from_reader(&mut Cursor::new(bytes.clone()), BitOrder::LsbFirst).unwrap();
+        let msb = BitVector::from_reader(&mut Cursor::new(bytes.clone()), BitOrder::MsbFirst).unwrap();
+        lsb == BitVector::from_bytes(bytes.as_slice(), 8 * bytes.len()).unwrap() && msb.len() == lsb.len()
+            && (0..lsb.len()).all(|i| msb.get(i) == ((bytes[i / 8] >> (7 - i % 8)) & 1 == 1))
+    }
+
     #[test]
     pub fn test_get() {
         let v = vec!(0b0110, 0b1001, 0b1100);
-        let bv = BitVector::from_vec(&v, 64*3);
-        assert_eq!(bv.get(0),  false);
-        assert_eq!(bv.get(1),  true);
-        assert_eq!(bv.get(2),  true);
-        assert_eq!(bv.get(3),  false);
-        assert_eq!(bv.get(64), true);
+        let bv = BitVector::from_vec(&v, 64*3).unwrap();
+        assert!(!bv.get(0));
+        assert!(bv.get(1));
+        assert!(bv.get(2));
+        assert!(!bv.get(3));
+        assert!(bv.get(64));
     }
 
     #[quickcheck]
-    fn rank_is_correct(bit: bool, v: Vec<u64>, n: uint) -> TestResult {
+    fn rank_is_correct(bit: bool, v: Vec<u64>, n: usize) -> TestResult {
         let bits = v.len() * 64;
         if n > bits {
             return TestResult::discard()
         }
-        let bv = BitVector::from_vec(&v, bits as int);
-        let ans = if bit { bv.rank1(n as int) } else { bv.rank0(n as int) };
-        TestResult::from_bool(ans == naive::rank(&bv, bit, n as int))
+        let bv = BitVector::from_vec(&v, bits).unwrap();
+        let ans = if bit { bv.rank1(n) } else { bv.rank0(n) };
+        TestResult::from_bool(ans == naive::rank(&bv, bit, n))
     }
 
     #[quickcheck]
-    fn select_is_correct(bit: bool, v: Vec<u64>, n: uint) -> TestResult {
-        use std::iter::AdditiveIterator;
-        if (v.iter().map(|x| x.count_ones()).sum() as uint) < n {
+    fn select_is_correct(bit: bool, v: Vec<u64>, n: usize) -> TestResult {
+        if (v.iter().map(|x| x.count_ones()).sum::<u32>() as usize) < n {
             return TestResult::discard()
         }
 
         let bits = v.len() * 64;
-        let bv = BitVector::from_vec(&v, bits as int);
-        match naive::select(&bv, bit, n as int) {
+        let bv = BitVector::from_vec(&v, bits).unwrap();
+        match naive::select(&bv, bit, n) {
             None => TestResult::discard(),
             Some(ans) =>
-                TestResult::from_bool(ans == bv.select(bit, n as int))
+                TestResult::from_bool(ans == bv.select(bit, n))
         }
     }
 }
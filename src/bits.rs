@@ -1,14 +1,36 @@
 //! Various traits for working with bits and objects composed of them
 
-use std::ops::{Shr, BitAnd};
-use std::iter::Iterator;
-use std::num::Int;
+use std::ops::{Shl, Shr, BitAnd, BitOr};
+use std::iter::repeat_n;
 use std::mem::size_of;
+use crate::build::Builder;
+use crate::error;
+
+/// The primitive unsigned integers, whose bits can be iterated over
+/// and built from
+pub trait Word: Copy + PartialEq + Shl<usize, Output=Self> + Shr<usize, Output=Self>
+    + BitAnd<Output=Self> + BitOr<Output=Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(impl Word for $t {
+            const ZERO: $t = 0;
+            const ONE: $t = 1;
+        })*
+    }
+}
+
+impl_word!(u8, u16, u32, u64, usize);
 
 /// An iterator over the bits of a primitive type
-/// The least significant bit is produced first.
+/// The most significant bit is produced first, so that the order of
+/// bit sequences agrees with the order of the values.
 pub struct BitIterator<T> {
-    bit: uint,
+    bit: usize,
     x: T,
 }
 
@@ -16,66 +38,263 @@ impl<T> BitIterator<T> {
     pub fn new(x: T) -> BitIterator<T> {
         BitIterator {
             bit: 8*size_of::<T>(),
-            x: x,
+            x,
         }
     }
 
-    pub fn with_width(bits: uint, x: T) -> BitIterator<T> {
+    /// Iterate over the `bits` least significant bits of `x`
+    pub fn with_width(bits: usize, x: T) -> BitIterator<T> {
         BitIterator {
             bit: bits,
-            x: x
+            x
         }
     }
 }
 
-impl<T: Shr<uint> + BitAnd<T> + Int> Iterator for BitIterator<T> {
+impl<T: Word> Iterator for BitIterator<T> {
     type Item = bool;
     fn next(&mut self) -> Option<bool> {
         match self.bit {
             0 => None,
             _ => {
-                let res = Some(!(self.x & Int::one()) == Int::zero());
                 self.bit -= 1;
-                self.x = self.x >> 1;
-                res
+                Some((self.x >> self.bit) & T::ONE != T::ZERO)
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.bit, Some(self.bit))
+    }
 }
 
+impl<T: Word> ExactSizeIterator for BitIterator<T> {}
+
 /// A trait for types for which one can get an iterator over bits
 pub trait BitIter {
-    type Iter: Iterator<Item=bool>;
+    /// The number of bits produced by `bit_iter`
+    const BITS: usize;
+    type Iter: Iterator<Item=bool> + ExactSizeIterator;
     fn bit_iter(self) -> <Self as BitIter>::Iter;
 }
 
 impl BitIter for u64 {
+    const BITS: usize = 64;
     type Iter = BitIterator<u64>;
     fn bit_iter(self) -> BitIterator<u64> {BitIterator::new(self)}
 }
 
 impl BitIter for u32 {
+    const BITS: usize = 32;
     type Iter = BitIterator<u32>;
     fn bit_iter(self) -> BitIterator<u32> {BitIterator::new(self)}
 }
 
 impl BitIter for u16 {
+    const BITS: usize = 16;
     type Iter = BitIterator<u16>;
     fn bit_iter(self) -> BitIterator<u16> {BitIterator::new(self)}
 }
 
 impl BitIter for u8 {
+    const BITS: usize = 8;
     type Iter = BitIterator<u8>;
     fn bit_iter(self) -> BitIterator<u8> {BitIterator::new(self)}
 }
 
-/// A trait for types for which one can extract arbitrary bits
-trait Bitwise {
-    fn width(&self) -> uint;
-    fn bit(&self, n: uint) -> bool;
+/// The bits of the code point of a `char`. Code points are at most
+/// `0x10FFFF` so only the low 21 bits are produced.
+impl BitIter for char {
+    const BITS: usize = CHAR_BITS;
+    type Iter = BitIterator<u32>;
+    fn bit_iter(self) -> BitIterator<u32> {BitIterator::with_width(CHAR_BITS, self as u32)}
+}
+
+/// The number of bits needed to represent any `char`
+pub const CHAR_BITS: usize = 21;
+
+/// The number of bits needed to represent `x`, that is, the
+/// position of its most significant one plus one
+pub fn bit_width<T: BitIter>(x: T) -> usize {
+    let mut bits = x.bit_iter();
+    let len = bits.len();
+    match bits.position(|b| b) {
+        Some(i) => len - i,
+        None => 0,
+    }
+}
+
+/// An iterator over the first `len` bits of a slice of words, the
+/// least significant bit of the first word first, as bitvectors hold
+/// them
+pub struct Bits<'a> {
+    words: &'a [u64],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a> Bits<'a> {
+    pub fn new(words: &'a [u64], len: usize) -> Bits<'a> {
+        assert!(len <= 64 * words.len(), "Bits: {} bits exceed {} words", len, words.len());
+        Bits { words, pos: 0, len }
+    }
+}
+
+impl<'a> Iterator for Bits<'a> {
+    type Item = bool;
+    fn next(&mut self) -> Option<bool> {
+        if self.pos < self.len {
+            let bit = (self.words[self.pos / 64] >> (self.pos % 64)) & 1 == 1;
+            self.pos += 1;
+            Some(bit)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Bits<'a> {}
+
+/// A cursor reading bits from a slice of words in the layout produced
+/// by `BitBuilder`, the least significant bit of the first word first
+pub struct BitReader<'a> {
+    words: &'a [u64],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Read from the first bit of `words`
+    pub fn new(words: &'a [u64]) -> BitReader<'a> {
+        BitReader::at(words, 0)
+    }
+
+    /// Read from bit `pos` of `words`
+    pub fn at(words: &'a [u64], pos: usize) -> BitReader<'a> {
+        BitReader { words, pos }
+    }
+
+    /// The position of the next bit to be read
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn read_bit(&mut self) -> bool {
+        let bit = (self.words[self.pos / 64] >> (self.pos % 64)) & 1 == 1;
+        self.pos += 1;
+        bit
+    }
+
+    /// Read `n` bits, the first read being the most significant
+    pub fn read_bits(&mut self, n: usize) -> u64 {
+        debug_assert!(n <= 64);
+        let mut x = 0;
+        for _ in 0..n {
+            x = (x << 1) | (self.read_bit() as u64);
+        }
+        x
+    }
+
+    /// Read a run of zeros and the one terminating it, returning the
+    /// length of the run
+    pub fn read_unary(&mut self) -> usize {
+        let mut zeros = 0;
+        loop {
+            let offset = self.pos % 64;
+            let word = self.words[self.pos / 64] >> offset;
+            if word != 0 {
+                let n = word.trailing_zeros() as usize;
+                self.pos += n + 1;
+                return zeros + n;
+            }
+            zeros += 64 - offset;
+            self.pos += 64 - offset;
+        }
+    }
+}
+
+/// Push the `n` least significant bits of `x`, most significant first
+pub fn write_bits<T, B: Builder<bool, T>>(builder: &mut B, n: usize, x: u64) {
+    for bit in BitIterator::with_width(n, x) {
+        builder.push(bit);
+    }
+}
+
+/// Push the Elias gamma code of `x`, which must be positive: the
+/// width of `x` less one in unary, followed by the bits of `x` below
+/// its leading one
+pub fn write_gamma<T, B: Builder<bool, T>>(builder: &mut B, x: u64) {
+    assert!(x > 0, "gamma code of zero");
+    let n = bit_width(x) - 1;
+    for _ in 0..n {
+        builder.push(false);
+    }
+    builder.push(true);
+    write_bits(builder, n, x);
+}
+
+/// Read a code written by `write_gamma`
+pub fn read_gamma(reader: &mut BitReader) -> u64 {
+    let n = reader.read_unary();
+    (1 << n) | reader.read_bits(n)
+}
+
+/// The length in bits of the gamma code of `x`
+pub fn gamma_len(x: u64) -> usize {
+    2 * bit_width(x) - 1
+}
+
+/// Push the Elias delta code of `x`, which must be positive: the
+/// width of `x` in gamma code, followed by the bits of `x` below its
+/// leading one
+pub fn write_delta<T, B: Builder<bool, T>>(builder: &mut B, x: u64) {
+    assert!(x > 0, "delta code of zero");
+    let n = bit_width(x);
+    write_gamma(builder, n as u64);
+    write_bits(builder, n - 1, x);
+}
+
+/// Read a code written by `write_delta`
+pub fn read_delta(reader: &mut BitReader) -> u64 {
+    let n = read_gamma(reader) as usize - 1;
+    (1 << n) | reader.read_bits(n)
 }
 
-impl Bitwise for u64 {
-    fn width(&self) -> uint {64}
-    fn bit(&self, n: uint) -> bool {(*self >> n) & 1 == 1}
+/// The length in bits of the delta code of `x`
+pub fn delta_len(x: u64) -> usize {
+    let n = bit_width(x);
+    gamma_len(n as u64) + n - 1
+}
+
+/// Pack bits into words, the `i`th bit being bit `i % 64` of word
+/// `i / 64`
+pub fn pack_bools(bits: &[bool]) -> Vec<u64> {
+    let mut words: Vec<u64> = repeat_n(0, bits.len().div_ceil(64)).collect();
+    for (i, bit) in bits.iter().enumerate() {
+        words[i / 64] |= (*bit as u64) << (i % 64);
+    }
+    words
+}
+
+/// Pack the first `bits` bits of `bytes` into words, the `i`th bit
+/// being bit `i % 8` of byte `i / 8`, so that each word holds eight
+/// bytes in little-endian order. The bits past the first `bits` are
+/// cleared. Fails if `bytes` doesn't hold `bits` bits.
+pub fn pack_bytes(bytes: &[u8], bits: usize) -> error::Result<Vec<u64>> {
+    if bits.div_ceil(8) > bytes.len() {
+        return Err(error::Error::LengthExceedsBuffer { bits, words: bytes.len() / 8 });
+    }
+    let mut words: Vec<u64> = repeat_n(0, bits.div_ceil(64)).collect();
+    for (i, byte) in bytes[..bits.div_ceil(8)].iter().enumerate() {
+        words[i / 8] |= (*byte as u64) << (8 * (i % 8));
+    }
+    if !bits.is_multiple_of(64) {
+        let last = words.len() - 1;
+        words[last] &= (1 << (bits % 64)) - 1;
+    }
+    Ok(words)
 }
@@ -1,32 +1,110 @@
 //! Traits for building up objects incrementally
 
-pub use build::buildable::{Buildable, PrimBuilder};
+use std::io::{self, Read, Write};
+use std::collections::BTreeMap;
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+
+pub use crate::build::buildable::{Buildable, PrimBuilder, CharBuilder};
 
 pub trait Builder<E, T> where Self: Sized {
     fn push(&mut self, element: E);
     fn finish(self) -> T;
 
-    fn from_iter<Iter: Iterator<Item=E>>(mut self, mut iter: Iter) -> T {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_iter<Iter: Iterator<Item=E>>(mut self, iter: Iter) -> T {
         for i in iter {
             self.push(i);
         }
         self.finish()
     }
+
+    /// A builder pushing each element through `f`
+    fn map<D, F: FnMut(D) -> E>(self, f: F) -> Map<Self, F> {
+        Map { builder: self, f }
+    }
+
+    /// A builder pushing only the elements satisfying `pred`
+    fn filter<F: FnMut(&E) -> bool>(self, pred: F) -> Filter<Self, F> {
+        Filter { builder: self, pred }
+    }
+
+    /// A builder showing each element to `f` before pushing it
+    fn inspect<F: FnMut(&E)>(self, f: F) -> Inspect<Self, F> {
+        Inspect { builder: self, f }
+    }
+
+    /// A builder pushing each element to both this builder and
+    /// `other`. Tees nest to feed any number of builders from one
+    /// pass, or a `Vec` of builders of one type may be used.
+    fn tee<U, B: Builder<E, U>>(self, other: B) -> (Self, B) {
+        (self, other)
+    }
+}
+
+/// Push elements through a function, as made by `Builder::map`
+pub struct Map<B, F> {
+    builder: B,
+    f: F,
+}
+
+impl<D, E, T, B: Builder<E, T>, F: FnMut(D) -> E> Builder<D, T> for Map<B, F> {
+    fn push(&mut self, e: D) {
+        let e = (self.f)(e);
+        self.builder.push(e);
+    }
+    fn finish(self) -> T {
+        self.builder.finish()
+    }
+}
+
+/// Push the elements satisfying a predicate, as made by
+/// `Builder::filter`
+pub struct Filter<B, F> {
+    builder: B,
+    pred: F,
+}
+
+impl<E, T, B: Builder<E, T>, F: FnMut(&E) -> bool> Builder<E, T> for Filter<B, F> {
+    fn push(&mut self, e: E) {
+        if (self.pred)(&e) {
+            self.builder.push(e);
+        }
+    }
+    fn finish(self) -> T {
+        self.builder.finish()
+    }
+}
+
+/// Debug elements to a function before pushing them, as made by
+/// `Builder::inspect`
+pub struct Inspect<B, F> {
+    builder: B,
+    f: F,
+}
+
+impl<E, T, B: Builder<E, T>, F: FnMut(&E)> Builder<E, T> for Inspect<B, F> {
+    fn push(&mut self, e: E) {
+        (self.f)(&e);
+        self.builder.push(e);
+    }
+    fn finish(self) -> T {
+        self.builder.finish()
+    }
 }
 
 /// Build a stream of `u64`s from a stream of bits
-#[derive(Show)]
+#[derive(Debug)]
 pub struct BitBuilder<B> {
     builder: B,
     accum: u64,
-    bit: uint,
-    size: uint,
+    bit: usize,
+    size: usize,
 }
 
 impl<B> BitBuilder<B> {
     pub fn new(builder: B) -> BitBuilder<B> {
         BitBuilder {
-            builder: builder,
+            builder,
             accum: 0,
             bit: 0,
             size: 0,
@@ -34,8 +112,64 @@ impl<B> BitBuilder<B> {
     }
 }
 
+impl<B> BitBuilder<B> {
+    /// The number of bits pushed
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Whether no bits have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The builder the words are pushed to
+    pub fn get_ref(&self) -> &B {
+        &self.builder
+    }
+
+    /// The builder the words are pushed to, mutably
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.builder
+    }
+
+    /// Push the `width` least significant bits of `value`, least
+    /// significant first
+    #[inline]
+    pub fn push_bits<T>(&mut self, value: u64, width: usize) where B: Builder<u64, T> {
+        assert!(width <= 64, "push_bits: width {} exceeds a word", width);
+        if width == 0 {
+            return;
+        }
+        let value = match width {
+            64 => value,
+            _ => value & ((1 << width) - 1),
+        };
+        self.accum |= value << self.bit;
+        self.size += width;
+        let bit = self.bit + width;
+        if bit >= 64 {
+            self.builder.push(self.accum);
+            // the bits of `value` that didn't fit in the word
+            self.accum = match self.bit {
+                0 => 0,
+                b => value >> (64 - b),
+            };
+            self.bit = bit - 64;
+        } else {
+            self.bit = bit;
+        }
+    }
+
+    /// Push the 64 bits of `word`, least significant first
+    #[inline]
+    pub fn push_word<T>(&mut self, word: u64) where B: Builder<u64, T> {
+        self.push_bits(word, 64)
+    }
+}
+
 /// Returns both result and size in bits
-impl<T, B: Builder<u64, T>> Builder<bool, (T, uint)> for BitBuilder<B> {
+impl<T, B: Builder<u64, T>> Builder<bool, (T, usize)> for BitBuilder<B> {
     #[inline(always)]
     fn push(&mut self, element: bool) {
         self.accum |= (element as u64) << self.bit;
@@ -49,27 +183,69 @@ impl<T, B: Builder<u64, T>> Builder<bool, (T, uint)> for BitBuilder<B> {
     }
 
     #[inline(always)]
-    fn finish(mut self) -> (T, uint) {
+    fn finish(mut self) -> (T, usize) {
         // push partial word
-        if self.bit % 64 != 0 {
+        if !self.bit.is_multiple_of(64) {
             self.builder.push(self.accum);
         }
         (self.builder.finish(), self.size)
     }
 }
 
+/// Checkpointed as the builder it feeds, its partial word and the
+/// number of bits pushed
+impl<B: Serialize> Serialize for BitBuilder<B> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.builder.serialize(w)?;
+        write_u64(w, self.accum)?;
+        write_uint(w, self.size)
+    }
+}
+
+impl<B: Deserialize> Deserialize for BitBuilder<B> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BitBuilder<B>> {
+        let builder = Deserialize::deserialize(r)?;
+        let accum = read_u64(r)?;
+        let size = read_uint(r)?;
+        if (size % 64 != 0 && accum >> (size % 64) != 0) || (size % 64 == 0 && accum != 0) {
+            return Err(invalid_data("BitBuilder partial word has bits past its size"));
+        }
+        Ok(BitBuilder { builder, accum, bit: size % 64, size })
+    }
+}
+
 /// Build up a `Vec` from elements
-#[derive(Show)]
+#[derive(Debug)]
 pub struct VecBuilder<T> {
     buffer: Vec<T>,
 }
 
 impl<T> VecBuilder<T> {
-    pub fn with_capacity(cap: uint) -> VecBuilder<T> {
+    pub fn with_capacity(cap: usize) -> VecBuilder<T> {
         VecBuilder {
             buffer: Vec::with_capacity(cap),
         }
     }
+
+    /// The number of elements pushed
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether no elements have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// The number of elements that can be held without reallocating
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Make room for at least `additional` more elements
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional)
+    }
 }
 
 impl<T: Clone> Builder<T, Vec<T>> for VecBuilder<T> {
@@ -81,6 +257,213 @@ impl<T: Clone> Builder<T, Vec<T>> for VecBuilder<T> {
     }
 }
 
+/// Checkpointed as the elements pushed
+impl<T: Serialize> Serialize for VecBuilder<T> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.buffer.serialize(w)
+    }
+}
+
+impl<T: Deserialize> Deserialize for VecBuilder<T> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<VecBuilder<T>> {
+        let buffer = Deserialize::deserialize(r)?;
+        Ok(VecBuilder { buffer })
+    }
+}
+
+/// Write words to a stream as they are built, giving back the stream
+/// or the first error met
+pub struct WriteBuilder<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> WriteBuilder<W> {
+    pub fn new(writer: W) -> WriteBuilder<W> {
+        WriteBuilder {
+            writer,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> Builder<u64, io::Result<W>> for WriteBuilder<W> {
+    fn push(&mut self, word: u64) {
+        if self.error.is_none() {
+            self.error = write_u64(&mut self.writer, word).err();
+        }
+    }
+    fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.writer),
+        }
+    }
+}
+
+/// Statistics of a sequence gathered by the first pass of a
+/// `TwoPass` build
+#[derive(Clone, Debug)]
+pub struct Stats<E> {
+    /// the number of elements
+    pub len: usize,
+    /// the largest element, if any
+    pub max: Option<E>,
+    /// the number of occurrences of each element, if requested
+    pub frequencies: BTreeMap<E, usize>,
+}
+
+/// Gather the `Stats` of the elements pushed
+pub struct StatsBuilder<E> {
+    stats: Stats<E>,
+    frequencies: bool,
+}
+
+impl<E: Ord> StatsBuilder<E> {
+    /// Gather the length and maximum, and the frequencies if
+    /// `frequencies`
+    pub fn new(frequencies: bool) -> StatsBuilder<E> {
+        StatsBuilder {
+            stats: Stats { len: 0, max: None, frequencies: BTreeMap::new() },
+            frequencies,
+        }
+    }
+}
+
+impl<E: Ord + Clone> Builder<E, Stats<E>> for StatsBuilder<E> {
+    fn push(&mut self, e: E) {
+        self.stats.len += 1;
+        if self.frequencies {
+            *self.stats.frequencies.entry(e.clone()).or_insert(0) += 1;
+        }
+        let larger = match self.stats.max {
+            Some(ref max) => e > *max,
+            None => true,
+        };
+        if larger {
+            self.stats.max = Some(e);
+        }
+    }
+    fn finish(self) -> Stats<E> {
+        self.stats
+    }
+}
+
+/// Build from a source that can be iterated twice, a first pass
+/// gathering the `Stats` of the elements and a second pushing them to
+/// a builder made from the statistics. This lets a builder be
+/// presized, or shaped by the symbols it will hold.
+pub struct TwoPass<I> {
+    iter: I,
+    frequencies: bool,
+}
+
+impl<E: Ord + Clone, I: Iterator<Item=E> + Clone> TwoPass<I> {
+    /// Build from the elements of `iter`, gathering their length and
+    /// maximum
+    pub fn new(iter: I) -> TwoPass<I> {
+        TwoPass { iter, frequencies: false }
+    }
+
+    /// Gather the frequencies of the elements as well
+    pub fn with_frequencies(self) -> TwoPass<I> {
+        TwoPass { iter: self.iter, frequencies: true }
+    }
+
+    /// The statistics of the elements
+    pub fn stats(&self) -> Stats<E> {
+        StatsBuilder::new(self.frequencies).from_iter(self.iter.clone())
+    }
+
+    /// Build with the builder made by `new_builder` from the
+    /// statistics of the elements
+    pub fn build<T, B: Builder<E, T>, F: FnOnce(&Stats<E>) -> B>(self, new_builder: F) -> T {
+        let stats = self.stats();
+        new_builder(&stats).from_iter(self.iter)
+    }
+}
+
+/// The order in which the bits of each byte read from a stream are
+/// pushed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Bit `i % 8` of byte `i / 8` is the `i`th, as in the words of
+    /// a bitvector
+    LsbFirst,
+    /// Bit `7 - i % 8` of byte `i / 8` is the `i`th, as in most file
+    /// formats
+    MsbFirst,
+}
+
+/// The bits of `byte` in reverse order
+fn reverse_byte(byte: u8) -> u8 {
+    let b = byte.rotate_left(4);
+    let b = ((b >> 2) & 0x33) | ((b & 0x33) << 2);
+    ((b >> 1) & 0x55) | ((b & 0x55) << 1)
+}
+
+/// Read `r` to its end, handing each chunk of bytes to `f` in turn
+fn read_chunks<R: Read, F: FnMut(&[u8])>(r: &mut R, mut f: F) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => f(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Push the bits of the bytes read from `r` to the end of the stream
+/// in `order`, giving the number of bits pushed. No more than a
+/// buffer of bytes is held at once.
+pub fn push_bits<R: Read, T, B: Builder<bool, T>>(r: &mut R, order: BitOrder, builder: &mut B)
+                                                  -> io::Result<usize> {
+    let mut bits = 0;
+    read_chunks(r, |bytes| {
+        for byte in bytes.iter() {
+            let byte = match order {
+                BitOrder::LsbFirst => *byte,
+                BitOrder::MsbFirst => reverse_byte(*byte),
+            };
+            for i in 0..8 {
+                builder.push((byte >> i) & 1 == 1);
+            }
+        }
+        bits += 8 * bytes.len();
+    })?;
+    Ok(bits)
+}
+
+/// Push the words of the bits of the bytes read from `r` to the end
+/// of the stream in `order`, eight bytes to a word with the first
+/// least significant, giving the number of bits pushed. The last word
+/// is padded with zeros.
+pub fn push_words<R: Read, T, B: Builder<u64, T>>(r: &mut R, order: BitOrder, builder: &mut B)
+                                                  -> io::Result<usize> {
+    let mut accum = 0u64;
+    let mut bytes_read = 0;
+    read_chunks(r, |bytes| {
+        for byte in bytes.iter() {
+            let byte = match order {
+                BitOrder::LsbFirst => *byte,
+                BitOrder::MsbFirst => reverse_byte(*byte),
+            };
+            accum |= (byte as u64) << (8 * (bytes_read % 8));
+            bytes_read += 1;
+            if bytes_read % 8 == 0 {
+                builder.push(accum);
+                accum = 0;
+            }
+        }
+    })?;
+    if bytes_read % 8 != 0 {
+        builder.push(accum);
+    }
+    Ok(8 * bytes_read)
+}
+
 /// A pair of `Builder`s is also a `Builder`
 impl<T: Clone, RA, RB, A: Builder<T, RA>, B: Builder<T, RB>> Builder<T, (RA, RB)> for (A, B) {
     fn push(&mut self, e: T) {
@@ -94,40 +477,65 @@ impl<T: Clone, RA, RB, A: Builder<T, RA>, B: Builder<T, RB>> Builder<T, (RA, RB)
     }
 }
 
+/// A `Vec` of `Builder`s is also a `Builder`, each being pushed every
+/// element
+impl<T: Clone, R, B: Builder<T, R>> Builder<T, Vec<R>> for Vec<B> {
+    fn push(&mut self, e: T) {
+        for b in self.iter_mut() {
+            b.push(e.clone());
+        }
+    }
+    fn finish(self) -> Vec<R> {
+        self.into_iter().map(|b| b.finish()).collect()
+    }
+}
+
 mod buildable {
-    use std::ops::{Shl, BitOr};
-    use std::num::Int;
     use std::mem::size_of;
+    use std::char;
     use super::Builder;
+    use crate::bits::Word;
 
     /// A trait for things that can be built from elements of type `E`
-    pub trait Buildable<E, BuilderT: Builder<E, Self>> {
-        // TODO: Associated type
-        fn new_builder() -> BuilderT;
+    /// without naming their `Builder`
+    pub trait Buildable<E> where Self: Sized {
+        /// The `Builder` producing values of this type
+        type Builder: Builder<E, Self>;
+        fn new_builder() -> <Self as Buildable<E>>::Builder;
+
+        /// Build a value from the elements of `iter`
+        fn build_from_iter<Iter: Iterator<Item=E>>(iter: Iter) -> Self {
+            <Self as Buildable<E>>::new_builder().from_iter(iter)
+        }
     }
 
-    /// Build primitive values from their bits (least significant bit first)
+    /// Build primitive values from their bits (most significant bit
+    /// first, as produced by `BitIter`)
     pub struct PrimBuilder<T> {
         prim: T,
-        bit: uint,
+        bit: usize,
     }
 
-    impl<T: Int> PrimBuilder<T> {
+    impl<T: Word> PrimBuilder<T> {
         pub fn new() -> PrimBuilder<T> {
             PrimBuilder {
-                prim: Int::zero(),
+                prim: T::ZERO,
                 bit: 0,
             }
         }
     }
 
-    impl<T: Shl<usize> + BitOr<T,Output=T> + Int> Builder<bool, T> for PrimBuilder<T> {
+    impl<T: Word> Default for PrimBuilder<T> {
+        fn default() -> PrimBuilder<T> {
+            PrimBuilder::new()
+        }
+    }
+
+    impl<T: Word> Builder<bool, T> for PrimBuilder<T> {
         fn push(&mut self, e: bool) {
             debug_assert!(self.bit < size_of::<T>() * 8);
-            if e {
-                let one: T = Int::one();
-                self.prim = self.prim | (one << self.bit);
-            }
+            let bit: T = if e { T::ONE } else { T::ZERO };
+            self.prim = (self.prim << 1) | bit;
             self.bit += 1;
         }
         fn finish(self) -> T {
@@ -135,9 +543,131 @@ mod buildable {
         }
     }
 
-    impl Buildable<bool, PrimBuilder<u64>> for u64 {
+    /// Build a `char` from the bits of its code point (most
+    /// significant bit first)
+    pub struct CharBuilder {
+        builder: PrimBuilder<u32>,
+    }
+
+    impl CharBuilder {
+        pub fn new() -> CharBuilder {
+            CharBuilder { builder: PrimBuilder::new() }
+        }
+    }
+
+    impl Default for CharBuilder {
+        fn default() -> CharBuilder {
+            CharBuilder::new()
+        }
+    }
+
+    impl Builder<bool, char> for CharBuilder {
+        fn push(&mut self, e: bool) {
+            self.builder.push(e);
+        }
+        fn finish(self) -> char {
+            let code = self.builder.finish();
+            match char::from_u32(code) {
+                Some(c) => c,
+                None => panic!("Invalid code point {}", code),
+            }
+        }
+    }
+
+    impl Buildable<bool> for char {
+        type Builder = CharBuilder;
+        fn new_builder() -> CharBuilder {
+            CharBuilder::new()
+        }
+    }
+
+    impl Buildable<bool> for u64 {
+        type Builder = PrimBuilder<u64>;
         fn new_builder() -> PrimBuilder<u64> {
             PrimBuilder::new()
         }
     }
+
+    impl Buildable<bool> for u32 {
+        type Builder = PrimBuilder<u32>;
+        fn new_builder() -> PrimBuilder<u32> {
+            PrimBuilder::new()
+        }
+    }
+
+    impl Buildable<bool> for u16 {
+        type Builder = PrimBuilder<u16>;
+        fn new_builder() -> PrimBuilder<u16> {
+            PrimBuilder::new()
+        }
+    }
+
+    impl Buildable<bool> for u8 {
+        type Builder = PrimBuilder<u8>;
+        fn new_builder() -> PrimBuilder<u8> {
+            PrimBuilder::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Builder;
+    use super::super::bit_vector::{self, BitVector};
+    use super::super::rank9::{self, Rank9};
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, BitRank};
+
+    #[quickcheck]
+    fn push_bits_is_correct(v: Vec<(u64, u8)>, bits: Vec<bool>) -> bool {
+        let mut by_bit = super::BitBuilder::new(super::VecBuilder::with_capacity(0));
+        let mut by_value = super::BitBuilder::new(super::VecBuilder::with_capacity(0));
+        for bit in bits.iter() {
+            by_bit.push(*bit);
+            by_value.push(*bit);
+        }
+        for &(value, width) in v.iter() {
+            let width = width as usize % 65;
+            for i in 0..width {
+                by_bit.push((value >> i) & 1 == 1);
+            }
+            match width {
+                64 => by_value.push_word(value),
+                _ => by_value.push_bits(value, width),
+            }
+        }
+        let expected: (Vec<u64>, usize) = by_bit.finish();
+        expected == by_value.finish()
+    }
+
+    #[quickcheck]
+    fn two_pass_is_correct(v: Vec<u8>) -> bool {
+        use super::TwoPass;
+        let pass = TwoPass::new(v.iter().copied()).with_frequencies();
+        let stats = pass.stats();
+        let built: Vec<u8> = pass.build(|stats| super::VecBuilder::with_capacity(stats.len));
+        stats.len == v.len() && stats.max == v.iter().max().copied() && built == v
+            && stats.frequencies.iter().all(|(x, n)| v.iter().filter(|y| *y == x).count() == *n)
+            && v.iter().all(|x| stats.frequencies.contains_key(x))
+    }
+
+    #[quickcheck]
+    fn combinators_are_correct(v: Vec<u8>) -> bool {
+        let mut seen = 0;
+        let ((rank9, bv), words): ((Rank9, BitVector), Vec<Vec<u64>>) = {
+            let bits = rank9::Builder::with_capacity(v.len())
+                .tee(bit_vector::Builder::with_capacity(v.len()))
+                .map(|x: u8| x.is_multiple_of(3))
+                .inspect(|_| seen += 1);
+            let words = vec!(super::VecBuilder::with_capacity(0), super::VecBuilder::with_capacity(0))
+                .filter(|x: &u64| *x > 100)
+                .map(|x: u8| x as u64);
+            (bits, words).from_iter(v.clone().into_iter())
+        };
+        let expected: Vec<u64> = v.iter().map(|x| *x as u64).filter(|x| *x > 100).collect();
+        seen == v.len() && rank9.len() == v.len() && bv.len() == v.len()
+            && (0..v.len()).all(|i| rank9.get(i) == v[i].is_multiple_of(3) && bv.get(i) == rank9.get(i))
+            && rank9.rank1(v.len()) == v.iter().filter(|x| **x % 3 == 0).count()
+            && words == vec!(expected.clone(), expected)
+    }
 }
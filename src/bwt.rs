@@ -0,0 +1,599 @@
+//! The Burrows-Wheeler transform of byte strings
+//
+// A text is taken to end with a sentinel smaller than any byte, so
+// that its rotations sort as its suffixes do. The transform lists the
+// byte preceding each suffix in sorted order, the whole text being
+// preceded by the sentinel alone. The sentinel is left out and its row
+// kept as the primary index. Backward search also needs the number of
+// suffixes starting with a byte smaller than each, the first row of
+// the suffixes starting with it.
+//
+// Suffixes are sorted by prefix doubling, in `O(n log^2 n)` time.
+//
+// A text too large for its suffix array to fit in memory is instead
+// transformed a block at a time, from its end, the transform of the
+// suffix of the text after the block being kept on disk along with a
+// bit for each of its suffixes telling whether it is greater than the
+// whole suffix. A suffix starting in the block is compared with the
+// suffix after the block by matching the block against the bytes
+// after it, with the Z-algorithm, and past the end of the block by the
+// bit of the suffix reached. These bits order the suffixes of the block
+// by prefix doubling over their bytes, a byte with its bit ranking
+// just before or after the suffix after the block, in `O(b log^2 b)`
+// time for a block of `b` bytes.
+//
+// The suffixes after the block are then placed among those of the
+// block by scanning the text after the block backwards, prepending a
+// byte to a suffix mapping the number of suffixes of the block before
+// it as backward search does, by a rank query on the bytes preceding
+// the sorted suffixes of the block. The number of suffixes after the
+// block falling between each two of the block, its gap array, lets the
+// two transforms be merged in a single pass, the row of the suffix
+// after the block getting the last byte of the block, and the bits of
+// the whole suffix are written as the suffixes are placed. Each block
+// thus reads the text, the transform and the bits after it
+// sequentially, the whole taking `O(n^2 / block)` sequential I/O.
+//
+// The transforms being merged are kept in a directory of their own
+// under the one given, removed once the transform is done or fails.
+//
+// See Ferragina, Gagie and Manzini 2012.
+
+use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::collection::Collection;
+use super::dictionary::Rank;
+use super::rank9;
+use super::serialize::invalid_data;
+use super::wavelet::Wavelet;
+
+/// The suffix array of `text` ended by a sentinel, the starts of its
+/// `n + 1` suffixes in increasing order, the first being `n`
+pub fn suffix_array(text: &[u8]) -> Vec<usize> {
+    // the sentinel ranking first
+    let mut rank: Vec<usize> = text.iter().map(|b| *b as usize + 1).collect();
+    rank.push(0);
+    sort_suffixes(rank)
+}
+
+/// The suffixes of a string of ranks, by prefix doubling, given the
+/// rank of each symbol, the last being unlike any other
+fn sort_suffixes(mut rank: Vec<usize>) -> Vec<usize> {
+    let n = rank.len() - 1;
+    let mut sa: Vec<usize> = (0..n + 1).collect();
+    let mut next: Vec<usize> = (0..n + 1).map(|_| 0).collect();
+    let mut k = 1;
+    loop {
+        // the rank of each suffix by its first `k` symbols
+        {
+            let key = |i: usize| (rank[i], if i + k <= n { rank[i + k] + 1 } else { 0 });
+            sa.sort_by_key(|a| key(*a));
+            next[sa[0]] = 0;
+            for j in 1..n + 1 {
+                let step = if key(sa[j - 1]) < key(sa[j]) { 1 } else { 0 };
+                next[sa[j]] = next[sa[j - 1]] + step;
+            }
+        }
+        ::std::mem::swap(&mut rank, &mut next);
+        if rank[sa[n]] == n || k > n {
+            return sa;
+        }
+        k *= 2;
+    }
+}
+
+/// The Burrows-Wheeler transform of a byte string
+pub struct Bwt {
+    /// the bytes preceding the sorted suffixes, but for the sentinel
+    bwt: Vec<u8>,
+    /// the row of the suffix preceded by the sentinel
+    primary: usize,
+    /// the number of suffixes starting with a byte smaller than each,
+    /// counting the sentinel's
+    counts: Vec<usize>,
+}
+
+impl Bwt {
+    /// Transform `text`
+    pub fn new(text: &[u8]) -> Bwt {
+        let sa = suffix_array(text);
+        Bwt::from_suffix_array(text, sa.as_slice())
+    }
+
+    /// Transform `text` given its suffix array
+    pub fn from_suffix_array(text: &[u8], sa: &[usize]) -> Bwt {
+        assert!(sa.len() == text.len() + 1, "Bwt: suffix array of the wrong length");
+        let mut bwt = Vec::with_capacity(text.len());
+        let mut primary = 0;
+        for (row, s) in sa.iter().enumerate() {
+            match *s {
+                0 => primary = row,
+                s => bwt.push(text[s - 1]),
+            }
+        }
+        let mut counts: Vec<usize> = (0..257).map(|_| 0).collect();
+        for b in text.iter() {
+            counts[*b as usize + 1] += 1;
+        }
+        counts[0] = 1;
+        for c in 1..257 {
+            counts[c] += counts[c - 1];
+        }
+        Bwt { bwt, primary, counts }
+    }
+
+    /// Transform the text read from `text`, holding at most `block`
+    /// bytes of it in memory at a time, along with a few words for
+    /// each, and the transform of the rest in temporary files under
+    /// `dir`
+    pub fn external<R: Read + Seek>(text: &mut R, block: usize, dir: &Path) -> io::Result<Bwt> {
+        assert!(block > 0, "Bwt: block size must be positive");
+        let n = text.seek(SeekFrom::End(0))? as usize;
+        let scratch = Scratch::create(dir)?;
+        let files = [scratch.path.join("0"), scratch.path.join("1")];
+        let greater = [scratch.path.join("0.gt"), scratch.path.join("1.gt")];
+        // start from the transform of the empty suffix, its only row
+        // being that of the sentinel, which is not greater than itself
+        File::create(&files[0])?;
+        let mut bits = BitSink::create(&greater[0])?;
+        bits.push(false)?;
+        bits.finish()?;
+        let counts: Vec<usize> = (0..257).map(|_| 1).collect();
+        let mut tail = Tail::open(&files[0], &greater[0], n, 0, counts)?;
+        let mut stages = 0;
+        while tail.start > 0 {
+            let start = tail.start.saturating_sub(block);
+            let mut buf: Vec<u8> = (start..tail.start).map(|_| 0).collect();
+            read_at(text, start, buf.as_mut_slice())?;
+            stages += 1;
+            tail = tail.prepend(text, n, buf.as_slice(), &files[stages % 2], &greater[stages % 2])?;
+        }
+        let mut bwt = Vec::with_capacity(n);
+        tail.bwt.seek(SeekFrom::Start(0))?;
+        tail.bwt.read_to_end(&mut bwt)?;
+        let (primary, counts) = (tail.primary, tail.counts.clone());
+        drop(tail);
+        drop(scratch);
+        Ok(Bwt { bwt, primary, counts })
+    }
+
+    /// The transformed text, without the sentinel
+    pub fn bwt(&self) -> &[u8] {
+        self.bwt.as_slice()
+    }
+
+    /// The row at which the sentinel was left out
+    pub fn primary(&self) -> usize {
+        self.primary
+    }
+
+    /// The first row of the suffixes starting with each byte, with the
+    /// number of rows last
+    pub fn counts(&self) -> &[usize] {
+        self.counts.as_slice()
+    }
+
+    /// Recover the text
+    pub fn inverse(&self) -> Vec<u8> {
+        // the occurrences of each byte before each position
+        let mut seen: Vec<usize> = (0..256).map(|_| 0).collect();
+        let ranks: Vec<usize> = self.bwt.iter().map(|b| {
+            seen[*b as usize] += 1;
+            seen[*b as usize] - 1
+        }).collect();
+        // walk back from the row of the sentinel's suffix
+        let mut text = Vec::with_capacity(self.bwt.len());
+        let mut row = 0;
+        for _ in 0..self.bwt.len() {
+            let i = if row > self.primary { row - 1 } else { row };
+            let b = self.bwt[i];
+            text.push(b);
+            row = self.counts[b as usize] + ranks[i];
+        }
+        text.reverse();
+        text
+    }
+}
+
+impl Collection for Bwt {
+    /// The length of the text
+    fn len(&self) -> usize {
+        self.bwt.len()
+    }
+}
+
+/// The number of scratch directories made by this process
+static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of the files of a transform being built, removed with
+/// them when dropped
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    /// A new directory under `dir`, named for the process and the
+    /// number of directories it has made so far
+    fn create(dir: &Path) -> io::Result<Scratch> {
+        loop {
+            let i = SCRATCH.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("succinct-bwt-{}-{}", process::id(), i));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Scratch { path }),
+                // left by an earlier process of the same id
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Read `buf.len()` bytes of `text` from `start`
+fn read_at<R: Read + Seek>(text: &mut R, start: usize, buf: &mut [u8]) -> io::Result<()> {
+    text.seek(SeekFrom::Start(start as u64))?;
+    text.read_exact(buf)
+}
+
+/// The length of the longest common prefix of `s` and each of its
+/// suffixes
+fn z_array(s: &[u16]) -> Vec<usize> {
+    let n = s.len();
+    let mut z: Vec<usize> = (0..n).map(|_| 0).collect();
+    let (mut l, mut r) = (0, 0);
+    for i in 1..n {
+        let mut k = if i < r { min(r - i, z[i - l]) } else { 0 };
+        while i + k < n && s[k] == s[i + k] {
+            k += 1;
+        }
+        z[i] = k;
+        if i + k > r {
+            l = i;
+            r = i + k;
+        }
+    }
+    z
+}
+
+/// Whether each suffix starting in `block` is greater than the suffix
+/// after it, given the first bytes `head` of that suffix, as many as
+/// the block has or up to the end of the text if `ended`, and whether
+/// the suffix `x` bytes after it is greater than it as `after[x]`
+fn greater_than_next(block: &[u8], head: &[u8], ended: bool, after: &[bool]) -> Vec<bool> {
+    // the head, the sentinel if it ends the text, a separator and the
+    // block, so that the Z-array matches the block against the head
+    let mut s: Vec<u16> = head.iter().map(|c| *c as u16).collect();
+    if ended {
+        s.push(256);
+    }
+    s.push(257);
+    let offset = s.len();
+    s.extend(block.iter().map(|c| *c as u16));
+    let z = z_array(s.as_slice());
+    let b = block.len();
+    (0..b).map(|p| match z[offset + p] {
+        // matching to the end of the block, the suffix is greater when
+        // the suffix after the block is smaller than the one as far
+        // after it
+        l if l == b - p => !after[b - p],
+        l if l == head.len() => true,
+        l => block[p + l] > head[l],
+    }).collect()
+}
+
+/// The transform of a suffix of a text, on disk
+struct Tail {
+    /// the transform, the sentinel being left out
+    bwt: File,
+    /// whether each suffix of the suffix is greater than it, from the
+    /// empty suffix back
+    greater: File,
+    /// the start of the suffix in the text
+    start: usize,
+    /// the row at which the sentinel was left out
+    primary: usize,
+    /// the first row of the suffixes starting with each byte
+    counts: Vec<usize>,
+}
+
+impl Tail {
+    fn open(bwt: &Path, greater: &Path, start: usize, primary: usize, counts: Vec<usize>) -> io::Result<Tail> {
+        Ok(Tail {
+            bwt: File::open(bwt)?,
+            greater: File::open(greater)?,
+            start,
+            primary,
+            counts,
+        })
+    }
+
+    /// The transform of `block` followed by the suffix, of the text of
+    /// length `n` read from `text`, written to the given files
+    fn prepend<R: Read + Seek>(mut self, text: &mut R, n: usize, block: &[u8],
+                               bwt: &Path, greater: &Path) -> io::Result<Tail> {
+        let b = block.len();
+        let (start, end) = (self.start - b, self.start);
+
+        // whether each suffix starting in the block is greater than the
+        // suffix after it, from the bytes and bits after the block
+        let mut head: Vec<u8> = (end..min(end + b, n)).map(|_| 0).collect();
+        read_at(text, end, head.as_mut_slice())?;
+        let h = head.len();
+        let mut window = BitSource::open(&mut self.greater, n - end - h)?;
+        let mut after: Vec<bool> = (0..h + 1).map(|_| false).collect();
+        for x in (1..h + 1).rev() {
+            after[x] = window.next()?;
+        }
+        let gt = greater_than_next(block, head.as_slice(), end + b >= n, after.as_slice());
+
+        // the suffixes of the block, ranking a byte by whether its
+        // suffix is greater than the suffix after the block, which
+        // comes between those of its first byte
+        let mut rank: Vec<usize> = (0..b).map(|p| 4 * block[p] as usize + 2 * gt[p] as usize).collect();
+        rank.push(match head.first() {
+            Some(c) => 4 * *c as usize + 1,
+            None => 0,
+        });
+        let order: Vec<usize> = sort_suffixes(rank).into_iter().filter(|j| *j < b).collect();
+        let mut pos: Vec<usize> = (0..b).map(|_| 0).collect();
+        for (q, j) in order.iter().enumerate() {
+            pos[*j] = q;
+        }
+
+        // backward search among the suffixes of the block, the byte
+        // before the whole block counting as a zero
+        let mut smaller: Vec<usize> = (0..257).map(|_| 0).collect();
+        for c in block.iter() {
+            smaller[*c as usize + 1] += 1;
+        }
+        for c in 1..257 {
+            smaller[c] += smaller[c - 1];
+        }
+        let preceding: Vec<u8> = order.iter().map(|j| if *j == 0 { 0 } else { block[*j - 1] }).collect();
+        let preceding = Wavelet::from_slice(preceding.as_slice(), || rank9::Builder::with_capacity(128));
+        let first = pos[0];
+        let occ = |c: u8, r: usize| preceding.rank(c, r) - (c == 0 && first < r) as usize;
+
+        // place the suffixes after the block from the empty one back,
+        // counting those before each suffix of the block
+        let mut gaps: Vec<usize> = (0..b + 1).map(|_| 0).collect();
+        let mut out = BitSink::create(greater)?;
+        let mut bits = BitSource::open(&mut self.greater, 0)?;
+        let mut r = 0;
+        gaps[0] += 1;
+        out.push(false)?;
+        let mut buf: Vec<u8> = (0..min(b, n - end)).map(|_| 0).collect();
+        let mut k = n;
+        while k > end {
+            let chunk = &mut buf[..min(b, k - end)];
+            read_at(text, k - chunk.len(), chunk)?;
+            for c in chunk.iter().rev() {
+                // the suffix at `k` is greater than the suffix after
+                // the block when its bit is set
+                let after_next = bits.next()?;
+                let last = (block[b - 1] == *c && after_next) as usize;
+                r = smaller[*c as usize] + occ(*c, r) + last;
+                gaps[r] += 1;
+                out.push(r > first)?;
+                k -= 1;
+            }
+        }
+        for p in (1..b).rev() {
+            out.push(pos[p] > first)?;
+        }
+        out.push(false)?;
+        out.finish()?;
+        drop(bits);
+
+        // merge the transforms, the rows of the suffix coming before
+        // each suffix of the block being those with no more suffixes of
+        // the block before them
+        let mut sink = BufWriter::new(File::create(bwt)?);
+        self.bwt.seek(SeekFrom::Start(0))?;
+        let mut old = BufReader::new(&mut self.bwt).bytes();
+        let (mut row, mut len, mut before) = (0, 0, 0);
+        let mut primary = 0;
+        for (q, next) in order.iter().map(|j| Some(*j)).chain(Some(None)).enumerate() {
+            before += gaps[q];
+            while row < before {
+                let c = match row == self.primary {
+                    true => block[b - 1],
+                    false => match old.next() {
+                        Some(c) => c?,
+                        None => return Err(invalid_data("transform truncated on disk")),
+                    },
+                };
+                sink.write_all(&[c])?;
+                row += 1;
+                len += 1;
+            }
+            match next {
+                Some(0) => primary = len,
+                Some(j) => { sink.write_all(&[block[j - 1]])?; len += 1; },
+                None => {},
+            }
+        }
+        sink.flush()?;
+
+        let mut freqs: Vec<usize> = (0..257).map(|_| 0).collect();
+        for c in block.iter() {
+            freqs[*c as usize + 1] += 1;
+        }
+        let mut counts = Vec::with_capacity(257);
+        let mut sum = 0;
+        for (count, freq) in self.counts.iter().zip(freqs.iter()) {
+            sum += freq;
+            counts.push(count + sum);
+        }
+        Tail::open(bwt, greater, start, primary, counts)
+    }
+}
+
+/// Bits being written to disk, eight to a byte from the lowest
+struct BitSink {
+    file: BufWriter<File>,
+    byte: u8,
+    len: usize,
+}
+
+impl BitSink {
+    fn create(path: &Path) -> io::Result<BitSink> {
+        Ok(BitSink { file: BufWriter::new(File::create(path)?), byte: 0, len: 0 })
+    }
+
+    fn push(&mut self, bit: bool) -> io::Result<()> {
+        self.byte |= (bit as u8) << (self.len % 8);
+        self.len += 1;
+        if self.len.is_multiple_of(8) {
+            self.file.write_all(&[self.byte])?;
+            self.byte = 0;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if !self.len.is_multiple_of(8) {
+            self.file.write_all(&[self.byte])?;
+        }
+        self.file.flush()
+    }
+}
+
+/// Bits written by a `BitSink`, read in order from some bit
+struct BitSource<'a> {
+    file: BufReader<&'a mut File>,
+    byte: u8,
+    bit: usize,
+}
+
+impl<'a> BitSource<'a> {
+    fn open(file: &'a mut File, bit: usize) -> io::Result<BitSource<'a>> {
+        file.seek(SeekFrom::Start((bit / 8) as u64))?;
+        let mut source = BitSource { file: BufReader::new(file), byte: 0, bit };
+        if !bit.is_multiple_of(8) {
+            source.fill()?;
+        }
+        Ok(source)
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut byte = [0u8; 1];
+        self.file.read_exact(&mut byte)?;
+        self.byte = byte[0];
+        Ok(())
+    }
+
+    fn next(&mut self) -> io::Result<bool> {
+        if self.bit.is_multiple_of(8) {
+            self.fill()?;
+        }
+        let bit = (self.byte >> (self.bit % 8)) & 1 == 1;
+        self.bit += 1;
+        Ok(bit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use std::process;
+    use std::thread;
+    use super::{Bwt, suffix_array};
+
+    #[quickcheck]
+    fn suffix_array_is_sorted(v: Vec<u8>) -> bool {
+        let mut naive: Vec<usize> = (0..v.len() + 1).collect();
+        naive.sort_by(|a, b| v[*a..].cmp(&v[*b..]));
+        suffix_array(v.as_slice()) == naive
+    }
+
+    #[quickcheck]
+    fn inverse_round_trips(v: Vec<u8>) -> bool {
+        Bwt::new(v.as_slice()).inverse() == v
+    }
+
+    #[quickcheck]
+    fn external_agrees(v: Vec<u8>, block: u8) -> bool {
+        // a small alphabet including the zero byte, which the backward
+        // search among the suffixes of a block treats specially
+        let v: Vec<u8> = v.iter().map(|x| *x % 3).collect();
+        let dir = env::temp_dir().join(format!("succinct-bwt-external-agrees-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ext = Bwt::external(&mut Cursor::new(v.clone()), block as usize % 8 + 1, &dir).unwrap();
+        fs::remove_dir(&dir).unwrap();
+        let bwt = Bwt::new(v.as_slice());
+        ext.bwt() == bwt.bwt() && ext.primary() == bwt.primary() && ext.counts() == bwt.counts()
+    }
+
+    #[test]
+    fn test_external() {
+        // enough for several blocks
+        let text: Vec<u8> = (0..10000usize).map(|i| b"abracadabra"[i * i % 11]).collect();
+        let dir = env::temp_dir().join(format!("succinct-bwt-test-external-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bwt = Bwt::external(&mut Cursor::new(text.clone()), 3000, &dir).unwrap();
+        fs::remove_dir(&dir).unwrap();
+        assert_eq!(bwt.bwt(), Bwt::new(text.as_slice()).bwt());
+        assert_eq!(bwt.inverse(), text);
+    }
+
+    #[test]
+    fn test_external_scratch() {
+        let dir = env::temp_dir().join(format!("succinct-bwt-test-scratch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // builds sharing a directory keep to their own files
+        let texts: Vec<Vec<u8>> = (0..4usize).map(|t| (0..3000usize).map(|i| b"ab"[i * (t + 1) % 7 % 2]).collect()).collect();
+        thread::scope(|scope| {
+            for text in texts.iter() {
+                let dir = &dir;
+                scope.spawn(move || {
+                    let bwt = Bwt::external(&mut Cursor::new(text.clone()), 500, dir).unwrap();
+                    assert_eq!(bwt.bwt(), Bwt::new(text.as_slice()).bwt());
+                });
+            }
+        });
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        // and remove them when failing
+        struct Failing(Cursor<Vec<u8>>);
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.position() < 1000 {
+                    true => self.0.read(buf),
+                    false => Err(io::Error::other("failing")),
+                }
+            }
+        }
+        impl Seek for Failing {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+        assert!(Bwt::external(&mut Failing(Cursor::new(texts[0].clone())), 500, &dir).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_banana() {
+        let bwt = Bwt::new(b"banana");
+        assert_eq!(suffix_array(b"banana"), vec!(6, 5, 3, 1, 0, 4, 2));
+        assert_eq!(bwt.bwt(), b"annbaa".as_slice());
+        assert_eq!(bwt.primary(), 4);
+        assert_eq!(bwt.counts()[b'a' as usize], 1);
+        assert_eq!(bwt.counts()[b'b' as usize], 4);
+        assert_eq!(bwt.counts()[b'n' as usize], 5);
+        assert_eq!(bwt.counts()[256], 7);
+    }
+}
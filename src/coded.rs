@@ -0,0 +1,287 @@
+//! A sequence of integers in Elias gamma or delta code
+//
+// The codes are written one after the other into a single bit
+// stream. Every `rate`th code's offset is sampled, so the `i`th value
+// is found by decoding forward from the nearest preceding sample.
+// Values are coded shifted up by one, since neither code can
+// represent zero.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use super::bits::{BitReader, read_gamma, read_delta};
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::int_vector::IntVector;
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+pub use crate::coded::build::Builder;
+
+/// An integer code
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Code {
+    /// Elias gamma, best for values smaller than a few hundred
+    Gamma,
+    /// Elias delta, best for larger values
+    Delta,
+}
+
+/// The largest value which can be coded
+pub const MAX_VALUE: u64 = u64::MAX - 1;
+
+impl Code {
+    fn read(self, reader: &mut BitReader) -> u64 {
+        match self {
+            Code::Gamma => read_gamma(reader),
+            Code::Delta => read_delta(reader),
+        }
+    }
+}
+
+/// A sequence of coded integers with sampled offsets
+#[derive(Clone, Debug)]
+pub struct CodedSequence {
+    code: Code,
+    /// the number of codes per sample
+    rate: usize,
+    len: usize,
+    /// the codes
+    bits: Vec<u64>,
+    /// the offset of every `rate`th code
+    samples: IntVector,
+}
+
+impl CodedSequence {
+    /// The code used for the values
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    /// The number of codes per sample
+    pub fn sample_rate(&self) -> usize {
+        self.rate
+    }
+
+    /// An iterator over the values
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { seq: self, reader: BitReader::new(self.bits.as_slice()), pos: 0 }
+    }
+
+    /// An iterator over the values from the `n`th on
+    pub fn iter_from<'a>(&'a self, n: usize) -> Iter<'a> {
+        assert!(n <= self.len, "CodedSequence: index {} out of bounds", n);
+        let sample = n / self.rate;
+        if sample >= self.samples.len() {
+            return Iter { seq: self, reader: BitReader::new(self.bits.as_slice()), pos: self.len };
+        }
+        let offset = self.samples.get(sample) as usize;
+        let mut iter = Iter {
+            seq: self,
+            reader: BitReader::at(self.bits.as_slice(), offset),
+            pos: sample * self.rate,
+        };
+        for _ in iter.pos..n {
+            iter.next();
+        }
+        iter
+    }
+}
+
+impl Collection for CodedSequence {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for CodedSequence {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "CodedSequence: index {} out of bounds", n);
+        self.iter_from(n).next().unwrap()
+    }
+}
+
+impl SpaceUsage for CodedSequence {
+    fn total_bytes(&self) -> usize {
+        size_of::<Code>() + 2 * size_of::<usize>()
+            + vec_bytes(&self.bits) + self.samples.total_bytes()
+    }
+}
+
+impl Serialize for CodedSequence {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, match self.code { Code::Gamma => 0, Code::Delta => 1 })?;
+        write_uint(w, self.rate)?;
+        write_uint(w, self.len)?;
+        self.bits.serialize(w)?;
+        self.samples.serialize(w)
+    }
+}
+
+impl Deserialize for CodedSequence {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<CodedSequence> {
+        let code = match read_u64(r)? {
+            0 => Code::Gamma,
+            1 => Code::Delta,
+            _ => return Err(invalid_data("invalid CodedSequence code")),
+        };
+        let rate = read_uint(r)?;
+        let len = read_uint(r)?;
+        let bits: Vec<u64> = Deserialize::deserialize(r)?;
+        let samples: IntVector = Deserialize::deserialize(r)?;
+        if rate == 0 {
+            return Err(invalid_data("CodedSequence sample rate is zero"));
+        }
+        if samples.len() != len.div_ceil(rate) {
+            return Err(invalid_data("CodedSequence samples inconsistent with length"));
+        }
+        if samples.iter().any(|s| s >= 64 * bits.len() as u64) {
+            return Err(invalid_data("CodedSequence sample exceeds its codes"));
+        }
+        Ok(CodedSequence { code, rate, len, bits, samples })
+    }
+}
+
+/// An iterator over the values of a `CodedSequence`, decoding them in
+/// order
+pub struct Iter<'a> {
+    seq: &'a CodedSequence,
+    reader: BitReader<'a>,
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.pos < self.seq.len {
+            self.pos += 1;
+            Some(self.seq.code.read(&mut self.reader) - 1)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.seq.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+mod build {
+    use super::super::build::{self, BitBuilder, VecBuilder};
+    use super::super::bits::{write_gamma, write_delta, gamma_len, delta_len, bit_width};
+    use super::super::int_vector;
+    use super::{CodedSequence, Code, MAX_VALUE};
+
+    /// Build a `CodedSequence` from its values
+    pub struct Builder {
+        code: Code,
+        rate: usize,
+        len: usize,
+        /// the number of bits written
+        size: usize,
+        bits: BitBuilder<VecBuilder<u64>>,
+        samples: Vec<usize>,
+    }
+
+    impl Builder {
+        /// Build a sequence in `code`, sampling every 64th value
+        pub fn new(code: Code) -> Builder {
+            Builder::with_sample_rate(code, 64)
+        }
+
+        /// Build a sequence in `code`, sampling every `rate`th value.
+        /// Access to a value decodes up to `rate` codes.
+        pub fn with_sample_rate(code: Code, rate: usize) -> Builder {
+            assert!(rate > 0, "CodedSequence: sample rate must be positive");
+            Builder {
+                code,
+                rate,
+                len: 0,
+                size: 0,
+                bits: BitBuilder::new(VecBuilder::with_capacity(64)),
+                samples: Vec::new(),
+            }
+        }
+    }
+
+    impl build::Builder<u64, CodedSequence> for Builder {
+        fn push(&mut self, x: u64) {
+            assert!(x <= MAX_VALUE, "CodedSequence: {} exceeds the largest codable value", x);
+            if self.len.is_multiple_of(self.rate) {
+                self.samples.push(self.size);
+            }
+            match self.code {
+                Code::Gamma => {
+                    write_gamma(&mut self.bits, x + 1);
+                    self.size += gamma_len(x + 1);
+                },
+                Code::Delta => {
+                    write_delta(&mut self.bits, x + 1);
+                    self.size += delta_len(x + 1);
+                },
+            }
+            self.len += 1;
+        }
+
+        fn finish(self) -> CodedSequence {
+            let (bits, _) = self.bits.finish();
+            let width = bit_width(self.size as u64);
+            let samples = int_vector::Builder::with_capacity(width, self.samples.len())
+                .from_iter(self.samples.iter().map(|s| *s as u64));
+            CodedSequence {
+                code: self.code,
+                rate: self.rate,
+                len: self.len,
+                bits,
+                samples,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CodedSequence, Code};
+    use super::super::dictionary::Access;
+    use super::super::build::Builder;
+
+    fn build(code: Code, rate: usize, v: &[u64]) -> CodedSequence {
+        super::Builder::with_sample_rate(code, rate).from_iter(v.iter().cloned())
+    }
+
+    #[quickcheck]
+    fn gamma_is_correct(rate: usize, v: Vec<u32>) -> bool {
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let seq = build(Code::Gamma, rate % 8 + 1, &v);
+        v.iter().enumerate().all(|(i, x)| seq.get(i) == *x)
+            && seq.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn delta_is_correct(rate: usize, v: Vec<u64>) -> bool {
+        let v: Vec<u64> = v.into_iter().map(|x| x >> 1).collect();
+        let seq = build(Code::Delta, rate % 8 + 1, &v);
+        v.iter().enumerate().all(|(i, x)| seq.get(i) == *x)
+            && seq.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u32>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let seq: CodedSequence = round_trip(&build(Code::Delta, 3, &v));
+        seq.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_code_lengths() {
+        // gamma: 1 -> 1, 4 -> 00100
+        let seq = build(Code::Gamma, 64, &[0, 3]);
+        assert_eq!(seq.bits[0] & 0x3f, 0b001001);
+        assert_eq!(super::super::bits::gamma_len(4), 5);
+        assert_eq!(super::super::bits::delta_len(4), 5);
+        assert_eq!(super::super::bits::delta_len(1), 1);
+    }
+}
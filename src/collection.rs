@@ -1,3 +1,7 @@
 pub trait Collection {
-    fn len(&self) -> uint;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
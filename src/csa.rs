@@ -0,0 +1,229 @@
+//! Compressed suffix arrays of byte strings
+//
+// The function `Ψ` maps the row of each suffix, in sorted order, to
+// the row of the suffix following it in the text, the suffix of the
+// whole text following the sentinel's. It increases over the rows of
+// suffixes starting with the same byte, so adding `n + 1` times the
+// index of that byte's bucket, the sentinel's coming first, makes it
+// increase over all rows, and it is stored in Elias-Fano code. The
+// first byte of a suffix is then its bucket and following `Ψ` reads
+// the suffix forwards.
+//
+// The start of a suffix is found by following `Ψ` to a row whose
+// suffix was sampled, at multiples of the rate and at the end of the
+// text. The row of a suffix is found by following `Ψ` from that of
+// the nearest earlier suffix starting at a multiple of the rate.
+// Patterns are counted by binary search over the rows, reading the
+// suffixes through `Ψ`.
+//
+// See Grossi and Vitter 2000 and Sadakane 2003.
+
+use std::cmp::Ordering;
+use super::bits::bit_width;
+use super::build::Builder;
+use super::bwt::suffix_array;
+use super::collection::Collection;
+use super::dictionary::{Access, BitRank};
+use super::elias_fano::EliasFano;
+use super::int_vector::IntVector;
+use super::rank9::{self, Rank9};
+use super::space::SpaceUsage;
+
+/// A compressed suffix array of a byte string
+pub struct Csa {
+    /// `Ψ` of each row plus `n + 1` times its bucket
+    psi: EliasFano,
+    /// the length of the text
+    len: usize,
+    /// the sampling rate
+    rate: usize,
+    /// whether the start of the suffix of each row is sampled
+    sampled: Rank9,
+    /// the start of each sampled suffix, by row
+    samples: IntVector,
+    /// the row of each suffix starting at a multiple of the rate
+    rows: IntVector,
+}
+
+impl Csa {
+    /// Index `text`, sampling the suffixes starting at every `rate`
+    /// positions
+    pub fn new(text: &[u8], rate: usize) -> Csa {
+        assert!(rate > 0, "Csa: sampling rate must be positive");
+        let n = text.len();
+        let sa = suffix_array(text);
+        let mut isa: Vec<usize> = (0..n + 1).map(|_| 0).collect();
+        for (row, s) in sa.iter().enumerate() {
+            isa[*s] = row;
+        }
+        let psi: Vec<u64> = sa.iter().map(|s| {
+            let bucket = if *s == n { 0 } else { text[*s] as u64 + 1 };
+            let next = isa[(*s + 1) % (n + 1)];
+            bucket * (n as u64 + 1) + next as u64
+        }).collect();
+
+        let width = bit_width(n as u64);
+        let keep = |s: usize| s.is_multiple_of(rate) || s == n;
+        let mut sampled = rank9::Builder::with_capacity(n + 1);
+        let mut starts = Vec::new();
+        for s in sa.iter() {
+            sampled.push(keep(*s));
+            if keep(*s) {
+                starts.push(*s);
+            }
+        }
+        let mut samples = IntVector::new(width, starts.len());
+        for (i, s) in starts.iter().enumerate() {
+            samples.set(i, *s as u64);
+        }
+        let mut rows = IntVector::new(width, n / rate + 1);
+        for k in 0..n / rate + 1 {
+            rows.set(k, isa[k * rate] as u64);
+        }
+        Csa {
+            psi: EliasFano::from_slice(psi.as_slice()),
+            len: n,
+            rate,
+            sampled: sampled.finish(),
+            samples,
+            rows,
+        }
+    }
+
+    /// The sampling rate
+    pub fn rate(&self) -> usize {
+        self.rate
+    }
+
+    /// The row of the suffix following that of `row` in the text
+    pub fn psi(&self, row: usize) -> usize {
+        (self.psi.get(row) % (self.len as u64 + 1)) as usize
+    }
+
+    /// The first byte of the suffix of `row`, unless it is the
+    /// sentinel
+    pub fn first(&self, row: usize) -> Option<u8> {
+        match self.psi.get(row) / (self.len as u64 + 1) {
+            0 => None,
+            b => Some((b - 1) as u8),
+        }
+    }
+
+    /// The start of the suffix of `row`
+    pub fn sa(&self, mut row: usize) -> usize {
+        assert!(row <= self.len, "Csa: row {} out of bounds", row);
+        let mut steps = 0;
+        while !self.sampled.get(row) {
+            row = self.psi(row);
+            steps += 1;
+        }
+        let n = self.sampled.rank1(row);
+        self.samples.get(n) as usize - steps
+    }
+
+    /// The row of the suffix starting at `i`
+    pub fn inverse_sa(&self, i: usize) -> usize {
+        assert!(i <= self.len, "Csa: position {} out of bounds", i);
+        let mut row = self.rows.get(i / self.rate) as usize;
+        for _ in 0..i % self.rate {
+            row = self.psi(row);
+        }
+        row
+    }
+
+    /// The range of rows of the suffixes prefixed by `pattern`
+    pub fn range(&self, pattern: &[u8]) -> (usize, usize) {
+        let sp = self.partition(|row| self.compare(row, pattern) == Ordering::Less);
+        let ep = self.partition(|row| self.compare(row, pattern) != Ordering::Greater);
+        (sp, ep)
+    }
+
+    /// The number of occurrences of `pattern` in the text, the empty
+    /// pattern occurring at each position and at the end
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        let (sp, ep) = self.range(pattern);
+        ep - sp
+    }
+
+    /// The first row not satisfying `pred`, which holds for a prefix
+    /// of the rows
+    fn partition<F: Fn(usize) -> bool>(&self, pred: F) -> usize {
+        let (mut lo, mut hi) = (0, self.len + 1);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if pred(mid) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// Compare the prefix of the suffix of `row` with `pattern`
+    fn compare(&self, mut row: usize, pattern: &[u8]) -> Ordering {
+        for b in pattern.iter() {
+            match self.first(row) {
+                None => return Ordering::Less,
+                Some(c) if c != *b => return c.cmp(b),
+                Some(_) => row = self.psi(row),
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl Collection for Csa {
+    /// The length of the text
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl SpaceUsage for Csa {
+    fn total_bytes(&self) -> usize {
+        self.psi.total_bytes() + self.sampled.total_bytes()
+            + self.samples.total_bytes() + self.rows.total_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Csa;
+    use super::super::bwt::suffix_array;
+
+    /// A string over a small alphabet, shaped by `v`
+    fn text(v: &[u8]) -> Vec<u8> {
+        v.iter().map(|x| b'a' + *x % 3).collect()
+    }
+
+    #[quickcheck]
+    fn sa_is_correct(v: Vec<u8>, rate: u8) -> bool {
+        let v = text(&v);
+        let csa = Csa::new(v.as_slice(), rate as usize % 8 + 1);
+        let sa = suffix_array(v.as_slice());
+        (0..v.len() + 1).all(|row| csa.sa(row) == sa[row] && csa.inverse_sa(sa[row]) == row)
+    }
+
+    #[quickcheck]
+    fn count_is_correct(v: Vec<u8>, p: Vec<u8>) -> bool {
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let csa = Csa::new(v.as_slice(), 4);
+        let naive = (0..v.len() + 1).filter(|i| v[*i..].starts_with(p)).count();
+        csa.count(p) == naive
+    }
+
+    #[test]
+    fn test_csa() {
+        let csa = Csa::new(b"banana", 3);
+        // $ a$ ana$ anana$ banana$ na$ nana$
+        assert_eq!((0..7).map(|row| csa.sa(row)).collect::<Vec<usize>>(), vec!(6, 5, 3, 1, 0, 4, 2));
+        assert_eq!(csa.psi(0), 4);
+        assert_eq!(csa.first(4), Some(b'b'));
+        assert_eq!(csa.first(0), None);
+        assert_eq!(csa.count(b"ana"), 2);
+        assert_eq!(csa.range(b"na"), (5, 7));
+        assert_eq!(csa.count(b"nab"), 0);
+    }
+}
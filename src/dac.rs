@@ -0,0 +1,256 @@
+//! Directly addressable codes
+//
+// Each value is split into chunks of `chunk` bits, least significant
+// first. Level `l` holds chunk `l` of every value having more than `l`
+// chunks, in sequence order, along with a bitvector recording which of
+// these values continue to level `l + 1`. The position of a value in
+// the next level is then the rank of its continuation bit, so small
+// values cost a single chunk and access takes a rank per level
+// visited.
+
+use std::mem::size_of;
+use std::io::{self, Read, Write};
+use super::dictionary::{Access, BitRank};
+use super::collection::Collection;
+use super::int_vector::IntVector;
+use super::rank9::Rank9;
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+pub use crate::dac::build::Builder;
+
+/// One level of chunks
+#[derive(Clone, Debug)]
+struct Level {
+    chunks: IntVector,
+    /// whether each value continues to the next level
+    more: Rank9,
+}
+
+/// A sequence of integers of varying magnitude, stored in chunks of a
+/// fixed number of bits
+#[derive(Clone, Debug)]
+pub struct Dac {
+    /// bits per chunk
+    chunk: usize,
+    len: usize,
+    levels: Vec<Level>,
+}
+
+impl Dac {
+    /// The number of bits per chunk
+    pub fn chunk_width(&self) -> usize {
+        self.chunk
+    }
+
+    /// The number of levels, that is the number of chunks of the
+    /// largest value
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// An iterator over the values
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { dac: self, pos: 0 }
+    }
+}
+
+impl Collection for Dac {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for Dac {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "Dac: index {} out of bounds", n);
+        let mut x = 0;
+        let mut pos = n;
+        for (l, level) in self.levels.iter().enumerate() {
+            x |= level.chunks.get(pos) << (l * self.chunk);
+            if !level.more.get(pos) {
+                break;
+            }
+            pos = level.more.rank1(pos);
+        }
+        x
+    }
+}
+
+impl SpaceUsage for Dac {
+    fn total_bytes(&self) -> usize {
+        let levels: usize = self.levels.iter()
+            .map(|l| l.chunks.total_bytes() + l.more.total_bytes())
+            .sum();
+        2 * size_of::<usize>() + vec_bytes(&self.levels) + levels
+    }
+}
+
+impl Serialize for Level {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.chunks.serialize(w)?;
+        self.more.serialize(w)
+    }
+}
+
+impl Deserialize for Level {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Level> {
+        let chunks: IntVector = Deserialize::deserialize(r)?;
+        let more: Rank9 = Deserialize::deserialize(r)?;
+        if chunks.len() != more.len() {
+            return Err(invalid_data("Dac level chunks inconsistent with continuation bits"));
+        }
+        Ok(Level { chunks, more })
+    }
+}
+
+impl Serialize for Dac {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.chunk)?;
+        write_uint(w, self.len)?;
+        self.levels.serialize(w)
+    }
+}
+
+impl Deserialize for Dac {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Dac> {
+        let chunk = read_uint(r)?;
+        let len = read_uint(r)?;
+        let levels: Vec<Level> = Deserialize::deserialize(r)?;
+        if chunk == 0 || chunk > 64 {
+            return Err(invalid_data("Dac chunk width out of range"));
+        }
+        // each level must hold exactly the values continued by the
+        // level above
+        let mut expected = len;
+        for level in levels.iter() {
+            if level.chunks.width() != chunk || level.chunks.len() != expected {
+                return Err(invalid_data("Dac level inconsistent with its parent"));
+            }
+            expected = level.more.rank1(level.more.len());
+        }
+        if expected != 0 {
+            return Err(invalid_data("Dac values continue past the last level"));
+        }
+        Ok(Dac { chunk, len, levels })
+    }
+}
+
+/// An iterator over the values of a `Dac`
+pub struct Iter<'a> {
+    dac: &'a Dac,
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.pos < self.dac.len {
+            self.pos += 1;
+            Some(self.dac.get(self.pos - 1))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.dac.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+mod build {
+    use super::super::build;
+    use super::super::int_vector::{self, low_mask};
+    use super::super::rank9;
+    use super::{Dac, Level};
+
+    /// Build a `Dac` from its values
+    ///
+    /// Levels are created as values requiring them are pushed, so the
+    /// values are streamed directly into their chunks.
+    pub struct Builder {
+        chunk: usize,
+        len: usize,
+        levels: Vec<(int_vector::Builder, rank9::Builder)>,
+    }
+
+    impl Builder {
+        /// Build a sequence of `chunk`-bit chunks, typically 8 for
+        /// byte-aligned or 4 for nibble-aligned levels
+        pub fn new(chunk: usize) -> Builder {
+            assert!(chunk > 0 && chunk <= 64, "Dac: chunk width {} out of range", chunk);
+            Builder { chunk, len: 0, levels: Vec::new() }
+        }
+    }
+
+    impl build::Builder<u64, Dac> for Builder {
+        fn push(&mut self, x: u64) {
+            let mut rest = x;
+            let mut l = 0;
+            loop {
+                if l == self.levels.len() {
+                    self.levels.push((int_vector::Builder::with_capacity(self.chunk, 64),
+                                      rank9::Builder::with_capacity(64)));
+                }
+                let (ref mut chunks, ref mut more) = self.levels[l];
+                chunks.push(rest & low_mask(self.chunk));
+                rest = match self.chunk {
+                    64 => 0,
+                    _ => rest >> self.chunk,
+                };
+                more.push(rest != 0);
+                if rest == 0 {
+                    break;
+                }
+                l += 1;
+            }
+            self.len += 1;
+        }
+
+        fn finish(self) -> Dac {
+            Dac {
+                chunk: self.chunk,
+                len: self.len,
+                levels: self.levels.into_iter()
+                    .map(|(chunks, more)| Level { chunks: chunks.finish(), more: more.finish() })
+                    .collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dac;
+    use super::super::dictionary::Access;
+    use super::super::build::Builder;
+
+    #[quickcheck]
+    fn get_is_correct(chunk: usize, v: Vec<u64>) -> bool {
+        let chunk = chunk % 64 + 1;
+        let dac: Dac = super::Builder::new(chunk).from_iter(v.clone().into_iter());
+        v.iter().enumerate().all(|(i, x)| dac.get(i) == *x)
+            && dac.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let dac: Dac = super::Builder::new(8).from_iter(v.clone().into_iter());
+        let dac2: Dac = round_trip(&dac);
+        dac2.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_levels() {
+        let v: Vec<u64> = vec!(3, 0x12, 0x345, 1, 0);
+        let dac: Dac = super::Builder::new(4).from_iter(v.clone().into_iter());
+        assert_eq!(dac.levels(), 3);
+        for (i, x) in v.iter().enumerate() {
+            assert_eq!(dac.get(i), *x);
+        }
+    }
+}
@@ -4,16 +4,15 @@
 //
 // Bit indices are 0-based.
 
-use std::num::Int;
 
 /// An analog to the usual `Index` trait but allowing return by value.
 pub trait Access<T> {
     /// Retrieve the `n`th item
-    fn get(&self, n: uint) -> T;
+    fn get(&self, n: usize) -> T;
 }
 
 impl Access<bool> for u64 {
-    fn get(&self, n: uint) -> bool {
+    fn get(&self, n: usize) -> bool {
         if n < 64 {
             false
         } else {
@@ -23,10 +22,10 @@ impl Access<bool> for u64 {
 }
 
 /// A bit position
-pub type Pos = int;
+pub type Pos = usize;
 
 /// A bit count
-pub type Count = int;
+pub type Count = usize;
 
 /// Rank operation
 pub trait Rank<T> {
@@ -61,17 +60,15 @@ impl Select<bool> for u64 {
             return 0;
         }
 
-        let mut idx: int = 0;
         let mut x: u64 = *self;
-        let mut n: int = n0;
-        for i in range(0u, 64) {
+        let mut n: usize = n0;
+        for idx in 0..64 {
             if (x & 1) == (bit as u64) {
                 n -= 1;
                 if n == 0 {
                     return idx + 1
                 }
             }
-            idx += 1;
             x >>= 1;
         }
         panic!("Not enough {} bits in {} to select({})", bit, *self, n0);
@@ -79,18 +76,18 @@ impl Select<bool> for u64 {
 }
 
 /*
-fn pop_count(x: u64) -> int {
+fn pop_count(x: u64) -> usize {
     // Broadword sideways addition
     let x0: u64 = x - ((x & 0xaaaa_aaaa_aaaa_aaaa) >> 1);
     let x1: u64 = (x0 & 0x3333_3333_3333_3333) + ((x0 >> 2) & 0x3333_3333_3333_3333);
     let x2: u64 = (x1 + (x1 >> 4)) & 0x0F0F0_F0F0_F0F0_F0F;
     let l8: u64 = 0x0101_0101_0101_0101;
-    ((x2 * l8) >> 56) as int
+    ((x2 * l8) >> 56) as usize
 }
 
 /// Find the index of the `i`th one in `x`
 /// Based on Algorithm 2 from Vigna 2014
-fn bit_search(i: uint, x: u64) -> uint {
+fn bit_search(i: usize, x: u64) -> usize {
     fn lt8(x: u64, y: u64) -> u64 {
         let h8 = 0x8080808080808080;
         (((x | h8) - (y & !h8)) ^ x ^ !y) & h8
@@ -106,46 +103,45 @@ fn bit_search(i: uint, x: u64) -> uint {
     let l = r - (((s << 8) >> b) & 0xff);
     let s4: u64 = ((((x >> b) & 0xff) * l8 & gt8(0x8040201008040201, 0)) >> 7) * l8;
     let res = b + (((lt8(s, l*l8) >> 7) * l8) >> 56);
-    res as uint
+    res as usize
 }
 */
 
 impl Rank<bool> for u64 {
-    fn rank(&self, bit: bool, n: int) -> int {
+    fn rank(&self, bit: bool, n: usize) -> usize {
         if bit {self.rank1(n)} else {self.rank0(n)}
     }
 }
 
 /// Out of range bits taken to be 0
 impl BitRank for u64 {
-    fn rank1(&self, n: int) -> int {
+    fn rank1(&self, n: usize) -> usize {
         if n < 64 {
-            let mask: u64 = (1 << (n as uint)) - 1;
-            (mask & *self).count_ones() as int
+            let mask: u64 = (1 << n) - 1;
+            (mask & *self).count_ones() as usize
         } else {
-            self.count_ones() as int
+            self.count_ones() as usize
         }
     }
 
-    fn rank0(&self, n: int) -> int {
+    fn rank0(&self, n: usize) -> usize {
         if n < 64 {
-            let mask = (1 << (n as uint)) - 1;
-            (mask | *self).count_zeros() as int
+            let mask = (1 << n) - 1;
+            (mask | *self).count_zeros() as usize
         } else {
-            self.count_zeros() as int
+            self.count_zeros() as usize
         }
     }
 }
 
 impl<T: Eq> Rank<T> for Vec<T> {
-    fn rank(&self, el: T, n: int) -> int {
-        use std::iter::AdditiveIterator;
-        self.iter().take(n as uint).map(|x| if x == &el {1i} else {0}).sum()
+    fn rank(&self, el: T, n: usize) -> usize {
+        self.iter().take(n).map(|x| if x == &el {1usize} else {0}).sum()
     }
 }
 
 impl<T: Eq> Select<T> for Vec<T> {
-    fn select(&self, el: T, mut n: int) -> int {
+    fn select(&self, el: T, mut n: usize) -> usize {
         if n == 0 {
             return 0;
         }
@@ -153,7 +149,7 @@ impl<T: Eq> Select<T> for Vec<T> {
             if x == &el {
                 n -= 1;
                 if n == 0 {
-                    return i as int + 1;
+                    return i + 1;
                 }
             }
         }
@@ -171,10 +167,13 @@ pub mod test {
         assert_eq!(0x5u64.select(true, 1), 1);
     }
 
-    pub fn test_select0<T: Select<bool>>(from_vec: &Fn(&Vec<u64>, int) -> T) {
+    // these tables spell out each position as a bit and the word
+    // holding it
+    #[allow(clippy::identity_op, clippy::erasing_op)]
+    pub fn test_select0<T: Select<bool>>(from_vec: &dyn Fn(&[u64], usize) -> T) {
         let v = vec!(0b0110, 0b1001, 0b1100);
         let bv = from_vec(&v, 64*3);
-        let select0: Vec<(int, int)> = vec!(
+        let select0: Vec<(usize, usize)> = vec!(
             (0,   0+0*64),
             (1,   1+0*64),
             (2,   4+0*64),
@@ -197,10 +196,11 @@ pub mod test {
         }
     }
 
-    pub fn test_select1<T: Select<bool>>(from_vec: &Fn(&Vec<u64>, int) -> T) {
+    #[allow(clippy::identity_op, clippy::erasing_op)]
+    pub fn test_select1<T: Select<bool>>(from_vec: &dyn Fn(&[u64], usize) -> T) {
         let v = vec!(0b0110, 0b1001, 0b1100);
         let bv = from_vec(&v, 64*3);
-        let select1: Vec<(int,int)> = vec!(
+        let select1: Vec<(usize,usize)> = vec!(
             (0, (0+0*64)),
             (1, (2+0*64)),
             (2, (3+0*64)),
@@ -217,10 +217,11 @@ pub mod test {
         }
     }
 
-    pub fn test_rank0<T: BitRank>(from_vec: &Fn(&Vec<u64>, int) -> T) {
+    #[allow(clippy::identity_op, clippy::erasing_op)]
+    pub fn test_rank0<T: BitRank>(from_vec: &dyn Fn(&[u64], usize) -> T) {
         let v = vec!(0b0110, 0b1001, 0b1100);
         let bv = from_vec(&v, 64*3);
-        let rank0: Vec<(int, int)> = vec!(
+        let rank0: Vec<(usize, usize)> = vec!(
             ((0+0*64), 0), // rank is non exclusive rank of zero is always 0
             ((1+0*64), 1),
             ((2+0*64), 1),
@@ -247,10 +248,11 @@ pub mod test {
         }
     }
 
-    pub fn test_rank1<T: BitRank>(from_vec: &Fn(&Vec<u64>, int) -> T) {
+    #[allow(clippy::identity_op, clippy::erasing_op)]
+    pub fn test_rank1<T: BitRank>(from_vec: &dyn Fn(&[u64], usize) -> T) {
         let v = vec!(0b0110, 0b1001, 0b1100);
-        let bv = from_vec(&v.clone(), 64*3);
-        let rank1: Vec<(int, int)> = vec!(
+        let bv = from_vec(&v, 64*3);
+        let rank1: Vec<(usize, usize)> = vec!(
             ((0+0*64), 0), // rank is non exclusive rank of zero is always 0
             ((1+0*64), 0),
             ((2+0*64), 1),
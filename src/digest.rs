@@ -0,0 +1,180 @@
+//! Digests of the content of structures
+//
+// The content digest of a structure is the 64-bit FNV-1a hash of its
+// length and elements as little-endian words: the words of a bitvector
+// with the bits past its end cleared. A sequence is instead hashed as
+// the value of each symbol and then its length, so that its digest can
+// be kept up to date as symbols are pushed. It depends on the content
+// alone, not on how the structure was built or laid out, so a
+// `BitVector` and a `Rank9` of the same bits share a digest, and the
+// digest of a structure is stable across versions of the crate. A
+// rebuilt index can so be compared with a deployed one by their
+// digests.
+
+use std::io::{self, Read, Write};
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64};
+use super::utils::{div_ceil, bit_word};
+
+/// A structure with a digest of its content
+pub trait ContentDigest {
+    /// The digest of the content
+    fn content_digest(&self) -> u64;
+}
+
+/// The running 64-bit FNV-1a hash of the bytes seen so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digest {
+    len: u64,
+    hash: u64,
+}
+
+impl Digest {
+    pub fn new() -> Digest {
+        Digest { len: 0, hash: 0xcbf29ce484222325 }
+    }
+
+    /// Hash `bytes`
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes.iter() {
+            self.hash ^= *b as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    /// Hash a word in little-endian byte order
+    pub fn update_u64(&mut self, x: u64) {
+        write_u64(self, x).unwrap();
+    }
+
+    /// The number of bytes hashed
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether no bytes were hashed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The hash of the bytes seen
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Default for Digest {
+    fn default() -> Digest {
+        Digest::new()
+    }
+}
+
+/// Hash the bytes written, discarding them
+impl Write for Digest {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The hash as it stands, from which hashing can be resumed
+impl Serialize for Digest {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, self.len)?;
+        write_u64(w, self.hash)
+    }
+}
+
+impl Deserialize for Digest {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Digest> {
+        let len = read_u64(r)?;
+        let hash = read_u64(r)?;
+        Ok(Digest { len, hash })
+    }
+}
+
+/// The content digest of the first `bits` bits of `words`
+pub fn bits_digest(words: &[u64], bits: usize) -> u64 {
+    let mut digest = Digest::new();
+    digest.update_u64(bits as u64);
+    for i in 0..div_ceil(bits, 64) {
+        digest.update_u64(bit_word(words, bits, i));
+    }
+    digest.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::{Hash, Hasher, DefaultHasher};
+    use super::ContentDigest;
+    use super::super::serialize::{Serialize, Deserialize};
+    use super::super::bit_vector::{self, BitVector};
+    use super::super::build::Builder;
+    use super::super::rank9::{self, Rank9};
+    use super::super::wavelet::{self, Wavelet};
+
+    fn hash<T: Hash>(x: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        x.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[quickcheck]
+    fn equal_content_is_equal(bits: Vec<bool>, junk: u64) -> bool {
+        let built: BitVector = bit_vector::Builder::with_capacity(8).from_iter(bits.clone().into_iter());
+        // the same bits with junk past the end
+        let mut words = built.words().to_vec();
+        if !bits.len().is_multiple_of(64) {
+            let last = words.len() - 1;
+            words[last] |= junk << (bits.len() % 64);
+        }
+        let junked = BitVector::from_vec(&words, bits.len()).unwrap();
+        let rank9: Rank9 = rank9::Builder::with_capacity(8).from_iter(bits.clone().into_iter());
+        let rank9_junked = Rank9::from_vec(&words, bits.len()).unwrap();
+        built == junked && rank9 == rank9_junked
+            && hash(&built) == hash(&junked)
+            && hash(&rank9) == hash(&rank9_junked)
+            && built.content_digest() == junked.content_digest()
+            && built.content_digest() == rank9.content_digest()
+    }
+
+    #[quickcheck]
+    fn wavelets_compare_content(v: Vec<u8>, w: Vec<u8>) -> bool {
+        let a: Wavelet<Rank9, u8> = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(128));
+        let b: Wavelet<Rank9, u8> = wavelet::Builder::new(|| rank9::Builder::with_capacity(128))
+            .from_iter(v.clone().into_iter());
+        let c: Wavelet<Rank9, u8> = Wavelet::from_slice(w.as_slice(), || rank9::Builder::with_capacity(128));
+        a == b && hash(&a) == hash(&b)
+            && a.content_digest() == b.content_digest()
+            && (a == c) == (v == w)
+            && (a.content_digest() == c.content_digest()) == (v == w)
+    }
+
+    #[quickcheck]
+    fn wavelet_digests_are_kept(v: Vec<u8>, w: Vec<u8>) -> bool {
+        let new = || rank9::Builder::with_capacity(128);
+        let a: Wavelet<Rank9, u8> = Wavelet::from_slice(v.as_slice(), new);
+        let b: Wavelet<Rank9, u8> = Wavelet::from_slice(w.as_slice(), new);
+        let whole: Wavelet<Rank9, u8> = Wavelet::from_slice([v, w].concat().as_slice(), new);
+        let mut buf = Vec::new();
+        a.serialize(&mut buf).unwrap();
+        let read: Wavelet<Rank9, u8> = Deserialize::deserialize(&mut buf.as_slice()).unwrap();
+        read.content_digest() == a.content_digest()
+            && a.concat(b, new).content_digest() == whole.content_digest()
+    }
+
+    #[test]
+    fn test_digest() {
+        let a = BitVector::from_vec(&[0b1011], 4).unwrap();
+        let b = BitVector::from_vec(&[0b1011], 5).unwrap();
+        let c = BitVector::from_vec(&[0b1111_1011], 4).unwrap();
+        assert!(a != b && a == c);
+        assert!(a.content_digest() != b.content_digest());
+        // the length and then the word, hashed as bytes
+        assert_eq!(a.content_digest(), super::super::format::checksum(&[4, 0, 0, 0, 0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,286 @@
+//! FM-indexes of nucleotide sequences
+//
+// The transform of a DNA sequence is packed two bits per base, 32
+// bases to a word, with the occurrences of each base counted before
+// every block of `BLOCK` bases. The occurrences of a base before a
+// position are then those before its block plus a popcount over the
+// words of the block: a base's positions in a word are those where
+// the word exclusive-or'd with the base repeated is zero in both bits.
+//
+// Bytes other than `A`, `C`, `G` and `T`, such as `N`, are exceptions:
+// their positions and bytes are kept apart, and they are packed as `A`
+// so are subtracted from its occurrences. They can be searched for
+// like bases, ranked among the exceptions.
+//
+// Otherwise this is an FM-index sampling suffixes at regular positions
+// of the text, as `fm::FmIndex` with `Sampling::TextOrder`.
+
+use super::bits::bit_width;
+use super::build::Builder;
+use super::bwt::{Bwt, suffix_array};
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, BitRank};
+use super::elias_fano::EliasFano;
+use super::int_vector::IntVector;
+use super::rank9::{self, Rank9};
+use super::space::{SpaceUsage, vec_bytes};
+use super::wavelet::Wavelet;
+
+/// The number of bases per block of counts
+pub const BLOCK: usize = 512;
+
+/// The bases in the order of their codes
+pub const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// The code of a base, unless the byte is an exception
+fn code(b: u8) -> Option<u64> {
+    match b {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// The positions of the bases with code `c` among the 32 of `word`,
+/// as the low bit of each
+fn matches(word: u64, c: u64) -> u64 {
+    let x = word ^ (c * 0x5555555555555555);
+    !(x | (x >> 1)) & 0x5555555555555555
+}
+
+/// A DNA text index counting and locating the occurrences of patterns
+pub struct DnaIndex {
+    /// the length of the text
+    len: usize,
+    /// the row at which the sentinel was left out of the transform
+    primary: usize,
+    /// the transform, two bits per base
+    packed: Vec<u64>,
+    /// the occurrences of each base before each block
+    blocks: Vec<u32>,
+    /// the positions of the exceptions in the transform
+    exceptions: EliasFano,
+    /// the byte of each exception
+    exception_bytes: Wavelet<Rank9, u8>,
+    /// the first row of the suffixes starting with each byte
+    counts: Vec<usize>,
+    /// the sampling rate
+    rate: usize,
+    /// whether the suffix of each row is sampled
+    sampled: Rank9,
+    /// the start of each sampled suffix, by row
+    samples: IntVector,
+}
+
+impl DnaIndex {
+    /// Index `text`, sampling the suffixes starting at every `rate`
+    /// positions
+    pub fn new(text: &[u8], rate: usize) -> DnaIndex {
+        assert!(rate > 0, "DnaIndex: sampling rate must be positive");
+        assert!(text.len() < u32::MAX as usize, "DnaIndex: text too long");
+        let sa = suffix_array(text);
+        let bwt = Bwt::from_suffix_array(text, sa.as_slice());
+
+        let mut packed: Vec<u64> = (0..text.len().div_ceil(32)).map(|_| 0).collect();
+        let mut blocks: Vec<u32> = Vec::new();
+        let mut seen = [0u32; 4];
+        let mut exceptions: Vec<u64> = Vec::new();
+        let mut exception_bytes: Vec<u8> = Vec::new();
+        for (i, b) in bwt.bwt().iter().enumerate() {
+            if i % BLOCK == 0 {
+                blocks.extend_from_slice(&seen);
+            }
+            match code(*b) {
+                Some(c) => {
+                    packed[i / 32] |= c << (2 * (i % 32));
+                    seen[c as usize] += 1;
+                },
+                None => {
+                    seen[0] += 1;
+                    exceptions.push(i as u64);
+                    exception_bytes.push(*b);
+                },
+            }
+        }
+        blocks.extend_from_slice(&seen);
+
+        let width = bit_width(text.len() as u64);
+        let mut sampled = rank9::Builder::with_capacity(sa.len());
+        let mut starts = Vec::new();
+        for s in sa.iter() {
+            sampled.push(*s % rate == 0);
+            if *s % rate == 0 {
+                starts.push(*s);
+            }
+        }
+        let mut samples = IntVector::new(width, starts.len());
+        for (i, s) in starts.iter().enumerate() {
+            samples.set(i, *s as u64);
+        }
+
+        DnaIndex {
+            len: text.len(),
+            primary: bwt.primary(),
+            packed,
+            blocks,
+            exceptions: EliasFano::from_slice(exceptions.as_slice()),
+            exception_bytes: Wavelet::from_slice(exception_bytes.as_slice(),
+                                                 || rank9::Builder::with_capacity(128)),
+            counts: bwt.counts().to_vec(),
+            rate,
+            sampled: sampled.finish(),
+            samples,
+        }
+    }
+
+    /// The sampling rate
+    pub fn rate(&self) -> usize {
+        self.rate
+    }
+
+    /// The number of exceptions in the text
+    pub fn exceptions(&self) -> usize {
+        self.exceptions.len()
+    }
+
+    /// The range of rows of the suffixes prefixed by `pattern`
+    pub fn range(&self, pattern: &[u8]) -> (usize, usize) {
+        let (mut sp, mut ep) = (0, self.len + 1);
+        for b in pattern.iter().rev() {
+            sp = self.lf(*b, sp);
+            ep = self.lf(*b, ep);
+            if sp >= ep {
+                return (sp, sp);
+            }
+        }
+        (sp, ep)
+    }
+
+    /// The number of occurrences of `pattern` in the text, the empty
+    /// pattern occurring at each position and at the end
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        let (sp, ep) = self.range(pattern);
+        ep - sp
+    }
+
+    /// The positions of the occurrences of `pattern` in the text, in
+    /// increasing order
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        let (sp, ep) = self.range(pattern);
+        let mut positions: Vec<usize> = (sp..ep).map(|row| self.suffix(row)).collect();
+        positions.sort();
+        positions
+    }
+
+    /// The start of the suffix at `row`
+    pub fn suffix(&self, mut row: usize) -> usize {
+        assert!(row <= self.len, "DnaIndex: row {} out of bounds", row);
+        let mut steps = 0;
+        while !self.sampled.get(row) {
+            let i = self.compact(row);
+            row = self.lf(self.byte(i), row);
+            steps += 1;
+        }
+        let n = self.sampled.rank1(row);
+        self.samples.get(n) as usize + steps
+    }
+
+    /// The position in the transform of `row`, which must not be the
+    /// sentinel's
+    fn compact(&self, row: usize) -> usize {
+        if row > self.primary { row - 1 } else { row }
+    }
+
+    /// The byte at position `i` of the transform
+    fn byte(&self, i: usize) -> u8 {
+        let e = self.exceptions.lower_bound(i as u64);
+        match e < self.exceptions.len() && self.exceptions.get(e) == i as u64 {
+            true => self.exception_bytes.get(e),
+            false => BASES[((self.packed[i / 32] >> (2 * (i % 32))) & 3) as usize],
+        }
+    }
+
+    /// The number of occurrences of `b` in the rows before `row`
+    fn occ(&self, b: u8, row: usize) -> usize {
+        let i = self.compact(row);
+        let e = self.exceptions.lower_bound(i as u64);
+        match code(b) {
+            None => self.exception_bytes.rank(b, e),
+            Some(c) => {
+                let block = i / BLOCK;
+                let mut n = self.blocks[4 * block + c as usize] as usize;
+                for w in block * BLOCK / 32..i / 32 {
+                    n += matches(self.packed[w], c).count_ones() as usize;
+                }
+                if !i.is_multiple_of(32) {
+                    let mask = (1u64 << (2 * (i % 32))) - 1;
+                    n += (matches(self.packed[i / 32], c) & mask).count_ones() as usize;
+                }
+                // the exceptions are packed as the first base
+                if c == 0 { n - e } else { n }
+            },
+        }
+    }
+
+    /// The row of the suffix `b` followed by that of `row`, or the
+    /// first after it if there is none
+    fn lf(&self, b: u8, row: usize) -> usize {
+        self.counts[b as usize] + self.occ(b, row)
+    }
+}
+
+impl Collection for DnaIndex {
+    /// The length of the text
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl SpaceUsage for DnaIndex {
+    fn total_bytes(&self) -> usize {
+        vec_bytes(&self.packed) + vec_bytes(&self.blocks) + self.exceptions.total_bytes()
+            + self.exception_bytes.stats().bytes + vec_bytes(&self.counts)
+            + self.sampled.total_bytes() + self.samples.total_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DnaIndex, BLOCK};
+
+    /// A DNA sequence with some `N`s, shaped by `v`
+    fn dna(v: &[u8]) -> Vec<u8> {
+        v.iter().map(|x| match *x % 17 {
+            0 => b'N',
+            x => b"ACGT"[(x % 4) as usize],
+        }).collect()
+    }
+
+    #[quickcheck]
+    fn locate_is_correct(v: Vec<u8>, p: Vec<u8>) -> bool {
+        let (v, p) = (dna(&v), dna(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let index = DnaIndex::new(v.as_slice(), 3);
+        let naive: Vec<usize> = (0..v.len() + 1).filter(|i| v[*i..].starts_with(p)).collect();
+        index.count(p) == naive.len() && index.locate(p) == naive
+    }
+
+    #[test]
+    fn test_long() {
+        // enough bases to span several blocks
+        let mut text = Vec::new();
+        for i in 0..3 * BLOCK {
+            text.push(b"ACGTTGCAN"[i * 7 % 9]);
+        }
+        let index = DnaIndex::new(text.as_slice(), 8);
+        let naive = |p: &[u8]| (0..text.len()).filter(|i| text[*i..].starts_with(p)).count();
+        let patterns = [b"A".as_slice(), b"GT".as_slice(), b"NA".as_slice(), b"TTG".as_slice(), b"CAN".as_slice()];
+        for p in patterns.iter() {
+            assert_eq!(index.count(p), naive(p));
+        }
+        assert_eq!(index.exceptions(), naive(b"N".as_slice()));
+        assert_eq!(index.locate(b"GTTGCAN").len(), naive(b"GTTGCAN".as_slice()));
+    }
+}
@@ -0,0 +1,163 @@
+//! Document retrieval over a collection of byte strings
+//
+// The documents are concatenated, each followed by a separator byte
+// they must not contain, and indexed by an FM-index. The document
+// array gives the document of the suffix of each row, the end of the
+// text belonging to the last document. The documents containing a
+// pattern are then the symbols of the document array over the range
+// of rows of the pattern, listed or ranked by a wavelet tree over it.
+//
+// See Muthukrishnan 2002 and Navarro 2014.
+
+use super::bwt::suffix_array;
+use super::collection::Collection;
+use super::fm::{FmIndex, Sampling, DEFAULT_RATE};
+use super::rank9::{self, Rank9};
+use super::space::{SpaceUsage, vec_bytes};
+use super::wavelet::Wavelet;
+
+/// The byte following each document
+pub const SEPARATOR: u8 = 0;
+
+/// An index of a collection of documents
+pub struct DocumentIndex {
+    fm: FmIndex,
+    /// the document of the suffix of each row
+    docs: Wavelet<Rank9, u32>,
+    /// the start of each document in the concatenation
+    starts: Vec<usize>,
+}
+
+impl DocumentIndex {
+    /// Index `docs`, none of which may contain `SEPARATOR`
+    pub fn new(docs: &[Vec<u8>]) -> DocumentIndex {
+        DocumentIndex::with_sampling(docs, Sampling::TextOrder(DEFAULT_RATE))
+    }
+
+    /// Index `docs`, sampling their suffixes as given
+    pub fn with_sampling(docs: &[Vec<u8>], sampling: Sampling) -> DocumentIndex {
+        assert!(!docs.is_empty(), "DocumentIndex: no documents");
+        let mut text = Vec::new();
+        let mut starts = Vec::with_capacity(docs.len());
+        // the document of each position of the text
+        let mut owners: Vec<u32> = Vec::new();
+        for (d, doc) in docs.iter().enumerate() {
+            assert!(!doc.contains(&SEPARATOR), "DocumentIndex: document {} contains the separator", d);
+            starts.push(text.len());
+            text.extend_from_slice(doc.as_slice());
+            text.push(SEPARATOR);
+            owners.extend((0..doc.len() + 1).map(|_| d as u32));
+        }
+        owners.push(docs.len() as u32 - 1);
+        let sa = suffix_array(text.as_slice());
+        let array: Vec<u32> = sa.iter().map(|s| owners[*s]).collect();
+        DocumentIndex {
+            fm: FmIndex::from_suffix_array(text.as_slice(), sa.as_slice(), sampling),
+            docs: Wavelet::from_slice(array.as_slice(), || rank9::Builder::with_capacity(128)),
+            starts,
+        }
+    }
+
+    /// The FM-index of the concatenated documents
+    pub fn fm(&self) -> &FmIndex {
+        &self.fm
+    }
+
+    /// The number of occurrences of `pattern` in all documents
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        self.fm.count(pattern)
+    }
+
+    /// The documents containing `pattern` with its number of
+    /// occurrences in each, in increasing order of document
+    pub fn list_docs(&self, pattern: &[u8]) -> Vec<(usize, usize)> {
+        let (sp, ep) = self.fm.range(pattern);
+        self.docs.histogram(sp, ep).into_iter().map(|(d, n)| (d as usize, n)).collect()
+    }
+
+    /// The `k` documents containing `pattern` most often with its
+    /// number of occurrences in each, most occurrences first
+    pub fn top_docs(&self, pattern: &[u8], k: usize) -> Vec<(usize, usize)> {
+        let (sp, ep) = self.fm.range(pattern);
+        self.docs.top_k(sp, ep, k).into_iter().map(|(d, n)| (d as usize, n)).collect()
+    }
+
+    /// The occurrences of `pattern` as documents and positions within
+    /// them, in increasing order
+    pub fn locate(&self, pattern: &[u8]) -> Vec<(usize, usize)> {
+        self.fm.locate(pattern).into_iter().map(|p| {
+            let d = match self.starts.binary_search(&p) {
+                Ok(d) => d,
+                Err(d) => d - 1,
+            };
+            (d, p - self.starts[d])
+        }).collect()
+    }
+}
+
+impl Collection for DocumentIndex {
+    /// The number of documents
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+impl SpaceUsage for DocumentIndex {
+    fn total_bytes(&self) -> usize {
+        self.fm.total_bytes() + self.docs.stats().bytes + vec_bytes(&self.starts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DocumentIndex;
+
+    /// Documents over a small alphabet, shaped by `v`
+    fn docs(v: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut docs: Vec<Vec<u8>> = v.iter().map(|d| d.iter().map(|x| b'a' + *x % 3).collect()).collect();
+        docs.push(b"ab".to_vec());
+        docs
+    }
+
+    fn occurrences(doc: &[u8], p: &[u8]) -> usize {
+        (0..doc.len() + 1).filter(|i| doc[*i..].starts_with(p)).count()
+    }
+
+    #[quickcheck]
+    fn list_docs_is_correct(v: Vec<Vec<u8>>, p: Vec<u8>) -> bool {
+        let docs = docs(&v);
+        let p: Vec<u8> = p.iter().take(3).map(|x| b'a' + *x % 3).chain(Some(b'a').into_iter()).collect();
+        let index = DocumentIndex::new(docs.as_slice());
+        let expected: Vec<(usize, usize)> = docs.iter().enumerate()
+            .map(|(d, doc)| (d, occurrences(doc, p.as_slice())))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        index.list_docs(p.as_slice()) == expected
+    }
+
+    #[quickcheck]
+    fn top_docs_is_correct(v: Vec<Vec<u8>>, p: Vec<u8>, k: usize) -> bool {
+        let docs = docs(&v);
+        let p: Vec<u8> = p.iter().take(2).map(|x| b'a' + *x % 3).chain(Some(b'b').into_iter()).collect();
+        let k = k % 4;
+        let index = DocumentIndex::new(docs.as_slice());
+        let top = index.top_docs(p.as_slice(), k);
+        let mut counts: Vec<usize> = docs.iter().map(|doc| occurrences(doc, p.as_slice())).filter(|n| *n > 0).collect();
+        counts.sort_by(|a, b| b.cmp(a));
+        counts.truncate(k);
+        top.iter().map(|&(_, n)| n).collect::<Vec<usize>>() == counts
+            && top.iter().all(|&(d, n)| occurrences(&docs[d], p.as_slice()) == n)
+    }
+
+    #[test]
+    fn test_docs() {
+        let docs = vec!(b"banana".to_vec(), b"bandana".to_vec(), b"cabana".to_vec());
+        let index = DocumentIndex::new(docs.as_slice());
+        assert_eq!(index.count(b"ana"), 4);
+        assert_eq!(index.list_docs(b"ana"), vec!((0, 2), (1, 1), (2, 1)));
+        assert_eq!(index.top_docs(b"na", 1), vec!((0, 2)));
+        assert_eq!(index.top_docs(b"na", 3).iter().map(|&(_, n)| n).collect::<Vec<usize>>(), vec!(2, 1, 1));
+        assert_eq!(index.top_docs(b"nd", 3), vec!((1, 1)));
+        assert_eq!(index.locate(b"ban"), vec!((0, 0), (1, 0), (2, 2)));
+    }
+}
@@ -0,0 +1,130 @@
+//! A structure of one of two representations chosen at runtime
+//
+// This allows structures built of many bitvectors, such as wavelet
+// trees, to use different representations for different parts while
+// remaining of a single type.
+
+use super::dictionary::{Access, Rank, BitRank, Select};
+use super::collection::Collection;
+use super::space::SpaceUsage;
+use super::build;
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, invalid_data};
+use std::io::{self, Read, Write};
+
+/// Either an `A` or a `B`
+#[derive(Debug)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A: Collection, B: Collection> Collection for Either<A, B> {
+    fn len(&self) -> usize {
+        match *self {
+            Either::Left(ref a) => a.len(),
+            Either::Right(ref b) => b.len(),
+        }
+    }
+}
+
+impl<T, A: Access<T>, B: Access<T>> Access<T> for Either<A, B> {
+    fn get(&self, n: usize) -> T {
+        match *self {
+            Either::Left(ref a) => a.get(n),
+            Either::Right(ref b) => b.get(n),
+        }
+    }
+}
+
+impl<T, A: Rank<T>, B: Rank<T>> Rank<T> for Either<A, B> {
+    fn rank(&self, el: T, n: usize) -> usize {
+        match *self {
+            Either::Left(ref a) => a.rank(el, n),
+            Either::Right(ref b) => b.rank(el, n),
+        }
+    }
+}
+
+impl<A: BitRank, B: BitRank> BitRank for Either<A, B> {
+    fn rank0(&self, n: usize) -> usize {
+        match *self {
+            Either::Left(ref a) => a.rank0(n),
+            Either::Right(ref b) => b.rank0(n),
+        }
+    }
+
+    fn rank1(&self, n: usize) -> usize {
+        match *self {
+            Either::Left(ref a) => a.rank1(n),
+            Either::Right(ref b) => b.rank1(n),
+        }
+    }
+}
+
+impl<T, A: Select<T>, B: Select<T>> Select<T> for Either<A, B> {
+    fn select(&self, el: T, n: usize) -> usize {
+        match *self {
+            Either::Left(ref a) => a.select(el, n),
+            Either::Right(ref b) => b.select(el, n),
+        }
+    }
+}
+
+impl<A: SpaceUsage, B: SpaceUsage> SpaceUsage for Either<A, B> {
+    fn total_bytes(&self) -> usize {
+        match *self {
+            Either::Left(ref a) => a.total_bytes(),
+            Either::Right(ref b) => b.total_bytes(),
+        }
+    }
+}
+
+impl<A: Serialize, B: Serialize> Serialize for Either<A, B> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Either::Left(ref a) => {
+                write_u64(w, 0)?;
+                a.serialize(w)
+            },
+            Either::Right(ref b) => {
+                write_u64(w, 1)?;
+                b.serialize(w)
+            },
+        }
+    }
+}
+
+impl<A: Deserialize, B: Deserialize> Deserialize for Either<A, B> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Either<A, B>> {
+        match read_u64(r)? {
+            0 => Ok(Either::Left(Deserialize::deserialize(r)?)),
+            1 => Ok(Either::Right(Deserialize::deserialize(r)?)),
+            _ => Err(invalid_data("invalid Either tag")),
+        }
+    }
+}
+
+/// Build either an `A` or a `B`, as decided when the builder is
+/// created
+pub enum Builder<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<E, TA, TB, A, B> build::Builder<E, Either<TA, TB>> for Builder<A, B>
+    where A: build::Builder<E, TA>, B: build::Builder<E, TB>
+{
+    fn push(&mut self, element: E) {
+        match *self {
+            Builder::Left(ref mut a) => a.push(element),
+            Builder::Right(ref mut b) => b.push(element),
+        }
+    }
+
+    fn finish(self) -> Either<TA, TB> {
+        match self {
+            Builder::Left(a) => Either::Left(a.finish()),
+            Builder::Right(b) => Either::Right(b.finish()),
+        }
+    }
+}
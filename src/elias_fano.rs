@@ -0,0 +1,329 @@
+//! Elias-Fano coding of non-decreasing integer sequences
+//
+// Each value is split into its `low_bits` least significant bits,
+// stored verbatim in an `IntVector`, and its remaining high part. The
+// high parts are stored in unary in a `Rank9` bitvector: the `i`th
+// value sets bit `(x >> low_bits) + i`, so the zeros before it count
+// its high part. With `low_bits` about `log2(max / len)`, the sequence
+// occupies at most `2 + log2(max / len)` bits per value.
+//
+// See Elias 1974, Fano 1971, Vigna 2013.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use super::bits::bit_width;
+use super::collection::Collection;
+use super::dictionary::{Access, Select};
+use super::int_vector::IntVector;
+use super::rank9::Rank9;
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::SpaceUsage;
+
+pub use crate::elias_fano::build::Builder;
+
+/// A non-decreasing sequence of integers
+#[derive(Clone, Debug)]
+pub struct EliasFano {
+    len: usize,
+    low_bits: usize,
+    lows: IntVector,
+    /// the high parts in unary, terminated by a zero
+    highs: Rank9,
+}
+
+/// The number of low bits to store verbatim for `len` values no
+/// greater than `max`
+fn low_bits(len: usize, max: u64) -> usize {
+    match len {
+        0 => 0,
+        _ => match bit_width(max / len as u64) {
+            0 => 0,
+            w => w - 1,
+        },
+    }
+}
+
+impl EliasFano {
+    /// Encode a non-decreasing sequence
+    pub fn from_slice(values: &[u64]) -> EliasFano {
+        use super::build::Builder;
+        let max = values.last().copied().unwrap_or(0);
+        build::Builder::new(values.len(), max).from_iter(values.iter().copied())
+    }
+
+    /// The index of the first value no less than `x`, or the length
+    /// of the sequence if there is none
+    pub fn lower_bound(&self, x: u64) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+        let bucket = x >> self.low_bits;
+        let buckets = self.highs.len() - self.len;
+        if bucket >= buckets as u64 {
+            return self.len;
+        }
+        // skip the values of lower buckets
+        let bucket = bucket as usize;
+        let mut pos = match bucket {
+            0 => 0,
+            _ => self.highs.select(false, bucket),
+        };
+        let mut i = pos - bucket;
+        while self.highs.get(pos) {
+            if self.value(bucket, i) >= x {
+                return i;
+            }
+            i += 1;
+            pos += 1;
+        }
+        i
+    }
+
+    /// The index and value of the smallest value no less than `x`
+    pub fn successor(&self, x: u64) -> Option<(usize, u64)> {
+        let i = self.lower_bound(x);
+        match i < self.len {
+            true => Some((i, self.get(i))),
+            false => None,
+        }
+    }
+
+    /// The index and value of the largest value no greater than `x`
+    pub fn predecessor(&self, x: u64) -> Option<(usize, u64)> {
+        let i = match x {
+            u64::MAX => self.len,
+            _ => self.lower_bound(x + 1),
+        };
+        match i {
+            0 => None,
+            _ => Some((i - 1, self.get(i - 1))),
+        }
+    }
+
+    /// An iterator over the values
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { ef: self, i: 0, pos: 0 }
+    }
+
+    /// An iterator over the values from the `n`th on
+    pub fn iter_from<'a>(&'a self, n: usize) -> Iter<'a> {
+        assert!(n <= self.len, "EliasFano: index {} out of bounds", n);
+        let pos = match n {
+            0 => 0,
+            _ => self.highs.select(true, n),
+        };
+        Iter { ef: self, i: n, pos }
+    }
+
+    /// The `i`th value, given that its high part is `bucket`
+    fn value(&self, bucket: usize, i: usize) -> u64 {
+        ((bucket as u64) << self.low_bits) | self.lows.get(i)
+    }
+}
+
+impl Collection for EliasFano {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for EliasFano {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "EliasFano: index {} out of bounds", n);
+        let pos = self.highs.select(true, n + 1) - 1;
+        self.value(pos - n, n)
+    }
+}
+
+impl SpaceUsage for EliasFano {
+    fn total_bytes(&self) -> usize {
+        2 * size_of::<usize>() + self.lows.total_bytes() + self.highs.total_bytes()
+    }
+}
+
+impl Serialize for EliasFano {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.len)?;
+        write_uint(w, self.low_bits)?;
+        self.lows.serialize(w)?;
+        self.highs.serialize(w)
+    }
+}
+
+impl Deserialize for EliasFano {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<EliasFano> {
+        use super::dictionary::BitRank;
+        let len = read_uint(r)?;
+        let low_bits = read_uint(r)?;
+        let lows: IntVector = Deserialize::deserialize(r)?;
+        let highs: Rank9 = Deserialize::deserialize(r)?;
+        if lows.len() != len || lows.width() != low_bits || low_bits >= 64 {
+            return Err(invalid_data("EliasFano low bits inconsistent with length"));
+        }
+        if highs.rank1(highs.len()) != len {
+            return Err(invalid_data("EliasFano high bits inconsistent with length"));
+        }
+        if highs.len() == 0 || highs.get(highs.len() - 1) {
+            return Err(invalid_data("EliasFano high bits not terminated"));
+        }
+        Ok(EliasFano { len, low_bits, lows, highs })
+    }
+}
+
+/// An iterator over the values of an `EliasFano` sequence
+pub struct Iter<'a> {
+    ef: &'a EliasFano,
+    i: usize,
+    /// the position in `highs` following the previous value
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.i == self.ef.len {
+            return None;
+        }
+        while !self.ef.highs.get(self.pos) {
+            self.pos += 1;
+        }
+        let x = self.ef.value(self.pos - self.i, self.i);
+        self.pos += 1;
+        self.i += 1;
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.ef.len - self.i;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+mod build {
+    use super::super::build;
+    use super::super::int_vector::{self, low_mask};
+    use super::super::rank9;
+    use super::{EliasFano, low_bits};
+
+    /// Build an `EliasFano` sequence from its values
+    pub struct Builder {
+        len: usize,
+        max: u64,
+        low_bits: usize,
+        /// the number of values pushed
+        pushed: usize,
+        /// the number of buckets closed, that is, of zeros pushed
+        bucket: u64,
+        last: u64,
+        lows: int_vector::Builder,
+        highs: rank9::Builder,
+    }
+
+    impl Builder {
+        /// Build a sequence of at most `len` values no greater than
+        /// `max`
+        pub fn new(len: usize, max: u64) -> Builder {
+            let low_bits = low_bits(len, max);
+            Builder {
+                len,
+                max,
+                low_bits,
+                pushed: 0,
+                bucket: 0,
+                last: 0,
+                lows: int_vector::Builder::with_capacity(low_bits, len),
+                highs: rank9::Builder::with_capacity(len + (max >> low_bits) as usize + 1),
+            }
+        }
+    }
+
+    impl build::Builder<u64, EliasFano> for Builder {
+        fn push(&mut self, x: u64) {
+            assert!(self.pushed < self.len, "EliasFano: more than {} values", self.len);
+            assert!(x >= self.last, "EliasFano: {} follows {}", x, self.last);
+            assert!(x <= self.max, "EliasFano: {} exceeds {}", x, self.max);
+            let bucket = x >> self.low_bits;
+            while self.bucket < bucket {
+                self.highs.push(false);
+                self.bucket += 1;
+            }
+            self.highs.push(true);
+            self.lows.push(x & low_mask(self.low_bits));
+            self.last = x;
+            self.pushed += 1;
+        }
+
+        fn finish(mut self) -> EliasFano {
+            // close every bucket up to that of `max`, so each value is
+            // followed by a zero
+            let buckets = (self.max >> self.low_bits) + 1;
+            while self.bucket < buckets {
+                self.highs.push(false);
+                self.bucket += 1;
+            }
+            EliasFano {
+                len: self.pushed,
+                low_bits: self.low_bits,
+                lows: self.lows.finish(),
+                highs: self.highs.finish(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EliasFano;
+    use super::super::dictionary::Access;
+    use super::super::naive;
+
+    fn sorted(mut v: Vec<u64>) -> Vec<u64> {
+        v.sort();
+        v
+    }
+
+    #[quickcheck]
+    fn get_is_correct(v: Vec<u64>) -> bool {
+        let v = sorted(v);
+        let ef = EliasFano::from_slice(v.as_slice());
+        v.iter().enumerate().all(|(i, x)| ef.get(i) == *x)
+            && ef.iter().collect::<Vec<u64>>() == v
+            && (0..v.len() + 1).all(|n| ef.iter_from(n).collect::<Vec<u64>>() == v[n..])
+    }
+
+    #[quickcheck]
+    fn successor_is_correct(v: Vec<u16>, x: u16) -> bool {
+        let v = sorted(v.into_iter().map(|x| x as u64).collect());
+        let x = x as u64;
+        let ef = EliasFano::from_slice(v.as_slice());
+        ef.successor(x) == naive::successor(&v, x)
+    }
+
+    #[quickcheck]
+    fn predecessor_is_correct(v: Vec<u16>, x: u16) -> bool {
+        let v = sorted(v.into_iter().map(|x| x as u64).collect());
+        let x = x as u64;
+        let ef = EliasFano::from_slice(v.as_slice());
+        ef.predecessor(x) == naive::predecessor(&v, x)
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let v = sorted(v);
+        let ef: EliasFano = round_trip(&EliasFano::from_slice(v.as_slice()));
+        ef.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let ef = EliasFano::from_slice(&[3, 3, 3, 9, 100]);
+        assert_eq!(ef.successor(3), Some((0, 3)));
+        assert_eq!(ef.successor(4), Some((3, 9)));
+        assert_eq!(ef.predecessor(8), Some((2, 3)));
+        assert_eq!(ef.predecessor(2), None);
+        assert_eq!(ef.successor(101), None);
+    }
+}
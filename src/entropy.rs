@@ -0,0 +1,200 @@
+//! Empirical entropy of sequences and estimates of index sizes
+//
+// The order-0 entropy `H0` of a sequence is the average number of
+// bits per symbol needed by a code assigning each symbol a fixed
+// codeword. The order-k entropy `Hk` additionally allows the code to
+// depend on the `k` preceding symbols, and is the size achievable by
+// compressed text indexes. Both are given in bits per symbol.
+//
+// The size estimates are in bits and account for the rank directories
+// of the bitvectors, but not for the small fixed overheads of each
+// structure.
+
+use std::collections::{BTreeMap, BinaryHeap};
+use super::bits::bit_width;
+
+/// The fraction of the bits of a `Rank9` bitvector spent on its counts
+pub const RANK9_OVERHEAD: f64 = 0.25;
+
+/// The bits per bit spent by an RRR bitvector, with blocks of 15 bits,
+/// on block classes and sampled offsets beyond the entropy of the
+/// bits
+pub const RRR_OVERHEAD: f64 = 4.0 / 15.0 + 0.1;
+
+/// The occurrences of each distinct element of `items`
+fn counts<T: Ord + Clone, I: Iterator<Item=T>>(items: I) -> BTreeMap<T, usize> {
+    let mut counts = BTreeMap::new();
+    for x in items {
+        let n = counts.get(&x).copied().unwrap_or(0);
+        counts.insert(x, n + 1);
+    }
+    counts
+}
+
+/// The entropy of a distribution given by the occurrences of its
+/// outcomes, in bits per outcome
+fn entropy_of_counts<'a, I: Iterator<Item=&'a usize>>(counts: I) -> f64 {
+    let counts: Vec<usize> = counts.copied().collect();
+    let total = counts.iter().fold(0, |a, b| a + *b) as f64;
+    counts.iter()
+        .filter(|n| **n > 0)
+        .map(|n| {
+            let p = *n as f64 / total;
+            -p * p.log2()
+        })
+        .fold(0.0, |a, b| a + b)
+}
+
+/// The order-0 empirical entropy of `seq` in bits per symbol
+pub fn h0<T: Ord + Clone>(seq: &[T]) -> f64 {
+    entropy_of_counts(counts(seq.iter().cloned()).values())
+}
+
+/// The order-`k` empirical entropy of `seq` in bits per symbol: the
+/// average, weighted by frequency, of the order-0 entropies of the
+/// symbols following each context of `k` symbols. The first `k`
+/// symbols, having no full context, are not counted.
+pub fn hk<T: Ord + Clone>(seq: &[T], k: usize) -> f64 {
+    if k == 0 {
+        return h0(seq);
+    }
+    if seq.len() <= k {
+        return 0.0;
+    }
+    let mut followers: BTreeMap<&[T], Vec<T>> = BTreeMap::new();
+    for i in k..seq.len() {
+        let context = &seq[i - k..i];
+        let mut v = followers.remove(&context).unwrap_or_default();
+        v.push(seq[i].clone());
+        followers.insert(context, v);
+    }
+    let bits = followers.values()
+        .map(|v| v.len() as f64 * h0(v.as_slice()))
+        .fold(0.0, |a, b| a + b);
+    bits / seq.len() as f64
+}
+
+/// The binary entropy of a bitvector of `len` bits of which `ones`
+/// are set, in bits per bit
+pub fn h0_bits(len: usize, ones: usize) -> f64 {
+    entropy_of_counts([ones, len - ones].iter())
+}
+
+/// The estimated size of a `Rank9` bitvector of `len` bits
+pub fn rank9_bits(len: usize) -> f64 {
+    len as f64 * (1.0 + RANK9_OVERHEAD)
+}
+
+/// The estimated size of an RRR compressed bitvector of `len` bits of
+/// which `ones` are set
+pub fn rrr_bits(len: usize, ones: usize) -> f64 {
+    len as f64 * (h0_bits(len, ones) + RRR_OVERHEAD)
+}
+
+/// The lengths of the codewords of a Huffman code for symbols of the
+/// given frequencies
+pub fn huffman_lengths(freqs: &[usize]) -> Vec<usize> {
+    // nodes are ordered by decreasing weight in the max-heap, so
+    // weights are negated
+    let mut heap: BinaryHeap<(isize, Vec<usize>)> = BinaryHeap::new();
+    for (sym, f) in freqs.iter().enumerate() {
+        heap.push((-(*f as isize), vec!(sym)));
+    }
+    let mut lengths: Vec<usize> = freqs.iter().map(|_| 0).collect();
+    if freqs.len() == 1 {
+        lengths[0] = 1;
+    }
+    while heap.len() > 1 {
+        let (wa, mut a) = heap.pop().unwrap();
+        let (wb, b) = heap.pop().unwrap();
+        for sym in a.iter().chain(b.iter()) {
+            lengths[*sym] += 1;
+        }
+        a.extend_from_slice(b.as_slice());
+        heap.push((wa + wb, a));
+    }
+    lengths
+}
+
+/// Estimated sizes of the wavelet tree representations of a sequence
+#[derive(Debug, Copy, Clone)]
+pub struct SizeEstimate {
+    /// the length of the sequence
+    pub len: usize,
+    /// the order-0 entropy of the sequence in bits per symbol
+    pub h0: f64,
+    /// a balanced tree of `Rank9` bitvectors
+    pub rank9: f64,
+    /// a balanced tree of RRR bitvectors
+    pub rrr: f64,
+    /// a Huffman-shaped tree of `Rank9` bitvectors
+    pub huffman: f64,
+}
+
+impl SizeEstimate {
+    /// Estimate the sizes of the representations of `seq`
+    pub fn new<T: Ord + Clone>(seq: &[T]) -> SizeEstimate {
+        let counts = counts(seq.iter().cloned());
+        let freqs: Vec<usize> = counts.values().copied().collect();
+        let n = seq.len();
+        let h0 = entropy_of_counts(freqs.iter());
+        let levels = match freqs.len() {
+            0 | 1 => freqs.len(),
+            sigma => bit_width((sigma - 1) as u64),
+        };
+        let huffman_bits = huffman_lengths(freqs.as_slice()).iter().zip(freqs.iter())
+            .map(|(l, f)| l * f)
+            .sum::<usize>();
+        SizeEstimate {
+            len: n,
+            h0,
+            rank9: rank9_bits(n * levels),
+            // the nodes of a wavelet tree compress to the order-0
+            // entropy of the sequence in total
+            rrr: n as f64 * (h0 + levels as f64 * RRR_OVERHEAD),
+            huffman: rank9_bits(huffman_bits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{h0, hk, SizeEstimate};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[quickcheck]
+    fn entropy_is_bounded(v: Vec<u8>, k: usize) -> bool {
+        let v: Vec<u8> = v.into_iter().map(|x| x % 16).collect();
+        let k = k % 4;
+        let e0 = h0(v.as_slice());
+        let ek = hk(v.as_slice(), k);
+        (0.0..=4.0 + 1e-9).contains(&e0) && ek >= -1e-9 && ek <= e0 + 1e-9
+    }
+
+    #[quickcheck]
+    fn huffman_beats_balanced(v: Vec<u8>) -> bool {
+        let est = SizeEstimate::new(v.as_slice());
+        // Huffman codes are no longer than fixed-width codes and no
+        // shorter than the entropy
+        est.huffman <= est.rank9 + 1e-9
+            && est.huffman >= super::rank9_bits(1) * est.h0 * v.len() as f64 - 1e-6
+    }
+
+    #[test]
+    fn test_entropy() {
+        let v: Vec<u8> = vec!(0, 1, 2, 3, 0, 1, 2, 3);
+        assert!(close(h0(v.as_slice()), 2.0));
+        // each symbol determines its successor
+        assert!(close(hk(v.as_slice(), 1), 0.0));
+        let est = SizeEstimate::new(v.as_slice());
+        assert!(close(est.rank9, 8.0 * 2.0 * 1.25));
+        assert!(close(est.huffman, est.rank9));
+        let freqs = [5, 1, 1, 1];
+        let lengths = super::huffman_lengths(&freqs);
+        assert_eq!(lengths[0], 1);
+        assert_eq!(lengths.iter().zip(freqs.iter()).map(|(l, f)| l * f).sum::<usize>(), 13);
+    }
+}
@@ -0,0 +1,95 @@
+//! Errors of the fallible constructors
+//
+// Constructors given buffers built elsewhere check them against the
+// lengths they are given, rather than answering queries wrongly later.
+// An `Error` converts to an `io::Error`, so readers of serialized
+// structures report it as they do any invalid data.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Why a structure couldn't be built
+#[derive(Debug)]
+pub enum Error {
+    /// A length of `bits` bits given with a buffer of only `words`
+    /// words, counting the whole words of a buffer of bytes
+    LengthExceedsBuffer { bits: usize, words: usize },
+    /// A buffer too long for its bits to be counted in a `usize`
+    Overflow,
+    /// An error reading or writing a stream
+    Io(io::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::LengthExceedsBuffer { bits, words } =>
+                write!(f, "length of {} bits exceeds a buffer of {} words", bits, words),
+            Error::Overflow => write!(f, "buffer too long to count its bits"),
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Errors other than I/O errors become invalid data, as from
+/// `serialize::invalid_data`
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Check that a length of `bits` bits fits in a buffer of `words`
+/// words whose bits can all be counted
+pub fn check_len(bits: usize, words: usize) -> Result<()> {
+    if words.checked_mul(64).is_none() {
+        return Err(Error::Overflow);
+    }
+    match bits / 64 + !bits.is_multiple_of(64) as usize > words {
+        true => Err(Error::LengthExceedsBuffer { bits, words }),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use super::{Error, check_len};
+
+    #[quickcheck]
+    fn check_len_is_correct(bits: usize, words: u16) -> bool {
+        let words = words as usize;
+        check_len(bits, words).is_ok() == (bits <= 64 * words)
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(check_len(0, usize::MAX).is_err());
+        assert!(check_len(usize::MAX, usize::MAX / 64).is_err());
+        assert!(check_len(usize::MAX - 63, usize::MAX / 64).is_ok());
+        let e: io::Error = Error::LengthExceedsBuffer { bits: 65, words: 1 }.into();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
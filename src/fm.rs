@@ -0,0 +1,823 @@
+//! FM-indexes of byte strings
+//
+// The suffixes of a text prefixed by a pattern occupy a range of rows
+// of its Burrows-Wheeler transform. Prepending a byte `b` maps the
+// range to that of the suffixes starting with `b`, in the same order,
+// so its bounds move to the first row of `b` plus the occurrences of
+// `b` in the transform before them. Searching from the last byte of
+// the pattern backwards takes two rank queries per byte, answered by a
+// wavelet tree over the transform.
+//
+// A row is located by stepping backwards through the text, each step
+// an LF mapping, until reaching a row whose suffix was sampled. The
+// samples are taken either at regular positions of the text, bounding
+// the number of steps by the rate, or at regular rows, which is
+// simpler to build but bounds the steps only on average. The suffix
+// of the whole text is always sampled, as it cannot be stepped back
+// from.
+//
+// Approximate matches are found by backtracking: each byte of the
+// pattern, from the last, is matched against every byte of the text in
+// turn while the mismatches allow it, abandoning empty ranges.
+//
+// The text itself is not kept. A substring is read backwards by LF
+// mapping from the row of the first suffix after it starting at a
+// multiple of the rate, the row of each such suffix being kept.
+//
+// Given only the transform, the suffixes to sample are found by walking
+// back through the whole text, one LF mapping per byte. A `Builder`
+// takes the walk in steps and can be checkpointed between them, so that
+// the walk of a long text resumes where it stopped rather than being
+// lost.
+//
+// See Ferragina and Manzini 2000.
+
+use std::io::{self, Read, Write};
+use super::bits::bit_width;
+use super::bwt::{Bwt, suffix_array};
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, BitRank};
+use super::int_vector::IntVector;
+use super::rank9::{self, Rank9};
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+use super::wavelet::Wavelet;
+
+/// The sampling rate used by `FmIndex::new`
+pub const DEFAULT_RATE: usize = 32;
+
+/// The suffixes whose positions an `FmIndex` keeps
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sampling {
+    /// Those starting at multiples of the rate
+    TextOrder(usize),
+    /// Those at every row that is a multiple of the rate
+    SuffixOrder(usize),
+}
+
+/// The rows of the suffixes prefixed by a string within some
+/// mismatches of a pattern
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ApproxMatch {
+    /// the first row
+    pub sp: usize,
+    /// the row after the last
+    pub ep: usize,
+    /// the number of bytes of the string differing from the pattern
+    pub mismatches: usize,
+}
+
+/// A text index counting and locating the occurrences of patterns
+pub struct FmIndex {
+    /// the transform, the sentinel being left out
+    bwt: Wavelet<Rank9, u8>,
+    /// the row at which the sentinel was left out
+    primary: usize,
+    /// the first row of the suffixes starting with each byte
+    counts: Vec<usize>,
+    sampling: Sampling,
+    /// whether the suffix of each row is sampled
+    sampled: Rank9,
+    /// the start of each sampled suffix, by row
+    samples: IntVector,
+    /// the row of each suffix starting at a multiple of the rate
+    rows: IntVector,
+}
+
+impl FmIndex {
+    /// Index `text`, sampling suffixes at every `DEFAULT_RATE`
+    /// positions of the text
+    pub fn new(text: &[u8]) -> FmIndex {
+        FmIndex::with_sampling(text, Sampling::TextOrder(DEFAULT_RATE))
+    }
+
+    /// Index `text`, sampling suffixes as given
+    pub fn with_sampling(text: &[u8], sampling: Sampling) -> FmIndex {
+        let sa = suffix_array(text);
+        FmIndex::from_suffix_array(text, sa.as_slice(), sampling)
+    }
+
+    /// Index `text` given its suffix array, sampling suffixes as given
+    pub fn from_suffix_array(text: &[u8], sa: &[usize], sampling: Sampling) -> FmIndex {
+        let rate = match sampling {
+            Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+        };
+        assert!(rate > 0, "FmIndex: sampling rate must be positive");
+        let bwt = Bwt::from_suffix_array(text, sa);
+        let keep = |row: usize| match sampling {
+            Sampling::TextOrder(rate) => sa[row].is_multiple_of(rate),
+            Sampling::SuffixOrder(rate) => row.is_multiple_of(rate) || sa[row] == 0,
+        };
+        use super::build::Builder;
+        let width = bit_width(text.len() as u64);
+        let mut sampled = rank9::Builder::with_capacity(sa.len());
+        let mut starts = Vec::new();
+        let mut rows = IntVector::new(width, text.len().div_ceil(rate));
+        for (row, &pos) in sa.iter().enumerate() {
+            sampled.push(keep(row));
+            if keep(row) {
+                starts.push(pos);
+            }
+            if pos.is_multiple_of(rate) && pos < text.len() {
+                rows.set(pos / rate, row as u64);
+            }
+        }
+        let mut samples = IntVector::new(width, starts.len());
+        for (i, start) in starts.iter().enumerate() {
+            samples.set(i, *start as u64);
+        }
+        FmIndex {
+            bwt: Wavelet::from_slice(bwt.bwt(), || rank9::Builder::with_capacity(128)),
+            primary: bwt.primary(),
+            counts: bwt.counts().to_vec(),
+            sampling,
+            sampled: sampled.finish(),
+            samples,
+            rows,
+        }
+    }
+
+    /// Index the text transformed by `bwt`, sampling suffixes as given,
+    /// without its suffix array, such as one built by `Bwt::external`
+    pub fn from_bwt(bwt: &Bwt, sampling: Sampling) -> FmIndex {
+        Builder::new(bwt, sampling).finish()
+    }
+
+    /// How the suffixes were sampled
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// A search for the empty pattern, to be extended a byte at a
+    /// time
+    pub fn search<'a>(&'a self) -> SearchState<'a> {
+        SearchState { index: self, sp: 0, ep: self.len() + 1, depth: 0 }
+    }
+
+    /// The range of rows of the suffixes prefixed by `pattern`
+    pub fn range(&self, pattern: &[u8]) -> (usize, usize) {
+        let mut state = self.search();
+        for b in pattern.iter().rev() {
+            state.extend(*b);
+            if state.is_empty() {
+                break;
+            }
+        }
+        state.range()
+    }
+
+    /// The number of occurrences of `pattern` in the text, the empty
+    /// pattern occurring at each position and at the end
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        let (sp, ep) = self.range(pattern);
+        ep - sp
+    }
+
+    /// The positions of the occurrences of `pattern` in the text, in
+    /// increasing order
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        let mut state = self.search();
+        for b in pattern.iter().rev() {
+            state.extend(*b);
+        }
+        state.locate()
+    }
+
+    /// The ranges of rows of the suffixes prefixed by each string
+    /// differing from `pattern` in at most `k` bytes, one range per
+    /// such string occurring in the text
+    pub fn search_approx(&self, pattern: &[u8], k: usize) -> Vec<ApproxMatch> {
+        let alphabet: Vec<u8> = (0..256usize)
+            .filter(|b| self.counts[*b] < self.counts[*b + 1])
+            .map(|b| b as u8)
+            .collect();
+        let mut matches = Vec::new();
+        self.backtrack(self.search(), pattern, 0, k, alphabet.as_slice(), &mut matches);
+        matches
+    }
+
+    /// The positions of the occurrences of strings differing from
+    /// `pattern` in at most `k` bytes and their number of mismatches,
+    /// in increasing order of position
+    pub fn locate_approx(&self, pattern: &[u8], k: usize) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for m in self.search_approx(pattern, k).iter() {
+            positions.extend((m.sp..m.ep).map(|row| (self.suffix(row), m.mismatches)));
+        }
+        positions.sort();
+        positions
+    }
+
+    /// Match the rest of `pattern` before the string searched for by
+    /// `state`, having made `mismatches` of at most `k`
+    fn backtrack(&self, state: SearchState, pattern: &[u8], mismatches: usize, k: usize,
+                 alphabet: &[u8], matches: &mut Vec<ApproxMatch>) {
+        let want = match pattern.last() {
+            None => {
+                let (sp, ep) = state.range();
+                matches.push(ApproxMatch { sp, ep, mismatches });
+                return;
+            },
+            Some(b) => *b,
+        };
+        let rest = &pattern[..pattern.len() - 1];
+        for b in alphabet.iter() {
+            let cost = if *b == want { 0 } else { 1 };
+            if mismatches + cost > k {
+                continue;
+            }
+            let mut next = state.clone();
+            next.extend(*b);
+            if !next.is_empty() {
+                self.backtrack(next, rest, mismatches + cost, k, alphabet, matches);
+            }
+        }
+    }
+
+    /// The start of the suffix at `row`
+    pub fn suffix(&self, mut row: usize) -> usize {
+        assert!(row <= self.len(), "FmIndex: row {} out of bounds", row);
+        let mut steps = 0;
+        while !self.sampled.get(row) {
+            row = self.lf(self.preceding(row), row);
+            steps += 1;
+        }
+        let n = self.sampled.rank1(row);
+        self.samples.get(n) as usize + steps
+    }
+
+    /// The bytes of the text from position `i` up to `j`
+    pub fn extract(&self, i: usize, j: usize) -> Vec<u8> {
+        assert!(i <= j && j <= self.len(), "FmIndex: invalid range [{}, {})", i, j);
+        let rate = match self.sampling {
+            Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+        };
+        // start from the first position after the range whose row is
+        // known, the end of the text being the first row
+        let (mut pos, mut row) = match j.div_ceil(rate) {
+            k if k * rate >= self.len() => (self.len(), 0),
+            k => (k * rate, self.rows.get(k) as usize),
+        };
+        let mut out = Vec::with_capacity(j - i);
+        while pos > i {
+            let b = self.preceding(row);
+            pos -= 1;
+            if pos < j {
+                out.push(b);
+            }
+            row = self.lf(b, row);
+        }
+        out.reverse();
+        out
+    }
+
+    /// The byte preceding the suffix at `row`, which must not be the
+    /// whole text
+    fn preceding(&self, row: usize) -> u8 {
+        debug_assert!(row != self.primary);
+        let i = if row > self.primary { row - 1 } else { row };
+        self.bwt.get(i)
+    }
+
+    /// The number of occurrences of `b` in the rows before `row`
+    fn occ(&self, b: u8, row: usize) -> usize {
+        let i = if row > self.primary { row - 1 } else { row };
+        self.bwt.rank(b, i)
+    }
+
+    /// The row of the suffix `b` followed by that of `row`, or the
+    /// first after it if there is none
+    fn lf(&self, b: u8, row: usize) -> usize {
+        self.counts[b as usize] + self.occ(b, row)
+    }
+}
+
+/// Build an `FmIndex` from a transform by walking back through the
+/// text to sample its suffixes, a given number of steps at a time
+pub struct Builder {
+    /// the index, lacking its samples
+    index: FmIndex,
+    /// the row and start of each suffix sampled so far
+    kept: Vec<(usize, usize)>,
+    /// the start of the next suffix of the walk
+    pos: usize,
+    /// the row of the next suffix of the walk
+    row: usize,
+    /// whether the walk has reached the start of the text
+    done: bool,
+}
+
+impl Builder {
+    /// Index the text transformed by `bwt`, sampling suffixes as
+    /// given. The walk starts from the suffix at the end of the text,
+    /// at the first row.
+    pub fn new(bwt: &Bwt, sampling: Sampling) -> Builder {
+        let rate = match sampling {
+            Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+        };
+        assert!(rate > 0, "FmIndex: sampling rate must be positive");
+        let n = bwt.len();
+        let width = bit_width(n as u64);
+        let index = FmIndex {
+            bwt: Wavelet::from_slice(bwt.bwt(), || rank9::Builder::with_capacity(128)),
+            primary: bwt.primary(),
+            counts: bwt.counts().to_vec(),
+            sampling,
+            sampled: Rank9::default(),
+            samples: IntVector::new(width, 0),
+            rows: IntVector::new(width, n.div_ceil(rate)),
+        };
+        Builder { index, kept: Vec::new(), pos: n, row: 0, done: false }
+    }
+
+    /// The number of suffixes left to visit
+    pub fn remaining(&self) -> usize {
+        if self.done { 0 } else { self.pos + 1 }
+    }
+
+    /// Visit up to `steps` more suffixes, giving whether the walk is
+    /// done
+    pub fn walk(&mut self, steps: usize) -> bool {
+        let index = &mut self.index;
+        for _ in 0..steps {
+            if self.done {
+                break;
+            }
+            let (pos, row) = (self.pos, self.row);
+            let keep = match index.sampling {
+                Sampling::TextOrder(rate) => pos.is_multiple_of(rate),
+                Sampling::SuffixOrder(rate) => row.is_multiple_of(rate) || pos == 0,
+            };
+            if keep {
+                self.kept.push((row, pos));
+            }
+            let rate = match index.sampling {
+                Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+            };
+            if pos.is_multiple_of(rate) && pos < index.len() {
+                index.rows.set(pos / rate, row as u64);
+            }
+            if pos == 0 {
+                self.done = true;
+            } else {
+                self.row = index.lf(index.preceding(row), row);
+                self.pos -= 1;
+            }
+        }
+        self.done
+    }
+
+    /// Finish the walk and the index
+    pub fn finish(mut self) -> FmIndex {
+        use super::build::Builder;
+        self.walk(self.remaining());
+        let mut index = self.index;
+        let mut kept = self.kept;
+        kept.sort();
+        let n = index.len();
+        let mut sampled = rank9::Builder::with_capacity(n + 1);
+        let mut samples = IntVector::new(bit_width(n as u64), kept.len());
+        let mut k = 0;
+        for row in 0..n + 1 {
+            let keep = k < kept.len() && kept[k].0 == row;
+            sampled.push(keep);
+            if keep {
+                samples.set(k, kept[k].1 as u64);
+                k += 1;
+            }
+        }
+        index.sampled = sampled.finish();
+        index.samples = samples;
+        index
+    }
+
+    /// Resume a build from a checkpoint written by `serialize`
+    pub fn resume<R: Read>(r: &mut R) -> io::Result<Builder> {
+        let (bwt, primary, counts, sampling) = read_transform(r)?;
+        let n = bwt.len();
+        let rows: IntVector = Deserialize::deserialize(r)?;
+        let len = read_uint(r)?;
+        if len > n + 1 {
+            return Err(invalid_data("FmIndex checkpoint samples more suffixes than the text has"));
+        }
+        let mut kept = Vec::with_capacity(len);
+        for _ in 0..len {
+            kept.push((read_uint(r)?, read_uint(r)?));
+        }
+        let pos = read_uint(r)?;
+        let row = read_uint(r)?;
+        let done = match read_uint(r)? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid_data("invalid FmIndex checkpoint")),
+        };
+        let rate = match sampling {
+            Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+        };
+        // the walk must be within the text, and at the row of the whole
+        // text exactly when at its start
+        if rows.len() != n.div_ceil(rate) || (0..rows.len()).any(|i| rows.get(i) > n as u64)
+            || kept.iter().any(|&(row, pos)| row > n || pos > n)
+            || pos > n || row > n || (row == primary) != (pos == 0) {
+            return Err(invalid_data("FmIndex checkpoint inconsistent with its transform"));
+        }
+        let width = bit_width(n as u64);
+        let index = FmIndex {
+            bwt,
+            primary,
+            counts,
+            sampling,
+            sampled: Rank9::default(),
+            samples: IntVector::new(width, 0),
+            rows,
+        };
+        Ok(Builder { index, kept, pos, row, done })
+    }
+}
+
+/// A checkpoint of the walk so far, from which the build can be
+/// resumed by `resume`
+impl Serialize for Builder {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.index.write_transform(w)?;
+        self.index.rows.serialize(w)?;
+        write_uint(w, self.kept.len())?;
+        for &(row, pos) in self.kept.iter() {
+            write_uint(w, row)?;
+            write_uint(w, pos)?;
+        }
+        write_uint(w, self.pos)?;
+        write_uint(w, self.row)?;
+        write_uint(w, self.done as usize)
+    }
+}
+
+/// A backward search in an `FmIndex`, the pattern being extended by
+/// prepending bytes. Cloning a state forks the search.
+#[derive(Clone)]
+pub struct SearchState<'a> {
+    index: &'a FmIndex,
+    sp: usize,
+    ep: usize,
+    /// the length of the pattern
+    depth: usize,
+}
+
+impl<'a> SearchState<'a> {
+    /// Prepend `b` to the pattern, returning the updated range of rows
+    pub fn extend(&mut self, b: u8) -> (usize, usize) {
+        if self.sp < self.ep {
+            self.sp = self.index.lf(b, self.sp);
+            self.ep = self.index.lf(b, self.ep);
+        }
+        self.depth += 1;
+        self.range()
+    }
+
+    /// The range of rows of the suffixes prefixed by the pattern
+    pub fn range(&self) -> (usize, usize) {
+        (self.sp, self.ep)
+    }
+
+    /// The number of occurrences of the pattern
+    pub fn count(&self) -> usize {
+        self.ep - self.sp
+    }
+
+    /// Whether the pattern does not occur
+    pub fn is_empty(&self) -> bool {
+        self.sp == self.ep
+    }
+
+    /// The length of the pattern
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The positions of the occurrences of the pattern, in increasing
+    /// order
+    pub fn locate(&self) -> Vec<usize> {
+        let mut positions: Vec<usize> = (self.sp..self.ep).map(|row| self.index.suffix(row)).collect();
+        positions.sort();
+        positions
+    }
+}
+
+impl Collection for FmIndex {
+    /// The length of the text
+    fn len(&self) -> usize {
+        self.bwt.len()
+    }
+}
+
+impl SpaceUsage for FmIndex {
+    fn total_bytes(&self) -> usize {
+        self.bwt.stats().bytes + vec_bytes(&self.counts)
+            + self.sampled.total_bytes() + self.samples.total_bytes() + self.rows.total_bytes()
+    }
+}
+
+impl FmIndex {
+    /// Write the transform, the primary row, the counts and the
+    /// sampling, with which the serializations of an index and of a
+    /// checkpoint of its build begin
+    fn write_transform<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.bwt.serialize(w)?;
+        write_uint(w, self.primary)?;
+        for count in self.counts.iter() {
+            write_uint(w, *count)?;
+        }
+        match self.sampling {
+            Sampling::TextOrder(rate) => { write_uint(w, 0)?; write_uint(w, rate)?; }
+            Sampling::SuffixOrder(rate) => { write_uint(w, 1)?; write_uint(w, rate)?; }
+        }
+        Ok(())
+    }
+}
+
+/// The transform, primary row, counts and sampling of an index
+type Transform = (Wavelet<Rank9, u8>, usize, Vec<usize>, Sampling);
+
+/// Read what `write_transform` wrote, checking the counts against the
+/// transform
+fn read_transform<R: Read>(r: &mut R) -> io::Result<Transform> {
+    let bwt: Wavelet<Rank9, u8> = Deserialize::deserialize(r)?;
+    let n = bwt.len();
+    let primary = read_uint(r)?;
+    let mut counts = Vec::with_capacity(257);
+    for _ in 0..257 {
+        counts.push(read_uint(r)?);
+    }
+    let sampling = match (read_uint(r)?, read_uint(r)?) {
+        (_, 0) => return Err(invalid_data("FmIndex sampling rate must be positive")),
+        (0, rate) => Sampling::TextOrder(rate),
+        (1, rate) => Sampling::SuffixOrder(rate),
+        _ => return Err(invalid_data("unknown FmIndex sampling")),
+    };
+    // the rows of each byte follow the first, that of the whole text,
+    // as many as it occurs in the transform
+    let mut first = 1;
+    for (b, count) in counts[..256].iter().enumerate() {
+        if *count != first {
+            return Err(invalid_data("FmIndex counts inconsistent with its transform"));
+        }
+        first += bwt.rank(b as u8, n);
+    }
+    if primary > n || counts[256] != first {
+        return Err(invalid_data("FmIndex counts inconsistent with its transform"));
+    }
+    Ok((bwt, primary, counts, sampling))
+}
+
+impl Serialize for FmIndex {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_transform(w)?;
+        self.sampled.serialize(w)?;
+        self.samples.serialize(w)?;
+        self.rows.serialize(w)
+    }
+}
+
+impl Deserialize for FmIndex {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<FmIndex> {
+        let (bwt, primary, counts, sampling) = read_transform(r)?;
+        let n = bwt.len();
+        let rate = match sampling {
+            Sampling::TextOrder(rate) | Sampling::SuffixOrder(rate) => rate,
+        };
+        let sampled: Rank9 = Deserialize::deserialize(r)?;
+        let samples: IntVector = Deserialize::deserialize(r)?;
+        let rows: IntVector = Deserialize::deserialize(r)?;
+
+        // the row of the whole text must be sampled for locating to end,
+        // and every position and row kept must be within the text
+        if sampled.len() != n + 1 || !sampled.get(primary) || samples.len() != sampled.rank1(n + 1)
+            || rows.len() != n.div_ceil(rate)
+            || (0..samples.len()).any(|i| samples.get(i) > n as u64)
+            || (0..rows.len()).any(|i| rows.get(i) > n as u64) {
+            return Err(invalid_data("FmIndex samples inconsistent with its transform"));
+        }
+        Ok(FmIndex { bwt, primary, counts, sampling, sampled, samples, rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{FmIndex, Sampling};
+    use super::super::serialize::{Serialize, Deserialize};
+
+    /// A string over a small alphabet, shaped by `v`
+    fn text(v: &[u8]) -> Vec<u8> {
+        v.iter().map(|x| b'a' + *x % 3).collect()
+    }
+
+    #[quickcheck]
+    fn count_is_correct(v: Vec<u8>, p: Vec<u8>) -> bool {
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let fm = FmIndex::new(v.as_slice());
+        let naive = (0..v.len() + 1).filter(|i| v[*i..].starts_with(p)).count();
+        fm.count(p) == naive
+    }
+
+    #[quickcheck]
+    fn locate_is_correct(v: Vec<u8>, p: Vec<u8>, rate: u8, by_row: bool) -> bool {
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let rate = rate as usize % 8 + 1;
+        let sampling = if by_row { Sampling::SuffixOrder(rate) } else { Sampling::TextOrder(rate) };
+        let fm = FmIndex::with_sampling(v.as_slice(), sampling);
+        let naive: Vec<usize> = (0..v.len() + 1).filter(|i| v[*i..].starts_with(p)).collect();
+        fm.locate(p) == naive
+    }
+
+    #[quickcheck]
+    fn extract_is_correct(v: Vec<u8>, i: usize, j: usize, rate: u8, by_row: bool) -> bool {
+        let v = text(&v);
+        let (i, j) = (i % (v.len() + 1), j % (v.len() + 1));
+        let (i, j) = (if i < j { i } else { j }, if i < j { j } else { i });
+        let rate = rate as usize % 8 + 1;
+        let sampling = if by_row { Sampling::SuffixOrder(rate) } else { Sampling::TextOrder(rate) };
+        let fm = FmIndex::with_sampling(v.as_slice(), sampling);
+        fm.extract(i, j) == v[i..j].to_vec()
+    }
+
+    #[quickcheck]
+    fn locate_approx_is_correct(v: Vec<u8>, p: Vec<u8>, k: u8) -> bool {
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 4 { p.len() } else { 4 }];
+        let k = k as usize % 3;
+        let fm = FmIndex::new(v.as_slice());
+        let naive: Vec<(usize, usize)> = (0..v.len() + 1)
+            .filter(|i| *i + p.len() <= v.len())
+            .map(|i| (i, v[i..i + p.len()].iter().zip(p.iter()).filter(|&(a, b)| a != b).count()))
+            .filter(|&(_, d)| d <= k)
+            .collect();
+        fm.locate_approx(p, k) == naive
+    }
+
+    #[quickcheck]
+    fn from_bwt_agrees(v: Vec<u8>, p: Vec<u8>, rate: u8, by_row: bool) -> bool {
+        use super::super::bwt::Bwt;
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let rate = rate as usize % 8 + 1;
+        let sampling = if by_row { Sampling::SuffixOrder(rate) } else { Sampling::TextOrder(rate) };
+        let fm = FmIndex::from_bwt(&Bwt::new(v.as_slice()), sampling);
+        let expected = FmIndex::with_sampling(v.as_slice(), sampling);
+        fm.locate(p) == expected.locate(p) && fm.extract(0, v.len()) == v
+    }
+
+    #[quickcheck]
+    fn resumed_build_agrees(v: Vec<u8>, rate: u8, by_row: bool, steps: usize) -> bool {
+        use super::Builder;
+        use super::super::bwt::Bwt;
+        let v = text(&v);
+        let rate = rate as usize % 8 + 1;
+        let sampling = if by_row { Sampling::SuffixOrder(rate) } else { Sampling::TextOrder(rate) };
+        let bwt = Bwt::new(v.as_slice());
+        let mut builder = Builder::new(&bwt, sampling);
+        builder.walk(steps % (v.len() + 2));
+        let mut checkpoint = Vec::new();
+        builder.serialize(&mut checkpoint).unwrap();
+        let fm = Builder::resume(&mut Cursor::new(checkpoint)).unwrap().finish();
+        let (mut built, mut expected) = (Vec::new(), Vec::new());
+        fm.serialize(&mut built).unwrap();
+        FmIndex::from_bwt(&bwt, sampling).serialize(&mut expected).unwrap();
+        built == expected
+    }
+
+    #[test]
+    fn test_resume_checks() {
+        use super::Builder;
+        use super::super::bwt::Bwt;
+        let mut builder = Builder::new(&Bwt::new(b"abracadabra"), Sampling::TextOrder(4));
+        builder.walk(3);
+        assert_eq!(builder.remaining(), 9);
+        let mut buf = Vec::new();
+        builder.serialize(&mut buf).unwrap();
+        assert!(Builder::resume(&mut Cursor::new(buf.clone())).is_ok());
+
+        // the walk at the row of the whole text, the row preceding the
+        // done flag
+        let at = buf.len() - 16;
+        buf[at] = builder.index.primary as u8;
+        assert!(Builder::resume(&mut Cursor::new(buf.clone())).is_err());
+
+        // a row past the end of the text
+        buf[at] = 12;
+        assert!(Builder::resume(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[test]
+    fn test_search_approx() {
+        let fm = FmIndex::new(b"abracadabra");
+        assert_eq!(fm.locate_approx(b"abra", 1), vec!((0, 0), (7, 0)));
+        assert_eq!(fm.locate_approx(b"aca", 1), vec!((3, 0), (5, 1)));
+        assert_eq!(fm.locate_approx(b"aca", 2), vec!((0, 2), (1, 2), (3, 0), (5, 1), (7, 2), (8, 2)));
+        let matches = fm.search_approx(b"cad", 1);
+        assert!(matches.iter().all(|m| m.mismatches <= 1 && m.sp < m.ep));
+        assert_eq!(matches.iter().map(|m| m.ep - m.sp).sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn test_search_state() {
+        let fm = FmIndex::new(b"abracadabra");
+        let mut state = fm.search();
+        assert_eq!(state.count(), 12);
+        assert_eq!(state.extend(b'a'), fm.range(b"a"));
+        // fork the search on the byte before
+        let mut ra = state.clone();
+        ra.extend(b'r');
+        state.extend(b'c');
+        assert_eq!(ra.count(), 2);
+        assert_eq!(ra.depth(), 2);
+        assert_eq!(state.locate(), vec!(4));
+        ra.extend(b'b');
+        ra.extend(b'a');
+        assert_eq!(ra.locate(), vec!(0, 7));
+        ra.extend(b'z');
+        assert!(ra.is_empty());
+        ra.extend(b'a');
+        assert!(ra.is_empty());
+    }
+
+    #[test]
+    fn test_extract() {
+        let fm = FmIndex::with_sampling(b"abracadabra", Sampling::TextOrder(4));
+        assert_eq!(fm.extract(0, 11), b"abracadabra".to_vec());
+        assert_eq!(fm.extract(3, 7), b"acad".to_vec());
+        assert!(fm.extract(5, 5).is_empty());
+    }
+
+    #[test]
+    fn test_locate() {
+        let fm = FmIndex::with_sampling(b"abracadabra", Sampling::SuffixOrder(4));
+        assert_eq!(fm.locate(b"abra"), vec!(0, 7));
+        assert_eq!(fm.locate(b"a"), vec!(0, 3, 5, 7, 10));
+        assert!(fm.locate(b"z").is_empty());
+        assert_eq!(fm.sampling(), Sampling::SuffixOrder(4));
+    }
+
+    #[test]
+    fn test_count() {
+        let fm = FmIndex::new(b"abracadabra");
+        assert_eq!(fm.count(b"abra"), 2);
+        assert_eq!(fm.count(b"a"), 5);
+        assert_eq!(fm.count(b"cad"), 1);
+        assert_eq!(fm.count(b"dab"), 1);
+        assert_eq!(fm.count(b"abc"), 0);
+        assert_eq!(fm.count(b"z"), 0);
+        assert_eq!(fm.count(b""), 12);
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u8>, p: Vec<u8>, rate: u8, by_row: bool) -> bool {
+        let (v, p) = (text(&v), text(&p));
+        let p = &p[..if p.len() < 3 { p.len() } else { 3 }];
+        let rate = rate as usize % 8 + 1;
+        let sampling = if by_row { Sampling::SuffixOrder(rate) } else { Sampling::TextOrder(rate) };
+        let fm = FmIndex::with_sampling(v.as_slice(), sampling);
+        let mut buf: Vec<u8> = Vec::new();
+        fm.serialize(&mut buf).unwrap();
+        let read: FmIndex = Deserialize::deserialize(&mut Cursor::new(buf)).unwrap();
+        read.sampling() == sampling && read.locate(p) == fm.locate(p)
+            && read.extract(0, v.len()) == v
+    }
+
+    #[test]
+    fn test_deserialize_checks() {
+        let fm = FmIndex::with_sampling(b"abracadabra", Sampling::TextOrder(4));
+        let mut buf: Vec<u8> = Vec::new();
+        fm.serialize(&mut buf).unwrap();
+        let read: FmIndex = Deserialize::deserialize(&mut Cursor::new(buf.clone())).unwrap();
+        assert_eq!(read.locate(b"abra"), vec!(0, 7));
+
+        // a rate of zero, the rate following the counts and the kind
+        // of sampling
+        let mut rate = Vec::new();
+        fm.bwt.serialize(&mut rate).unwrap();
+        let at = rate.len() + 8 * (1 + 257 + 1);
+        buf[at] = 0;
+        assert!(<FmIndex as Deserialize>::deserialize(&mut Cursor::new(buf.clone())).is_err());
+
+        // a rate disagreeing with the rows kept
+        buf[at] = 3;
+        assert!(<FmIndex as Deserialize>::deserialize(&mut Cursor::new(buf.clone())).is_err());
+        buf[at] = 4;
+
+        // counts disagreeing with the transform, though still ordered
+        // and ending at the length, the first row of b moving into a's
+        let b = rate.len() + 8 * (1 + b'b' as usize);
+        buf[b] -= 1;
+        assert!(<FmIndex as Deserialize>::deserialize(&mut Cursor::new(buf.clone())).is_err());
+        buf[b] += 1;
+
+        // a row past the end of the text, the rows being packed in the
+        // last word
+        let last = buf.len() - 8;
+        buf[last] = 12;
+        assert!(<FmIndex as Deserialize>::deserialize(&mut Cursor::new(buf)).is_err());
+    }
+}
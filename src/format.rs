@@ -0,0 +1,250 @@
+//! A versioned on-disk format for long-lived index files
+//
+// A file holds a single structure in the following layout, all words
+// being little-endian 64-bit words as written by `write_u64`:
+//
+//   bytes 0..8    the magic `SUCCINCT`
+//   word 1        the format version, currently `VERSION`
+//   word 2        the type tag of the structure, listed below
+//   word 3        the length `n` of the payload in bytes
+//   bytes 32..    the payload, the `Serialize` encoding of the structure
+//   last word     the 64-bit FNV-1a hash of the payload
+//
+// The version is raised whenever the payload of any structure changes,
+// so a file of a given version always reads back the same structure.
+// Reading checks the header before streaming the payload into the
+// structure's `Deserialize` implementation, which must consume it
+// exactly, and the checksum once it has.
+//
+// Type tags of plain structures are below 256:
+//
+//    1 BitVector       2 Rank9          3 IntVector      4 Dac
+//    5 EliasFano       6 CodedSequence  7 VByteSequence  8 PForArray
+//    9 PrefixSums     10 GapList       11 RleColumn     12 Multiset
+//   13 Trie           14 Rmq           15 BalancedParens 16 BpTree
+//   17 Louds          18 Dfuds         19 CardinalTree  20 K2Tree
+//   21 K2Raster       22 BinaryRelation 23 EliasFanoGraph 24 BvGraph
+//   25 SequenceGraph  26 FmIndex
+//
+// A wavelet tree is tagged with 1 for `Wavelet`, 2 for `FlatWavelet`
+// and 3 for `AlphabeticWavelet`, plus the tag of its bitvectors shifted
+// left by eight bits and that of its symbols shifted left by sixteen:
+//
+//    1 u8    2 u16    3 u32    4 u64    5 char
+//
+// so `Wavelet<Rank9, u8>` has the tag `0x10201`. A tree read as one of
+// symbols of another type would answer with the wrong symbols.
+
+use std::io::{self, Read, Write};
+use super::digest::Digest;
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, invalid_data};
+
+use super::bit_vector::BitVector;
+use super::bits::BitIter;
+use super::coded::CodedSequence;
+use super::collection::Collection;
+use super::dac::Dac;
+use super::dictionary::{Rank, Access};
+use super::elias_fano::EliasFano;
+use super::fm::FmIndex;
+use super::graph::elias_fano::EliasFanoGraph;
+use super::graph::sequence::SequenceGraph;
+use super::graph::webgraph::BvGraph;
+use super::int_vector::IntVector;
+use super::k2raster::K2Raster;
+use super::k2tree::K2Tree;
+use super::multiset::Multiset;
+use super::pfor::PForArray;
+use super::postings::GapList;
+use super::prefix_sums::PrefixSums;
+use super::rank9::Rank9;
+use super::relation::BinaryRelation;
+use super::rle::RleColumn;
+use super::rmq::Rmq;
+use super::tree::bp::BpTree;
+use super::tree::cardinal::CardinalTree;
+use super::tree::dfuds::Dfuds;
+use super::tree::louds::Louds;
+use super::tree::parens::BalancedParens;
+use super::trie::Trie;
+use super::vbyte::VByteSequence;
+use super::wavelet::{Wavelet, FlatWavelet, AlphabeticWavelet};
+
+/// The first bytes of every file
+pub const MAGIC: &[u8; 8] = b"SUCCINCT";
+
+/// The version of the format written
+pub const VERSION: u64 = 1;
+
+/// The 64-bit FNV-1a hash of `bytes`
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut digest = Digest::new();
+    digest.update(bytes);
+    digest.finish()
+}
+
+/// Digest the bytes read through it
+struct Digesting<R> {
+    reader: R,
+    digest: Digest,
+}
+
+impl<R: Read> Read for Digesting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// A structure with a type tag in the versioned format
+pub trait Format: Serialize + Deserialize {
+    /// The type tag identifying the structure in a file
+    fn tag() -> u64;
+
+    /// Write the structure with its header and checksum. The payload
+    /// is serialized twice, first to find its length and checksum, so
+    /// that it is streamed to `w` rather than held in memory.
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut digest = Digest::new();
+        self.serialize(&mut digest)?;
+        w.write_all(MAGIC)?;
+        write_u64(w, VERSION)?;
+        write_u64(w, <Self as Format>::tag())?;
+        write_u64(w, digest.len())?;
+        self.serialize(w)?;
+        write_u64(w, digest.finish())
+    }
+
+    /// Read a structure written by `write_to`, checking its header and
+    /// checksum. The payload is streamed from `r` into the structure.
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        if r.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Err(invalid_data("not a succinct index file"));
+        }
+        if read_u64(r)? != VERSION {
+            return Err(invalid_data("unsupported format version"));
+        }
+        if read_u64(r)? != <Self as Format>::tag() {
+            return Err(invalid_data("file holds a structure of another type"));
+        }
+        let len = read_u64(r)?;
+        let mut payload = Digesting { reader: r.by_ref().take(len), digest: Digest::new() };
+        let x = Deserialize::deserialize(&mut payload)?;
+        let digest = payload.digest;
+        if digest.len() != len {
+            return Err(invalid_data("trailing bytes in payload"));
+        }
+        if read_u64(r)? != digest.finish() {
+            return Err(invalid_data("checksum mismatch"));
+        }
+        Ok(x)
+    }
+}
+
+macro_rules! tags {
+    ($($t:ident = $tag:expr),*) => {$(
+        impl Format for $t {
+            fn tag() -> u64 { $tag }
+        }
+    )*};
+}
+
+tags!(BitVector = 1, Rank9 = 2, IntVector = 3, Dac = 4, EliasFano = 5, CodedSequence = 6,
+      VByteSequence = 7, PForArray = 8, PrefixSums = 9, GapList = 10, RleColumn = 11,
+      Multiset = 12, Trie = 13, Rmq = 14, BalancedParens = 15, BpTree = 16, Louds = 17,
+      Dfuds = 18, CardinalTree = 19, K2Tree = 20, K2Raster = 21, BinaryRelation = 22,
+      EliasFanoGraph = 23, BvGraph = 24, SequenceGraph = 25, FmIndex = 26);
+
+/// A type of the symbols of a wavelet tree in a file
+pub trait Symbol {
+    /// The type tag identifying the symbols
+    fn symbol_tag() -> u64;
+}
+
+macro_rules! symbol_tags {
+    ($($t:ident = $tag:expr),*) => {$(
+        impl Symbol for $t {
+            fn symbol_tag() -> u64 { $tag }
+        }
+    )*};
+}
+
+symbol_tags!(u8 = 1, u16 = 2, u32 = 3, u64 = 4, char = 5);
+
+/// The tag of a wavelet tree of the given kind
+fn wavelet_tag<BitV: Format, Sym: Symbol>(kind: u64) -> u64 {
+    (Sym::symbol_tag() << 16) | (<BitV as Format>::tag() << 8) | kind
+}
+
+impl<BitV, Sym> Format for Wavelet<BitV, Sym>
+    where BitV: Format + Rank<bool> + Access<bool> + Collection, Sym: Symbol + BitIter
+{
+    fn tag() -> u64 { wavelet_tag::<BitV, Sym>(1) }
+}
+
+impl<BitV: Format + Collection, Sym: Symbol> Format for FlatWavelet<BitV, Sym> {
+    fn tag() -> u64 { wavelet_tag::<BitV, Sym>(2) }
+}
+
+impl<BitV: Format, Sym: Symbol + Serialize + Deserialize> Format for AlphabeticWavelet<BitV, Sym> {
+    fn tag() -> u64 { wavelet_tag::<BitV, Sym>(3) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::Format;
+    use super::super::dictionary::Access;
+    use super::super::elias_fano::EliasFano;
+    use super::super::rank9::{self, Rank9};
+    use super::super::wavelet::Wavelet;
+
+    #[quickcheck]
+    fn wavelet_round_trips(v: Vec<u8>) -> bool {
+        let wavelet = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(128));
+        let mut buf: Vec<u8> = Vec::new();
+        wavelet.write_to(&mut buf).unwrap();
+        let read: Wavelet<Rank9, u8> = Format::read_from(&mut Cursor::new(buf)).unwrap();
+        (0..v.len()).all(|i| read.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_symbol_type() {
+        let v: Vec<u64> = vec!(1, 300, 2);
+        let wavelet = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(16));
+        let mut buf: Vec<u8> = Vec::new();
+        wavelet.write_to(&mut buf).unwrap();
+        let read: Wavelet<Rank9, u64> = Format::read_from(&mut Cursor::new(buf.clone())).unwrap();
+        assert_eq!(read.get(1), 300);
+        assert!(<Wavelet<Rank9, u8> as Format>::read_from(&mut Cursor::new(buf.clone())).is_err());
+        assert!(<Wavelet<Rank9, u32> as Format>::read_from(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[test]
+    fn test_corruption() {
+        let ef = EliasFano::from_slice(&[2, 3, 5, 7, 11]);
+        let mut buf: Vec<u8> = Vec::new();
+        ef.write_to(&mut buf).unwrap();
+        let read: EliasFano = Format::read_from(&mut Cursor::new(buf.clone())).unwrap();
+        assert_eq!(read.get(4), 11);
+
+        // a flipped payload bit fails the checksum
+        let mut flipped = buf.clone();
+        flipped[40] ^= 1;
+        assert!(<EliasFano as Format>::read_from(&mut Cursor::new(flipped)).is_err());
+
+        // the tag is checked
+        assert!(<Rank9 as Format>::read_from(&mut Cursor::new(buf.clone())).is_err());
+
+        // as is the version
+        let mut future = buf.clone();
+        future[8] += 1;
+        assert!(<EliasFano as Format>::read_from(&mut Cursor::new(future)).is_err());
+
+        let mut truncated = buf;
+        truncated.pop();
+        assert!(<EliasFano as Format>::read_from(&mut Cursor::new(truncated)).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Frozen structures, queried in place in shared memory
+//
+// A frozen structure is a region of words holding no pointers. Its
+// first word is the length of the region and the fields of its header
+// follow, each array or substructure it holds being found through a
+// field giving its offset from the start of the region. A region can
+// so be copied, or mapped by any number of processes at once, to any
+// word-aligned address, and thawing it merely checks its header before
+// queries read it in place. Unlike the views of `view`, which read a
+// serialization from front to back, any substructure of a region is
+// found without reading those before it, so a vector of structures
+// thaws in constant time.
+
+use std::io;
+use std::marker::PhantomData;
+use super::serialize::invalid_data;
+
+/// A structure which can be frozen into a region
+pub trait Freeze {
+    /// Append the region of the structure to `out`
+    fn freeze(&self, out: &mut Vec<u64>);
+}
+
+/// A structure queried in place in a region
+pub trait Thaw<'a>: Sized {
+    /// The structure frozen in `region`, as given by `region`
+    fn thaw(region: &'a [u64]) -> io::Result<Self>;
+}
+
+/// A region being written, with a header of `fields` fields
+pub struct Region<'a> {
+    out: &'a mut Vec<u64>,
+    start: usize,
+}
+
+impl<'a> Region<'a> {
+    /// Start a region at the end of `out` with a header of `fields`
+    /// fields, all zero
+    pub fn new(out: &'a mut Vec<u64>, fields: usize) -> Region<'a> {
+        let start = out.len();
+        for _ in 0..fields + 1 {
+            out.push(0);
+        }
+        Region { out, start }
+    }
+
+    /// Set field `i` of the header
+    pub fn set(&mut self, i: usize, x: u64) {
+        self.out[self.start + 1 + i] = x;
+    }
+
+    /// Append `words`, setting field `i` to their offset and field
+    /// `i + 1` to their number
+    pub fn array(&mut self, i: usize, words: &[u64]) {
+        let offset = self.out.len() - self.start;
+        self.set(i, offset as u64);
+        self.set(i + 1, words.len() as u64);
+        self.out.extend_from_slice(words);
+    }
+
+    /// Append the region of `x`, setting field `i` to its offset
+    pub fn child<T: Freeze>(&mut self, i: usize, x: &T) {
+        let offset = self.out.len() - self.start;
+        self.set(i, offset as u64);
+        x.freeze(self.out);
+    }
+
+    /// Finish the region, recording its length
+    pub fn finish(self) {
+        self.out[self.start] = (self.out.len() - self.start) as u64;
+    }
+}
+
+/// The region at the start of `words`
+pub fn region(words: &[u64]) -> io::Result<&[u64]> {
+    match words.first() {
+        Some(len) if *len > 0 && *len <= words.len() as u64 => Ok(&words[..*len as usize]),
+        _ => Err(invalid_data("frozen region exceeds its memory")),
+    }
+}
+
+/// Field `i` of the header of `region`
+pub fn field(region: &[u64], i: usize) -> io::Result<u64> {
+    match region.get(1 + i) {
+        Some(x) => Ok(*x),
+        None => Err(invalid_data("frozen header exceeds its region")),
+    }
+}
+
+/// Field `i` of the header of `region`, a length or position
+pub fn uint_field(region: &[u64], i: usize) -> io::Result<usize> {
+    match usize::try_from(field(region, i)?) {
+        Ok(x) => Ok(x),
+        Err(_) => Err(invalid_data("length exceeds address space")),
+    }
+}
+
+/// The array whose offset and number of words are fields `i` and
+/// `i + 1` of `region`
+pub fn array(region: &[u64], i: usize) -> io::Result<&[u64]> {
+    let offset = field(region, i)?;
+    let len = field(region, i + 1)?;
+    match offset.checked_add(len) {
+        Some(end) if end <= region.len() as u64 => Ok(&region[offset as usize..end as usize]),
+        _ => Err(invalid_data("frozen array exceeds its region")),
+    }
+}
+
+/// Thaw the structure whose offset is field `i` of `region`
+pub fn child<'a, T: Thaw<'a>>(region: &'a [u64], i: usize) -> io::Result<T> {
+    let offset = field(region, i)?;
+    if offset >= region.len() as u64 {
+        return Err(invalid_data("frozen structure exceeds its region"));
+    }
+    Thaw::thaw(self::region(&region[offset as usize..])?)
+}
+
+/// The number of structures, then the offset of each
+impl<T: Freeze> Freeze for Vec<T> {
+    fn freeze(&self, out: &mut Vec<u64>) {
+        let mut region = Region::new(out, self.len() + 1);
+        region.set(0, self.len() as u64);
+        for (i, x) in self.iter().enumerate() {
+            region.child(i + 1, x);
+        }
+        region.finish()
+    }
+}
+
+/// A frozen vector of structures, each thawed as it is taken
+pub struct FrozenVec<'a, T> {
+    region: &'a [u64],
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T: Thaw<'a>> FrozenVec<'a, T> {
+    /// The number of structures
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no structures
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Thaw the `i`th structure
+    pub fn get(&self, i: usize) -> io::Result<T> {
+        assert!(i < self.len, "FrozenVec: index {} out of bounds", i);
+        child(self.region, i + 1)
+    }
+}
+
+impl<'a, T: Thaw<'a>> Thaw<'a> for FrozenVec<'a, T> {
+    fn thaw(region: &'a [u64]) -> io::Result<FrozenVec<'a, T>> {
+        let len = field(region, 0)?;
+        if len >= region.len() as u64 {
+            return Err(invalid_data("frozen vector exceeds its region"));
+        }
+        Ok(FrozenVec { region, len: len as usize, phantom: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Freeze, Thaw, FrozenVec, region};
+    use super::super::bit_vector::{BitVector, BitVectorRef};
+    use super::super::build::Builder;
+    use super::super::dictionary::{Access, BitRank, Select};
+    use super::super::rank9::{self, Rank9, Rank9Ref};
+    use super::super::wavelet::FlatWavelet;
+
+    #[quickcheck]
+    fn frozen_vectors_agree(vs: Vec<Vec<bool>>, shift: u8) -> bool {
+        let bvs: Vec<Rank9> = vs.iter().map(|v| rank9::Builder::with_capacity(v.len()).from_iter(v.clone().into_iter())).collect();
+        // a region is relocatable
+        let mut words: Vec<u64> = (0..shift).map(|_| 0xdead).collect();
+        bvs.freeze(&mut words);
+        let frozen: FrozenVec<Rank9Ref> = Thaw::thaw(region(&words[shift as usize..]).unwrap()).unwrap();
+        frozen.len() == vs.len() && vs.iter().enumerate().all(|(i, v)| {
+            let bv = frozen.get(i).unwrap();
+            let ones = v.iter().filter(|b| **b).count();
+            (0..v.len()).all(|j| bv.get(j) == v[j])
+                && bv.rank1(v.len()) == ones
+                && (1..ones + 1).all(|n| bv.select(true, n) == bvs[i].select(true, n))
+        })
+    }
+
+    #[quickcheck]
+    fn frozen_flat_wavelet(v: Vec<u8>) -> bool {
+        let flat: FlatWavelet<Rank9, u8> = FlatWavelet::from_slice(v.as_slice());
+        let mut words = Vec::new();
+        flat.freeze(&mut words);
+        let frozen: FlatWavelet<Rank9Ref, u8> = Thaw::thaw(region(words.as_slice()).unwrap()).unwrap();
+        (0..v.len()).all(|i| frozen.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let bv = BitVector::from_vec(&[0b1011, 7], 128).unwrap();
+        let mut words = Vec::new();
+        bv.freeze(&mut words);
+        {
+            let frozen: BitVectorRef = Thaw::thaw(region(words.as_slice()).unwrap()).unwrap();
+            assert_eq!(frozen.rank1(128), 6);
+        }
+        // claim more bits than the array holds
+        words[1] = 129;
+        assert!(<BitVectorRef as Thaw>::thaw(region(words.as_slice()).unwrap()).is_err());
+        // and a count which truncates to one it holds on 32-bit targets
+        words[1] = (1 << 32) + 64;
+        assert!(<BitVectorRef as Thaw>::thaw(region(words.as_slice()).unwrap()).is_err());
+        words[0] = 100;
+        assert!(region(words.as_slice()).is_err());
+    }
+}
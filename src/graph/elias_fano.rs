@@ -0,0 +1,194 @@
+//! Graphs with Elias-Fano coded adjacency lists
+//
+// The edge `(u, v)` of a graph with `n` nodes is mapped to `u * n + v`,
+// so that the adjacency lists concatenated in order of source node
+// form a single increasing sequence, coded with Elias-Fano in about
+// `2 + log2(n^2 / m)` bits per edge. The index of the first edge of
+// each node, with the number of edges last, is coded likewise. An edge
+// is looked up by a successor search for its value.
+
+use std::io::{self, Read, Write};
+use super::super::collection::Collection;
+use super::super::dictionary::Access;
+use super::super::elias_fano::{self, EliasFano};
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::super::space::SpaceUsage;
+
+/// A static directed graph in compressed sparse row form
+pub struct EliasFanoGraph {
+    nodes: usize,
+    /// `u * nodes + v` for each edge `(u, v)`, in increasing order
+    edges: EliasFano,
+    /// the index of the first edge of each node, and the number of
+    /// edges
+    offsets: EliasFano,
+}
+
+impl EliasFanoGraph {
+    /// The graph on `nodes` nodes with the given edges, repeated edges
+    /// being kept once
+    pub fn new(nodes: usize, edges: &[(usize, usize)]) -> EliasFanoGraph {
+        use super::super::build::Builder;
+        for &(u, v) in edges.iter() {
+            assert!(u < nodes && v < nodes, "EliasFanoGraph: edge ({}, {}) out of bounds", u, v);
+        }
+        let mut edges = edges.to_vec();
+        edges.sort();
+        edges.dedup();
+        let max = (nodes * nodes) as u64;
+        let codes = edges.iter().map(|&(u, v)| (u * nodes + v) as u64);
+        let mut offsets = elias_fano::Builder::new(nodes + 1, edges.len() as u64);
+        let mut next = 0;
+        for u in 0..nodes + 1 {
+            while next < edges.len() && edges[next].0 < u {
+                next += 1;
+            }
+            offsets.push(next as u64);
+        }
+        EliasFanoGraph {
+            nodes,
+            edges: elias_fano::Builder::new(edges.len(), max).from_iter(codes),
+            offsets: offsets.finish(),
+        }
+    }
+
+    /// The number of edges
+    pub fn edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// The number of out-neighbors of `u`
+    pub fn degree(&self, u: usize) -> usize {
+        let (start, end) = self.bounds(u);
+        end - start
+    }
+
+    /// The out-neighbors of `u` in increasing order
+    pub fn neighbors<'a>(&'a self, u: usize) -> Neighbors<'a> {
+        let (start, end) = self.bounds(u);
+        Neighbors { iter: self.edges.iter_from(start), base: (u * self.nodes) as u64, left: end - start }
+    }
+
+    /// Whether there is an edge from `u` to `v`
+    pub fn has_edge(&self, u: usize, v: usize) -> bool {
+        assert!(v < self.nodes, "EliasFanoGraph: node {} out of bounds", v);
+        let (_, end) = self.bounds(u);
+        let code = (u * self.nodes + v) as u64;
+        match self.edges.successor(code) {
+            Some((i, x)) => i < end && x == code,
+            None => false,
+        }
+    }
+
+    /// The indexes of the first edge of `u` and of the first after its
+    /// last
+    fn bounds(&self, u: usize) -> (usize, usize) {
+        assert!(u < self.nodes, "EliasFanoGraph: node {} out of bounds", u);
+        let mut offsets = self.offsets.iter_from(u);
+        let start = offsets.next().unwrap() as usize;
+        (start, offsets.next().unwrap() as usize)
+    }
+}
+
+impl Collection for EliasFanoGraph {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.nodes
+    }
+}
+
+impl SpaceUsage for EliasFanoGraph {
+    fn total_bytes(&self) -> usize {
+        self.edges.total_bytes() + self.offsets.total_bytes()
+    }
+}
+
+impl Serialize for EliasFanoGraph {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.nodes)?;
+        self.edges.serialize(w)?;
+        self.offsets.serialize(w)
+    }
+}
+
+impl Deserialize for EliasFanoGraph {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<EliasFanoGraph> {
+        let nodes = read_uint(r)?;
+        let edges: EliasFano = Deserialize::deserialize(r)?;
+        let offsets: EliasFano = Deserialize::deserialize(r)?;
+        if offsets.len() != nodes + 1 || offsets.get(0) != 0 || offsets.get(nodes) != edges.len() as u64 {
+            return Err(invalid_data("EliasFanoGraph offsets inconsistent with its edges"));
+        }
+        if edges.len() > 0 && edges.get(edges.len() - 1) >= (nodes * nodes) as u64 {
+            return Err(invalid_data("EliasFanoGraph edge out of bounds"));
+        }
+        Ok(EliasFanoGraph { nodes, edges, offsets })
+    }
+}
+
+/// An iterator over the out-neighbors of a node of an `EliasFanoGraph`
+pub struct Neighbors<'a> {
+    iter: elias_fano::Iter<'a>,
+    /// the code of the edge from the node to the first
+    base: u64,
+    /// the number of neighbors left
+    left: usize,
+}
+
+impl<'a> Iterator for Neighbors<'a> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        self.iter.next().map(|x| (x - self.base) as usize)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl<'a> ExactSizeIterator for Neighbors<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::EliasFanoGraph;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn queries_are_correct(v: Vec<(u8, u8)>, nodes: u8) -> bool {
+        let nodes = nodes as usize % 30 + 1;
+        let mut edges: Vec<(usize, usize)> = v.iter().map(|&(u, w)| (u as usize % nodes, w as usize % nodes)).collect();
+        let graph = EliasFanoGraph::new(nodes, edges.as_slice());
+        edges.sort();
+        edges.dedup();
+        graph.len() == nodes && graph.edges() == edges.len() && (0..nodes).all(|u| {
+            let expected: Vec<usize> = edges.iter().filter(|e| e.0 == u).map(|e| e.1).collect();
+            graph.degree(u) == expected.len()
+                && graph.neighbors(u).collect::<Vec<usize>>() == expected
+                && (0..nodes).all(|w| graph.has_edge(u, w) == expected.contains(&w))
+        })
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<(u8, u8)>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let edges: Vec<(usize, usize)> = v.iter().map(|&(u, w)| (u as usize, w as usize)).collect();
+        let graph = EliasFanoGraph::new(256, edges.as_slice());
+        let read: EliasFanoGraph = round_trip(&graph);
+        read.edges() == graph.edges() && edges.iter().all(|&(u, w)| read.has_edge(u, w))
+    }
+
+    #[test]
+    fn test_graph() {
+        let graph = EliasFanoGraph::new(5, &[(0, 1), (0, 4), (2, 0), (4, 4), (0, 1)]);
+        assert_eq!(graph.edges(), 4);
+        assert_eq!(graph.degree(0), 2);
+        assert_eq!(graph.degree(1), 0);
+        assert_eq!(graph.neighbors(0).collect::<Vec<usize>>(), vec!(1, 4));
+        assert!(graph.has_edge(2, 0) && !graph.has_edge(2, 1) && !graph.has_edge(1, 0));
+        assert!(graph.has_edge(4, 4));
+    }
+}
@@ -0,0 +1,8 @@
+//! Static directed graphs
+//
+// The nodes of a graph with `n` nodes are numbered from zero, and the
+// out-neighbors of each are kept in increasing order.
+
+pub mod elias_fano;
+pub mod webgraph;
+pub mod sequence;
@@ -0,0 +1,144 @@
+//! Graphs answering both out- and in-neighbor queries
+//
+// The adjacency lists concatenated in order of source node form a
+// sequence of targets, kept in a wavelet tree with the lengths of the
+// lists in unary, which is a `BinaryRelation` between sources and
+// targets. The out-neighbors of a node are the targets in its range of
+// the sequence, and its in-neighbors the sources of the ranges holding
+// its occurrences, so the transposed graph needs no storage of its
+// own.
+//
+// See Claude and Navarro 2010.
+
+use std::io::{self, Read, Write};
+use super::super::collection::Collection;
+use super::super::relation::BinaryRelation;
+use super::super::serialize::{Serialize, Deserialize};
+use super::super::space::SpaceUsage;
+
+/// A static directed graph supporting out- and in-neighbor queries
+pub struct SequenceGraph {
+    relation: BinaryRelation,
+}
+
+impl SequenceGraph {
+    /// The graph on `nodes` nodes with the given edges, repeated edges
+    /// being kept once
+    pub fn new(nodes: usize, edges: &[(usize, usize)]) -> SequenceGraph {
+        let pairs: Vec<(usize, u32)> = edges.iter().map(|&(u, v)| {
+            assert!(u < nodes && v < nodes, "SequenceGraph: edge ({}, {}) out of bounds", u, v);
+            (u, v as u32)
+        }).collect();
+        SequenceGraph { relation: BinaryRelation::new(nodes, pairs.as_slice()) }
+    }
+
+    /// The relation between sources and targets of the edges
+    pub fn relation(&self) -> &BinaryRelation {
+        &self.relation
+    }
+
+    /// The number of edges
+    pub fn edges(&self) -> usize {
+        self.relation.len()
+    }
+
+    /// The number of out-neighbors of `u`
+    pub fn degree(&self, u: usize) -> usize {
+        self.relation.count_labels(u)
+    }
+
+    /// The number of in-neighbors of `v`
+    pub fn in_degree(&self, v: usize) -> usize {
+        self.relation.count_objects(self.target(v))
+    }
+
+    /// The out-neighbors of `u` in increasing order
+    pub fn neighbors(&self, u: usize) -> Vec<usize> {
+        self.relation.labels_of(u).into_iter().map(|v| v as usize).collect()
+    }
+
+    /// The in-neighbors of `v` in increasing order
+    pub fn in_neighbors(&self, v: usize) -> Vec<usize> {
+        self.relation.objects_with(self.target(v))
+    }
+
+    /// The `n`th out-neighbor of `u`, counting from zero
+    pub fn neighbor(&self, u: usize, n: usize) -> Option<usize> {
+        self.relation.select_label(u, n).map(|v| v as usize)
+    }
+
+    /// The `n`th in-neighbor of `v`, counting from zero
+    pub fn in_neighbor(&self, v: usize, n: usize) -> Option<usize> {
+        self.relation.select_object(self.target(v), n)
+    }
+
+    /// Whether there is an edge from `u` to `v`
+    pub fn has_edge(&self, u: usize, v: usize) -> bool {
+        self.relation.related(u, self.target(v))
+    }
+
+    fn target(&self, v: usize) -> u32 {
+        assert!(v < self.len(), "SequenceGraph: node {} out of bounds", v);
+        v as u32
+    }
+}
+
+impl Collection for SequenceGraph {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.relation.objects()
+    }
+}
+
+impl SpaceUsage for SequenceGraph {
+    fn total_bytes(&self) -> usize {
+        self.relation.total_bytes()
+    }
+}
+
+impl Serialize for SequenceGraph {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.relation.serialize(w)
+    }
+}
+
+impl Deserialize for SequenceGraph {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<SequenceGraph> {
+        let relation: BinaryRelation = Deserialize::deserialize(r)?;
+        Ok(SequenceGraph { relation })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SequenceGraph;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn neighbors_are_correct(v: Vec<(u8, u8)>, nodes: u8) -> bool {
+        let nodes = nodes as usize % 30 + 1;
+        let mut edges: Vec<(usize, usize)> = v.iter().map(|&(u, w)| (u as usize % nodes, w as usize % nodes)).collect();
+        let graph = SequenceGraph::new(nodes, edges.as_slice());
+        edges.sort();
+        edges.dedup();
+        graph.len() == nodes && graph.edges() == edges.len() && (0..nodes).all(|u| {
+            let out: Vec<usize> = edges.iter().filter(|e| e.0 == u).map(|e| e.1).collect();
+            let ins: Vec<usize> = edges.iter().filter(|e| e.1 == u).map(|e| e.0).collect();
+            graph.neighbors(u) == out && graph.degree(u) == out.len()
+                && graph.in_neighbors(u) == ins && graph.in_degree(u) == ins.len()
+                && (0..ins.len() + 1).all(|n| graph.in_neighbor(u, n) == ins.get(n).copied())
+                && (0..nodes).all(|w| graph.has_edge(u, w) == out.contains(&w))
+        })
+    }
+
+    #[test]
+    fn test_graph() {
+        let graph = SequenceGraph::new(4, &[(0, 1), (0, 2), (1, 2), (3, 2), (2, 0)]);
+        assert_eq!(graph.neighbors(0), vec!(1, 2));
+        assert_eq!(graph.in_neighbors(2), vec!(0, 1, 3));
+        assert_eq!(graph.in_degree(3), 0);
+        assert_eq!(graph.neighbor(3, 0), Some(2));
+        assert_eq!(graph.in_neighbor(2, 2), Some(3));
+        assert!(graph.has_edge(2, 0) && !graph.has_edge(0, 3));
+    }
+}
@@ -0,0 +1,403 @@
+//! Graphs with adjacency lists coded by reference to earlier ones
+//
+// As in the BV format of WebGraph, the list of each node is coded
+// either on its own or by reference to the list of one of the
+// `window` nodes before it, whichever shares the most neighbors. A
+// reference is followed by a copy list telling which neighbors of the
+// referenced node are shared, as alternating runs of copied and
+// skipped neighbors, the first possibly empty and the last implied.
+// The neighbors left over, the residuals, are coded as gaps, the first
+// relative to the node itself. All numbers are in Elias gamma code.
+//
+// A list is decoded after the list it references, so chains of
+// references are bounded by `max_ref` to bound random access. Scanning
+// the lists in order instead keeps the last `window` lists decoded,
+// decoding each list once. The bit offset of each list is kept with
+// Elias-Fano.
+//
+// See Boldi and Vigna 2004.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use super::super::bits::{BitReader, read_gamma, write_gamma, gamma_len};
+use super::super::build::Builder;
+use super::super::collection::Collection;
+use super::super::dictionary::Access;
+use super::super::elias_fano::EliasFano;
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::super::space::{SpaceUsage, vec_bytes};
+
+/// The number of earlier lists `BvGraph::new` may reference
+pub const DEFAULT_WINDOW: usize = 7;
+
+/// The longest chain of references `BvGraph::new` makes
+pub const DEFAULT_MAX_REF: usize = 3;
+
+/// A static directed graph with adjacency lists compressed by
+/// referencing similar earlier lists
+pub struct BvGraph {
+    nodes: usize,
+    edges: usize,
+    window: usize,
+    max_ref: usize,
+    /// the coded lists
+    bits: Vec<u64>,
+    /// the bit offset of the list of each node, and the number of bits
+    offsets: EliasFano,
+}
+
+/// Map an integer to a natural number, alternating signs
+fn zigzag(x: i64) -> u64 {
+    if x < 0 { (-x as u64) * 2 - 1 } else { x as u64 * 2 }
+}
+
+/// The inverse of `zigzag`
+fn unzigzag(x: u64) -> i64 {
+    if x % 2 == 1 { -(x.div_ceil(2) as i64) } else { (x / 2) as i64 }
+}
+
+/// Push `x` in gamma code, adding its length to `size`
+fn put<T, B: Builder<bool, T>>(bits: &mut B, size: &mut usize, x: u64) {
+    write_gamma(bits, x + 1);
+    *size += gamma_len(x + 1);
+}
+
+/// Read a number pushed by `put`
+fn get(reader: &mut BitReader) -> usize {
+    read_gamma(reader) as usize - 1
+}
+
+impl BvGraph {
+    /// The graph on `nodes` nodes with the given edges, repeated edges
+    /// being kept once
+    pub fn new(nodes: usize, edges: &[(usize, usize)]) -> BvGraph {
+        BvGraph::with_params(nodes, edges, DEFAULT_WINDOW, DEFAULT_MAX_REF)
+    }
+
+    /// The graph on `nodes` nodes with the given edges, each list
+    /// referencing one of the `window` before it, in chains of at most
+    /// `max_ref` references
+    pub fn with_params(nodes: usize, edges: &[(usize, usize)], window: usize, max_ref: usize) -> BvGraph {
+        use super::super::build::{BitBuilder, VecBuilder};
+        for &(u, v) in edges.iter() {
+            assert!(u < nodes && v < nodes, "BvGraph: edge ({}, {}) out of bounds", u, v);
+        }
+        let mut edges = edges.to_vec();
+        edges.sort();
+        edges.dedup();
+        let mut lists: Vec<Vec<usize>> = (0..nodes).map(|_| Vec::new()).collect();
+        for &(u, v) in edges.iter() {
+            lists[u].push(v);
+        }
+
+        let mut bits = BitBuilder::new(VecBuilder::with_capacity(64));
+        let mut size = 0;
+        let mut offsets = Vec::with_capacity(nodes + 1);
+        // the length of the chain of references from each list
+        let mut depths: Vec<usize> = Vec::with_capacity(nodes);
+        for u in 0..nodes {
+            offsets.push(size as u64);
+            let list = lists[u].as_slice();
+            put(&mut bits, &mut size, list.len() as u64);
+            if list.is_empty() {
+                depths.push(0);
+                continue;
+            }
+            // the reference sharing the most neighbors, if any
+            let mut best = (0, 0);
+            for r in 1..if window < u { window } else { u } + 1 {
+                if depths[u - r] >= max_ref {
+                    continue;
+                }
+                let shared = lists[u - r].iter().filter(|v| list.binary_search(*v).is_ok()).count();
+                if shared > best.1 {
+                    best = (r, shared);
+                }
+            }
+            let r = best.0;
+            put(&mut bits, &mut size, r as u64);
+            let reference: &[usize] = if r > 0 { lists[u - r].as_slice() } else { &[] };
+            if r > 0 {
+                depths.push(depths[u - r] + 1);
+                // runs of copied and skipped neighbors of the reference
+                let mut runs = vec!(0usize);
+                let mut copying = true;
+                for v in reference.iter() {
+                    let copied = list.binary_search(v).is_ok();
+                    if copied != copying {
+                        runs.push(0);
+                        copying = copied;
+                    }
+                    *runs.last_mut().unwrap() += 1;
+                }
+                put(&mut bits, &mut size, runs.len() as u64 - 1);
+                for (i, run) in runs[..runs.len() - 1].iter().enumerate() {
+                    // only the first run may be empty
+                    put(&mut bits, &mut size, (if i == 0 { *run } else { *run - 1 }) as u64);
+                }
+            } else {
+                depths.push(0);
+            }
+            let mut prev = None;
+            for v in list.iter().filter(|v| reference.binary_search(*v).is_err()) {
+                let x = match prev {
+                    None => zigzag(*v as i64 - u as i64),
+                    Some(p) => (*v - p - 1) as u64,
+                };
+                put(&mut bits, &mut size, x);
+                prev = Some(*v);
+            }
+        }
+        offsets.push(size as u64);
+        let (mut bits, _) = bits.finish();
+        bits.shrink_to_fit();
+        BvGraph {
+            nodes,
+            edges: edges.len(),
+            window,
+            max_ref,
+            bits,
+            offsets: EliasFano::from_slice(offsets.as_slice()),
+        }
+    }
+
+    /// The number of edges
+    pub fn edges(&self) -> usize {
+        self.edges
+    }
+
+    /// The number of earlier lists a list may reference
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// The longest chain of references
+    pub fn max_ref(&self) -> usize {
+        self.max_ref
+    }
+
+    /// The number of out-neighbors of `u`
+    pub fn degree(&self, u: usize) -> usize {
+        let (_, degree, _) = self.header(u);
+        degree
+    }
+
+    /// The out-neighbors of `u` in increasing order, decoding the
+    /// lists it references in turn
+    pub fn neighbors(&self, u: usize) -> Vec<usize> {
+        let (mut reader, degree, r) = self.header(u);
+        match r {
+            0 => self.decode(&mut reader, u, degree, &[]),
+            _ => self.decode(&mut reader, u, degree, self.neighbors(u - r).as_slice()),
+        }
+    }
+
+    /// Whether there is an edge from `u` to `v`
+    pub fn has_edge(&self, u: usize, v: usize) -> bool {
+        assert!(v < self.nodes, "BvGraph: node {} out of bounds", v);
+        self.neighbors(u).binary_search(&v).is_ok()
+    }
+
+    /// An iterator over the out-neighbors of each node in order,
+    /// decoding each list once
+    pub fn lists<'a>(&'a self) -> Lists<'a> {
+        Lists { graph: self, u: 0, window: VecDeque::with_capacity(self.window + 1) }
+    }
+
+    /// A reader after the degree and reference of the list of `u`,
+    /// and these
+    fn header<'a>(&'a self, u: usize) -> (BitReader<'a>, usize, usize) {
+        assert!(u < self.nodes, "BvGraph: node {} out of bounds", u);
+        let mut reader = BitReader::at(self.bits.as_slice(), self.offsets.get(u) as usize);
+        let degree = get(&mut reader);
+        let r = if degree > 0 { get(&mut reader) } else { 0 };
+        (reader, degree, r)
+    }
+
+    /// Decode the rest of the list of `u`, given its degree and the
+    /// list it references
+    fn decode(&self, reader: &mut BitReader, u: usize, degree: usize, reference: &[usize]) -> Vec<usize> {
+        let mut copied = Vec::new();
+        if !reference.is_empty() {
+            let runs = get(reader) + 1;
+            let mut pos = 0;
+            let mut copying = true;
+            for i in 0..runs - 1 {
+                let run = get(reader) + if i == 0 { 0 } else { 1 };
+                if copying {
+                    copied.extend_from_slice(&reference[pos..pos + run]);
+                }
+                pos += run;
+                copying = !copying;
+            }
+            if copying {
+                copied.extend_from_slice(&reference[pos..]);
+            }
+        }
+        let mut residuals = Vec::with_capacity(degree - copied.len());
+        for i in 0..degree - copied.len() {
+            let v = match i {
+                0 => (u as i64 + unzigzag(get(reader) as u64)) as usize,
+                _ => residuals[i - 1] + get(reader) + 1,
+            };
+            residuals.push(v);
+        }
+        // merge the copied neighbors and the residuals
+        let mut list = Vec::with_capacity(degree);
+        let (mut i, mut j) = (0, 0);
+        while i < copied.len() || j < residuals.len() {
+            if j == residuals.len() || (i < copied.len() && copied[i] < residuals[j]) {
+                list.push(copied[i]);
+                i += 1;
+            } else {
+                list.push(residuals[j]);
+                j += 1;
+            }
+        }
+        list
+    }
+}
+
+impl Collection for BvGraph {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.nodes
+    }
+}
+
+impl SpaceUsage for BvGraph {
+    fn total_bytes(&self) -> usize {
+        vec_bytes(&self.bits) + self.offsets.total_bytes()
+    }
+}
+
+impl Serialize for BvGraph {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.nodes)?;
+        write_uint(w, self.edges)?;
+        write_uint(w, self.window)?;
+        write_uint(w, self.max_ref)?;
+        self.bits.serialize(w)?;
+        self.offsets.serialize(w)
+    }
+}
+
+impl Deserialize for BvGraph {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BvGraph> {
+        let nodes = read_uint(r)?;
+        let edges = read_uint(r)?;
+        let window = read_uint(r)?;
+        let max_ref = read_uint(r)?;
+        let bits: Vec<u64> = Deserialize::deserialize(r)?;
+        let offsets: EliasFano = Deserialize::deserialize(r)?;
+        if offsets.len() != nodes + 1 || offsets.get(nodes) > 64 * bits.len() as u64 {
+            return Err(invalid_data("BvGraph offsets inconsistent with its lists"));
+        }
+        Ok(BvGraph {
+            nodes,
+            edges,
+            window,
+            max_ref,
+            bits,
+            offsets,
+        })
+    }
+}
+
+/// An iterator over the adjacency lists of a `BvGraph`, keeping those
+/// in the window decoded
+pub struct Lists<'a> {
+    graph: &'a BvGraph,
+    /// the next node
+    u: usize,
+    /// the lists of the nodes in the window before it, oldest first
+    window: VecDeque<Vec<usize>>,
+}
+
+impl<'a> Iterator for Lists<'a> {
+    type Item = Vec<usize>;
+    fn next(&mut self) -> Option<Vec<usize>> {
+        if self.u == self.graph.nodes {
+            return None;
+        }
+        let u = self.u;
+        let (mut reader, degree, r) = self.graph.header(u);
+        let list = match r {
+            0 => self.graph.decode(&mut reader, u, degree, &[]),
+            _ => {
+                let reference = &self.window[self.window.len() - r];
+                self.graph.decode(&mut reader, u, degree, reference.as_slice())
+            },
+        };
+        self.window.push_back(list.clone());
+        if self.window.len() > self.graph.window {
+            self.window.pop_front();
+        }
+        self.u += 1;
+        Some(list)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.graph.nodes - self.u;
+        (left, Some(left))
+    }
+}
+
+impl<'a> ExactSizeIterator for Lists<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::BvGraph;
+    use super::super::super::collection::Collection;
+    use super::super::super::space::SpaceUsage;
+
+    /// Lists shaped by `v`, similar lists following one another
+    fn edges(v: &[(u8, u8)], nodes: usize) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        for &(u, w) in v.iter() {
+            let (u, w) = (u as usize % nodes, w as usize % nodes);
+            edges.push((u, w));
+            if u + 1 < nodes {
+                edges.push((u + 1, w));
+            }
+        }
+        edges
+    }
+
+    #[quickcheck]
+    fn lists_are_correct(v: Vec<(u8, u8)>, nodes: u8, window: u8, max_ref: u8) -> bool {
+        let nodes = nodes as usize % 30 + 1;
+        let mut es = edges(&v, nodes);
+        let graph = BvGraph::with_params(nodes, es.as_slice(), window as usize % 4, max_ref as usize % 3);
+        es.sort();
+        es.dedup();
+        let expected: Vec<Vec<usize>> = (0..nodes)
+            .map(|u| es.iter().filter(|e| e.0 == u).map(|e| e.1).collect())
+            .collect();
+        graph.len() == nodes && graph.edges() == es.len()
+            && graph.lists().collect::<Vec<Vec<usize>>>() == expected
+            && (0..nodes).all(|u| graph.neighbors(u) == expected[u] && graph.degree(u) == expected[u].len())
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<(u8, u8)>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let graph = BvGraph::new(256, edges(&v, 256).as_slice());
+        let read: BvGraph = round_trip(&graph);
+        read.lists().zip(graph.lists()).all(|(a, b)| a == b)
+    }
+
+    #[test]
+    fn test_copy_lists() {
+        // each node links to the same hundred nodes but its own
+        let mut edges = Vec::new();
+        for u in 0..100usize {
+            edges.extend((0..100usize).filter(|w| *w != u).map(|w| (u, w)));
+        }
+        let graph = BvGraph::new(100, edges.as_slice());
+        assert_eq!(graph.neighbors(50).len(), 99);
+        assert!(graph.has_edge(50, 51) && !graph.has_edge(50, 50));
+        // well under a bit per edge when copied
+        assert!(graph.total_bytes() * 8 < edges.len());
+    }
+}
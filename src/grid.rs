@@ -0,0 +1,115 @@
+//! A static set of points in the plane supporting range queries
+//
+// The points are sorted by x coordinate and their y coordinates,
+// reduced to their ranks among the distinct y coordinates, stored in
+// a wavelet tree. An orthogonal range query then becomes a query for
+// the positions of a range of the sequence holding values in a range,
+// answered by descending the tree.
+
+use std::cmp::{max, Ordering};
+use super::bits::bit_width;
+use super::rank9::Rank9;
+use super::wavelet::FlatWavelet;
+
+/// A static set of points supporting orthogonal range counting and
+/// reporting in time logarithmic in the number of distinct y
+/// coordinates
+pub struct Grid {
+    /// the x coordinates of the points in increasing order
+    xs: Vec<u64>,
+    /// the distinct y coordinates in increasing order
+    ys: Vec<u64>,
+    /// the rank among `ys` of the y coordinate of each point, in order
+    /// of x coordinate
+    wavelet: FlatWavelet<Rank9, u64>,
+}
+
+/// The number of elements of a sorted slice less than `x`
+fn lower_bound(v: &[u64], x: u64) -> usize {
+    match v.binary_search_by(|y| if *y < x { Ordering::Less } else { Ordering::Greater }) {
+        Ok(i) => i,
+        Err(i) => i,
+    }
+}
+
+impl Grid {
+    /// Index a set of points
+    pub fn new(mut points: Vec<(u64, u64)>) -> Grid {
+        points.sort();
+        let mut ys: Vec<u64> = points.iter().map(|&(_, y)| y).collect();
+        ys.sort();
+        ys.dedup();
+        let codes: Vec<u64> = points.iter().map(|&(_, y)| lower_bound(ys.as_slice(), y) as u64).collect();
+        let width = max(bit_width(ys.len().saturating_sub(1) as u64), 1);
+        Grid {
+            xs: points.iter().map(|&(x, _)| x).collect(),
+            wavelet: FlatWavelet::from_slice_with_width(codes.as_slice(), width),
+            ys,
+        }
+    }
+
+    /// The number of points
+    pub fn len(&self) -> usize {
+        self.xs.len()
+    }
+
+    /// Whether there are no points
+    pub fn is_empty(&self) -> bool {
+        self.xs.is_empty()
+    }
+
+    /// The positions of the points with `x1 <= x < x2` and the codes
+    /// of `y1 <= y < y2`
+    fn query_range(&self, x1: u64, x2: u64, y1: u64, y2: u64) -> (usize, usize, u64, u64) {
+        let i = lower_bound(self.xs.as_slice(), x1);
+        let j = max(i, lower_bound(self.xs.as_slice(), x2));
+        let lo = lower_bound(self.ys.as_slice(), y1) as u64;
+        let hi = lower_bound(self.ys.as_slice(), y2) as u64;
+        (i, j, lo, hi)
+    }
+
+    /// The number of points `(x, y)` with `x1 <= x < x2` and
+    /// `y1 <= y < y2`
+    pub fn count(&self, x1: u64, x2: u64, y1: u64, y2: u64) -> usize {
+        let (i, j, lo, hi) = self.query_range(x1, x2, y1, y2);
+        self.wavelet.range_count(i, j, lo, hi)
+    }
+
+    /// The points `(x, y)` with `x1 <= x < x2` and `y1 <= y < y2` in
+    /// increasing order of x coordinate
+    pub fn report(&self, x1: u64, x2: u64, y1: u64, y2: u64) -> Vec<(u64, u64)> {
+        let (i, j, lo, hi) = self.query_range(x1, x2, y1, y2);
+        self.wavelet.range_report(i, j, lo, hi).into_iter()
+            .map(|(pos, code)| (self.xs[pos], self.ys[code as usize]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::Grid;
+
+    #[quickcheck]
+    fn count_is_correct(points: Vec<(u8, u8)>, x1: u8, x2: u8, y1: u8, y2: u8) -> TestResult {
+        let points: Vec<(u64, u64)> = points.into_iter().map(|(x, y)| (x as u64, y as u64)).collect();
+        let (x1, x2, y1, y2) = (x1 as u64, x2 as u64, y1 as u64, y2 as u64);
+        let grid = Grid::new(points.clone());
+        let inside = |p: &(u64, u64)| x1 <= p.0 && p.0 < x2 && y1 <= p.1 && p.1 < y2;
+        let mut expected: Vec<(u64, u64)> = points.iter().filter(|p| inside(p)).copied().collect();
+        expected.sort();
+        let mut reported = grid.report(x1, x2, y1, y2);
+        reported.sort();
+        TestResult::from_bool(grid.count(x1, x2, y1, y2) == expected.len() && reported == expected)
+    }
+
+    #[test]
+    fn test_grid() {
+        let grid = Grid::new(vec!((1, 5), (2, 1), (3, 5), (4, 9), (4, 2), (10, 5)));
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.count(0, 100, 0, 100), 6);
+        assert_eq!(grid.count(2, 5, 2, 6), 2);
+        assert_eq!(grid.report(2, 5, 2, 6), vec!((3, 5), (4, 2)));
+        assert_eq!(grid.count(5, 10, 0, 100), 0);
+    }
+}
@@ -0,0 +1,353 @@
+//! A packed array of fixed-width integers
+//
+// The `i`th element occupies bits `[i*width, (i+1)*width)` of the
+// buffer, least significant bit first, in the same layout as
+// `BitVector`. Elements may straddle a word boundary.
+
+use std::iter::repeat_n;
+use super::dictionary::Access;
+use super::collection::Collection;
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+use super::utils::div_ceil;
+use super::bits::bit_width;
+use std::mem::size_of;
+use std::io::{self, Read, Write};
+
+pub use crate::int_vector::build::{Builder, WideningBuilder};
+
+/// A mask of the `width` least significant bits
+pub fn low_mask(width: usize) -> u64 {
+    match width {
+        64 => u64::MAX,
+        _ => (1 << width) - 1,
+    }
+}
+
+/// Read the `width`-bit field starting at bit `offset` of `words`
+pub fn read_field(words: &[u64], offset: usize, width: usize) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+    let word = offset / 64;
+    let bit = offset % 64;
+    let low = words[word] >> bit;
+    let x = if bit + width > 64 {
+        low | (words[word + 1] << (64 - bit))
+    } else {
+        low
+    };
+    x & low_mask(width)
+}
+
+/// Write `x` to the `width`-bit field starting at bit `offset` of
+/// `words`
+pub fn write_field(words: &mut [u64], offset: usize, width: usize, x: u64) {
+    if width == 0 {
+        return;
+    }
+    let word = offset / 64;
+    let bit = offset % 64;
+    let mask = low_mask(width);
+    words[word] = (words[word] & !(mask << bit)) | ((x & mask) << bit);
+    if bit + width > 64 {
+        let spill = 64 - bit;
+        words[word + 1] = (words[word + 1] & !(mask >> spill)) | ((x & mask) >> spill);
+    }
+}
+
+/// A packed array of `width`-bit integers
+#[derive(Debug, Clone)]
+pub struct IntVector {
+    /// bits per element
+    width: usize,
+    /// number of elements
+    len: usize,
+    /// the packed elements
+    buffer: Vec<u64>,
+}
+
+impl IntVector {
+    /// A vector of `len` zeros of `width` bits
+    pub fn new(width: usize, len: usize) -> IntVector {
+        assert!(width <= 64, "IntVector: width {} exceeds 64 bits", width);
+        IntVector {
+            width,
+            len,
+            buffer: repeat_n(0, div_ceil(width * len, 64)).collect(),
+        }
+    }
+
+    /// The number of bits per element
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Set the `n`th element, which must fit in `width` bits
+    pub fn set(&mut self, n: usize, x: u64) {
+        assert!(n < self.len, "IntVector: index {} out of bounds", n);
+        assert!(x & !low_mask(self.width) == 0, "IntVector: {} exceeds width of {} bits", x, self.width);
+        write_field(self.buffer.as_mut_slice(), n * self.width, self.width, x);
+    }
+
+    /// An iterator over the elements
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { vec: self, pos: 0 }
+    }
+
+    /// A copy of the vector with elements of `width` bits, which must
+    /// be wide enough for every element
+    pub fn repack(&self, width: usize) -> IntVector {
+        let mut vec = IntVector::new(width, self.len);
+        for (i, x) in self.iter().enumerate() {
+            vec.set(i, x);
+        }
+        vec
+    }
+
+    /// The number of bits needed by the largest element
+    pub fn max_width(&self) -> usize {
+        self.iter().map(bit_width).max().unwrap_or(0)
+    }
+}
+
+impl Collection for IntVector {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for IntVector {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "IntVector: index {} out of bounds", n);
+        read_field(self.buffer.as_slice(), n * self.width, self.width)
+    }
+}
+
+impl SpaceUsage for IntVector {
+    fn total_bytes(&self) -> usize {
+        2 * size_of::<usize>() + vec_bytes(&self.buffer)
+    }
+}
+
+impl Serialize for IntVector {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.width)?;
+        write_uint(w, self.len)?;
+        self.buffer.serialize(w)
+    }
+}
+
+impl Deserialize for IntVector {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<IntVector> {
+        let width = read_uint(r)?;
+        let len = read_uint(r)?;
+        let buffer: Vec<u64> = Deserialize::deserialize(r)?;
+        if width > 64 {
+            return Err(invalid_data("IntVector width exceeds 64 bits"));
+        }
+        if width.checked_mul(len).map(|bits| div_ceil(bits, 64) > buffer.len()).unwrap_or(true) {
+            return Err(invalid_data("IntVector length exceeds its buffer"));
+        }
+        Ok(IntVector { width, len, buffer })
+    }
+}
+
+/// An iterator over the elements of an `IntVector`
+pub struct Iter<'a> {
+    vec: &'a IntVector,
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.pos < self.vec.len {
+            self.pos += 1;
+            Some(self.vec.get(self.pos - 1))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.vec.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+mod build {
+    use super::super::build;
+    use std::cmp::{min, max};
+    use super::super::utils::div_ceil;
+    use super::super::bits::bit_width;
+    use super::{IntVector, low_mask, write_field};
+
+    /// Build an `IntVector` from its elements
+    #[derive(Debug)]
+    pub struct Builder {
+        vec: IntVector,
+    }
+
+    impl Builder {
+        /// Build a vector of `width`-bit elements with capacity for
+        /// `cap` elements
+        pub fn with_capacity(width: usize, cap: usize) -> Builder {
+            assert!(width <= 64, "IntVector: width {} exceeds 64 bits", width);
+            Builder {
+                vec: IntVector {
+                    width,
+                    len: 0,
+                    buffer: Vec::with_capacity(div_ceil(width * cap, 64)),
+                },
+            }
+        }
+    }
+
+    /// Append an element to a vector
+    fn append(vec: &mut IntVector, x: u64) {
+        let width = vec.width;
+        assert!(x & !low_mask(width) == 0, "IntVector: {} exceeds width of {} bits", x, width);
+        let offset = vec.len * width;
+        while vec.buffer.len() < div_ceil(offset + width, 64) {
+            vec.buffer.push(0);
+        }
+        write_field(vec.buffer.as_mut_slice(), offset, width, x);
+        vec.len += 1;
+    }
+
+    impl build::Builder<u64, IntVector> for Builder {
+        fn push(&mut self, x: u64) {
+            append(&mut self.vec, x);
+        }
+
+        fn finish(self) -> IntVector {
+            self.vec
+        }
+    }
+
+    /// Build an `IntVector` from elements of unknown magnitude
+    ///
+    /// The builder starts at a narrow width and repacks the elements
+    /// pushed so far whenever an element doesn't fit, at least doubling
+    /// the width so that an element is repacked at most six times.
+    /// Optionally, the vector is repacked once more when finished to
+    /// the width of its largest element.
+    #[derive(Debug)]
+    pub struct WideningBuilder {
+        vec: IntVector,
+        /// the number of bits needed by the largest element
+        max_width: usize,
+        shrink: bool,
+    }
+
+    impl WideningBuilder {
+        /// Build a vector starting with `width`-bit elements
+        pub fn new(width: usize) -> WideningBuilder {
+            WideningBuilder {
+                vec: Builder::with_capacity(width, 0).vec,
+                max_width: 0,
+                shrink: false,
+            }
+        }
+
+        /// Repack the finished vector to the width of its largest
+        /// element
+        pub fn shrink(mut self) -> WideningBuilder {
+            self.shrink = true;
+            self
+        }
+    }
+
+    impl build::Builder<u64, IntVector> for WideningBuilder {
+        fn push(&mut self, x: u64) {
+            let width = bit_width(x);
+            if width > self.vec.width {
+                let wider = min(max(width, 2 * self.vec.width), 64);
+                self.vec = self.vec.repack(wider);
+            }
+            if width > self.max_width {
+                self.max_width = width;
+            }
+            append(&mut self.vec, x);
+        }
+
+        fn finish(self) -> IntVector {
+            match self.shrink && self.max_width < self.vec.width {
+                true => self.vec.repack(self.max_width),
+                false => self.vec,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::IntVector;
+    use super::super::dictionary::Access;
+    use super::super::build::Builder;
+
+    #[quickcheck]
+    fn builder_round_trips(width: usize, v: Vec<u64>) -> bool {
+        let width = width % 65;
+        let v: Vec<u64> = v.into_iter().map(|x| x & super::low_mask(width)).collect();
+        let vec = super::Builder::with_capacity(width, 4).from_iter(v.clone().into_iter());
+        vec.iter().collect::<Vec<u64>>() == v
+            && v.iter().enumerate().all(|(i, x)| vec.get(i) == *x)
+    }
+
+    #[quickcheck]
+    fn set_is_correct(width: usize, v: Vec<u64>, n: usize, x: u64) -> TestResult {
+        let width = width % 65;
+        if n >= v.len() {
+            return TestResult::discard()
+        }
+        let mut v: Vec<u64> = v.into_iter().map(|x| x & super::low_mask(width)).collect();
+        let x = x & super::low_mask(width);
+        let mut vec = IntVector::new(width, v.len());
+        for (i, y) in v.iter().enumerate() {
+            vec.set(i, *y);
+        }
+        vec.set(n, x);
+        v[n] = x;
+        TestResult::from_bool(v.iter().enumerate().all(|(i, y)| vec.get(i) == *y))
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u16>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let vec = super::Builder::with_capacity(16, 4).from_iter(v.iter().map(|x| *x as u64));
+        let vec2 = round_trip(&vec);
+        vec2.iter().collect::<Vec<u64>>() == vec.iter().collect::<Vec<u64>>()
+    }
+
+    #[quickcheck]
+    fn widening_round_trips(v: Vec<u64>, shrink: bool) -> bool {
+        let v: Vec<u64> = v.into_iter().enumerate().map(|(i, x)| x >> (i % 64)).collect();
+        let builder = super::WideningBuilder::new(1);
+        let builder = if shrink { builder.shrink() } else { builder };
+        let vec = builder.from_iter(v.clone().into_iter());
+        vec.iter().collect::<Vec<u64>>() == v
+            && (!shrink || vec.width() == vec.max_width())
+    }
+
+    #[test]
+    fn test_widening() {
+        let vec = super::WideningBuilder::new(2).from_iter(vec!(1, 2, 5, 300).into_iter());
+        assert_eq!(vec.width(), 9);
+        let vec = super::WideningBuilder::new(2).shrink().from_iter(vec!(1, 2, 5, 9).into_iter());
+        assert_eq!(vec.width(), 4);
+    }
+
+    #[test]
+    fn test_straddle() {
+        let mut vec = IntVector::new(7, 20);
+        vec.set(9, 0x7f);
+        assert_eq!(vec.get(8), 0);
+        assert_eq!(vec.get(9), 0x7f);
+        assert_eq!(vec.get(10), 0);
+    }
+}
@@ -0,0 +1,135 @@
+//! Conversions to and from the bitsets of other crates
+//
+// `bit-vec` and `fixedbitset` hold their bits in 32-bit blocks, least
+// significant bit first, so two blocks make up one of our words in the
+// same bit order and a conversion is a single pass over the blocks.
+// Their blocks can't be borrowed as words, as a slice of blocks needn't
+// be aligned as words are, so conversions copy. Every conversion
+// preserves the bits and can't fail. Each is enabled by the feature of
+// the same name as the crate.
+
+#[cfg(feature = "bit-vec")] use bit_vec::BitVec;
+#[cfg(feature = "fixedbitset")] use fixedbitset::FixedBitSet;
+use std::convert::From;
+use super::bit_vector::BitVector;
+use super::collection::Collection;
+use super::int_vector::low_mask;
+use super::rank9::Rank9;
+use super::utils::div_ceil;
+
+/// The words of `bits` bits held in `blocks`
+fn words(blocks: &[u32], bits: usize) -> Vec<u64> {
+    (0..div_ceil(bits, 64)).map(|i| {
+        let lo = blocks[2 * i] as u64;
+        let hi = blocks.get(2 * i + 1).map(|b| *b as u64).unwrap_or(0);
+        lo | (hi << 32)
+    }).collect()
+}
+
+/// The blocks of the first `bits` bits of `words`, clearing those past
+/// them as the other crates expect
+fn blocks(words: &[u64], bits: usize) -> Vec<u32> {
+    (0..div_ceil(bits, 32)).map(|i| {
+        let block = (words[i / 2] >> (32 * (i % 2))) as u32;
+        match i + 1 == div_ceil(bits, 32) && !bits.is_multiple_of(32) {
+            true => block & low_mask(bits % 32) as u32,
+            false => block,
+        }
+    }).collect()
+}
+
+#[cfg(feature = "bit-vec")]
+impl<'a> From<&'a BitVec> for BitVector {
+    fn from(bv: &'a BitVec) -> BitVector {
+        // `words` holds exactly the bits
+        BitVector::from_vec(&words(bv.storage(), bv.len()), bv.len()).unwrap()
+    }
+}
+
+#[cfg(feature = "bit-vec")]
+impl<'a> From<&'a BitVec> for Rank9 {
+    fn from(bv: &'a BitVec) -> Rank9 {
+        // `words` holds exactly the bits
+        Rank9::from_vec(&words(bv.storage(), bv.len()), bv.len()).unwrap()
+    }
+}
+
+#[cfg(feature = "bit-vec")]
+impl<'a> From<&'a BitVector> for BitVec {
+    fn from(bv: &'a BitVector) -> BitVec {
+        let mut out = BitVec::from_elem(bv.len(), false);
+        // the storage holds exactly the blocks of the bits
+        let storage = unsafe { out.storage_mut() };
+        for (i, block) in blocks(bv.words(), bv.len()).into_iter().enumerate() {
+            storage[i] = block;
+        }
+        out
+    }
+}
+
+#[cfg(feature = "fixedbitset")]
+impl<'a> From<&'a FixedBitSet> for BitVector {
+    fn from(set: &'a FixedBitSet) -> BitVector {
+        // `words` holds exactly the bits
+        BitVector::from_vec(&words(set.as_slice(), set.len()), set.len()).unwrap()
+    }
+}
+
+#[cfg(feature = "fixedbitset")]
+impl<'a> From<&'a FixedBitSet> for Rank9 {
+    fn from(set: &'a FixedBitSet) -> Rank9 {
+        // `words` holds exactly the bits
+        Rank9::from_vec(&words(set.as_slice(), set.len()), set.len()).unwrap()
+    }
+}
+
+#[cfg(feature = "fixedbitset")]
+impl<'a> From<&'a BitVector> for FixedBitSet {
+    fn from(bv: &'a BitVector) -> FixedBitSet {
+        FixedBitSet::with_capacity_and_blocks(bv.len(), blocks(bv.words(), bv.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::From;
+    use super::super::bit_vector::BitVector;
+    use super::super::dictionary::{Access, BitRank};
+    use super::super::rank9::Rank9;
+
+    #[cfg(feature = "bit-vec")]
+    #[quickcheck]
+    fn bit_vec_round_trips(bits: Vec<bool>) -> bool {
+        use bit_vec::BitVec;
+        let bv: BitVec = bits.iter().copied().collect();
+        let ours = BitVector::from(&bv);
+        let rank9 = Rank9::from(&bv);
+        let back = BitVec::from(&ours);
+        back == bv && (0..bits.len()).all(|i| ours.get(i) == bits[i])
+            && rank9.rank1(bits.len()) == bits.iter().filter(|b| **b).count()
+    }
+
+    #[cfg(feature = "fixedbitset")]
+    #[quickcheck]
+    fn fixedbitset_round_trips(bits: Vec<bool>) -> bool {
+        use fixedbitset::FixedBitSet;
+        let mut set = FixedBitSet::with_capacity(bits.len());
+        for (i, b) in bits.iter().enumerate() {
+            set.set(i, *b);
+        }
+        let ours = BitVector::from(&set);
+        let rank9 = Rank9::from(&set);
+        let back = FixedBitSet::from(&ours);
+        back == set && (0..bits.len()).all(|i| ours.get(i) == bits[i])
+            && rank9.rank1(bits.len()) == bits.iter().filter(|b| **b).count()
+    }
+
+    #[test]
+    fn test_blocks() {
+        // bits past the length are cleared in the blocks
+        let words = vec!(0xffff_ffff_ffff_ffffu64, 0b101);
+        assert_eq!(super::blocks(words.as_slice(), 67), vec!(0xffff_ffff, 0xffff_ffff, 0b101));
+        assert_eq!(super::blocks(words.as_slice(), 40), vec!(0xffff_ffff, 0xff));
+        assert_eq!(super::words(&[1, 2, 3], 65), vec!(1 | (2 << 32), 3));
+    }
+}
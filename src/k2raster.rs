@@ -0,0 +1,403 @@
+//! Compressed integer grids
+//
+// A k2-raster splits a grid like a `K2Tree`, the grid being padded to
+// a square side that is a power of two with its smallest value. Each
+// quadrant records its largest value as its difference from that of
+// its parent, and a quadrant holding more than one value is split
+// further, recording its smallest value as its difference from that of
+// its parent. A set bit marks such quadrants, laid out level by level
+// as in a `K2Tree`, so that the children of the quadrant at `x` start
+// at four times the number of set bits up to and including it. The
+// differences are small where values are clustered, and are kept in
+// directly addressable codes.
+//
+// A query descends only into the quadrants meeting the cells asked
+// about, and stops at those whose extremes answer it, such as a
+// quadrant inside the window holding a single value.
+//
+// See Ladra, Paramá and Silva-Coira 2016.
+
+use std::cmp::{max, min};
+use std::io::{self, Read, Write};
+use super::collection::Collection;
+use super::dac::{self, Dac};
+use super::dictionary::{Access, BitRank};
+use super::rank9::{self, Rank9};
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use super::space::SpaceUsage;
+
+/// The bits per chunk of the differences
+const CHUNK: usize = 4;
+
+/// A static grid of integers
+pub struct K2Raster {
+    rows: usize,
+    cols: usize,
+    /// the number of levels, the padded side being two to its power
+    height: usize,
+    /// the largest and smallest values
+    max: u64,
+    min: u64,
+    /// whether each quadrant but the cells holds more than one value
+    tree: Rank9,
+    /// the largest value of each quadrant, less that of its parent
+    maxes: Dac,
+    /// the smallest value of each quadrant holding more than one,
+    /// less that of its parent
+    mins: Dac,
+}
+
+/// A quadrant of a `K2Raster`
+#[derive(Copy, Clone)]
+struct Quadrant {
+    /// the position of the first of its children
+    block: usize,
+    size: usize,
+    /// its top left cell
+    r: usize,
+    c: usize,
+    max: u64,
+    min: u64,
+}
+
+/// A range of cells, `r1 <= r < r2` and `c1 <= c < c2`
+#[derive(Copy, Clone)]
+struct Window {
+    r1: usize,
+    r2: usize,
+    c1: usize,
+    c2: usize,
+}
+
+impl Quadrant {
+    fn meets(&self, w: &Window) -> bool {
+        w.r1 < w.r2 && w.c1 < w.c2
+            && self.r < w.r2 && w.r1 < self.r + self.size && self.c < w.c2 && w.c1 < self.c + self.size
+    }
+
+    fn inside(&self, w: &Window) -> bool {
+        w.r1 <= self.r && self.r + self.size <= w.r2 && w.c1 <= self.c && self.c + self.size <= w.c2
+    }
+}
+
+/// The per-level bits and differences of a raster being built
+struct Levels {
+    tree: Vec<Vec<bool>>,
+    maxes: Vec<Vec<u64>>,
+    mins: Vec<Vec<u64>>,
+}
+
+/// The smallest and largest of the values of `cell` in the square of
+/// side `size` at `(r0, c0)`
+fn extremes<F: Fn(usize, usize) -> u64>(cell: &F, size: usize, r0: usize, c0: usize) -> (u64, u64) {
+    let (mut lo, mut hi) = (cell(r0, c0), cell(r0, c0));
+    for r in r0..r0 + size {
+        for c in c0..c0 + size {
+            lo = min(lo, cell(r, c));
+            hi = max(hi, cell(r, c));
+        }
+    }
+    (lo, hi)
+}
+
+/// Append the bits and differences of the quadrants of side `size` of
+/// the square at `(r0, c0)`, with the given extremes, to `levels`
+/// from `level`
+#[allow(clippy::too_many_arguments)]
+fn build<F: Fn(usize, usize) -> u64>(cell: &F, level: usize, size: usize, r0: usize, c0: usize,
+                                   hi: u64, lo: u64, levels: &mut Levels) {
+    for i in 0..2 {
+        for j in 0..2 {
+            let (r, c) = (r0 + i * size, c0 + j * size);
+            let (cmin, cmax) = extremes(cell, size, r, c);
+            levels.maxes[level].push(hi - cmax);
+            if size > 1 {
+                levels.tree[level].push(cmin < cmax);
+            }
+            if cmin < cmax {
+                levels.mins[level].push(cmin - lo);
+                build(cell, level + 1, size / 2, r, c, cmax, cmin, levels);
+            }
+        }
+    }
+}
+
+impl K2Raster {
+    /// The grid of `rows` rows and `cols` columns holding `values` in
+    /// row-major order
+    pub fn new(rows: usize, cols: usize, values: &[u64]) -> K2Raster {
+        use super::build::Builder;
+        assert!(values.len() == rows * cols, "K2Raster: {} values for {} cells", values.len(), rows * cols);
+        let mut height = 1;
+        while (1 << height) < max(rows, cols) {
+            height += 1;
+        }
+        let pad = values.iter().copied().min().unwrap_or(0);
+        let cell = |r: usize, c: usize| if r < rows && c < cols { values[r * cols + c] } else { pad };
+        let side = 1 << height;
+        let (lo, hi) = extremes(&cell, side, 0, 0);
+        let mut levels = Levels {
+            tree: (0..height).map(|_| Vec::new()).collect(),
+            maxes: (0..height).map(|_| Vec::new()).collect(),
+            mins: (0..height).map(|_| Vec::new()).collect(),
+        };
+        if lo < hi {
+            build(&cell, 0, side / 2, 0, 0, hi, lo, &mut levels);
+        }
+        let mut tree = rank9::Builder::with_capacity(levels.tree.iter().map(|l| l.len()).sum());
+        for b in levels.tree.iter().flat_map(|l| l.iter()) {
+            tree.push(*b);
+        }
+        let maxes = dac::Builder::new(CHUNK).from_iter(levels.maxes.iter().flat_map(|l| l.iter()).copied());
+        let mins = dac::Builder::new(CHUNK).from_iter(levels.mins.iter().flat_map(|l| l.iter()).copied());
+        K2Raster::from_parts(rows, cols, height, hi, lo, tree.finish(), maxes, mins).unwrap()
+    }
+
+    /// The raster with the given parts, if they are laid out as those
+    /// of a tree of the given height
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(rows: usize, cols: usize, height: usize, hi: u64, lo: u64,
+                  tree: Rank9, maxes: Dac, mins: Dac) -> Option<K2Raster> {
+        if height == 0 || height >= 64 || (1 << height) < max(rows, cols) || lo > hi {
+            return None;
+        }
+        let ones = tree.rank1(tree.len());
+        let slots = match lo < hi {
+            false => 0,
+            true => {
+                // follow the number of quadrants of each level from
+                // the root's children
+                let (mut start, mut size) = (0, 4);
+                for _ in 1..height {
+                    if start + size > tree.len() {
+                        return None;
+                    }
+                    let internal = tree.rank1(start + size) - tree.rank1(start);
+                    start += size;
+                    size = 4 * internal;
+                }
+                if start != tree.len() {
+                    return None;
+                }
+                start + size
+            },
+        };
+        if maxes.len() != slots || mins.len() != ones {
+            return None;
+        }
+        Some(K2Raster {
+            rows,
+            cols,
+            height,
+            max: hi,
+            min: lo,
+            tree,
+            maxes,
+            mins,
+        })
+    }
+
+    /// The number of rows
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of columns
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The value at row `r` and column `c`
+    pub fn get(&self, r: usize, c: usize) -> u64 {
+        assert!(r < self.rows && c < self.cols, "K2Raster: cell ({}, {}) out of bounds", r, c);
+        let mut q = self.root();
+        while q.min < q.max {
+            let size = q.size / 2;
+            q = self.child(&q, r / size % 2, c / size % 2);
+        }
+        q.max
+    }
+
+    /// The largest value of the cells with `r1 <= r < r2` and
+    /// `c1 <= c < c2`, if there are any
+    pub fn window_max(&self, r1: usize, r2: usize, c1: usize, c2: usize) -> Option<u64> {
+        self.extreme(&self.root(), &self.window(r1, r2, c1, c2), true)
+    }
+
+    /// The smallest value of the cells with `r1 <= r < r2` and
+    /// `c1 <= c < c2`, if there are any
+    pub fn window_min(&self, r1: usize, r2: usize, c1: usize, c2: usize) -> Option<u64> {
+        self.extreme(&self.root(), &self.window(r1, r2, c1, c2), false)
+    }
+
+    /// The cells `(r, c)` with `r1 <= r < r2` and `c1 <= c < c2` whose
+    /// values are at least `lo` and at most `hi`, with their values, in
+    /// increasing order
+    pub fn report(&self, r1: usize, r2: usize, c1: usize, c2: usize, lo: u64, hi: u64) -> Vec<(usize, usize, u64)> {
+        let mut out = Vec::new();
+        self.report_from(&self.root(), &self.window(r1, r2, c1, c2), lo, hi, &mut out);
+        out.sort();
+        out
+    }
+
+    /// The cells asked about, clipped to the grid
+    fn window(&self, r1: usize, r2: usize, c1: usize, c2: usize) -> Window {
+        Window { r1, r2: min(r2, self.rows), c1, c2: min(c2, self.cols) }
+    }
+
+    fn root(&self) -> Quadrant {
+        Quadrant { block: 0, size: 1 << self.height, r: 0, c: 0, max: self.max, min: self.min }
+    }
+
+    /// The child of `q` in row `i` and column `j` of its quadrants
+    fn child(&self, q: &Quadrant, i: usize, j: usize) -> Quadrant {
+        let x = q.block + 2 * i + j;
+        let size = q.size / 2;
+        let hi = q.max - self.maxes.get(x);
+        let (lo, block) = match size > 1 && self.tree.get(x) {
+            true => {
+                let n = self.tree.rank1(x);
+                (q.min + self.mins.get(n), 4 * (n + 1))
+            },
+            false => (hi, 0),
+        };
+        Quadrant { block, size, r: q.r + i * size, c: q.c + j * size, max: hi, min: lo }
+    }
+
+    /// The largest or smallest value of the cells of `q` in `w`
+    fn extreme(&self, q: &Quadrant, w: &Window, largest: bool) -> Option<u64> {
+        if !q.meets(w) {
+            return None;
+        }
+        if q.min == q.max || q.inside(w) {
+            return Some(if largest { q.max } else { q.min });
+        }
+        let mut best = None;
+        for i in 0..2 {
+            for j in 0..2 {
+                let x = self.extreme(&self.child(q, i, j), w, largest);
+                best = match (best, x) {
+                    (None, x) => x,
+                    (b, None) => b,
+                    (Some(b), Some(x)) => Some(if largest { max(b, x) } else { min(b, x) }),
+                };
+            }
+        }
+        best
+    }
+
+    /// Report the cells of `q` in `w` with values in `[lo, hi]`
+    fn report_from(&self, q: &Quadrant, w: &Window, lo: u64, hi: u64, out: &mut Vec<(usize, usize, u64)>) {
+        if !q.meets(w) || q.max < lo || q.min > hi {
+            return;
+        }
+        if q.min == q.max {
+            for r in max(q.r, w.r1)..min(q.r + q.size, w.r2) {
+                for c in max(q.c, w.c1)..min(q.c + q.size, w.c2) {
+                    out.push((r, c, q.max));
+                }
+            }
+            return;
+        }
+        for i in 0..2 {
+            for j in 0..2 {
+                self.report_from(&self.child(q, i, j), w, lo, hi, out);
+            }
+        }
+    }
+}
+
+impl Collection for K2Raster {
+    /// The number of cells
+    fn len(&self) -> usize {
+        self.rows * self.cols
+    }
+}
+
+impl SpaceUsage for K2Raster {
+    fn total_bytes(&self) -> usize {
+        self.tree.total_bytes() + self.maxes.total_bytes() + self.mins.total_bytes()
+    }
+}
+
+impl Serialize for K2Raster {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.rows)?;
+        write_uint(w, self.cols)?;
+        write_uint(w, self.height)?;
+        write_u64(w, self.max)?;
+        write_u64(w, self.min)?;
+        self.tree.serialize(w)?;
+        self.maxes.serialize(w)?;
+        self.mins.serialize(w)
+    }
+}
+
+impl Deserialize for K2Raster {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<K2Raster> {
+        let rows = read_uint(r)?;
+        let cols = read_uint(r)?;
+        let height = read_uint(r)?;
+        let hi = read_u64(r)?;
+        let lo = read_u64(r)?;
+        let tree: Rank9 = Deserialize::deserialize(r)?;
+        let maxes: Dac = Deserialize::deserialize(r)?;
+        let mins: Dac = Deserialize::deserialize(r)?;
+        match K2Raster::from_parts(rows, cols, height, hi, lo, tree, maxes, mins) {
+            Some(raster) => Ok(raster),
+            None => Err(invalid_data("K2Raster quadrants inconsistent with its height")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::K2Raster;
+
+    #[quickcheck]
+    #[allow(clippy::too_many_arguments)]
+    fn queries_are_correct(v: Vec<u8>, cols: u8, r1: u8, r2: u8, c1: u8, c2: u8, lo: u8, hi: u8) -> bool {
+        let cols = cols as usize % 9 + 1;
+        let rows = v.len() / cols;
+        let values: Vec<u64> = v[..rows * cols].iter().map(|x| (*x % 8) as u64).collect();
+        let raster = K2Raster::new(rows, cols, values.as_slice());
+        let (r1, r2, c1, c2) = (r1 as usize % (rows + 1), r2 as usize % (rows + 1),
+                                c1 as usize % (cols + 1), c2 as usize % (cols + 1));
+        let (lo, hi) = ((lo % 8) as u64, (hi % 8) as u64);
+        let mut cells = Vec::new();
+        for r in r1..r2 {
+            for c in c1..c2 {
+                cells.push((r, c, values[r * cols + c]));
+            }
+        }
+        (0..rows).all(|r| (0..cols).all(|c| raster.get(r, c) == values[r * cols + c]))
+            && raster.window_max(r1, r2, c1, c2) == cells.iter().map(|x| x.2).max()
+            && raster.window_min(r1, r2, c1, c2) == cells.iter().map(|x| x.2).min()
+            && raster.report(r1, r2, c1, c2, lo, hi)
+                == cells.into_iter().filter(|x| lo <= x.2 && x.2 <= hi).collect::<Vec<(usize, usize, u64)>>()
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u8>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let values: Vec<u64> = v.iter().map(|x| *x as u64).collect();
+        let raster = K2Raster::new(1, values.len(), values.as_slice());
+        let read: K2Raster = round_trip(&raster);
+        (0..values.len()).all(|c| read.get(0, c) == values[c])
+    }
+
+    #[test]
+    fn test_raster() {
+        let values = [5u64, 5, 5, 7,
+                      5, 5, 6, 7,
+                      1, 5, 5, 5];
+        let raster = K2Raster::new(3, 4, &values);
+        assert_eq!(raster.get(1, 2), 6);
+        assert_eq!(raster.get(2, 0), 1);
+        assert_eq!(raster.window_max(0, 2, 0, 2), Some(5));
+        assert_eq!(raster.window_max(0, 3, 0, 4), Some(7));
+        assert_eq!(raster.window_min(1, 3, 1, 4), Some(5));
+        assert_eq!(raster.window_min(2, 2, 0, 4), None);
+        assert_eq!(raster.report(0, 3, 0, 4, 6, 7), vec!((0, 3, 7), (1, 2, 6), (1, 3, 7)));
+    }
+}
@@ -0,0 +1,270 @@
+//! Sparse square boolean matrices
+//
+// A k2-tree, with k = 2, pads the matrix to a side that is a power of
+// two and splits it into four quadrants, in row-major order, each
+// given a bit telling whether it holds any one. The quadrants holding
+// ones are split in turn, down to single cells. The bits are laid out
+// level by level, those of the children of each set bit of a level
+// following in order in the next, so that the children of the set bit
+// at `x` start at four times the number of set bits up to and
+// including it, the root's children coming first. A query descends
+// only into the quadrants meeting the cells asked about.
+//
+// See Brisaboa, Ladra and Navarro 2009.
+
+use std::io::{self, Read, Write};
+use super::collection::Collection;
+use super::dictionary::{Access, BitRank};
+use super::rank9::{self, Rank9};
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::SpaceUsage;
+
+/// A static `n` by `n` boolean matrix, such as the adjacency matrix of
+/// a graph, in space proportional to its ones and their clustering
+pub struct K2Tree {
+    /// the number of rows and columns
+    dim: usize,
+    /// the number of levels, the padded side being two to its power
+    height: usize,
+    /// the bits of the quadrants, level by level
+    bits: Rank9,
+    /// the number of bits before the last level
+    internal: usize,
+    /// the number of ones
+    len: usize,
+}
+
+/// Append the bits of the quadrants of side `size` of the submatrix at
+/// `(r0, c0)` holding `edges` to `levels`, starting at `level`
+fn build(edges: &[(usize, usize)], level: usize, size: usize, r0: usize, c0: usize,
+         levels: &mut Vec<Vec<bool>>) {
+    for i in 0..2 {
+        for j in 0..2 {
+            let (r, c) = (r0 + i * size, c0 + j * size);
+            let inside: Vec<(usize, usize)> = edges.iter()
+                .filter(|&&(x, y)| r <= x && x < r + size && c <= y && y < c + size).copied()
+                .collect();
+            levels[level].push(!inside.is_empty());
+            if !inside.is_empty() && size > 1 {
+                build(inside.as_slice(), level + 1, size / 2, r, c, levels);
+            }
+        }
+    }
+}
+
+impl K2Tree {
+    /// The `dim` by `dim` matrix with ones at the given `(row, column)`
+    /// positions
+    pub fn new(dim: usize, edges: &[(usize, usize)]) -> K2Tree {
+        use super::build::Builder;
+        for &(r, c) in edges.iter() {
+            assert!(r < dim && c < dim, "K2Tree: edge ({}, {}) out of bounds", r, c);
+        }
+        let mut edges = edges.to_vec();
+        edges.sort();
+        edges.dedup();
+        let mut height = 1;
+        while (1 << height) < dim {
+            height += 1;
+        }
+        let mut levels: Vec<Vec<bool>> = (0..height).map(|_| Vec::new()).collect();
+        build(edges.as_slice(), 0, 1 << (height - 1), 0, 0, &mut levels);
+        let total = levels.iter().map(|l| l.len()).sum::<usize>();
+        let mut bits = rank9::Builder::with_capacity(total);
+        for level in levels.iter() {
+            for b in level.iter() {
+                bits.push(*b);
+            }
+        }
+        K2Tree::from_bits(dim, height, bits.finish()).unwrap()
+    }
+
+    /// The matrix with the given bits, if they are laid out as those of
+    /// a tree of the given height
+    fn from_bits(dim: usize, height: usize, bits: Rank9) -> Option<K2Tree> {
+        if height == 0 || height >= 64 || (1 << height) < dim {
+            return None;
+        }
+        // follow the number of bits of each level from that of the
+        // root's children
+        let (mut start, mut size) = (0, 4);
+        for _ in 1..height {
+            if start + size > bits.len() {
+                return None;
+            }
+            let ones = bits.rank1(start + size) - bits.rank1(start);
+            start += size;
+            size = 4 * ones;
+        }
+        if start + size != bits.len() {
+            return None;
+        }
+        let len = bits.rank1(bits.len()) - bits.rank1(start);
+        Some(K2Tree { dim, height, bits, internal: start, len })
+    }
+
+    /// The number of rows and columns
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Whether the cell at row `r` and column `c` is a one
+    pub fn get(&self, r: usize, c: usize) -> bool {
+        assert!(r < self.dim && c < self.dim, "K2Tree: cell ({}, {}) out of bounds", r, c);
+        let mut block = 0;
+        let mut size = 1 << (self.height - 1);
+        loop {
+            let x = block + 2 * (r / size % 2) + c / size % 2;
+            if !self.bits.get(x) {
+                return false;
+            }
+            if size == 1 {
+                return true;
+            }
+            block = self.children(x);
+            size /= 2;
+        }
+    }
+
+    /// The columns of the ones of row `r` in increasing order, the
+    /// successors of `r` in a graph
+    pub fn row(&self, r: usize) -> Vec<usize> {
+        assert!(r < self.dim, "K2Tree: row {} out of bounds", r);
+        self.report(r, r + 1, 0, self.dim).into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// The rows of the ones of column `c` in increasing order, the
+    /// predecessors of `c` in a graph
+    pub fn column(&self, c: usize) -> Vec<usize> {
+        assert!(c < self.dim, "K2Tree: column {} out of bounds", c);
+        self.report(0, self.dim, c, c + 1).into_iter().map(|(r, _)| r).collect()
+    }
+
+    /// The ones `(r, c)` with `r1 <= r < r2` and `c1 <= c < c2` in
+    /// increasing order
+    pub fn report(&self, r1: usize, r2: usize, c1: usize, c2: usize) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        self.report_from(0, 1 << (self.height - 1), 0, 0, (r1, r2), (c1, c2), &mut out);
+        out.sort();
+        out
+    }
+
+    /// Report the ones in the given ranges of the quadrants of side
+    /// `size` of the submatrix at `(r0, c0)`, whose bits start at
+    /// `block`
+    #[allow(clippy::too_many_arguments)]
+    fn report_from(&self, block: usize, size: usize, r0: usize, c0: usize,
+                   rows: (usize, usize), cols: (usize, usize), out: &mut Vec<(usize, usize)>) {
+        for i in 0..2 {
+            for j in 0..2 {
+                let (r, c) = (r0 + i * size, c0 + j * size);
+                if r >= rows.1 || r + size <= rows.0 || c >= cols.1 || c + size <= cols.0 {
+                    continue;
+                }
+                let x = block + 2 * i + j;
+                if !self.bits.get(x) {
+                    continue;
+                }
+                match size {
+                    1 => out.push((r, c)),
+                    _ => self.report_from(self.children(x), size / 2, r, c, rows, cols, out),
+                }
+            }
+        }
+    }
+
+    /// The position of the bits of the children of the set bit at `x`
+    fn children(&self, x: usize) -> usize {
+        debug_assert!(x < self.internal);
+        4 * self.bits.rank1(x + 1)
+    }
+}
+
+impl Collection for K2Tree {
+    /// The number of ones
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl SpaceUsage for K2Tree {
+    fn total_bytes(&self) -> usize {
+        self.bits.total_bytes()
+    }
+}
+
+impl Serialize for K2Tree {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.dim)?;
+        write_uint(w, self.height)?;
+        self.bits.serialize(w)
+    }
+}
+
+impl Deserialize for K2Tree {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<K2Tree> {
+        let dim = read_uint(r)?;
+        let height = read_uint(r)?;
+        let bits: Rank9 = Deserialize::deserialize(r)?;
+        match K2Tree::from_bits(dim, height, bits) {
+            Some(tree) => Ok(tree),
+            None => Err(invalid_data("K2Tree bits inconsistent with its height")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::K2Tree;
+    use super::super::collection::Collection;
+
+    fn edges(v: &[(u8, u8)], dim: usize) -> Vec<(usize, usize)> {
+        v.iter().map(|&(r, c)| (r as usize % dim, c as usize % dim)).collect()
+    }
+
+    #[quickcheck]
+    fn queries_are_correct(v: Vec<(u8, u8)>, dim: u8, r1: u8, r2: u8, c1: u8, c2: u8) -> bool {
+        let dim = dim as usize % 20 + 1;
+        let mut es = edges(&v, dim);
+        let tree = K2Tree::new(dim, es.as_slice());
+        es.sort();
+        es.dedup();
+        let (r1, r2, c1, c2) = (r1 as usize % (dim + 1), r2 as usize % (dim + 1),
+                                c1 as usize % (dim + 1), c2 as usize % (dim + 1));
+        let reported: Vec<(usize, usize)> = es.iter()
+            .filter(|&&(r, c)| r1 <= r && r < r2 && c1 <= c && c < c2).copied()
+            .collect();
+        tree.len() == es.len()
+            && (0..dim).all(|r| (0..dim).all(|c| tree.get(r, c) == es.contains(&(r, c))))
+            && (0..dim).all(|r| {
+                tree.row(r) == es.iter().filter(|e| e.0 == r).map(|e| e.1).collect::<Vec<usize>>()
+            })
+            && (0..dim).all(|c| {
+                let mut rows: Vec<usize> = es.iter().filter(|e| e.1 == c).map(|e| e.0).collect();
+                rows.sort();
+                tree.column(c) == rows
+            })
+            && tree.report(r1, r2, c1, c2) == reported
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<(u8, u8)>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let es = edges(&v, 100);
+        let tree = K2Tree::new(100, es.as_slice());
+        let read: K2Tree = round_trip(&tree);
+        read.len() == tree.len() && es.iter().all(|&(r, c)| read.get(r, c))
+    }
+
+    #[test]
+    fn test_k2tree() {
+        let tree = K2Tree::new(10, &[(0, 1), (0, 9), (3, 3), (9, 0), (3, 3)]);
+        assert_eq!(tree.dim(), 10);
+        assert_eq!(tree.len(), 4);
+        assert!(tree.get(0, 9) && !tree.get(9, 9));
+        assert_eq!(tree.row(0), vec!(1, 9));
+        assert_eq!(tree.column(3), vec!(3));
+        assert!(tree.row(5).is_empty());
+        assert_eq!(tree.report(0, 4, 0, 4), vec!((0, 1), (3, 3)));
+    }
+}
@@ -1,18 +1,89 @@
-#![crate_name = "succinct"]
-#![crate_type = "lib"]
-#![feature(box_syntax, int_uint)]
-#![allow(unstable)]
+//! Succinct data structures
+//!
+//! # Threads
+//!
+//! A structure never changes once built, its queries taking `&self`,
+//! and all are `Send` and `Sync`: a single `Wavelet`, `FmIndex` or
+//! other structure can be queried from any number of threads at once,
+//! borrowed by scoped threads or held in an `Arc`. The same holds of
+//! views and thawed regions for as long as the words they borrow.
+//! Updating a `PersistentBitVector` makes a new version, which may
+//! share blocks with versions on other threads.
+//!
+//! Builders are `Send` whenever the builders or closures they hold
+//! are, so a structure can be built on one thread and handed to
+//! others. The exceptions are the handles of `spill`, which share
+//! their file and must stay on the thread that made it.
+//!
+//! # Verification
+//!
+//! With the `verify` feature, `Rank9` checks the result of every
+//! `rank` and `select` against the bits themselves, panicking should
+//! its counts disagree. This makes queries take time linear in the
+//! length of the vector, so it is for tests and debugging only.
 
-#[cfg(test)] extern crate quickcheck;
+#[cfg(any(test, feature = "testing"))] extern crate quickcheck;
 #[cfg(test)] #[macro_use] extern crate quickcheck_macros;
+#[cfg(feature = "serde")] extern crate serde;
+#[cfg(feature = "bit-vec")] extern crate bit_vec;
+#[cfg(feature = "fixedbitset")] extern crate fixedbitset;
+#[cfg(feature = "python")] extern crate pyo3;
+#[cfg(feature = "python")] extern crate numpy;
+#[cfg(all(test, feature = "serde"))] extern crate serde_json;
 
+pub mod error;
 pub mod collection;
 pub mod dictionary;
 pub mod bit_vector;
 pub mod rank9;
+pub mod persistent;
+pub mod int_vector;
+pub mod dac;
+pub mod coded;
+pub mod elias_fano;
+pub mod vbyte;
+pub mod prefix_sums;
+pub mod rle;
+pub mod postings;
+pub mod entropy;
+pub mod pfor;
+pub mod multiset;
+pub mod sparse_array;
+pub mod trie;
+pub mod rmq;
+pub mod bwt;
+pub mod fm;
+pub mod csa;
+pub mod rindex;
+pub mod docs;
+pub mod dna;
 pub mod naive;
 pub mod bits;
 pub mod utils;
 pub mod tree;
 pub mod build;
 pub mod wavelet;
+pub mod serialize;
+pub mod format;
+pub mod digest;
+pub mod view;
+pub mod frozen;
+pub mod sdsl;
+pub mod stream;
+pub mod spill;
+pub mod merge;
+#[cfg(any(feature = "bit-vec", feature = "fixedbitset"))] pub mod interop;
+#[cfg(feature = "python")] pub mod python;
+pub mod either;
+pub mod space;
+#[cfg(feature = "serde")] pub mod serde_support;
+#[cfg(any(test, feature = "testing"))] pub mod testing;
+pub mod sequence;
+pub mod grid;
+pub mod k2tree;
+pub mod k2raster;
+pub mod graph;
+pub mod relation;
+mod sync;
+
+pub use crate::error::{Error, Result};
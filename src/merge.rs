@@ -0,0 +1,219 @@
+//! Merging structures built on separate shards
+//
+// A sequence too large to index at once may be cut into shards indexed
+// separately, whose structures are then merged into that of the whole
+// without going back to its symbols.
+//
+// Bitvectors are concatenated from their serializations, read one
+// shard after the other, and the merged serialization written as it
+// is produced: the words of each shard are shifted into place and the
+// counts of a `Rank9` rebuilt from them, as its blocks no longer line
+// up with those of the shards. Only the lengths of the shards, at the
+// head of each serialization, are read before the rest.
+//
+// A packed wavelet tree of the concatenation holds at each level the
+// nodes of that level in order of their paths, the bits of a node
+// being those of the same node in each shard in turn. The levels are
+// merged one at a time, following the extents of every node in each
+// shard, so the shards may be views of serializations held on disk.
+//
+// Elias-Fano sequences are merged into one sorted sequence.
+
+use std::collections::BinaryHeap;
+use std::cmp::min;
+use std::io::{self, Read, Write};
+use super::bit_vector;
+use super::build::Builder;
+use super::collection::Collection;
+use super::dictionary::{Access, Rank};
+use super::elias_fano::{self, EliasFano};
+use super::rank9;
+use super::serialize::{read_u64, read_uint, write_uint, invalid_data};
+use super::utils::div_ceil;
+use super::wavelet::FlatWavelet;
+
+/// Read the length in bits and the number of words at the head of the
+/// serialization of a bitvector
+fn read_header<R: Read>(r: &mut R) -> io::Result<(usize, usize)> {
+    let bits = read_uint(r)?;
+    let words = read_uint(r)?;
+    if words != div_ceil(bits, 64) {
+        return Err(invalid_data("bitvector length inconsistent with its words"));
+    }
+    Ok((bits, words))
+}
+
+/// Read the words of a shard of `bits` bits, handing each to `push`
+/// with the number of its bits in the vector
+fn read_words<R: Read, F: FnMut(u64, usize)>(r: &mut R, bits: usize, words: usize, mut push: F)
+                                            -> io::Result<()> {
+    for i in 0..words {
+        let word = read_u64(r)?;
+        push(word, min(64, bits - 64 * i));
+    }
+    Ok(())
+}
+
+/// Write the serialization of the concatenation of the `BitVector`s
+/// serialized in `shards`, each read to the end of its vector
+pub fn concat_bit_vectors<R: Read, W: Write>(shards: &mut [R], w: W) -> io::Result<W> {
+    let mut headers = Vec::with_capacity(shards.len());
+    for r in shards.iter_mut() {
+        headers.push(read_header(r)?);
+    }
+    let total = headers.iter().fold(0, |a, &(bits, _)| a + bits);
+    let mut builder = bit_vector::StreamBuilder::new(w, total)?;
+    for (r, &(bits, words)) in shards.iter_mut().zip(headers.iter()) {
+        read_words(r, bits, words, |word, n| builder.push_bits(word, n))?;
+    }
+    builder.finish()
+}
+
+/// Write the serialization of the concatenation of the `Rank9`s
+/// serialized in `shards`, each read to the end of its vector
+pub fn concat_rank9<R: Read, W: Write>(shards: &mut [R], w: W) -> io::Result<W> {
+    let mut headers = Vec::with_capacity(shards.len());
+    for r in shards.iter_mut() {
+        headers.push(read_header(r)?);
+    }
+    let total = headers.iter().fold(0, |a, &(bits, _)| a + bits);
+    let mut builder = rank9::StreamBuilder::new(w, total)?;
+    for (r, &(bits, words)) in shards.iter_mut().zip(headers.iter()) {
+        read_words(r, bits, words, |word, n| builder.push_bits(word, n))?;
+        // the counts of the shard, rebuilt for the concatenation
+        let blocks = read_uint(r)?;
+        if blocks != words / 8 + 1 {
+            return Err(invalid_data("Rank9 counts inconsistent with its words"));
+        }
+        for _ in 0..2 * blocks {
+            read_u64(r)?;
+        }
+    }
+    builder.finish()
+}
+
+/// Write the serialization of the packed wavelet tree, over a
+/// `Rank9`, of the concatenation of the sequences of `shards`, which
+/// must have the same number of levels
+pub fn concat_flat_wavelets<BitV, Sym, W>(shards: &[FlatWavelet<BitV, Sym>], mut w: W) -> io::Result<W>
+    where BitV: Access<bool> + Rank<bool>, W: Write
+{
+    let levels = match shards.first() {
+        Some(shard) => shard.levels(),
+        None => 0,
+    };
+    if shards.iter().any(|shard| shard.levels() != levels) {
+        return Err(invalid_data("concat_flat_wavelets: shards differ in their levels"));
+    }
+    let len = shards.iter().fold(0, |a, shard| a + shard.len());
+    write_uint(&mut w, len)?;
+    write_uint(&mut w, levels)?;
+    let mut builder = rank9::StreamBuilder::new(w, len * levels)?;
+
+    // the extent of each node of the level within each shard, in
+    // order of their paths, empty nodes being dropped
+    let mut nodes: Vec<Vec<(usize, usize)>> = vec!(shards.iter().map(|shard| (0, shard.len())).collect());
+    for level in 0..levels {
+        let mut next = Vec::with_capacity(2 * nodes.len());
+        for extents in nodes.iter() {
+            let mut left = Vec::with_capacity(shards.len());
+            let mut right = Vec::with_capacity(shards.len());
+            for (shard, &(start, end)) in shards.iter().zip(extents.iter()) {
+                let offset = level * shard.len();
+                for i in start..end {
+                    builder.push(shard.bits().get(offset + i));
+                }
+                let zeros = shard.bits().rank(false, offset + end)
+                             - shard.bits().rank(false, offset + start);
+                left.push((start, start + zeros));
+                right.push((start + zeros, end));
+            }
+            for child in vec!(left, right).into_iter() {
+                if child.iter().any(|&(start, end)| start < end) {
+                    next.push(child);
+                }
+            }
+        }
+        nodes = next;
+    }
+    builder.finish()
+}
+
+/// The sorted sequence of the values of all of `lists`
+pub fn merge_elias_fano(lists: &[&EliasFano]) -> EliasFano {
+    let len = lists.iter().fold(0, |a, ef| a + ef.len());
+    let max = lists.iter().filter(|ef| ef.len() > 0).map(|ef| ef.get(ef.len() - 1)).max().unwrap_or(0);
+    let mut builder = elias_fano::Builder::new(len, max);
+    let mut iters: Vec<elias_fano::Iter> = lists.iter().map(|ef| ef.iter()).collect();
+    // the next value of each list, complemented to take the least first
+    let mut heap = BinaryHeap::new();
+    for (i, iter) in iters.iter_mut().enumerate() {
+        for x in iter.next().into_iter() {
+            heap.push((!x, i));
+        }
+    }
+    while let Some((x, i)) = heap.pop() {
+        builder.push(!x);
+        for y in iters[i].next().into_iter() {
+            heap.push((!y, i));
+        }
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::From;
+    use std::io::Cursor;
+    use super::super::bit_vector::BitVector;
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, Rank};
+    use super::super::elias_fano::EliasFano;
+    use super::super::rank9::Rank9;
+    use super::super::serialize::{Serialize, Deserialize};
+    use super::super::wavelet::FlatWavelet;
+
+    fn serialized<T: Serialize>(x: &T) -> Cursor<Vec<u8>> {
+        let mut bytes = Vec::new();
+        x.serialize(&mut bytes).unwrap();
+        Cursor::new(bytes)
+    }
+
+    #[quickcheck]
+    fn bitvectors_concatenate(shards: Vec<Vec<bool>>) -> bool {
+        let all: Vec<bool> = shards.iter().flat_map(|s| s.iter().copied()).collect();
+        let mut bvs: Vec<_> = shards.iter().map(|s| serialized(&BitVector::from(s.as_slice()))).collect();
+        let mut rank9s: Vec<_> = shards.iter().map(|s| serialized(&Rank9::from(s.as_slice()))).collect();
+        let bv = super::concat_bit_vectors(bvs.as_mut_slice(), Vec::new()).unwrap();
+        let bv: BitVector = Deserialize::deserialize(&mut Cursor::new(bv)).unwrap();
+        let rank9 = super::concat_rank9(rank9s.as_mut_slice(), Vec::new()).unwrap();
+        let rank9: Rank9 = Deserialize::deserialize(&mut Cursor::new(rank9)).unwrap();
+        let expected = Rank9::from(all.as_slice());
+        bv == BitVector::from(all.as_slice()) && rank9 == expected
+            && (0..all.len() + 1).all(|i| rank9.rank(true, i) == expected.rank(true, i))
+    }
+
+    #[quickcheck]
+    fn flat_wavelets_concatenate(shards: Vec<Vec<u8>>) -> bool {
+        let all: Vec<u8> = shards.iter().flat_map(|s| s.iter().copied()).collect();
+        let flats: Vec<FlatWavelet<Rank9, u8>> = shards.iter().map(|s| FlatWavelet::from_slice_with_width(s.as_slice(), 8)).collect();
+        let merged = super::concat_flat_wavelets(flats.as_slice(), Vec::new()).unwrap();
+        let merged: FlatWavelet<Rank9, u8> = Deserialize::deserialize(&mut Cursor::new(merged)).unwrap();
+        merged.len() == all.len() && all.iter().enumerate().all(|(i, x)| {
+            merged.get(i) == *x && merged.rank(*x, i) == all.rank(*x, i)
+        })
+    }
+
+    #[quickcheck]
+    fn elias_fano_merges(lists: Vec<Vec<u16>>) -> bool {
+        let efs: Vec<EliasFano> = lists.iter().map(|l| {
+            let mut l: Vec<u64> = l.iter().map(|x| *x as u64).collect();
+            l.sort();
+            EliasFano::from_slice(l.as_slice())
+        }).collect();
+        let refs: Vec<&EliasFano> = efs.iter().collect();
+        let mut all: Vec<u64> = lists.iter().flat_map(|l| l.iter().map(|x| *x as u64)).collect();
+        all.sort();
+        super::merge_elias_fano(refs.as_slice()).iter().collect::<Vec<u64>>() == all
+    }
+}
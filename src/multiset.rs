@@ -0,0 +1,126 @@
+//! A static multiset of integers supporting order statistics
+//
+// The elements are kept sorted in Elias-Fano code, so that the rank
+// of a value is a successor search and the `n`th smallest element is
+// an access.
+
+use std::io::{self, Read, Write};
+use std::iter::FromIterator;
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::elias_fano::{self, EliasFano};
+use super::serialize::{Serialize, Deserialize};
+use super::space::SpaceUsage;
+
+/// A multiset of `u64`s
+#[derive(Clone, Debug)]
+pub struct Multiset {
+    sorted: EliasFano,
+}
+
+impl Multiset {
+    /// The multiset of the elements of `values`
+    pub fn new(mut values: Vec<u64>) -> Multiset {
+        values.sort();
+        Multiset { sorted: EliasFano::from_slice(values.as_slice()) }
+    }
+
+    /// The number of elements less than `x`
+    pub fn rank(&self, x: u64) -> usize {
+        self.sorted.lower_bound(x)
+    }
+
+    /// The `n`th smallest element, counting from zero
+    pub fn select(&self, n: usize) -> u64 {
+        self.sorted.get(n)
+    }
+
+    /// The number of occurrences of `x`
+    pub fn count(&self, x: u64) -> usize {
+        self.upper_rank(x) - self.rank(x)
+    }
+
+    /// Whether `x` is an element
+    pub fn contains(&self, x: u64) -> bool {
+        self.count(x) > 0
+    }
+
+    /// The number of elements `x` with `lo <= x < hi`
+    pub fn range_count(&self, lo: u64, hi: u64) -> usize {
+        match hi > lo {
+            true => self.rank(hi) - self.rank(lo),
+            false => 0,
+        }
+    }
+
+    /// The elements in increasing order
+    pub fn iter<'a>(&'a self) -> elias_fano::Iter<'a> {
+        self.sorted.iter()
+    }
+
+    /// The number of elements no greater than `x`
+    fn upper_rank(&self, x: u64) -> usize {
+        match x {
+            u64::MAX => self.sorted.len(),
+            _ => self.sorted.lower_bound(x + 1),
+        }
+    }
+}
+
+impl FromIterator<u64> for Multiset {
+    fn from_iter<I: IntoIterator<Item=u64>>(iter: I) -> Multiset {
+        Multiset::new(iter.into_iter().collect())
+    }
+}
+
+impl Collection for Multiset {
+    fn len(&self) -> usize {
+        self.sorted.len()
+    }
+}
+
+impl SpaceUsage for Multiset {
+    fn total_bytes(&self) -> usize {
+        self.sorted.total_bytes()
+    }
+}
+
+impl Serialize for Multiset {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.sorted.serialize(w)
+    }
+}
+
+impl Deserialize for Multiset {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Multiset> {
+        Ok(Multiset { sorted: Deserialize::deserialize(r)? })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Multiset;
+
+    #[quickcheck]
+    fn queries_are_correct(v: Vec<u8>, x: u8, y: u8) -> bool {
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let (x, y) = (x as u64, y as u64);
+        let set: Multiset = v.iter().copied().collect();
+        let mut sorted = v.clone();
+        sorted.sort();
+        set.rank(x) == v.iter().filter(|z| **z < x).count()
+            && set.count(x) == v.iter().filter(|z| **z == x).count()
+            && set.range_count(x, y) == v.iter().filter(|z| x <= **z && **z < y).count()
+            && (0..v.len()).all(|n| set.select(n) == sorted[n])
+    }
+
+    #[test]
+    fn test_multiset() {
+        let set = Multiset::new(vec!(7, 3, 7, 1, 7));
+        assert_eq!(set.count(7), 3);
+        assert!(!set.contains(4));
+        assert_eq!(set.select(1), 3);
+        assert_eq!(set.rank(7), 2);
+        assert_eq!(set.iter().collect::<Vec<u64>>(), vec!(1, 3, 7, 7, 7));
+    }
+}
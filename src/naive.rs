@@ -1,35 +1,107 @@
-//! Exceedingly naive implementations for dictionary operations
+//! Exceedingly naive implementations for dictionary and sequence
+//! operations
 
-use super::dictionary::{Access};
+use super::dictionary::{Access, Rank, Select};
 use super::collection::{Collection};
 use std::option::Option::{self, Some, None};
 use std::cmp::{Eq, min};
 
 /// A very simple rank implementation written to test against
-pub fn rank<T: Eq, BitVec: Access<T> + Collection>(v: &BitVec, bit: T, n: int) -> int {
-    assert!(n <= v.len() as int);
-    let n = min(n, v.len() as int);
+pub fn rank<T: Eq, BitVec: Access<T> + Collection>(v: &BitVec, bit: T, n: usize) -> usize {
+    assert!(n <= v.len());
+    let n = min(n, v.len());
     let mut accum = 0;
-    for i in range(0, n) {
-        if v.get(i as uint) == bit {
+    for i in 0..n {
+        if v.get(i) == bit {
             accum += 1;
         }
     }
     accum
 }
 
-pub fn select<T: Eq, BitVec: Access<T> + Collection>(v: &BitVec, bit: T, n: int) -> Option<int> {
+pub fn select<T: Eq, BitVec: Access<T> + Collection>(v: &BitVec, bit: T, n: usize) -> Option<usize> {
     let mut n = n;
     if n == 0 {
         return Some(0);
     }
-    for i in range(0, v.len()) {
+    for i in 0..v.len() {
         if v.get(i) == bit {
             n -= 1;
             if n == 0 {
-                return Some(i as int + 1);
+                return Some(i + 1);
             }
         }
     }
     None
 }
+
+/// A sequence of symbols answering queries by scanning them, written
+/// to test the wavelet trees against
+pub struct Sequence<Sym> {
+    syms: Vec<Sym>,
+}
+
+impl<Sym> Sequence<Sym> {
+    pub fn new(syms: Vec<Sym>) -> Sequence<Sym> {
+        Sequence { syms }
+    }
+}
+
+impl<Sym: Ord + Clone> Sequence<Sym> {
+    /// The `k`th smallest (counting from zero) symbol occurring in
+    /// positions `[i, j)`
+    pub fn quantile(&self, i: usize, j: usize, k: usize) -> Sym {
+        let mut sorted = self.syms[i..j].to_vec();
+        sorted.sort();
+        sorted[k].clone()
+    }
+
+    /// The number of positions in `[i, j)` holding a symbol `s` with
+    /// `lo <= s < hi`
+    pub fn range_count(&self, i: usize, j: usize, lo: Sym, hi: Sym) -> usize {
+        self.syms[i..j].iter().filter(|s| lo <= **s && **s < hi).count()
+    }
+}
+
+impl<Sym> Collection for Sequence<Sym> {
+    fn len(&self) -> usize {
+        self.syms.len()
+    }
+}
+
+impl<Sym: Clone> Access<Sym> for Sequence<Sym> {
+    fn get(&self, n: usize) -> Sym {
+        self.syms[n].clone()
+    }
+}
+
+impl<Sym: Eq> Rank<Sym> for Sequence<Sym> {
+    fn rank(&self, sym: Sym, n: usize) -> usize {
+        assert!(n <= self.syms.len());
+        self.syms[..n].iter().filter(|s| **s == sym).count()
+    }
+}
+
+impl<Sym: Eq> Select<Sym> for Sequence<Sym> {
+    fn select(&self, sym: Sym, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        match self.syms.iter().enumerate().filter(|&(_, s)| *s == sym).nth(n - 1) {
+            Some((i, _)) => i + 1,
+            None => panic!("select: symbol does not occur {} times", n),
+        }
+    }
+}
+
+/// The index and value of the smallest of the sorted `values` no less
+/// than `x`
+pub fn successor(values: &[u64], x: u64) -> Option<(usize, u64)> {
+    values.iter().position(|y| *y >= x).map(|i| (i, values[i]))
+}
+
+/// The index and value of the largest of the sorted `values` no
+/// greater than `x`
+pub fn predecessor(values: &[u64], x: u64) -> Option<(usize, u64)> {
+    values.iter().rposition(|y| *y <= x).map(|i| (i, values[i]))
+}
@@ -0,0 +1,252 @@
+//! Persistent bitvectors
+//
+// A bitvector is cut into blocks of `BLOCK` words, each shared by
+// atomic reference counting between the versions that don't change
+// it, so versions can be shared by and updated on any threads.
+// Updating a version copies the blocks it changes alone and rebuilds
+// the rank directory of the new version, the number of ones before
+// each block, from that of the old one and the counts of the blocks
+// changed, so a version costs a word per block and its own blocks
+// rather than a copy of the whole bitvector. Rank adds the directory
+// entry of a block to a count within it, and select searches the
+// directory for the block holding the answer.
+
+use std::mem::size_of;
+use std::sync::Arc;
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, BitRank, Select};
+use super::space::{SpaceUsage, vec_bytes};
+
+/// The number of words per block
+const BLOCK: usize = 64;
+
+/// A version of a bitvector, sharing blocks with the versions it was
+/// updated from or to. Cloning a version is cheap.
+#[derive(Clone)]
+pub struct PersistentBitVector {
+    len: usize,
+    blocks: Vec<Arc<Vec<u64>>>,
+    /// the number of ones before each block, and in all
+    ranks: Vec<usize>,
+}
+
+/// The number of ones in `words`
+fn ones(words: &[u64]) -> usize {
+    words.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+impl PersistentBitVector {
+    /// `len` zeros, in blocks all shared
+    pub fn new(len: usize) -> PersistentBitVector {
+        let zeros: Arc<Vec<u64>> = Arc::new((0..BLOCK).map(|_| 0).collect());
+        let blocks = len.div_ceil(64 * BLOCK);
+        PersistentBitVector {
+            len,
+            blocks: (0..blocks).map(|_| zeros.clone()).collect(),
+            ranks: (0..blocks + 1).map(|_| 0).collect(),
+        }
+    }
+
+    /// The given bits
+    pub fn from_bits(bits: &[bool]) -> PersistentBitVector {
+        let changes: Vec<(usize, bool)> = bits.iter().copied().enumerate().filter(|&(_, b)| b).collect();
+        PersistentBitVector::new(bits.len()).update(changes.as_slice())
+    }
+
+    /// A new version with bit `i` set to `bit`
+    pub fn set(&self, i: usize, bit: bool) -> PersistentBitVector {
+        self.update(&[(i, bit)])
+    }
+
+    /// A new version with each bit `i` set to `bit` for every `(i, bit)`
+    /// of `changes` in turn, copying each block changed once
+    pub fn update(&self, changes: &[(usize, bool)]) -> PersistentBitVector {
+        let mut blocks = self.blocks.clone();
+        let mut changed: Vec<bool> = (0..blocks.len()).map(|_| false).collect();
+        for &(i, bit) in changes.iter() {
+            assert!(i < self.len, "PersistentBitVector: index {} out of bounds", i);
+            let k = i / (64 * BLOCK);
+            changed[k] = true;
+            // copies the block unless this version already owns it
+            let word = &mut Arc::make_mut(&mut blocks[k])[i / 64 % BLOCK];
+            match bit {
+                true => *word |= 1 << (i % 64),
+                false => *word &= !(1 << (i % 64)),
+            }
+        }
+        let mut ranks = Vec::with_capacity(blocks.len() + 1);
+        ranks.push(0);
+        for k in 0..blocks.len() {
+            let n = match changed[k] {
+                true => ones(blocks[k].as_slice()),
+                false => self.ranks[k + 1] - self.ranks[k],
+            };
+            let before = ranks[k];
+            ranks.push(before + n);
+        }
+        PersistentBitVector { len: self.len, blocks, ranks }
+    }
+
+    /// The number of blocks shared with `other`
+    pub fn shared_blocks(&self, other: &PersistentBitVector) -> usize {
+        self.blocks.iter().zip(other.blocks.iter())
+            .filter(|&(a, b)| std::ptr::eq(&**a, &**b))
+            .count()
+    }
+
+    /// The number of zeros or ones before block `k`, where the padding
+    /// of the last block past `len` counts as neither
+    fn before(&self, bit: bool, k: usize) -> usize {
+        match bit {
+            true => self.ranks[k],
+            false => (64 * BLOCK * k).min(self.len) - self.ranks[k],
+        }
+    }
+}
+
+impl Collection for PersistentBitVector {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<bool> for PersistentBitVector {
+    fn get(&self, n: usize) -> bool {
+        assert!(n < self.len, "PersistentBitVector: index {} out of bounds", n);
+        let word = self.blocks[n / (64 * BLOCK)][n / 64 % BLOCK];
+        (word >> (n % 64)) & 1 == 1
+    }
+}
+
+impl Rank<bool> for PersistentBitVector {
+    fn rank(&self, el: bool, n: usize) -> usize {
+        if el {self.rank1(n)} else {self.rank0(n)}
+    }
+}
+
+impl BitRank for PersistentBitVector {
+    fn rank1(&self, n: usize) -> usize {
+        assert!(n <= self.len, "PersistentBitVector: rank of {} out of bounds", n);
+        let k = n / (64 * BLOCK);
+        if k == self.blocks.len() {
+            return self.ranks[k];
+        }
+        let word = n / 64 % BLOCK;
+        let block = self.blocks[k].as_slice();
+        let partial = match n % 64 {
+            0 => 0,
+            bits => (block[word] & ((1 << bits) - 1)).count_ones() as usize,
+        };
+        self.ranks[k] + ones(&block[..word]) + partial
+    }
+
+    fn rank0(&self, n: usize) -> usize {
+        n - self.rank1(n)
+    }
+}
+
+impl Select<bool> for PersistentBitVector {
+    fn select(&self, bit: bool, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        assert!(n <= self.before(bit, self.blocks.len()),
+                "PersistentBitVector: select({}, {}) out of range", bit, n);
+        // the last block with fewer than `n` matching bits before it
+        let (mut lo, mut hi) = (0, self.blocks.len());
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if self.before(bit, mid) < n {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let mut left = n - self.before(bit, lo);
+        for (w, word) in self.blocks[lo].iter().enumerate() {
+            let count = match bit {
+                true => word.count_ones(),
+                false => word.count_zeros(),
+            } as usize;
+            if left <= count {
+                return ((lo * BLOCK + w) * 64) + word.select(bit, left);
+            }
+            left -= count;
+        }
+        panic!("PersistentBitVector: select({}, {}) out of range", bit, n)
+    }
+}
+
+impl SpaceUsage for PersistentBitVector {
+    /// The space of this version, counting its shared blocks in full
+    fn total_bytes(&self) -> usize {
+        size_of::<usize>() + vec_bytes(&self.blocks) + vec_bytes(&self.ranks)
+            + self.blocks.len() * BLOCK * size_of::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PersistentBitVector, BLOCK};
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, BitRank, Select};
+
+    #[quickcheck]
+    fn versions_are_correct(v: Vec<bool>, changes: Vec<(u16, bool)>) -> bool {
+        if v.is_empty() {
+            return true;
+        }
+        let mut naive = vec!(v.clone());
+        let mut versions = vec!(PersistentBitVector::from_bits(v.as_slice()));
+        for &(i, bit) in changes.iter() {
+            let i = i as usize % v.len();
+            let mut bits = naive.last().unwrap().clone();
+            bits[i] = bit;
+            naive.push(bits);
+            let next = versions.last().unwrap().set(i, bit);
+            versions.push(next);
+        }
+        versions.iter().zip(naive.iter()).all(|(bv, bits)| {
+            let ones: Vec<usize> = (0..bits.len()).filter(|i| bits[*i]).collect();
+            let zeros: Vec<usize> = (0..bits.len()).filter(|i| !bits[*i]).collect();
+            bv.len() == bits.len()
+                && (0..bits.len()).all(|i| bv.get(i) == bits[i])
+                && (0..bits.len() + 1).all(|i| bv.rank1(i) == ones.iter().filter(|j| **j < i).count())
+                && ones.iter().enumerate().all(|(n, i)| bv.select(true, n + 1) == *i + 1)
+                && zeros.iter().enumerate().all(|(n, i)| bv.select(false, n + 1) == *i + 1)
+        })
+    }
+
+    #[quickcheck]
+    fn select_stops_at_len(blocks: u8, rest: u16, ones: Vec<u32>) -> bool {
+        let len = blocks as usize % 3 * 64 * BLOCK + rest as usize % (64 * BLOCK);
+        if len == 0 {
+            return true;
+        }
+        let changes: Vec<(usize, bool)> = ones.iter().map(|&i| (i as usize % len, true)).collect();
+        let bv = PersistentBitVector::new(len).update(changes.as_slice());
+        let zeros: Vec<usize> = (0..len).filter(|&i| !bv.get(i)).collect();
+        bv.rank0(len) == zeros.len()
+            && zeros.iter().enumerate().all(|(n, i)| bv.select(false, n + 1) == *i + 1)
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_select_past_zeros() {
+        PersistentBitVector::new(64 * BLOCK + 3).select(false, 64 * BLOCK + 4);
+    }
+
+    #[test]
+    fn test_sharing() {
+        let len = 10 * 64 * BLOCK;
+        let a = PersistentBitVector::new(len);
+        let b = a.set(100, true);
+        let c = b.update(&[(100, false), (len - 1, true), (len - 2, true)]);
+        assert_eq!(b.shared_blocks(&a), 9);
+        assert_eq!(c.shared_blocks(&b), 8);
+        assert!(!a.get(100) && b.get(100) && !c.get(100));
+        assert_eq!(b.rank1(len), 1);
+        assert_eq!(c.rank1(len), 2);
+        assert_eq!(c.select(true, 1), len - 1);
+    }
+}
@@ -0,0 +1,324 @@
+//! Patched frame-of-reference coding of integer arrays
+//
+// The array is cut into blocks, each stored as its minimum and the
+// differences of its values from it. The differences are packed at a
+// width chosen per block to minimize its size; those too wide for it
+// are exceptions, whose high bits are kept in a separate list sorted
+// by position. The descriptors of the blocks are packed in
+// `IntVector`s, so access needs a few field reads and a search of the
+// block's exceptions.
+//
+// See Zukowski et al. 2006.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use std::cmp::{min, max};
+use super::bits::bit_width;
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::int_vector::{IntVector, read_field, low_mask};
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+/// An array of integers in blocks coded relative to their minimum
+#[derive(Clone, Debug)]
+pub struct PForArray {
+    /// the number of values per block
+    block: usize,
+    len: usize,
+    /// the minimum of each block
+    mins: IntVector,
+    /// the width of the differences of each block
+    widths: IntVector,
+    /// the bit offset of the differences of each block
+    offsets: IntVector,
+    /// the packed differences
+    slots: Vec<u64>,
+    /// the index of the first exception of each block, followed by
+    /// the number of exceptions
+    exception_starts: IntVector,
+    /// the position of each exception
+    exception_positions: IntVector,
+    /// the bits of each exception above the width of its block
+    exception_highs: Vec<u64>,
+}
+
+/// The width minimizing the size of a block with differences `deltas`,
+/// each exception costing `exception_bits`
+fn best_width(deltas: &[u64], exception_bits: usize) -> usize {
+    let cost = |w: usize| -> usize {
+        let exceptions = deltas.iter().filter(|d| bit_width(**d) > w).count();
+        w * deltas.len() + exceptions * exception_bits
+    };
+    let widest = deltas.iter().map(|d| bit_width(*d)).max().unwrap_or(0);
+    (0..widest + 1).min_by_key(|w| cost(*w)).unwrap()
+}
+
+impl PForArray {
+    /// Encode `values` in blocks of 128
+    pub fn new(values: &[u64]) -> PForArray {
+        PForArray::with_block(128, values)
+    }
+
+    /// Encode `values` in blocks of `block` values
+    pub fn with_block(block: usize, values: &[u64]) -> PForArray {
+        use super::build::Builder;
+        use super::int_vector::{WideningBuilder, write_field};
+        assert!(block > 0, "PForArray: block size must be positive");
+
+        let position_bits = bit_width(values.len() as u64);
+        let mut mins = WideningBuilder::new(1).shrink();
+        let mut widths = WideningBuilder::new(1).shrink();
+        let mut offsets = WideningBuilder::new(1).shrink();
+        let mut exception_starts = WideningBuilder::new(1).shrink();
+        let mut exception_positions = WideningBuilder::new(1).shrink();
+        let mut slots = Vec::new();
+        let mut exception_highs = Vec::new();
+        let mut size = 0;
+        for (b, chunk) in values.chunks(block).enumerate() {
+            let base = *chunk.iter().min().unwrap();
+            let deltas: Vec<u64> = chunk.iter().map(|x| x - base).collect();
+            let w = best_width(deltas.as_slice(), 64 + position_bits);
+            mins.push(base);
+            widths.push(w as u64);
+            offsets.push(size as u64);
+            exception_starts.push(exception_highs.len() as u64);
+            let words = max(slots.len(), (size + w * deltas.len()).div_ceil(64));
+            slots.resize(words, 0);
+            for (i, d) in deltas.iter().enumerate() {
+                write_field(slots.as_mut_slice(), size + i * w, w, d & low_mask(w));
+                if bit_width(*d) > w {
+                    exception_positions.push((b * block + i) as u64);
+                    exception_highs.push(d >> w);
+                }
+            }
+            size += w * deltas.len();
+        }
+        exception_starts.push(exception_highs.len() as u64);
+
+        PForArray {
+            block,
+            len: values.len(),
+            mins: mins.finish(),
+            widths: widths.finish(),
+            offsets: offsets.finish(),
+            slots,
+            exception_starts: exception_starts.finish(),
+            exception_positions: exception_positions.finish(),
+            exception_highs,
+        }
+    }
+
+    /// The number of values too wide for their block
+    pub fn exceptions(&self) -> usize {
+        self.exception_highs.len()
+    }
+
+    /// The number of values per block
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    /// An iterator over the values, decoding a block at a time
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { array: self, next_block: 0, buffer: Vec::with_capacity(self.block), pos: 0 }
+    }
+
+    /// Decode block `b` into `out`
+    fn decode_block(&self, b: usize, out: &mut Vec<u64>) {
+        out.clear();
+        let start = b * self.block;
+        let n = if start + self.block < self.len { self.block } else { self.len - start };
+        let base = self.mins.get(b);
+        let w = self.widths.get(b) as usize;
+        let offset = self.offsets.get(b) as usize;
+        for i in 0..n {
+            out.push(read_field(self.slots.as_slice(), offset + i * w, w));
+        }
+        for e in self.exception_starts.get(b) as usize..self.exception_starts.get(b + 1) as usize {
+            let i = self.exception_positions.get(e) as usize - start;
+            out[i] |= self.exception_highs[e] << w;
+        }
+        for x in out.iter_mut() {
+            *x += base;
+        }
+    }
+
+    /// The high bits of the exception at position `n` of block `b`, if
+    /// any
+    fn exception(&self, b: usize, n: usize) -> Option<u64> {
+        let mut lo = self.exception_starts.get(b) as usize;
+        let mut hi = self.exception_starts.get(b + 1) as usize;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let pos = self.exception_positions.get(mid) as usize;
+            if pos == n {
+                return Some(self.exception_highs[mid]);
+            } else if pos < n {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        None
+    }
+}
+
+impl Collection for PForArray {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for PForArray {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "PForArray: index {} out of bounds", n);
+        let b = n / self.block;
+        let w = self.widths.get(b) as usize;
+        let offset = self.offsets.get(b) as usize + (n % self.block) * w;
+        let low = read_field(self.slots.as_slice(), offset, w);
+        let delta = match self.exception(b, n) {
+            Some(high) => low | (high << w),
+            None => low,
+        };
+        self.mins.get(b) + delta
+    }
+}
+
+impl SpaceUsage for PForArray {
+    fn total_bytes(&self) -> usize {
+        2 * size_of::<usize>() + self.mins.total_bytes() + self.widths.total_bytes()
+            + self.offsets.total_bytes() + vec_bytes(&self.slots)
+            + self.exception_starts.total_bytes() + self.exception_positions.total_bytes()
+            + vec_bytes(&self.exception_highs)
+    }
+}
+
+impl Serialize for PForArray {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.block)?;
+        write_uint(w, self.len)?;
+        self.mins.serialize(w)?;
+        self.widths.serialize(w)?;
+        self.offsets.serialize(w)?;
+        self.slots.serialize(w)?;
+        self.exception_starts.serialize(w)?;
+        self.exception_positions.serialize(w)?;
+        self.exception_highs.serialize(w)
+    }
+}
+
+impl Deserialize for PForArray {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<PForArray> {
+        let block = read_uint(r)?;
+        let len = read_uint(r)?;
+        let mins: IntVector = Deserialize::deserialize(r)?;
+        let widths: IntVector = Deserialize::deserialize(r)?;
+        let offsets: IntVector = Deserialize::deserialize(r)?;
+        let slots: Vec<u64> = Deserialize::deserialize(r)?;
+        let exception_starts: IntVector = Deserialize::deserialize(r)?;
+        let exception_positions: IntVector = Deserialize::deserialize(r)?;
+        let exception_highs: Vec<u64> = Deserialize::deserialize(r)?;
+        if block == 0 {
+            return Err(invalid_data("PForArray block size is zero"));
+        }
+        let blocks = len.div_ceil(block);
+        if mins.len() != blocks || widths.len() != blocks || offsets.len() != blocks
+            || exception_starts.len() != blocks + 1 {
+            return Err(invalid_data("PForArray blocks inconsistent with length"));
+        }
+        if widths.iter().any(|w| w > 64) {
+            return Err(invalid_data("PForArray width exceeds 64 bits"));
+        }
+        if blocks > 0 {
+            let last = blocks - 1;
+            let end = offsets.get(last) + widths.get(last) * (len - last * block) as u64;
+            if end > 64 * slots.len() as u64 {
+                return Err(invalid_data("PForArray differences exceed their buffer"));
+            }
+        }
+        if exception_positions.len() != exception_highs.len()
+            || exception_starts.iter().any(|e| e as usize > exception_highs.len()) {
+            return Err(invalid_data("PForArray exceptions inconsistent"));
+        }
+        Ok(PForArray {
+            block,
+            len,
+            mins,
+            widths,
+            offsets,
+            slots,
+            exception_starts,
+            exception_positions,
+            exception_highs,
+        })
+    }
+}
+
+/// An iterator over the values of a `PForArray`
+pub struct Iter<'a> {
+    array: &'a PForArray,
+    next_block: usize,
+    /// the values of the current block
+    buffer: Vec<u64>,
+    /// the position in `buffer` of the next value
+    pos: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.pos == self.buffer.len() {
+            if self.next_block * self.array.block >= self.array.len {
+                return None;
+            }
+            self.array.decode_block(self.next_block, &mut self.buffer);
+            self.next_block += 1;
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Some(self.buffer[self.pos - 1])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let decoded = min(self.next_block * self.array.block, self.array.len);
+        let remaining = self.array.len - decoded + (self.buffer.len() - self.pos);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::PForArray;
+    use super::super::dictionary::Access;
+
+    #[quickcheck]
+    fn access_is_correct(block: usize, v: Vec<u64>, small: Vec<u8>) -> bool {
+        // mostly small differences with occasional large ones
+        let mut v: Vec<u64> = v.into_iter().map(|x| x % 1000000).collect();
+        v.extend(small.into_iter().map(|x| 500 + x as u64));
+        let array = PForArray::with_block(block % 16 + 1, v.as_slice());
+        (0..v.len()).all(|i| array.get(i) == v[i])
+            && array.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let array: PForArray = round_trip(&PForArray::with_block(7, v.as_slice()));
+        array.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let mut v: Vec<u64> = (0..128).map(|i| 1000 + (i % 4)).collect();
+        v[17] = 1 << 40;
+        let array = PForArray::new(v.as_slice());
+        assert_eq!(array.exceptions(), 1);
+        assert_eq!(array.widths.get(0), 2);
+        assert_eq!(array.get(17), 1 << 40);
+        assert_eq!(array.get(18), 1002);
+    }
+}
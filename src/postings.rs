@@ -0,0 +1,367 @@
+//! Compressed posting lists
+//
+// A posting list is a non-decreasing sequence of integers searched
+// with `next_geq`. Besides `EliasFano`, lists may be stored as the
+// gaps between consecutive values in Elias gamma or Golomb-Rice code.
+// Gap-coded lists are cut into blocks whose first values and bit
+// offsets are sampled, so that a search decodes only the block
+// containing its answer. Rice-coded blocks each use the parameter
+// minimizing their size.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use std::cmp::Ordering::{Less, Greater};
+use super::bits::{BitReader, read_gamma};
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::elias_fano::EliasFano;
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+/// A non-decreasing sequence supporting successor search
+pub trait Postings: Collection + Access<u64> {
+    /// The index and value of the first value no less than `x`
+    fn next_geq(&self, x: u64) -> Option<(usize, u64)>;
+}
+
+impl Postings for EliasFano {
+    fn next_geq(&self, x: u64) -> Option<(usize, u64)> {
+        self.successor(x)
+    }
+}
+
+/// The code of the gaps of a `GapList`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GapCode {
+    /// Elias gamma code of each gap plus one
+    Gamma,
+    /// Golomb-Rice code with a parameter chosen per block
+    Rice,
+}
+
+/// A non-decreasing sequence stored as coded gaps
+#[derive(Clone, Debug)]
+pub struct GapList {
+    code: GapCode,
+    /// the number of values per block
+    block: usize,
+    len: usize,
+    /// the coded gaps
+    bits: Vec<u64>,
+    /// the first value of each block
+    firsts: Vec<u64>,
+    /// the bit offset of the gaps of each block
+    offsets: Vec<u64>,
+    /// the Rice parameter of each block
+    params: Vec<u8>,
+}
+
+/// The Rice parameter minimizing the size of `gaps`
+fn rice_param(gaps: &[u64]) -> usize {
+    // summed in 128 bits, as the costs of huge gaps overflow a word
+    let cost = |k: usize| -> u128 {
+        gaps.iter().map(|g| (g >> k) as u128 + 1 + k as u128).sum::<u128>()
+    };
+    (0..64).min_by_key(|k| cost(*k)).unwrap()
+}
+
+impl GapList {
+    /// Encode a non-decreasing sequence in blocks of 128 values
+    pub fn new(code: GapCode, values: &[u64]) -> GapList {
+        GapList::with_block(code, 128, values)
+    }
+
+    /// Encode a non-decreasing sequence in blocks of `block` values.
+    /// Access decodes up to `block` gaps.
+    pub fn with_block(code: GapCode, block: usize, values: &[u64]) -> GapList {
+        use super::build::{Builder, BitBuilder, VecBuilder};
+        use super::bits::{write_gamma, write_bits, gamma_len};
+        assert!(block > 0, "GapList: block size must be positive");
+
+        let mut bits = BitBuilder::new(VecBuilder::with_capacity(64));
+        let mut size = 0;
+        let mut firsts = Vec::new();
+        let mut offsets = Vec::new();
+        let mut params = Vec::new();
+        for (i, x) in values.iter().enumerate().skip(1) {
+            assert!(*x >= values[i - 1], "GapList: {} follows {}", *x, values[i - 1]);
+        }
+        for chunk in values.chunks(block) {
+            let gaps: Vec<u64> = chunk.windows(2).map(|w| w[1] - w[0]).collect();
+            firsts.push(chunk[0]);
+            offsets.push(size as u64);
+            let k = match code {
+                GapCode::Gamma => 0,
+                GapCode::Rice => rice_param(gaps.as_slice()),
+            };
+            params.push(k as u8);
+            for gap in gaps.iter() {
+                match code {
+                    GapCode::Gamma => {
+                        assert!(*gap < u64::MAX, "GapList: gap too large for gamma code");
+                        write_gamma(&mut bits, gap + 1);
+                        size += gamma_len(gap + 1);
+                    },
+                    GapCode::Rice => {
+                        let q = gap >> k;
+                        for _ in 0..q {
+                            bits.push(false);
+                        }
+                        bits.push(true);
+                        write_bits(&mut bits, k, *gap);
+                        size += q as usize + 1 + k;
+                    },
+                }
+            }
+        }
+        let (bits, _) = bits.finish();
+        GapList {
+            code,
+            block,
+            len: values.len(),
+            bits,
+            firsts,
+            offsets,
+            params,
+        }
+    }
+
+    /// The code of the gaps
+    pub fn code(&self) -> GapCode {
+        self.code
+    }
+
+    /// The number of values per block
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    /// An iterator over the values
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        self.iter_block(0)
+    }
+
+    /// An iterator over the values from the first of block `b` on
+    fn iter_block<'a>(&'a self, b: usize) -> Iter<'a> {
+        let offset = match b < self.offsets.len() {
+            true => self.offsets[b] as usize,
+            false => 0,
+        };
+        Iter {
+            list: self,
+            reader: BitReader::at(self.bits.as_slice(), offset),
+            n: b * self.block,
+            value: 0,
+        }
+    }
+
+    /// Decode the next gap of block `b`
+    fn read_gap(&self, b: usize, reader: &mut BitReader) -> u64 {
+        match self.code {
+            GapCode::Gamma => read_gamma(reader) - 1,
+            GapCode::Rice => {
+                let k = self.params[b] as usize;
+                let q = reader.read_unary() as u64;
+                (q << k) | reader.read_bits(k)
+            },
+        }
+    }
+}
+
+impl Collection for GapList {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for GapList {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "GapList: index {} out of bounds", n);
+        self.iter_block(n / self.block).nth(n % self.block).unwrap()
+    }
+}
+
+impl Postings for GapList {
+    fn next_geq(&self, x: u64) -> Option<(usize, u64)> {
+        // the number of blocks starting below `x`; the answer lies in
+        // the last of them or is the first value of the next
+        let b = match self.firsts.binary_search_by(|f| if *f < x { Less } else { Greater }) {
+            Ok(_) => unreachable!(),
+            Err(b) => b,
+        };
+        if b == 0 {
+            return self.firsts.first().map(|f| (0, *f));
+        }
+        let start = (b - 1) * self.block;
+        self.iter_block(b - 1).enumerate()
+            .map(|(i, y)| (start + i, y))
+            .find(|&(_, y)| y >= x)
+    }
+}
+
+impl SpaceUsage for GapList {
+    fn total_bytes(&self) -> usize {
+        size_of::<GapCode>() + 2 * size_of::<usize>() + vec_bytes(&self.bits)
+            + vec_bytes(&self.firsts) + vec_bytes(&self.offsets) + vec_bytes(&self.params)
+    }
+}
+
+impl Serialize for GapList {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, match self.code { GapCode::Gamma => 0, GapCode::Rice => 1 })?;
+        write_uint(w, self.block)?;
+        write_uint(w, self.len)?;
+        self.bits.serialize(w)?;
+        self.firsts.serialize(w)?;
+        self.offsets.serialize(w)?;
+        self.params.serialize(w)
+    }
+}
+
+impl Deserialize for GapList {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<GapList> {
+        let code = match read_u64(r)? {
+            0 => GapCode::Gamma,
+            1 => GapCode::Rice,
+            _ => return Err(invalid_data("invalid GapList code")),
+        };
+        let block = read_uint(r)?;
+        let len = read_uint(r)?;
+        let bits: Vec<u64> = Deserialize::deserialize(r)?;
+        let firsts: Vec<u64> = Deserialize::deserialize(r)?;
+        let offsets: Vec<u64> = Deserialize::deserialize(r)?;
+        let params: Vec<u8> = Deserialize::deserialize(r)?;
+        if block == 0 {
+            return Err(invalid_data("GapList block size is zero"));
+        }
+        let blocks = len.div_ceil(block);
+        if firsts.len() != blocks || offsets.len() != blocks || params.len() != blocks {
+            return Err(invalid_data("GapList blocks inconsistent with length"));
+        }
+        if params.iter().any(|k| *k >= 64) {
+            return Err(invalid_data("GapList Rice parameter out of range"));
+        }
+        if firsts.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid_data("GapList block firsts decrease"));
+        }
+        let stream = (bits.len() as u64).saturating_mul(64);
+        if offsets.first().is_some_and(|o| *o != 0) || offsets.windows(2).any(|w| w[0] > w[1])
+            || offsets.last().is_some_and(|o| *o > stream) {
+            return Err(invalid_data("GapList block offsets inconsistent with the gaps"));
+        }
+        Ok(GapList {
+            code,
+            block,
+            len,
+            bits,
+            firsts,
+            offsets,
+            params,
+        })
+    }
+}
+
+/// An iterator over the values of a `GapList`, decoding them in order
+pub struct Iter<'a> {
+    list: &'a GapList,
+    reader: BitReader<'a>,
+    /// the index of the next value
+    n: usize,
+    /// the previous value
+    value: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.n == self.list.len {
+            return None;
+        }
+        let b = self.n / self.list.block;
+        self.value = match self.n % self.list.block {
+            0 => self.list.firsts[b],
+            _ => self.value + self.list.read_gap(b, &mut self.reader),
+        };
+        self.n += 1;
+        Some(self.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.list.len - self.n;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::{GapList, GapCode, Postings};
+    use super::super::elias_fano::EliasFano;
+
+    fn sorted(v: Vec<u16>) -> Vec<u64> {
+        let mut v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        v.sort();
+        v
+    }
+
+    fn check<P: Postings>(list: &P, v: &[u64], x: u64) -> bool {
+        let expected = v.iter().position(|y| *y >= x).map(|i| (i, v[i]));
+        (0..v.len()).all(|i| list.get(i) == v[i])
+            && list.next_geq(x) == expected
+    }
+
+    #[quickcheck]
+    fn postings_are_correct(block: usize, v: Vec<u16>, x: u16) -> bool {
+        let v = sorted(v);
+        let x = x as u64;
+        let block = block % 8 + 1;
+        check(&GapList::with_block(GapCode::Rice, block, v.as_slice()), &v, x)
+            && check(&GapList::with_block(GapCode::Gamma, block, v.as_slice()), &v, x)
+            && check(&EliasFano::from_slice(v.as_slice()), &v, x)
+    }
+
+    #[quickcheck]
+    fn iter_is_correct(v: Vec<u16>) -> bool {
+        let v = sorted(v);
+        let list = GapList::with_block(GapCode::Rice, 5, v.as_slice());
+        list.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u16>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let v = sorted(v);
+        let list: GapList = round_trip(&GapList::with_block(GapCode::Rice, 3, v.as_slice()));
+        list.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_deserialize_checks_blocks() {
+        use super::super::serialize::{Serialize, Deserialize};
+        let v: Vec<u64> = (0..20).map(|x| 3 * x).collect();
+        let corrupt = |f: &dyn Fn(&mut GapList)| {
+            let mut list = GapList::with_block(GapCode::Rice, 4, v.as_slice());
+            f(&mut list);
+            let mut buf = Vec::new();
+            list.serialize(&mut buf).unwrap();
+            <GapList as Deserialize>::deserialize(&mut buf.as_slice()).is_err()
+        };
+        assert!(!corrupt(&|_| {}));
+        assert!(corrupt(&|list| list.firsts.swap(1, 2)));
+        assert!(corrupt(&|list| list.offsets.swap(1, 2)));
+        assert!(corrupt(&|list| list.offsets[0] = 1));
+        assert!(corrupt(&|list| *list.offsets.last_mut().unwrap() = 64 * list.bits.len() as u64 + 1));
+    }
+
+    #[test]
+    fn test_rice_param() {
+        // gaps of about 40 cost seven bits each with four, five or six
+        // low bits; the smallest parameter is taken
+        assert_eq!(super::rice_param(&[40, 38, 45, 41, 36]), 4);
+        assert_eq!(super::rice_param(&[1, 0, 2, 1]), 0);
+        assert_eq!(super::rice_param(&[]), 0);
+        // the costs of gaps this large overflow a word
+        assert_eq!(super::rice_param(&[u64::MAX, u64::MAX - 1]), 63);
+    }
+}
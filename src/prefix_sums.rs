@@ -0,0 +1,140 @@
+//! Searchable prefix sums of a sequence of non-negative integers
+//
+// The sums `s_0 = 0, s_1 = x_0, ..., s_n = x_0 + ... + x_{n-1}` form
+// a non-decreasing sequence, stored in Elias-Fano code. Reading a sum
+// is then an access and a search is a successor query.
+
+use std::io::{self, Read, Write};
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::elias_fano::EliasFano;
+use super::serialize::{Serialize, Deserialize, invalid_data};
+use super::space::SpaceUsage;
+
+/// The prefix sums of a sequence of non-negative integers
+#[derive(Clone, Debug)]
+pub struct PrefixSums {
+    sums: EliasFano,
+}
+
+impl PrefixSums {
+    /// The prefix sums of `values`, whose total must fit in a `u64`
+    pub fn from_slice(values: &[u64]) -> PrefixSums {
+        let mut sums = Vec::with_capacity(values.len() + 1);
+        let mut total: u64 = 0;
+        sums.push(0);
+        for x in values.iter() {
+            total += *x;
+            assert!(total >= *x, "PrefixSums: total overflows");
+            sums.push(total);
+        }
+        PrefixSums { sums: EliasFano::from_slice(sums.as_slice()) }
+    }
+
+    /// The sum of the first `i` values
+    pub fn sum(&self, i: usize) -> u64 {
+        self.sums.get(i)
+    }
+
+    /// The sum of all values
+    pub fn total(&self) -> u64 {
+        self.sums.get(self.len())
+    }
+
+    /// The smallest `i` such that `sum(i) >= s`, or `None` if `s`
+    /// exceeds the total
+    pub fn search(&self, s: u64) -> Option<usize> {
+        self.sums.successor(s).map(|(i, _)| i)
+    }
+
+    /// The index of the value whose span `[sum(i), sum(i+1))` contains
+    /// `x`, or `None` if `x` is no less than the total. Taking the
+    /// values as record lengths, this maps an offset to its record.
+    pub fn containing(&self, x: u64) -> Option<usize> {
+        match self.sums.predecessor(x) {
+            Some((i, _)) if i < self.len() => Some(i),
+            _ => None,
+        }
+    }
+}
+
+impl Collection for PrefixSums {
+    fn len(&self) -> usize {
+        self.sums.len() - 1
+    }
+}
+
+impl Access<u64> for PrefixSums {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len(), "PrefixSums: index {} out of bounds", n);
+        let mut sums = self.sums.iter_from(n);
+        let a = sums.next().unwrap();
+        sums.next().unwrap() - a
+    }
+}
+
+impl SpaceUsage for PrefixSums {
+    fn total_bytes(&self) -> usize {
+        self.sums.total_bytes()
+    }
+}
+
+impl Serialize for PrefixSums {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.sums.serialize(w)
+    }
+}
+
+impl Deserialize for PrefixSums {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<PrefixSums> {
+        let sums: EliasFano = Deserialize::deserialize(r)?;
+        if sums.len() == 0 || sums.get(0) != 0 {
+            return Err(invalid_data("PrefixSums must begin with zero"));
+        }
+        Ok(PrefixSums { sums })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PrefixSums;
+    use super::super::dictionary::Access;
+
+    fn sums(v: &[u64]) -> Vec<u64> {
+        let mut out = vec!(0);
+        for x in v.iter() {
+            let last = out[out.len() - 1];
+            out.push(last + *x);
+        }
+        out
+    }
+
+    #[quickcheck]
+    fn sum_is_correct(v: Vec<u32>) -> bool {
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let ps = PrefixSums::from_slice(v.as_slice());
+        let expected = sums(&v);
+        (0..v.len() + 1).all(|i| ps.sum(i) == expected[i])
+            && (0..v.len()).all(|i| ps.get(i) == v[i])
+    }
+
+    #[quickcheck]
+    fn search_is_correct(v: Vec<u8>, s: u16) -> bool {
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let s = s as u64;
+        let ps = PrefixSums::from_slice(v.as_slice());
+        let expected = sums(&v);
+        ps.search(s) == expected.iter().position(|x| *x >= s)
+            && ps.containing(s) == (0..v.len()).find(|i| expected[*i] <= s && s < expected[*i + 1])
+    }
+
+    #[test]
+    fn test_records() {
+        let ps = PrefixSums::from_slice(&[3, 0, 5]);
+        assert_eq!(ps.total(), 8);
+        assert_eq!(ps.containing(2), Some(0));
+        assert_eq!(ps.containing(3), Some(2));
+        assert_eq!(ps.containing(8), None);
+        assert_eq!(ps.search(4), Some(3));
+    }
+}
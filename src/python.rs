@@ -0,0 +1,304 @@
+//! Python bindings, enabled by the `python` feature
+//
+// The module `succinct` exposes `BitVector`, `Rank9`, `Wavelet`, a
+// wavelet tree of 32-bit symbols over `Rank9`, and `FmIndex`. Besides
+// single queries, each class answers a batch of queries given as a
+// numpy array with a numpy array of the answers, so that a batch costs
+// one call rather than one per query. Bitvectors and wavelet trees
+// move between Python and Rust as files of the versioned format of
+// `format`, so indexes built in Rust can be loaded as they are.
+//
+// Following Python, positions and occurrences are counted from zero:
+// `select(bit, n)` is the position of the `n`th matching bit after
+// the first, and an occurrence that doesn't exist raises `IndexError`.
+//
+// The extension is built by maturin, which links it as a cdylib of its
+// own, the crate itself being an rlib, with the feature enabled as
+// `pyproject.toml` configures it:
+//
+//     maturin develop
+
+use std::io::{self, Cursor};
+use numpy::{PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use super::bit_vector::{self, BitVector as RawBitVector};
+use super::build::Builder;
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, Select};
+use super::fm::{self, FmIndex as RawFmIndex, Sampling};
+use super::format::Format;
+use super::rank9::{self, Rank9 as RawRank9};
+use super::wavelet::Wavelet as RawWavelet;
+
+fn value_error(e: io::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn write_format<'py, T: Format>(py: Python<'py>, x: &T) -> Bound<'py, PyBytes> {
+    let mut buf: Vec<u8> = Vec::new();
+    x.write_to(&mut buf).unwrap();
+    PyBytes::new(py, buf.as_slice())
+}
+
+fn read_format<T: Format>(bytes: &[u8]) -> PyResult<T> {
+    Format::read_from(&mut Cursor::new(bytes)).map_err(value_error)
+}
+
+/// Check that position `i` lies within `len`
+fn check(i: usize, len: usize) -> PyResult<()> {
+    match i < len {
+        true => Ok(()),
+        false => Err(PyIndexError::new_err(format!("position {} out of bounds", i))),
+    }
+}
+
+/// The position of the `n`th occurrence of `x`, counting from zero,
+/// given the number of occurrences
+fn select_from_zero<T, S: Select<T>>(s: &S, x: T, n: usize, count: usize) -> PyResult<usize> {
+    match n < count {
+        true => Ok(s.select(x, n + 1) - 1),
+        false => Err(PyIndexError::new_err(format!("occurrence {} out of bounds", n))),
+    }
+}
+
+/// Answer each query of a batch
+fn batch<'py, T: numpy::Element, F>(py: Python<'py>, queries: PyReadonlyArray1<u64>, f: F)
+                                    -> PyResult<Bound<'py, PyArray1<T>>>
+    where F: Fn(usize) -> PyResult<T>
+{
+    let mut out = Vec::with_capacity(queries.as_array().len());
+    for q in queries.as_array().iter() {
+        out.push(f(*q as usize)?);
+    }
+    Ok(PyArray1::from_vec(py, out))
+}
+
+/// A plain bitvector
+#[pyclass(name = "BitVector")]
+pub struct BitVector {
+    inner: RawBitVector,
+}
+
+#[pymethods]
+impl BitVector {
+    #[new]
+    fn new(bits: PyReadonlyArray1<bool>) -> BitVector {
+        let iter = bits.as_array().iter().copied().collect::<Vec<bool>>().into_iter();
+        BitVector { inner: bit_vector::Builder::with_capacity(bits.as_array().len()).from_iter(iter) }
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<BitVector> {
+        read_format(bytes).map(|inner| BitVector { inner })
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        write_format(py, &self.inner)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn get(&self, i: usize) -> PyResult<bool> {
+        check(i, self.inner.len())?;
+        Ok(self.inner.get(i))
+    }
+
+    /// The number of `bit`s before position `i`
+    fn rank(&self, bit: bool, i: usize) -> PyResult<usize> {
+        check(i, self.inner.len() + 1)?;
+        Ok(self.inner.rank(bit, i))
+    }
+
+    fn select(&self, bit: bool, n: usize) -> PyResult<usize> {
+        let count = self.inner.rank(bit, self.inner.len());
+        select_from_zero(&self.inner, bit, n, count)
+    }
+
+    fn get_many<'py>(&self, py: Python<'py>, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<bool>>> {
+        batch(py, positions, |i| self.get(i))
+    }
+
+    fn rank_many<'py>(&self, py: Python<'py>, bit: bool, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        batch(py, positions, |i| self.rank(bit, i).map(|r| r as u64))
+    }
+
+    fn select_many<'py>(&self, py: Python<'py>, bit: bool, ns: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        let count = self.inner.rank(bit, self.inner.len());
+        batch(py, ns, |n| select_from_zero(&self.inner, bit, n, count).map(|p| p as u64))
+    }
+}
+
+/// A bitvector with constant-time rank and fast select
+#[pyclass(name = "Rank9")]
+pub struct Rank9 {
+    inner: RawRank9,
+}
+
+#[pymethods]
+impl Rank9 {
+    #[new]
+    fn new(bits: PyReadonlyArray1<bool>) -> Rank9 {
+        let iter = bits.as_array().iter().copied().collect::<Vec<bool>>().into_iter();
+        Rank9 { inner: rank9::Builder::with_capacity(bits.as_array().len()).from_iter(iter) }
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Rank9> {
+        read_format(bytes).map(|inner| Rank9 { inner })
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        write_format(py, &self.inner)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn get(&self, i: usize) -> PyResult<bool> {
+        check(i, self.inner.len())?;
+        Ok(self.inner.get(i))
+    }
+
+    /// The number of `bit`s before position `i`
+    fn rank(&self, bit: bool, i: usize) -> PyResult<usize> {
+        check(i, self.inner.len() + 1)?;
+        Ok(self.inner.rank(bit, i))
+    }
+
+    fn select(&self, bit: bool, n: usize) -> PyResult<usize> {
+        let count = self.inner.rank(bit, self.inner.len());
+        select_from_zero(&self.inner, bit, n, count)
+    }
+
+    fn get_many<'py>(&self, py: Python<'py>, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<bool>>> {
+        batch(py, positions, |i| self.get(i))
+    }
+
+    fn rank_many<'py>(&self, py: Python<'py>, bit: bool, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        batch(py, positions, |i| self.rank(bit, i).map(|r| r as u64))
+    }
+
+    fn select_many<'py>(&self, py: Python<'py>, bit: bool, ns: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        let count = self.inner.rank(bit, self.inner.len());
+        batch(py, ns, |n| select_from_zero(&self.inner, bit, n, count).map(|p| p as u64))
+    }
+}
+
+/// A wavelet tree over a sequence of 32-bit symbols
+#[pyclass(name = "Wavelet")]
+pub struct Wavelet {
+    inner: RawWavelet<RawRank9, u32>,
+}
+
+#[pymethods]
+impl Wavelet {
+    #[new]
+    fn new(symbols: PyReadonlyArray1<u32>) -> Wavelet {
+        let symbols: Vec<u32> = symbols.as_array().iter().copied().collect();
+        Wavelet { inner: RawWavelet::from_slice(symbols.as_slice(), || rank9::Builder::with_capacity(128)) }
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Wavelet> {
+        read_format(bytes).map(|inner| Wavelet { inner })
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        write_format(py, &self.inner)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn get(&self, i: usize) -> PyResult<u32> {
+        check(i, self.inner.len())?;
+        Ok(self.inner.get(i))
+    }
+
+    /// The number of occurrences of `sym` before position `i`
+    fn rank(&self, sym: u32, i: usize) -> PyResult<usize> {
+        check(i, self.inner.len() + 1)?;
+        Ok(self.inner.rank(sym, i))
+    }
+
+    fn select(&self, sym: u32, n: usize) -> PyResult<usize> {
+        let count = self.inner.rank(sym, self.inner.len());
+        select_from_zero(&self.inner, sym, n, count)
+    }
+
+    fn get_many<'py>(&self, py: Python<'py>, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u32>>> {
+        batch(py, positions, |i| self.get(i))
+    }
+
+    fn rank_many<'py>(&self, py: Python<'py>, sym: u32, positions: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        batch(py, positions, |i| self.rank(sym, i).map(|r| r as u64))
+    }
+
+    fn select_many<'py>(&self, py: Python<'py>, sym: u32, ns: PyReadonlyArray1<u64>) -> PyResult<Bound<'py, PyArray1<u64>>> {
+        let count = self.inner.rank(sym, self.inner.len());
+        batch(py, ns, |n| select_from_zero(&self.inner, sym, n, count).map(|p| p as u64))
+    }
+}
+
+/// An FM-index of a text, sampling the positions of the suffixes
+/// starting at multiples of `rate`
+#[pyclass(name = "FmIndex")]
+pub struct FmIndex {
+    inner: RawFmIndex,
+}
+
+#[pymethods]
+impl FmIndex {
+    #[new]
+    #[pyo3(signature = (text, rate = fm::DEFAULT_RATE))]
+    fn new(text: &[u8], rate: usize) -> PyResult<FmIndex> {
+        if rate == 0 {
+            return Err(PyValueError::new_err("the sampling rate must be positive"));
+        }
+        Ok(FmIndex { inner: RawFmIndex::with_sampling(text, Sampling::TextOrder(rate)) })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// The number of occurrences of `pattern`
+    fn count(&self, pattern: &[u8]) -> usize {
+        self.inner.count(pattern)
+    }
+
+    /// The positions of the occurrences of `pattern`, in increasing
+    /// order
+    fn locate<'py>(&self, py: Python<'py>, pattern: &[u8]) -> Bound<'py, PyArray1<u64>> {
+        let positions = self.inner.locate(pattern);
+        PyArray1::from_vec(py, positions.into_iter().map(|p| p as u64).collect())
+    }
+
+    /// The number of occurrences of each pattern
+    fn count_many<'py>(&self, py: Python<'py>, patterns: Vec<Vec<u8>>) -> Bound<'py, PyArray1<u64>> {
+        PyArray1::from_vec(py, patterns.iter().map(|p| self.inner.count(p.as_slice()) as u64).collect())
+    }
+
+    /// The text in `[i, j)`
+    fn extract<'py>(&self, py: Python<'py>, i: usize, j: usize) -> PyResult<Bound<'py, PyBytes>> {
+        if i > j || j > self.inner.len() {
+            return Err(PyIndexError::new_err(format!("range [{}, {}) out of bounds", i, j)));
+        }
+        Ok(PyBytes::new(py, self.inner.extract(i, j).as_slice()))
+    }
+}
+
+#[pymodule]
+fn succinct(m: &Bound<PyModule>) -> PyResult<()> {
+    (m.add_class::<BitVector>())?;
+    (m.add_class::<Rank9>())?;
+    (m.add_class::<Wavelet>())?;
+    (m.add_class::<FmIndex>())?;
+    Ok(())
+}
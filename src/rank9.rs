@@ -7,16 +7,29 @@
 // See Vigna 2014.
 
 use std::cmp::{min, Ordering};
-use std::num::Int;
-use std::iter::range_step_inclusive;
-use std::ops::Shr;
 use super::dictionary::{Rank, BitRank, Select, Access};
 use super::collection::Collection;
+use super::error::{self, check_len};
+use super::bits::{Bits, pack_bools, pack_bytes};
+use super::build::{BitOrder, push_words};
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use super::utils::same_bits;
+use super::digest::{ContentDigest, bits_digest};
+use std::hash::{Hash, Hasher};
+use super::space::{SpaceUsage, vec_bytes};
+use super::view::{View, take_uint, take_words};
+use super::frozen::{self, Freeze, Thaw, Region};
+use std::mem::size_of;
+use std::io::{self, Read, Write};
+use std::slice;
+use std::convert::From;
+use std::ops::Index;
 
-pub use rank9::build::Builder;
+pub use crate::rank9::build::{Builder, StreamBuilder};
 
-/// Counts for a basic block
-#[derive(Copy)]
+/// Counts for a basic block, laid out as its two serialized words
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
 pub struct Counts {
     /// first level count (rank up to p)
     _block_rank: u64,
@@ -26,12 +39,12 @@ pub struct Counts {
 
 impl Counts {
     /// The rank within the block up to but not including the `i`th broadword
-    fn word_rank(&self, bit:bool, i: uint) -> uint {
+    fn word_rank(&self, bit:bool, i: usize) -> usize {
         debug_assert!(i < 8);
         match i {
             0 => 0,
             _ => {
-                let ones = ((self.word_ranks >> (9*(i-1))) & 0x1ff) as uint;
+                let ones = ((self.word_ranks >> (9*(i-1))) & 0x1ff) as usize;
                 if bit {
                     ones
                 } else {
@@ -43,7 +56,7 @@ impl Counts {
 
     /// The number of matching bits in blocks up to but not including
     /// `block_idx`
-    fn block_rank(&self, bit: bool, block_idx: uint) -> u64 {
+    fn block_rank(&self, bit: bool, block_idx: usize) -> u64 {
         match bit {
             true => self._block_rank,
             false => 64*8*(block_idx as u64) - self._block_rank,
@@ -52,50 +65,241 @@ impl Counts {
 
     /// Search for the word that contains the `n`th matching bit
     /// within this block
-    fn select_word(&self, bit: bool, n: uint) -> uint {
-        for i in range(0,7) {
+    fn select_word(&self, bit: bool, n: usize) -> usize {
+        for i in 0..7 {
             if n <= self.word_rank(bit, i + 1) {
                 return i;
             }
         }
-        return 7;
+        7
+    }
+}
+
+impl Serialize for Counts {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, self._block_rank)?;
+        write_u64(w, self.word_ranks)
+    }
+}
+
+impl Deserialize for Counts {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Counts> {
+        let block_rank = read_u64(r)?;
+        let word_ranks = read_u64(r)?;
+        Ok(Counts { _block_rank: block_rank, word_ranks })
     }
 }
 
 /// Bitvector supporting efficient rank and select
+#[derive(Clone, Debug)]
 pub struct Rank9 {
     /// length of bitvector in bits
-    bits: int,
+    bits: usize,
     /// the bitvector data
     buffer: Vec<u64>,
     /// the basic block counts
     counts: Vec<Counts>,
+    /// the content digest of the bits
+    digest: u64,
+}
+
+/// The `i`th bit of the vector is the `i`th of the slice
+impl<'a> From<&'a [bool]> for Rank9 {
+    fn from(bits: &'a [bool]) -> Rank9 {
+        Rank9::from_words(pack_bools(bits), bits.len())
+    }
+}
+
+impl From<Vec<bool>> for Rank9 {
+    fn from(bits: Vec<bool>) -> Rank9 {
+        Rank9::from(bits.as_slice())
+    }
+}
+
+/// A `Rank9` borrowing its words, as viewed in a serialization held
+/// in memory
+#[derive(Copy, Clone)]
+pub struct Rank9Ref<'a> {
+    bits: usize,
+    buffer: &'a [u64],
+    counts: &'a [Counts],
+}
+
+impl Rank9 {
+    /// Borrow the bitvector
+    pub fn view(&self) -> Rank9Ref<'_> {
+        Rank9Ref { bits: self.bits, buffer: self.buffer.as_slice(), counts: self.counts.as_slice() }
+    }
+
+    /// The words holding the bits, least significant bit first
+    pub fn words(&self) -> &[u64] {
+        self.buffer.as_slice()
+    }
+
+    /// An iterator over the bits
+    pub fn iter(&self) -> Bits<'_> {
+        Bits::new(self.buffer.as_slice(), self.bits)
+    }
 }
 
 impl Access<bool> for Rank9 {
-    fn get(&self, n: uint) -> bool {
+    fn get(&self, n: usize) -> bool {
+        self.view().get(n)
+    }
+}
+
+impl<'a> Access<bool> for Rank9Ref<'a> {
+    fn get(&self, n: usize) -> bool {
         let word = self.buffer[n / 64];
         (word >> (n % 64)) & 1 == 1
     }
 }
 
+impl<'a> Collection for Rank9Ref<'a> {
+    fn len(&self) -> usize {
+        self.bits
+    }
+}
+
+/// The empty bitvector
+impl Default for Rank9 {
+    fn default() -> Rank9 {
+        Rank9::from_words(Vec::new(), 0)
+    }
+}
+
+/// The `i`th bit, as given by `get`
+impl Index<usize> for Rank9 {
+    type Output = bool;
+    fn index(&self, i: usize) -> &bool {
+        if self.get(i) { &true } else { &false }
+    }
+}
+
+impl<'a> IntoIterator for &'a Rank9 {
+    type Item = bool;
+    type IntoIter = Bits<'a>;
+    fn into_iter(self) -> Bits<'a> {
+        self.iter()
+    }
+}
+
 impl Collection for Rank9 {
-    fn len(&self) -> uint {
-        self.bits as uint
+    fn len(&self) -> usize {
+        self.bits
     }
 }
 
-impl Rank9 {
+impl Serialize for Rank9 {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.bits)?;
+        self.buffer.serialize(w)?;
+        self.counts.serialize(w)
+    }
+}
+
+impl Deserialize for Rank9 {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Rank9> {
+        let bits = read_uint(r)?;
+        let buffer: Vec<u64> = Deserialize::deserialize(r)?;
+        let counts: Vec<Counts> = Deserialize::deserialize(r)?;
+        check_len(bits, buffer.len())?;
+        if counts.len() != buffer.len() / 8 + 1 {
+            return Err(invalid_data("Rank9 counts inconsistent with buffer"));
+        }
+        Ok(Rank9::from_counts(buffer, bits, counts))
+    }
+}
+
+impl<'a> View<'a> for Rank9Ref<'a> {
+    fn view(words: &mut &'a [u64]) -> io::Result<Rank9Ref<'a>> {
+        let bits = take_uint(words)?;
+        let buffer = take_words(words)?;
+        let len = take_uint(words)?;
+        if len > words.len() / 2 {
+            return Err(invalid_data("Rank9 counts exceed the buffer"));
+        }
+        let (counts, rest) = words.split_at(2 * len);
+        *words = rest;
+        check_len(bits, buffer.len())?;
+        if len != buffer.len() / 8 + 1 {
+            return Err(invalid_data("Rank9 counts inconsistent with buffer"));
+        }
+        // `Counts` is laid out as its two words
+        let counts = unsafe { slice::from_raw_parts(counts.as_ptr() as *const Counts, len) };
+        Ok(Rank9Ref { bits, buffer, counts })
+    }
+}
+
+/// The number of bits, then the words and the counts
+impl Freeze for Rank9 {
+    fn freeze(&self, out: &mut Vec<u64>) {
+        let counts: Vec<u64> = self.counts.iter()
+            .flat_map(|c| vec!(c._block_rank, c.word_ranks).into_iter())
+            .collect();
+        let mut region = Region::new(out, 5);
+        region.set(0, self.bits as u64);
+        region.array(1, self.buffer.as_slice());
+        region.array(3, counts.as_slice());
+        region.finish()
+    }
+}
+
+impl<'a> Thaw<'a> for Rank9Ref<'a> {
+    fn thaw(region: &'a [u64]) -> io::Result<Rank9Ref<'a>> {
+        let bits = frozen::uint_field(region, 0)?;
+        let buffer = frozen::array(region, 1)?;
+        let counts = frozen::array(region, 3)?;
+        check_len(bits, buffer.len())?;
+        if counts.len() != 2 * (buffer.len() / 8 + 1) {
+            return Err(invalid_data("Rank9 counts inconsistent with buffer"));
+        }
+        // `Counts` is laid out as its two words
+        let counts = unsafe { slice::from_raw_parts(counts.as_ptr() as *const Counts, counts.len() / 2) };
+        Ok(Rank9Ref { bits, buffer, counts })
+    }
+}
+
+/// Bitvectors are equal when they hold the same bits
+impl PartialEq for Rank9 {
+    fn eq(&self, other: &Rank9) -> bool {
+        self.bits == other.bits && self.digest == other.digest
+            && same_bits(self.buffer.as_slice(), other.buffer.as_slice(), self.bits)
+    }
+}
+
+impl Eq for Rank9 {}
+
+impl Hash for Rank9 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.digest.hash(state);
+    }
+}
+
+/// The digest computed when the bitvector was built
+impl ContentDigest for Rank9 {
+    fn content_digest(&self) -> u64 {
+        self.digest
+    }
+}
+
+impl SpaceUsage for Rank9 {
+    fn total_bytes(&self) -> usize {
+        size_of::<usize>() + vec_bytes(&self.buffer) + vec_bytes(&self.counts) + size_of::<u64>()
+    }
+}
+
+impl<'a> Rank9Ref<'a> {
     /// Search for the block that contains the `n`th matching bit
-    fn select_block(&self, bit: bool, n: uint) -> uint {
+    fn select_block(&self, bit: bool, n: usize) -> usize {
         debug_assert!(n > 0);
         self.select_block_hlpr(bit, n, 0, self.counts.len())
         // todo LD: can we have default settings for method arguments?
     }
 
 
-    fn select_block_hlpr(&self, bit:bool, n:uint, lower:uint, upper:uint) -> uint {
-            let block_search: Result<uint,uint> =
+    fn select_block_hlpr(&self, bit:bool, n:usize, lower:usize, upper:usize) -> usize {
+            let block_search: Result<usize,usize> =
                 binary_search(|idx| self.counts[*idx].block_rank(bit, *idx).cmp(&(n as u64)),
                               lower, upper);
             let start_block = match block_search {
@@ -106,9 +310,9 @@ impl Rank9 {
             // we found a block that is potentially surrounded by blocks
             // with the same block rank; we need to find the next matching
             // bit
-            for block_idx in range_step_inclusive(start_block as int, 0, -1) {
-                if self.counts[block_idx as uint].block_rank(bit, block_idx as uint) != n as u64 {
-                    return block_idx as uint;
+            for block_idx in (0..start_block + 1).rev() {
+                if self.counts[block_idx].block_rank(bit, block_idx) != n as u64 {
+                    return block_idx;
                 }
             }
             self.counts.len() - 1
@@ -116,22 +320,23 @@ impl Rank9 {
 
 
     /// implements the recursion of select_all_blocks
-    fn select_all_blocks_rec(&self, bit:bool, ns:Vec<uint>, lower:uint, upper:uint) -> Vec<uint> {
+    #[allow(dead_code)]
+    fn select_all_blocks_rec(&self, bit:bool, ns:Vec<usize>, lower:usize, upper:usize) -> Vec<usize> {
         let idx = ns.len()/2;
         let pos = self.select_block_hlpr(bit, ns[idx], lower, upper);
 
-        let left_result: Vec<uint> = if idx > 0 {
+        let left_result: Vec<usize> = if idx > 0 {
                 self.select_all_blocks_rec(bit, ns[0..idx].to_vec(), lower, pos)
             } else {vec!()};
-        let right_result: Vec<uint> = if idx < ns.len()-1 {
+        let right_result: Vec<usize> = if idx < ns.len()-1 {
                 self.select_all_blocks_rec(bit, ns[idx+1..ns.len()].to_vec(), pos+1, upper)
             } else {vec!()};
 
         // leftResult ++ (pos) ++ rightResult
         let mut result = Vec::with_capacity(ns.len());
-        result.push_all(left_result.as_slice());
+        result.extend_from_slice(left_result.as_slice());
         result.push(pos);
-        result.push_all(right_result.as_slice());
+        result.extend_from_slice(right_result.as_slice());
         result
     }
 
@@ -139,40 +344,100 @@ impl Rank9 {
     /// naive implementation of multiple selecs = O( ns.len() * log(bitv))
     /// this implementation = O(ns.len * log log(bitv) )
     /// = [ log(bitv) + 2 log(bitv/2) + 4 log(bitv/4) ...]
-    fn select_all_blocks(&self, bit:bool, ns: Vec<uint>) -> Vec<uint> {
+    #[allow(dead_code)]
+    fn select_all_blocks(&self, bit:bool, ns: Vec<usize>) -> Vec<usize> {
         self.select_all_blocks_rec(bit, ns, 0, self.counts.len())
     }
+}
 
-    pub fn from_vec<'a>(v: &'a Vec<u64>, length_in_bits: int) -> Rank9 {
+impl Rank9 {
+    /// The first `length_in_bits` bits of `v`, which must hold them
+    pub fn from_vec(v: &[u64], length_in_bits: usize) -> error::Result<Rank9> {
+        check_len(length_in_bits, v.len())?;
+        Ok(Rank9::from_words(v.to_vec(), length_in_bits))
+    }
+
+    /// The first `bits` bits of `bytes`, the `i`th bit being bit
+    /// `i % 8` of byte `i / 8` as in the words of the vector, which
+    /// must hold them
+    pub fn from_bytes(bytes: &[u8], bits: usize) -> error::Result<Rank9> {
+        Ok(Rank9::from_words(pack_bytes(bytes, bits)?, bits))
+    }
+
+    /// The bitvector of `len` bits whose `i`th bit is `f(i)`
+    pub fn from_fn<F: Fn(usize) -> bool>(len: usize, f: F) -> Rank9 {
+        use super::build::Builder;
+        build::Builder::with_capacity(len).from_iter((0..len).map(f))
+    }
+
+    /// The bits of the bytes read from `r` to its end, taken in
+    /// `order`, without buffering the input
+    pub fn from_reader<R: Read>(r: &mut R, order: BitOrder) -> io::Result<Rank9> {
         use super::build::Builder;
-        let mut builder = build::CountsBuilder::with_capacity(v.len());
-        for x in v.iter() {
+        let mut builder = build::WordBuilder::with_capacity(0);
+        let bits = push_words(r, order, &mut builder)?;
+        let (words, counts) = builder.finish();
+        Ok(Rank9::from_counts(words, bits, counts))
+    }
+
+    /// The first `bits` bits of `words`, taking ownership of them and
+    /// building the counts with `threads` threads. This pays off for
+    /// vectors of many millions of words.
+    pub fn from_words_parallel(words: Vec<u64>, bits: usize, threads: usize) -> error::Result<Rank9> {
+        check_len(bits, words.len())?;
+        let counts = build::parallel_counts(words.as_slice(), threads);
+        Ok(Rank9::from_counts(words, bits, counts))
+    }
+
+    /// The first `bits` bits of `words`, taking ownership of them
+    fn from_words(words: Vec<u64>, bits: usize) -> Rank9 {
+        use super::build::Builder;
+        let mut builder = build::CountsBuilder::with_capacity(words.len());
+        for x in words.iter() {
             builder.push(*x);
         }
-        return Rank9 {
-            bits: length_in_bits,
-            buffer: v.clone(), // TODO: no clone
-            counts: builder.finish(),
-        };
+        Rank9::from_counts(words, bits, builder.finish())
+    }
+
+    /// The first `bits` bits of `words`, whose counts are `counts`
+    fn from_counts(words: Vec<u64>, bits: usize, counts: Vec<Counts>) -> Rank9 {
+        let digest = bits_digest(words.as_slice(), bits);
+        Rank9 { bits, buffer: words, counts, digest }
     }
 }
 
 impl Rank<bool> for Rank9 {
-    fn rank(&self, el: bool, n: int) -> int {
+    fn rank(&self, el: bool, n: usize) -> usize {
+        if el {self.rank1(n)} else {self.rank0(n)}
+    }
+}
+
+impl<'a> Rank<bool> for Rank9Ref<'a> {
+    fn rank(&self, el: bool, n: usize) -> usize {
         if el {self.rank1(n)} else {self.rank0(n)}
     }
 }
 
 impl BitRank for Rank9 {
-    fn rank1(&self, n: int) -> int {
+    fn rank1(&self, n: usize) -> usize {
+        self.view().rank1(n)
+    }
+
+    fn rank0(&self, n: usize) -> usize {
+        n - self.rank1(n)
+    }
+}
+
+impl<'a> BitRank for Rank9Ref<'a> {
+    fn rank1(&self, n: usize) -> usize {
         assert!(n <= self.bits);
         let n = min(n, self.bits);
         let word = n / 64;
         let bit_idx = n % 64;
         let block = word / 8;
         let block_word = word % 8;
-        let counts = &self.counts[block as uint];
-        let t: int = block_word - 1;
+        let counts = &self.counts[block];
+        let t = block_word as isize - 1;
 
         // compute second-level contribution
         // This is a bit tricky to avoid an unnecessary branch; functionally,
@@ -184,21 +449,22 @@ impl BitRank for Rank9 {
         // };
         // ```
         let shift = (t + ((t >> 60) & 8)) * 9;
-        let word_rank = (counts.word_ranks >> (shift as uint)) & 0x1ff;
+        let word_rank = (counts.word_ranks >> (shift as usize)) & 0x1ff;
 
-        // TODO: kill me
-        match block_word {
-            0 => {},
-            _ => assert_eq!(counts.word_rank(true, block_word as uint), word_rank as uint),
+        // within-word contribution; when `n` is word-aligned this word
+        // may lie past the end of the buffer
+        let masked = match bit_idx {
+            0 => 0,
+            _ => self.buffer[word] & ((1 << bit_idx) - 1),
         };
 
-        // within-word contribution
-        let masked = self.buffer[word as uint] & ((1 << (bit_idx as uint)) - 1);
-
-        (counts._block_rank + word_rank + masked.count_ones() as u64) as int
+        let rank = (counts._block_rank + word_rank + masked.count_ones() as u64) as usize;
+        #[cfg(feature = "verify")]
+        self.verify_rank1(n, word_rank as usize, rank);
+        rank
     }
 
-    fn rank0(&self, n: int) -> int {
+    fn rank0(&self, n: usize) -> usize {
         n - self.rank1(n)
     }
 }
@@ -207,53 +473,92 @@ impl BitRank for Rank9 {
 /// If the predicate returns `Equal`, `Ok` with the matching index
 /// is returned. Otherwise, `Err` is returned with the index of a
 /// valid insertion point.
-fn binary_search<T: Shr<T> + Ord + Int + Clone, F>(cmp: F, lower: T, upper: T) -> Result<T,T>
-    where F: Fn(&T) -> Ordering
+fn binary_search<F>(cmp: F, lower: usize, upper: usize) -> Result<usize, usize>
+    where F: Fn(&usize) -> Ordering
 {
-    let mut base : T = lower.clone();
-    let mut lim : T = upper.clone();
+    let mut base = lower;
+    let mut lim = upper;
 
     while lim > lower {
-        let ix = base + (lim >> 1u);
+        let ix = base + (lim >> 1);
         match cmp(&ix) {
             Ordering::Equal => return Ok(ix),
             Ordering::Less => {
-                base = ix + Int::one();
-                lim = lim - Int::one();
+                base = ix + 1;
+                lim -= 1;
             }
             Ordering::Greater => ()
         }
-        lim = lim >> 1u;
+        lim >>= 1;
     }
-    return Err(base);
+    Err(base)
 }
 
 impl Select<bool> for Rank9 {
-    fn select(&self, bit: bool, n: int) -> int {
+    fn select(&self, bit: bool, n: usize) -> usize {
+        self.view().select(bit, n)
+    }
+}
+
+impl<'a> Select<bool> for Rank9Ref<'a> {
+    fn select(&self, bit: bool, n: usize) -> usize {
         // uses `laura-select`
-        debug_assert!(n >= 0);
 
         if n == 0 { return 0; }
-        let block_idx = self.select_block(bit, n as uint);
+        let block_idx = self.select_block(bit, n);
         let counts = &self.counts[block_idx];
-        let mut remaining = n - counts.block_rank(bit, block_idx) as int;
-        let word_idx = counts.select_word(bit, remaining as uint);
+        let mut remaining = n - counts.block_rank(bit, block_idx) as usize;
+        let word_idx = counts.select_word(bit, remaining);
         let word: u64 = self.buffer[word_idx + 8*block_idx];
-        remaining -= counts.word_rank(bit, word_idx) as int;
-        (block_idx as int)*64*8 + (word_idx as int) * 64 + word.select(bit, remaining)
+        remaining -= counts.word_rank(bit, word_idx);
+        let pos = block_idx*64*8 + word_idx * 64 + word.select(bit, remaining);
+        #[cfg(feature = "verify")]
+        self.verify_select(bit, n, pos);
+        pos
+    }
+}
+
+/// Checks of query results against the words themselves, made by every
+/// query when the `verify` feature is enabled
+#[cfg(feature = "verify")]
+impl<'a> Rank9Ref<'a> {
+    /// Check the word rank read from the packed counts and the rank of
+    /// the first `n` bits against a count of their ones
+    fn verify_rank1(&self, n: usize, word_rank: usize, rank: usize) {
+        let word = n / 64;
+        let block_word = word % 8;
+        assert_eq!(self.counts[word / 8].word_rank(true, block_word), word_rank,
+                   "Rank9: bad word rank of word {}", word);
+        let mut ones: usize = self.buffer[..word].iter().map(|w| w.count_ones() as usize).sum();
+        if !n.is_multiple_of(64) {
+            ones += (self.buffer[word] & ((1 << (n % 64)) - 1)).count_ones() as usize;
+        }
+        assert_eq!(rank, ones, "Rank9: bad rank of the first {} bits", n);
+    }
+
+    /// Check that the `n`th matching bit lies just before `pos`
+    fn verify_select(&self, bit: bool, n: usize, pos: usize) {
+        assert!(pos <= self.bits && self.get(pos - 1) == bit && self.rank(bit, pos) == n,
+                "Rank9: bad select of the {}th {} bit: {}", n, bit, pos);
     }
 }
 
 mod build {
-    use std::num::Int;
+    use std::io::{self, Read, Write};
+    use std::cmp::{min, max};
+    
+    use std::iter::repeat_n;
+    use std::thread;
     use super::super::build;
+    use super::super::collection::Collection;
+    use super::super::serialize::{Serialize, Deserialize, write_uint, invalid_data};
     use super::{Counts, Rank9};
-    use utils::div_ceil;
+    use crate::utils::div_ceil;
 
     /// Build up the counts metadata for rank-9 from a stream of `u64`s
     pub struct CountsBuilder {
         /// length in broadwords
-        length: uint,
+        length: usize,
         counts: Vec<Counts>,
         /// accumulate `Counts` for the current block
         accum: Counts,
@@ -265,17 +570,23 @@ mod build {
 
     impl CountsBuilder {
         /// Create a `CountsBuilder` with capacity for `cap` broadwords.
-        pub fn with_capacity(cap: uint) -> CountsBuilder {
-            let n_blocks = div_ceil(cap, 64*8);
+        pub fn with_capacity(cap: usize) -> CountsBuilder {
             CountsBuilder {
                 length: 0,
-                counts: Vec::with_capacity(n_blocks as uint),
+                counts: Vec::with_capacity(cap / 8 + 1),
                 accum: Counts { _block_rank: 0, word_ranks: 0 },
                 block_accum: 0,
                 rank_accum: 0,
             }
         }
 
+        /// Make room for the counts of at least `additional` more
+        /// broadwords
+        fn reserve(&mut self, additional: usize) {
+            let blocks = (self.length + additional) / 8 + 1 - self.counts.len();
+            self.counts.reserve(blocks)
+        }
+
         fn push_block(&mut self) {
             self.counts.push(self.accum);
             self.block_accum = 0;
@@ -299,15 +610,74 @@ mod build {
         }
 
         fn finish(mut self) -> Vec<Counts> {
-            // Finish up final partial block
-            while self.length % 8 != 0 {
+            // Finish up final partial block. We always push at least
+            // one word so there is a block covering the end of the
+            // vector and `rank1(len)` is defined.
+            loop {
                 self.push(0);
+                if self.length.is_multiple_of(8) { break; }
             }
             self.counts
         }
     }
 
-    /// Build a rank-9 bitvector from broadwords
+    /// The counts of a block of at most 8 `words` with `block_rank`
+    /// ones before it
+    fn block_counts(words: &[u64], block_rank: u64) -> Counts {
+        let mut word_ranks = 0;
+        let mut ones = 0;
+        for i in 1..8 {
+            if i <= words.len() {
+                ones += words[i - 1].count_ones() as u64;
+            }
+            word_ranks |= ones << (9*(i-1));
+        }
+        Counts { _block_rank: block_rank, word_ranks }
+    }
+
+    /// Fill `counts` with those of the blocks of `words` from
+    /// `first_block` on, with `rank` ones before the first
+    fn fill_counts(words: &[u64], first_block: usize, mut rank: u64, counts: &mut [Counts]) {
+        for (k, c) in counts.iter_mut().enumerate() {
+            let start = min(8*(first_block + k), words.len());
+            let end = min(start + 8, words.len());
+            let block = &words[start..end];
+            *c = block_counts(block, rank);
+            rank += block.iter().map(|w| w.count_ones() as u64).sum::<u64>();
+        }
+    }
+
+    /// The counts of `words`, as built by a `CountsBuilder`, computed
+    /// by `threads` threads each taking a run of blocks: the ones of
+    /// each run are counted in parallel, summed to give the rank
+    /// before each, and the counts of each run filled in parallel.
+    pub fn parallel_counts(words: &[u64], threads: usize) -> Vec<Counts> {
+        // there is always a block covering the end of the vector
+        let n_blocks = words.len() / 8 + 1;
+        let per_thread = div_ceil(n_blocks, max(threads, 1));
+        let mut counts: Vec<Counts> = repeat_n(Counts { _block_rank: 0, word_ranks: 0 }, n_blocks).collect();
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..n_blocks).step_by(per_thread).map(|first| {
+                let run = &words[min(8*first, words.len())..min(8*(first + per_thread), words.len())];
+                scope.spawn(move || run.iter().map(|w| w.count_ones() as u64).sum::<u64>())
+            }).collect();
+            let mut rank = 0;
+            let ranks: Vec<u64> = handles.into_iter().map(|h| {
+                let before = rank;
+                rank += h.join().unwrap();
+                before
+            }).collect();
+
+            for (i, (run, rank)) in counts.chunks_mut(per_thread).zip(ranks).enumerate() {
+                scope.spawn(move || fill_counts(words, i*per_thread, rank, run));
+            }
+        });
+        counts
+    }
+
+    /// Build the words and counts of a rank-9 bitvector from
+    /// broadwords
     pub struct WordBuilder {
         builder: CountsBuilder,
         buffer: Vec<u64>,
@@ -315,7 +685,7 @@ mod build {
 
     impl WordBuilder {
         /// Create a `WordBuilder` with capacity for `cap` broadwords
-        pub fn with_capacity(cap: uint) -> WordBuilder {
+        pub fn with_capacity(cap: usize) -> WordBuilder {
             WordBuilder {
                 builder: CountsBuilder::with_capacity(cap),
                 buffer: Vec::with_capacity(cap),
@@ -323,17 +693,41 @@ mod build {
         }
     }
 
-    impl build::Builder<u64, Rank9> for WordBuilder {
+    /// Checkpointed as the words pushed, the counts being rebuilt
+    /// when resumed
+    impl Serialize for WordBuilder {
+        fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            self.buffer.serialize(w)
+        }
+    }
+
+    impl Deserialize for WordBuilder {
+        fn deserialize<R: Read>(r: &mut R) -> io::Result<WordBuilder> {
+            use super::super::build::Builder;
+            let words: Vec<u64> = Deserialize::deserialize(r)?;
+            let mut builder = WordBuilder::with_capacity(words.len());
+            for word in words.into_iter() {
+                builder.push(word);
+            }
+            Ok(builder)
+        }
+    }
+
+    impl WordBuilder {
+        /// Make room for at least `additional` more broadwords
+        fn reserve(&mut self, additional: usize) {
+            self.builder.reserve(additional);
+            self.buffer.reserve(additional);
+        }
+    }
+
+    impl build::Builder<u64, (Vec<u64>, Vec<Counts>)> for WordBuilder {
         fn push(&mut self, word: u64) {
             self.builder.push(word);
             self.buffer.push(word);
         }
-        fn finish(self) -> Rank9 {
-            Rank9 {
-                bits: 64*self.builder.length as int,
-                buffer: self.buffer,
-                counts: self.builder.finish(),
-            }
+        fn finish(self) -> (Vec<u64>, Vec<Counts>) {
+            (self.buffer, self.builder.finish())
         }
     }
 
@@ -344,12 +738,85 @@ mod build {
 
     impl Builder {
         /// Build a rank-9 bitvector with capacity for `cap` bits
-        pub fn with_capacity(cap: uint) -> Builder {
-            let b: WordBuilder = WordBuilder::with_capacity(64*cap);
+        pub fn with_capacity(cap: usize) -> Builder {
+            let b: WordBuilder = WordBuilder::with_capacity(div_ceil(cap, 64));
             Builder {
                 builder: build::BitBuilder::new(b)
             }
         }
+
+        /// Build a rank-9 bitvector starting with `len` zeros
+        pub fn with_len(len: usize) -> Builder {
+            let mut builder = Builder::with_capacity(len);
+            for _ in 0..len / 64 {
+                builder.push_word(0);
+            }
+            builder.push_bits(0, len % 64);
+            builder
+        }
+
+        /// The number of bits pushed
+        pub fn len(&self) -> usize {
+            self.builder.len()
+        }
+
+        /// Whether no bits have been pushed
+        pub fn is_empty(&self) -> bool {
+            self.builder.is_empty()
+        }
+
+        /// The number of bits that can be held without reallocating
+        pub fn capacity(&self) -> usize {
+            64 * self.builder.get_ref().buffer.capacity()
+        }
+
+        /// Make room for at least `additional` more bits
+        pub fn reserve(&mut self, additional: usize) {
+            let words = div_ceil(self.len() + additional, 64) - self.len() / 64;
+            self.builder.get_mut().reserve(words)
+        }
+    }
+
+    impl Builder {
+        /// Push the `width` least significant bits of `value`, least
+        /// significant first
+        pub fn push_bits(&mut self, value: u64, width: usize) {
+            self.builder.push_bits(value, width)
+        }
+
+        /// Push the 64 bits of `word`, least significant first
+        pub fn push_word(&mut self, word: u64) {
+            self.builder.push_word(word)
+        }
+    }
+
+    impl build::Buildable<bool> for Rank9 {
+        type Builder = Builder;
+        fn new_builder() -> Builder {
+            Builder::with_capacity(64)
+        }
+    }
+
+    /// A checkpoint of the bits pushed so far, from which the build
+    /// can be resumed
+    impl Serialize for Builder {
+        fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+            self.builder.serialize(w)
+        }
+    }
+
+    impl Deserialize for Builder {
+        fn deserialize<R: Read>(r: &mut R) -> io::Result<Builder> {
+            let builder = Deserialize::deserialize(r)?;
+            Ok(Builder { builder })
+        }
+    }
+
+    /// The bits pushed so far
+    impl Collection for Builder {
+        fn len(&self) -> usize {
+            self.builder.len()
+        }
     }
 
     impl build::Builder<bool, Rank9> for Builder {
@@ -357,60 +824,167 @@ mod build {
             self.builder.push(bit)
         }
         fn finish(self) -> Rank9 {
-            match self.builder.finish() {
-                (mut rank9, bits) => {
-                    rank9.bits = bits as int;
-                    rank9
-                }
+            let ((words, counts), bits) = self.builder.finish();
+            Rank9::from_counts(words, bits, counts)
+        }
+    }
+
+    /// Write the serialization of a `Rank9` of `len` bits to a stream
+    /// as its bits are pushed, holding only its counts in memory until
+    /// they are written last, and give back the stream
+    pub struct StreamBuilder<W> {
+        builder: build::BitBuilder<(build::WriteBuilder<W>, CountsBuilder)>,
+        len: usize,
+    }
+
+    impl<W: Write> StreamBuilder<W> {
+        /// Start the serialization of a bitvector of `len` bits
+        pub fn new(mut w: W, len: usize) -> io::Result<StreamBuilder<W>> {
+            let words = div_ceil(len, 64);
+            write_uint(&mut w, len)?;
+            write_uint(&mut w, words)?;
+            let builders = (build::WriteBuilder::new(w), CountsBuilder::with_capacity(words));
+            Ok(StreamBuilder {
+                builder: build::BitBuilder::new(builders),
+                len,
+            })
+        }
+
+        /// Push the `width` least significant bits of `value`, least
+        /// significant first
+        pub fn push_bits(&mut self, value: u64, width: usize) {
+            self.builder.push_bits(value, width)
+        }
+    }
+
+    impl<W: Write> build::Builder<bool, io::Result<W>> for StreamBuilder<W> {
+        fn push(&mut self, bit: bool) {
+            self.builder.push(bit)
+        }
+        fn finish(self) -> io::Result<W> {
+            let ((w, counts), bits) = self.builder.finish();
+            if bits != self.len {
+                return Err(invalid_data("StreamBuilder: bits pushed differ from its length"));
             }
+            let mut w = w?;
+            counts.serialize(&mut w)?;
+            Ok(w)
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::num::Int;
     use quickcheck::TestResult;
 
     use super::Rank9;
     use super::super::dictionary::{BitRank, Select};
+    use super::super::collection::Collection;
     use super::super::naive;
 
+    #[quickcheck]
+    fn from_reader_is_correct(bytes: Vec<u8>) -> bool {
+        use std::io::Cursor;
+        use super::super::build::BitOrder;
+        let bits = 8 * bytes.len();
+        let rank9 = Rank9::from_reader(&mut Cursor::new(bytes.clone()), BitOrder::LsbFirst).unwrap();
+        let expected = Rank9::from_bytes(bytes.as_slice(), bits).unwrap();
+        rank9 == expected && (0..bits + 1).all(|i| rank9.rank1(i) == expected.rank1(i))
+    }
+
+    #[quickcheck]
+    fn builder_sizes_are_correct(len: u16, bits: Vec<bool>) -> bool {
+        use super::super::build::Builder;
+        let len = len as usize;
+        let mut builder = super::Builder::with_len(len);
+        let zeros = builder.len() == len && builder.capacity() >= len;
+        builder.reserve(bits.len());
+        let reserved = builder.capacity() >= len + bits.len();
+        for bit in bits.iter() {
+            builder.push(*bit);
+        }
+        let pushed = builder.len() == len + bits.len();
+        let rank9 = builder.finish();
+        let ones = bits.iter().filter(|b| **b).count();
+        zeros && reserved && pushed && rank9.rank1(len) == 0
+            && rank9.rank1(len + bits.len()) == ones
+    }
+
+    #[quickcheck]
+    fn push_bits_is_correct(v: Vec<(u64, u8)>) -> bool {
+        use std::convert::From;
+        use super::super::build::Builder;
+        let mut by_value = super::Builder::with_capacity(0);
+        let mut bits = Vec::new();
+        for &(value, width) in v.iter() {
+            let width = width as usize % 65;
+            by_value.push_bits(value, width);
+            bits.extend((0..width).map(|i| (value >> i) & 1 == 1));
+        }
+        let rank9 = by_value.finish();
+        let expected = Rank9::from(bits.as_slice());
+        rank9 == expected && (0..bits.len() + 1).all(|i| rank9.rank1(i) == expected.rank1(i))
+    }
+
+    #[quickcheck]
+    fn parallel_counts_agree(v: Vec<u64>, threads: u8) -> bool {
+        let bits = 64 * v.len();
+        let rank9 = Rank9::from_vec(&v, bits).unwrap();
+        let parallel = Rank9::from_words_parallel(v, bits, threads as usize % 8).unwrap();
+        parallel == rank9 && (0..bits + 1).all(|i| parallel.rank1(i) == rank9.rank1(i))
+    }
+
+    #[quickcheck]
+    fn from_vec_checks_length(v: Vec<u64>, bits: u16) -> bool {
+        let bits = bits as usize;
+        let ok = bits <= 64 * v.len();
+        Rank9::from_vec(&v, bits).is_ok() == ok
+            && Rank9::from_words_parallel(v, bits, 2).is_ok() == ok
+    }
+
+    #[test]
+    #[cfg(feature = "verify")]
+    #[should_panic(expected = "bad rank")]
+    fn test_verify_catches_bad_counts() {
+        let mut rank9 = Rank9::from_vec(&[u64::MAX; 16], 1024).unwrap();
+        rank9.counts[1]._block_rank += 1;
+        rank9.rank1(600);
+    }
+
     #[test]
     fn test_rank0() {
-        super::super::dictionary::test::test_rank0(&Rank9::from_vec);
+        super::super::dictionary::test::test_rank0(&|v, n| Rank9::from_vec(v, n).unwrap());
     }
 
     #[test]
     fn test_rank1() {
-        super::super::dictionary::test::test_rank1(&Rank9::from_vec);
+        super::super::dictionary::test::test_rank1(&|v, n| Rank9::from_vec(v, n).unwrap());
     }
 
     #[test]
     fn test_select0() {
-        super::super::dictionary::test::test_select0(&Rank9::from_vec);
+        super::super::dictionary::test::test_select0(&|v, n| Rank9::from_vec(v, n).unwrap());
     }
 
     #[test]
     fn test_select1() {
-        super::super::dictionary::test::test_select1(&Rank9::from_vec);
+        super::super::dictionary::test::test_select1(&|v, n| Rank9::from_vec(v, n).unwrap());
     }
 
     #[quickcheck]
-    fn rank_is_correct(bit: bool, v: Vec<u64>, n: uint) -> TestResult {
+    fn rank_is_correct(bit: bool, v: Vec<u64>, n: usize) -> TestResult {
         let bits = v.len() * 64;
         if n > bits {
             return TestResult::discard()
         }
-        let bv = Rank9::from_vec(&v, bits as int);
-        let ans = if bit { bv.rank1(n as int) } else { bv.rank0(n as int) };
-        TestResult::from_bool(ans == naive::rank(&bv, bit, n as int))
+        let bv = Rank9::from_vec(&v, bits).unwrap();
+        let ans = if bit { bv.rank1(n) } else { bv.rank0(n) };
+        TestResult::from_bool(ans == naive::rank(&bv, bit, n))
     }
 
     #[quickcheck]
-    fn select_is_correct(bit: bool, v: Vec<u64>, n: uint) -> TestResult {
-        use std::iter::AdditiveIterator;
-        if (v.iter().map(|x| x.count_ones()).sum() as uint) < n {
+    fn select_is_correct(bit: bool, v: Vec<u64>, n: usize) -> TestResult {
+        if (v.iter().map(|x| x.count_ones()).sum::<u32>() as usize) < n {
             return TestResult::discard()
         }
 
@@ -418,25 +992,63 @@ mod test {
         if v.is_empty() || n >= bits {
             return TestResult::discard()
         }
-        let bv = Rank9::from_vec(&v, bits as int);
-        match naive::select(&bv, bit, n as int) {
+        let bv = Rank9::from_vec(&v, bits).unwrap();
+        match naive::select(&bv, bit, n) {
             None => TestResult::discard(),
             Some(ans) =>
-                TestResult::from_bool(ans == bv.select(bit, n as int))
+                TestResult::from_bool(ans == bv.select(bit, n))
         }
     }
 
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>, n: usize) -> TestResult {
+        use super::super::serialize::test::round_trip;
+        let bits = v.len() * 64;
+        if n > bits {
+            return TestResult::discard()
+        }
+        let bv = Rank9::from_vec(&v, bits).unwrap();
+        let bv2 = round_trip(&bv);
+        TestResult::from_bool(bv.rank1(n) == bv2.rank1(n))
+    }
+
+    #[quickcheck]
+    fn std_traits_agree(bits: Vec<bool>) -> bool {
+        let rank9 = Rank9::from(bits.as_slice());
+        let empty = Rank9::default();
+        (0..bits.len()).all(|i| rank9[i] == bits[i])
+            && rank9.iter().collect::<Vec<bool>>() == bits
+            && (&rank9).into_iter().len() == bits.len()
+            && rank9.clone() == rank9
+            && empty.len() == 0 && empty.rank1(0) == 0 && empty.iter().next().is_none()
+    }
+
+    #[quickcheck]
+    fn constructors_agree(bits: Vec<bool>) -> bool {
+        use std::convert::From;
+        use super::super::build::{Builder, Buildable};
+        let built: Rank9 = super::Builder::with_capacity(bits.len()).from_iter(bits.clone().into_iter());
+        let buildable: Rank9 = Buildable::build_from_iter(bits.clone().into_iter());
+        let bytes: Vec<u8> = (0..bits.len().div_ceil(8)).map(|i| {
+            (0..8).filter(|j| 8 * i + j < bits.len() && bits[8 * i + j]).fold(0, |b, j| b | (1 << j))
+        }).collect();
+        let from_slice = Rank9::from(bits.as_slice());
+        let from_bytes = Rank9::from_bytes(bytes.as_slice(), bits.len()).unwrap();
+        from_slice == built && from_bytes == built && buildable == built
+            && (0..bits.len() + 1).all(|i| from_bytes.rank1(i) == built.rank1(i))
+    }
+
     #[test]
     fn test_binary_search2() {
         use super::binary_search;
-        let xs: Vec<int> = vec!(0, 22, 41, 63);
+        let xs: Vec<isize> = vec!(0, 22, 41, 63);
         assert_eq!(binary_search(|i| xs[*i].cmp(&63), 0, xs.len()), Ok(3));
     }
 
     #[test]
     fn test_binary_search() {
         use super::binary_search;
-        let xs: Vec<int> = vec!(0, 3, 5, 8);
+        let xs: Vec<isize> = vec!(0, 3, 5, 8);
         assert_eq!(binary_search(|i| xs[*i].cmp(&5), 0, xs.len()), Ok(2));
         assert_eq!(binary_search(|i| xs[*i].cmp(&4), 0, xs.len()), Err(2));
         assert_eq!(binary_search(|i| xs[*i].cmp(&0), 0, xs.len()), Ok(0));
@@ -444,13 +1056,13 @@ mod test {
     }
 
     #[quickcheck]
-    fn binary_search_works(v: Vec<int>, s: int) -> TestResult {
-        use std::iter::FromIterator;
+    fn binary_search_works(v: Vec<i8>, s: i8) -> TestResult {
         use super::binary_search;
         if v.len() < 2 {return TestResult::discard()}
-        let xs: Vec<int> = FromIterator::from_iter(
-            v.clone().into_iter()
-                .scan(0, |acc, x| {*acc += x; Some(*acc+x)}));
+        let s = s as isize;
+        let xs: Vec<isize> = v.iter()
+            .scan(0, |acc, x| {*acc += *x as isize; Some(*acc + *x as isize)})
+            .collect();
         let res = match binary_search(|i| xs[*i].cmp(&s), 0, xs.len()) {
             Ok(i) =>
                 xs[i] == s,
@@ -0,0 +1,231 @@
+//! Binary relations between objects and labels
+//
+// The pairs of a relation are sorted by object and then by label, and
+// the labels listed in that order in a wavelet tree. A bitvector holds
+// the number of pairs of each object in unary, a one for each pair
+// followed by a zero, so that the pairs of an object form a range of
+// the sequence. The labels of an object are read from its range, and
+// the objects of a label are found by selecting its occurrences and
+// mapping each back to the object whose range holds it.
+//
+// See Barbay, Golynski, Munro and Rao 2007.
+
+use std::io::{self, Read, Write};
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, BitRank, Select};
+use super::rank9::{self, Rank9};
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::SpaceUsage;
+use super::wavelet::Wavelet;
+
+/// A static set of pairs relating objects, numbered from zero, to
+/// labels
+pub struct BinaryRelation {
+    objects: usize,
+    /// the labels of the pairs, sorted by object and then by label
+    labels: Wavelet<Rank9, u32>,
+    /// the number of pairs of each object in unary
+    bounds: Rank9,
+}
+
+impl BinaryRelation {
+    /// The relation between `objects` objects and the labels holding
+    /// the given pairs, repeated pairs being kept once
+    pub fn new(objects: usize, pairs: &[(usize, u32)]) -> BinaryRelation {
+        use super::build::Builder;
+        for &(o, _) in pairs.iter() {
+            assert!(o < objects, "BinaryRelation: object {} out of bounds", o);
+        }
+        let mut pairs = pairs.to_vec();
+        pairs.sort();
+        pairs.dedup();
+        let labels: Vec<u32> = pairs.iter().map(|&(_, l)| l).collect();
+        let mut bounds = rank9::Builder::with_capacity(pairs.len() + objects);
+        let mut next = 0;
+        for o in 0..objects {
+            while next < pairs.len() && pairs[next].0 == o {
+                bounds.push(true);
+                next += 1;
+            }
+            bounds.push(false);
+        }
+        BinaryRelation {
+            objects,
+            labels: Wavelet::from_slice(labels.as_slice(), || rank9::Builder::with_capacity(128)),
+            bounds: bounds.finish(),
+        }
+    }
+
+    /// The number of objects
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+
+    /// Whether `o` is related to `l`
+    pub fn related(&self, o: usize, l: u32) -> bool {
+        let (start, end) = self.range(o);
+        self.labels.rank(l, end) > self.labels.rank(l, start)
+    }
+
+    /// The labels of `o` in increasing order
+    pub fn labels_of(&self, o: usize) -> Vec<u32> {
+        let (start, end) = self.range(o);
+        (start..end).map(|p| self.labels.get(p)).collect()
+    }
+
+    /// The objects related to `l` in increasing order
+    pub fn objects_with(&self, l: u32) -> Vec<usize> {
+        (0..self.count_objects(l)).map(|n| self.select_object(l, n).unwrap()).collect()
+    }
+
+    /// The number of labels of `o`
+    pub fn count_labels(&self, o: usize) -> usize {
+        let (start, end) = self.range(o);
+        end - start
+    }
+
+    /// The number of objects related to `l`
+    pub fn count_objects(&self, l: u32) -> usize {
+        self.labels.rank(l, self.labels.len())
+    }
+
+    /// The number of labels of `o` smaller than `l`
+    pub fn rank_labels(&self, o: usize, l: u32) -> usize {
+        let (start, end) = self.range(o);
+        let (mut lo, mut hi) = (start, end);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.labels.get(mid) < l {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo - start
+    }
+
+    /// The `n`th smallest label of `o`, counting from zero
+    pub fn select_label(&self, o: usize, n: usize) -> Option<u32> {
+        let (start, end) = self.range(o);
+        match start + n < end {
+            true => Some(self.labels.get(start + n)),
+            false => None,
+        }
+    }
+
+    /// The number of objects before `o` related to `l`
+    pub fn rank_objects(&self, l: u32, o: usize) -> usize {
+        let (start, _) = self.range(o);
+        self.labels.rank(l, start)
+    }
+
+    /// The `n`th smallest object related to `l`, counting from zero
+    pub fn select_object(&self, l: u32, n: usize) -> Option<usize> {
+        if n >= self.count_objects(l) {
+            return None;
+        }
+        let p = self.labels.select(l, n + 1) - 1;
+        // the zeros before the pair count the objects before its own
+        let pos = self.bounds.select(true, p + 1) - 1;
+        Some(pos - p)
+    }
+
+    /// The range of the pairs of `o`
+    fn range(&self, o: usize) -> (usize, usize) {
+        assert!(o < self.objects, "BinaryRelation: object {} out of bounds", o);
+        let start = match o {
+            0 => 0,
+            _ => self.bounds.select(false, o) - o,
+        };
+        let end = self.bounds.select(false, o + 1) - o - 1;
+        (start, end)
+    }
+}
+
+impl Collection for BinaryRelation {
+    /// The number of pairs
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+}
+
+impl SpaceUsage for BinaryRelation {
+    fn total_bytes(&self) -> usize {
+        self.labels.stats().bytes + self.bounds.total_bytes()
+    }
+}
+
+impl Serialize for BinaryRelation {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.objects)?;
+        self.labels.serialize(w)?;
+        self.bounds.serialize(w)
+    }
+}
+
+impl Deserialize for BinaryRelation {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BinaryRelation> {
+        let objects = read_uint(r)?;
+        let labels: Wavelet<Rank9, u32> = Deserialize::deserialize(r)?;
+        let bounds: Rank9 = Deserialize::deserialize(r)?;
+        let ones = bounds.rank1(bounds.len());
+        if ones != labels.len() || bounds.len() != ones + objects {
+            return Err(invalid_data("BinaryRelation bounds inconsistent with its pairs"));
+        }
+        Ok(BinaryRelation { objects, labels, bounds })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BinaryRelation;
+    use super::super::collection::Collection;
+
+    #[quickcheck]
+    fn queries_are_correct(v: Vec<(u8, u8)>, objects: u8) -> bool {
+        let objects = objects as usize % 20 + 1;
+        let mut pairs: Vec<(usize, u32)> = v.iter().map(|&(o, l)| (o as usize % objects, (l % 16) as u32)).collect();
+        let rel = BinaryRelation::new(objects, pairs.as_slice());
+        pairs.sort();
+        pairs.dedup();
+        rel.len() == pairs.len() && (0..objects).all(|o| {
+            let labels: Vec<u32> = pairs.iter().filter(|p| p.0 == o).map(|p| p.1).collect();
+            rel.labels_of(o) == labels
+                && rel.count_labels(o) == labels.len()
+                && (0..17u32).all(|l| {
+                    rel.related(o, l) == labels.contains(&l)
+                        && rel.rank_labels(o, l) == labels.iter().filter(|x| **x < l).count()
+                        && rel.rank_objects(l, o) == pairs.iter().filter(|p| p.0 < o && p.1 == l).count()
+                })
+                && (0..labels.len() + 1).all(|n| rel.select_label(o, n) == labels.get(n).copied())
+        }) && (0..17u32).all(|l| {
+            let objs: Vec<usize> = pairs.iter().filter(|p| p.1 == l).map(|p| p.0).collect();
+            rel.objects_with(l) == objs
+                && rel.count_objects(l) == objs.len()
+                && rel.select_object(l, objs.len()).is_none()
+        })
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<(u8, u8)>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let pairs: Vec<(usize, u32)> = v.iter().map(|&(o, l)| (o as usize, l as u32)).collect();
+        let rel = BinaryRelation::new(256, pairs.as_slice());
+        let read: BinaryRelation = round_trip(&rel);
+        (0..256).all(|o| read.labels_of(o) == rel.labels_of(o))
+    }
+
+    #[test]
+    fn test_relation() {
+        let rel = BinaryRelation::new(4, &[(0, 3), (0, 1), (2, 3), (3, 0), (3, 3)]);
+        assert_eq!(rel.len(), 5);
+        assert_eq!(rel.labels_of(0), vec!(1, 3));
+        assert!(rel.labels_of(1).is_empty());
+        assert_eq!(rel.objects_with(3), vec!(0, 2, 3));
+        assert!(rel.related(2, 3) && !rel.related(2, 1));
+        assert_eq!(rel.rank_objects(3, 3), 2);
+        assert_eq!(rel.select_object(3, 1), Some(2));
+        assert_eq!(rel.rank_labels(3, 3), 1);
+        assert_eq!(rel.select_label(0, 1), Some(3));
+    }
+}
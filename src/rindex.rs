@@ -0,0 +1,271 @@
+//! Run-length compressed FM-indexes of repetitive texts
+//
+// The Burrows-Wheeler transform of a repetitive text has few runs of
+// equal bytes. Rows here include that of the sentinel, which forms a
+// run of its own, so the transform is stored as the start row and
+// symbol of each run, the sentinel being symbol zero and byte `b`
+// symbol `b + 1`. The occurrences of a symbol before a row are those
+// in its earlier runs, read from the prefix sums of the run lengths
+// grouped by symbol, plus those in the run containing the row.
+//
+// Locating follows the r-index. During backward search the start of
+// the suffix of the last row of the range is kept, as it is known for
+// the last row of each run. The other occurrences are found by the
+// function `φ` mapping the start of each suffix to that of the suffix
+// in the row before. Within the rows of a run `φ` moves in step with
+// the text, so it is stored only at the suffixes starting runs.
+//
+// See Gagie, Navarro and Prezza 2020.
+
+use super::bits::bit_width;
+use super::bwt::{Bwt, suffix_array};
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, Select};
+use super::elias_fano::EliasFano;
+use super::int_vector::IntVector;
+use super::rank9::{self, Rank9};
+use super::space::{SpaceUsage, vec_bytes};
+use super::wavelet::Wavelet;
+
+/// A text index of size proportional to the runs of its transform
+pub struct RIndex {
+    /// the length of the text
+    len: usize,
+    /// the first row of each run
+    starts: EliasFano,
+    /// the symbol of each run
+    heads: Wavelet<Rank9, u16>,
+    /// the prefix sums of the lengths of the runs ordered by symbol,
+    /// then by row
+    sorted: EliasFano,
+    /// the number of runs of a symbol smaller than each
+    firsts: Vec<usize>,
+    /// the first row of the suffixes starting with each byte
+    counts: Vec<usize>,
+    /// the start of the suffix of the last row of each run
+    ends: IntVector,
+    /// the starts of the suffixes of the first rows of the runs, in
+    /// increasing order
+    phi_keys: EliasFano,
+    /// `φ` of each of those
+    phi_values: IntVector,
+}
+
+impl RIndex {
+    /// Index `text`
+    pub fn new(text: &[u8]) -> RIndex {
+        let n = text.len();
+        let sa = suffix_array(text);
+        let bwt = Bwt::from_suffix_array(text, sa.as_slice());
+        let symbol = |row: usize| match sa[row] {
+            0 => 0u16,
+            s => text[s - 1] as u16 + 1,
+        };
+
+        let mut starts: Vec<usize> = Vec::new();
+        let mut heads: Vec<u16> = Vec::new();
+        for row in 0..n + 1 {
+            if row == 0 || symbol(row) != symbol(row - 1) {
+                starts.push(row);
+                heads.push(symbol(row));
+            }
+        }
+        let r = starts.len();
+        let length = |k: usize| if k + 1 < r { starts[k + 1] - starts[k] } else { n + 1 - starts[k] };
+
+        let mut order: Vec<usize> = (0..r).collect();
+        order.sort_by(|a, b| (heads[*a], *a).cmp(&(heads[*b], *b)));
+        let mut sorted: Vec<u64> = Vec::with_capacity(r + 1);
+        let mut total = 0;
+        sorted.push(0);
+        for k in order.iter() {
+            total += length(*k);
+            sorted.push(total as u64);
+        }
+        let mut firsts: Vec<usize> = (0..258).map(|_| 0).collect();
+        for h in heads.iter() {
+            firsts[*h as usize + 1] += 1;
+        }
+        for s in 1..258 {
+            firsts[s] += firsts[s - 1];
+        }
+
+        let width = bit_width(n as u64);
+        let mut ends = IntVector::new(width, r);
+        let mut phi: Vec<(u64, u64)> = Vec::with_capacity(r);
+        for (k, start) in starts.iter().enumerate() {
+            ends.set(k, sa[*start + length(k) - 1] as u64);
+            // the first row has no row before it, and its suffix is
+            // never stepped back from
+            let before = if *start == 0 { 0 } else { sa[*start - 1] };
+            phi.push((sa[*start] as u64, before as u64));
+        }
+        phi.sort();
+        let mut phi_values = IntVector::new(width, r);
+        for (k, &(_, before)) in phi.iter().enumerate() {
+            phi_values.set(k, before);
+        }
+        let phi_keys: Vec<u64> = phi.iter().map(|&(key, _)| key).collect();
+        let starts: Vec<u64> = starts.iter().map(|s| *s as u64).collect();
+
+        RIndex {
+            len: n,
+            starts: EliasFano::from_slice(starts.as_slice()),
+            heads: Wavelet::from_slice(heads.as_slice(), || rank9::Builder::with_capacity(128)),
+            sorted: EliasFano::from_slice(sorted.as_slice()),
+            firsts,
+            counts: bwt.counts().to_vec(),
+            ends,
+            phi_keys: EliasFano::from_slice(phi_keys.as_slice()),
+            phi_values,
+        }
+    }
+
+    /// The number of runs of the transform, counting the sentinel's
+    pub fn runs(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The range of rows of the suffixes prefixed by `pattern`
+    pub fn range(&self, pattern: &[u8]) -> (usize, usize) {
+        let (sp, ep, _) = self.search(pattern);
+        (sp, ep)
+    }
+
+    /// The number of occurrences of `pattern` in the text, the empty
+    /// pattern occurring at each position and at the end
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        let (sp, ep) = self.range(pattern);
+        ep - sp
+    }
+
+    /// The positions of the occurrences of `pattern` in the text, in
+    /// increasing order
+    pub fn locate(&self, pattern: &[u8]) -> Vec<usize> {
+        let (sp, ep, last) = self.search(pattern);
+        let mut positions = Vec::with_capacity(ep - sp);
+        if sp < ep {
+            positions.push(last);
+            for _ in sp + 1..ep {
+                let next = self.phi(*positions.last().unwrap());
+                positions.push(next);
+            }
+        }
+        positions.sort();
+        positions
+    }
+
+    /// The range of rows of the suffixes prefixed by `pattern`, and
+    /// the start of the suffix of the last row if it is not empty
+    fn search(&self, pattern: &[u8]) -> (usize, usize, usize) {
+        let (mut sp, mut ep) = (0, self.len + 1);
+        let mut last = self.ends.get(self.runs() - 1) as usize;
+        for b in pattern.iter().rev() {
+            let s = *b as u16 + 1;
+            let (rank_sp, rank_ep) = (self.rank(s, sp), self.rank(s, ep));
+            if rank_sp == rank_ep {
+                return (sp, sp, 0);
+            }
+            last = match self.symbol(ep - 1) == s {
+                true => last - 1,
+                // the last occurrence in the range ends a run
+                false => self.ends.get(self.run_of_occurrence(s, rank_ep)) as usize - 1,
+            };
+            sp = self.counts[*b as usize] + rank_sp;
+            ep = self.counts[*b as usize] + rank_ep;
+        }
+        (sp, ep, last)
+    }
+
+    /// The run containing `row`
+    fn run(&self, row: usize) -> usize {
+        self.starts.predecessor(row as u64).unwrap().0
+    }
+
+    /// The symbol of `row`
+    fn symbol(&self, row: usize) -> u16 {
+        self.heads.get(self.run(row))
+    }
+
+    /// The number of rows before `row` of symbol `s`
+    fn rank(&self, s: u16, row: usize) -> usize {
+        if row == 0 {
+            return 0;
+        }
+        let k = self.run(row - 1);
+        let j = self.heads.rank(s, k);
+        let base = self.firsts[s as usize];
+        let earlier = (self.sorted.get(base + j) - self.sorted.get(base)) as usize;
+        match self.heads.get(k) == s {
+            true => earlier + row - self.starts.get(k) as usize,
+            false => earlier,
+        }
+    }
+
+    /// The run containing the `n`th occurrence of symbol `s`, counting
+    /// from one
+    fn run_of_occurrence(&self, s: u16, n: usize) -> usize {
+        let base = self.firsts[s as usize];
+        let offset = self.sorted.get(base) + n as u64 - 1;
+        let (i, _) = self.sorted.predecessor(offset).unwrap();
+        self.heads.select(s, i - base + 1) - 1
+    }
+
+    /// The start of the suffix in the row before that of the suffix
+    /// starting at `i`
+    fn phi(&self, i: usize) -> usize {
+        let (k, key) = self.phi_keys.predecessor(i as u64).unwrap();
+        self.phi_values.get(k) as usize + i - key as usize
+    }
+}
+
+impl Collection for RIndex {
+    /// The length of the text
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl SpaceUsage for RIndex {
+    fn total_bytes(&self) -> usize {
+        self.starts.total_bytes() + self.heads.stats().bytes + self.sorted.total_bytes()
+            + vec_bytes(&self.firsts) + vec_bytes(&self.counts) + self.ends.total_bytes()
+            + self.phi_keys.total_bytes() + self.phi_values.total_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RIndex;
+
+    /// A repetitive string over a small alphabet, shaped by `v`
+    fn text(v: &[u8]) -> Vec<u8> {
+        let unit: Vec<u8> = v.iter().take(5).map(|x| b'a' + *x % 3).collect();
+        let mut out = Vec::new();
+        for (i, x) in v.iter().enumerate() {
+            out.extend_from_slice(unit.as_slice());
+            if i % 3 == 0 {
+                out.push(b'a' + *x % 3);
+            }
+        }
+        out
+    }
+
+    #[quickcheck]
+    fn locate_is_correct(v: Vec<u8>, p: Vec<u8>) -> bool {
+        let (v, p) = (text(&v), p.iter().take(4).map(|x| b'a' + *x % 3).collect::<Vec<u8>>());
+        let index = RIndex::new(v.as_slice());
+        let naive: Vec<usize> = (0..v.len() + 1).filter(|i| v[*i..].starts_with(p.as_slice())).collect();
+        index.count(p.as_slice()) == naive.len() && index.locate(p.as_slice()) == naive
+    }
+
+    #[test]
+    fn test_rindex() {
+        let text = b"abababababababab";
+        let index = RIndex::new(text);
+        assert!(index.runs() < 6);
+        assert_eq!(index.count(b"ab"), 8);
+        assert_eq!(index.locate(b"bab"), vec!(1, 3, 5, 7, 9, 11, 13));
+        assert!(index.locate(b"aa").is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+//! A run-length encoded column of integers
+//
+// The column is stored as the value of each run together with the
+// starting positions of the runs in Elias-Fano code. For `rank` and
+// `select` by value, each distinct value also records the indices of
+// its runs and the prefix sums of their lengths, so both queries cost
+// a few successor searches regardless of the length of the runs.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use super::collection::Collection;
+use super::dictionary::{Access, Rank, Select};
+use super::elias_fano::EliasFano;
+use super::int_vector::IntVector;
+use super::prefix_sums::PrefixSums;
+use super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+pub use crate::rle::build::Builder;
+
+/// The runs of a single value
+#[derive(Clone, Debug)]
+struct ValueRuns {
+    /// the indices of the runs
+    runs: EliasFano,
+    /// the lengths of the runs
+    lengths: PrefixSums,
+}
+
+/// A column of integers compressed into runs of equal values
+#[derive(Clone, Debug)]
+pub struct RleColumn {
+    len: usize,
+    /// the value of each run
+    values: IntVector,
+    /// the starting position of each run, followed by `len`
+    starts: EliasFano,
+    /// the distinct values in increasing order
+    keys: Vec<u64>,
+    /// the runs of each distinct value
+    by_value: Vec<ValueRuns>,
+}
+
+impl RleColumn {
+    /// The number of runs
+    pub fn runs(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The `r`th run as its value, starting position and length
+    pub fn run(&self, r: usize) -> (u64, usize, usize) {
+        let mut starts = self.starts.iter_from(r);
+        let start = starts.next().unwrap() as usize;
+        let end = starts.next().unwrap() as usize;
+        (self.values.get(r), start, end - start)
+    }
+
+    /// The index of the run containing position `n`
+    fn run_of(&self, n: usize) -> usize {
+        match self.starts.predecessor(n as u64) {
+            Some((r, _)) => r,
+            None => unreachable!(),
+        }
+    }
+
+    fn value_runs(&self, x: u64) -> Option<&ValueRuns> {
+        match self.keys.binary_search_by(|k| k.cmp(&x)) {
+            Ok(i) => Some(&self.by_value[i]),
+            Err(_) => None,
+        }
+    }
+}
+
+impl Collection for RleColumn {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for RleColumn {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "RleColumn: index {} out of bounds", n);
+        self.values.get(self.run_of(n))
+    }
+}
+
+impl Rank<u64> for RleColumn {
+    fn rank(&self, x: u64, n: usize) -> usize {
+        assert!(n <= self.len, "RleColumn: rank({}) out of bounds", n);
+        let vr = match self.value_runs(x) {
+            Some(vr) => vr,
+            None => return 0,
+        };
+        // the run containing `n`, if any
+        let r = match n {
+            _ if n == self.len => self.runs(),
+            _ => self.run_of(n),
+        };
+        let k = vr.runs.lower_bound(r as u64);
+        let partial = match k < vr.runs.len() && vr.runs.get(k) == r as u64 {
+            true => n - self.starts.get(r) as usize,
+            false => 0,
+        };
+        vr.lengths.sum(k) as usize + partial
+    }
+}
+
+impl Select<u64> for RleColumn {
+    fn select(&self, x: u64, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        let n = n as u64;
+        let vr = match self.value_runs(x) {
+            Some(vr) => vr,
+            None => panic!("RleColumn: no occurrences of {}", x),
+        };
+        let k = match vr.lengths.search(n) {
+            Some(k) => k,
+            None => panic!("RleColumn: fewer than {} occurrences of {}", n, x),
+        };
+        let r = vr.runs.get(k - 1) as usize;
+        (self.starts.get(r) + n - vr.lengths.sum(k - 1)) as usize
+    }
+}
+
+impl SpaceUsage for RleColumn {
+    fn total_bytes(&self) -> usize {
+        let by_value: usize = self.by_value.iter()
+            .map(|vr| vr.runs.total_bytes() + vr.lengths.total_bytes())
+            .sum();
+        size_of::<usize>() + self.values.total_bytes() + self.starts.total_bytes()
+            + vec_bytes(&self.keys) + vec_bytes(&self.by_value) + by_value
+    }
+}
+
+impl Serialize for ValueRuns {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.runs.serialize(w)?;
+        self.lengths.serialize(w)
+    }
+}
+
+impl Deserialize for ValueRuns {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<ValueRuns> {
+        let runs: EliasFano = Deserialize::deserialize(r)?;
+        let lengths: PrefixSums = Deserialize::deserialize(r)?;
+        if runs.len() != lengths.len() {
+            return Err(invalid_data("RleColumn run lengths inconsistent with runs"));
+        }
+        Ok(ValueRuns { runs, lengths })
+    }
+}
+
+impl Serialize for RleColumn {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.len)?;
+        self.values.serialize(w)?;
+        self.starts.serialize(w)?;
+        self.keys.serialize(w)?;
+        self.by_value.serialize(w)
+    }
+}
+
+impl Deserialize for RleColumn {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<RleColumn> {
+        let len = read_uint(r)?;
+        let values: IntVector = Deserialize::deserialize(r)?;
+        let starts: EliasFano = Deserialize::deserialize(r)?;
+        let keys: Vec<u64> = Deserialize::deserialize(r)?;
+        let by_value: Vec<ValueRuns> = Deserialize::deserialize(r)?;
+        if starts.len() != values.len() + 1 || starts.get(values.len()) != len as u64 {
+            return Err(invalid_data("RleColumn run starts inconsistent with length"));
+        }
+        if keys.len() != by_value.len() {
+            return Err(invalid_data("RleColumn keys inconsistent with runs"));
+        }
+        let runs: usize = by_value.iter().map(|vr| vr.runs.len()).sum();
+        if runs != values.len() {
+            return Err(invalid_data("RleColumn runs by value inconsistent with runs"));
+        }
+        Ok(RleColumn { len, values, starts, keys, by_value })
+    }
+}
+
+mod build {
+    use super::super::build;
+    use super::super::bits::bit_width;
+    use super::super::int_vector;
+    use super::super::elias_fano::EliasFano;
+    use super::super::prefix_sums::PrefixSums;
+    use super::{RleColumn, ValueRuns};
+
+    /// Build an `RleColumn` from its values
+    pub struct Builder {
+        len: usize,
+        /// the value and length of each completed run
+        runs: Vec<(u64, usize)>,
+        /// the value and length of the current run
+        current: Option<(u64, usize)>,
+    }
+
+    impl Builder {
+        pub fn new() -> Builder {
+            Builder { len: 0, runs: Vec::new(), current: None }
+        }
+    }
+
+    impl Default for Builder {
+        fn default() -> Builder {
+            Builder::new()
+        }
+    }
+
+    impl build::Builder<u64, RleColumn> for Builder {
+        fn push(&mut self, x: u64) {
+            self.current = match self.current {
+                Some((y, n)) if y == x => Some((y, n + 1)),
+                Some(run) => {
+                    self.runs.push(run);
+                    Some((x, 1))
+                },
+                None => Some((x, 1)),
+            };
+            self.len += 1;
+        }
+
+        fn finish(mut self) -> RleColumn {
+            for run in self.current.take().into_iter() {
+                self.runs.push(run);
+            }
+
+            let max = self.runs.iter().map(|&(x, _)| x).max().unwrap_or(0);
+            let values = int_vector::Builder::with_capacity(bit_width(max), self.runs.len())
+                .from_iter(self.runs.iter().map(|&(x, _)| x));
+
+            let mut starts = Vec::with_capacity(self.runs.len() + 1);
+            let mut start = 0;
+            for &(_, n) in self.runs.iter() {
+                starts.push(start as u64);
+                start += n;
+            }
+            starts.push(start as u64);
+
+            // group the runs by value, keeping each group in order
+            let mut order: Vec<usize> = (0..self.runs.len()).collect();
+            order.sort_by(|a, b| self.runs[*a].0.cmp(&self.runs[*b].0));
+            let mut keys = Vec::new();
+            let mut by_value = Vec::new();
+            let mut i = 0;
+            while i < order.len() {
+                let x = self.runs[order[i]].0;
+                let mut j = i;
+                while j < order.len() && self.runs[order[j]].0 == x {
+                    j += 1;
+                }
+                let group = &order[i..j];
+                let runs: Vec<u64> = group.iter().map(|r| *r as u64).collect();
+                let lengths: Vec<u64> = group.iter().map(|r| self.runs[*r].1 as u64).collect();
+                keys.push(x);
+                by_value.push(ValueRuns {
+                    runs: EliasFano::from_slice(runs.as_slice()),
+                    lengths: PrefixSums::from_slice(lengths.as_slice()),
+                });
+                i = j;
+            }
+
+            RleColumn {
+                len: self.len,
+                values,
+                starts: EliasFano::from_slice(starts.as_slice()),
+                keys,
+                by_value,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RleColumn;
+    use super::super::dictionary::{Access, Rank, Select};
+    use super::super::build::Builder;
+
+    /// A column of runs of the given values and lengths
+    fn runs(v: Vec<(u8, u8)>) -> Vec<u64> {
+        let mut out = Vec::new();
+        for (x, n) in v.into_iter() {
+            for _ in 0..n % 8 {
+                out.push((x % 4) as u64);
+            }
+        }
+        out
+    }
+
+    fn build(v: &[u64]) -> RleColumn {
+        super::Builder::new().from_iter(v.iter().cloned())
+    }
+
+    #[quickcheck]
+    fn get_is_correct(v: Vec<(u8, u8)>) -> bool {
+        let v = runs(v);
+        let col = build(&v);
+        (0..v.len()).all(|i| col.get(i) == v[i])
+    }
+
+    #[quickcheck]
+    fn rank_is_correct(v: Vec<(u8, u8)>, x: u8) -> bool {
+        let v = runs(v);
+        let x = (x % 5) as u64;
+        let col = build(&v);
+        (0..v.len() + 1).all(|n| col.rank(x, n) == v.rank(x, n))
+    }
+
+    #[quickcheck]
+    fn select_is_correct(v: Vec<(u8, u8)>, x: u8) -> bool {
+        let v = runs(v);
+        let x = (x % 4) as u64;
+        let col = build(&v);
+        let count = v.rank(x, v.len());
+        (0..count + 1).all(|n| col.select(x, n) == v.select(x, n))
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<(u8, u8)>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let v = runs(v);
+        let col: RleColumn = round_trip(&build(&v));
+        (0..v.len()).all(|i| col.get(i) == v[i])
+            && (0..v.len() + 1).all(|n| col.rank(1, n) == v.rank(1, n))
+    }
+
+    #[test]
+    fn test_runs() {
+        let col = build(&[5, 5, 5, 2, 2, 5, 7]);
+        assert_eq!(col.runs(), 4);
+        assert_eq!(col.run(2), (5, 5, 1));
+        assert_eq!(col.rank(5, 6), 4);
+        assert_eq!(col.select(5, 4), 6);
+        assert_eq!(col.select(2, 1), 4);
+    }
+}
@@ -0,0 +1,108 @@
+//! Range minimum queries without the values
+//
+// The Cartesian tree of a sequence, in balanced parentheses, answers
+// for any range the position of its leftmost minimum without keeping
+// the values. If the first element of the range is an ancestor of the
+// last it is the minimum, the following elements of its subtree being
+// no smaller. Otherwise the minimum is the ancestor of the last
+// element among the children of their lowest common ancestor, the
+// earlier children and their subtrees being greater.
+//
+// See Fischer and Heun 2011.
+
+use std::io::{self, Read, Write};
+use super::collection::Collection;
+use super::serialize::{Serialize, Deserialize};
+use super::space::SpaceUsage;
+use super::tree::bp::BpTree;
+use super::tree::cartesian::cartesian_tree;
+
+/// Positions of range minima of a sequence
+pub struct Rmq {
+    tree: BpTree,
+}
+
+impl Rmq {
+    /// Index the minima of `xs`
+    pub fn new<T: Ord + Clone>(xs: &[T]) -> Rmq {
+        Rmq::from_tree(cartesian_tree(xs))
+    }
+
+    /// Index the minima of the sequence whose Cartesian tree is `tree`
+    pub fn from_tree(tree: BpTree) -> Rmq {
+        Rmq { tree }
+    }
+
+    /// The Cartesian tree of the sequence
+    pub fn tree(&self) -> &BpTree {
+        &self.tree
+    }
+
+    /// The position of the leftmost minimum among elements `i` to `j`
+    /// inclusive
+    pub fn min_index(&self, i: usize, j: usize) -> usize {
+        assert!(i <= j && j < self.len(), "Rmq: invalid range [{}, {}]", i, j);
+        let (u, v) = (self.tree.node(i + 1), self.tree.node(j + 1));
+        let lca = self.tree.lca(u, v);
+        if lca == u {
+            return i;
+        }
+        let child = self.tree.level_ancestor(v, self.tree.depth(lca) + 1).unwrap();
+        self.tree.preorder(child) - 1
+    }
+}
+
+impl Collection for Rmq {
+    fn len(&self) -> usize {
+        self.tree.len() - 1
+    }
+}
+
+impl SpaceUsage for Rmq {
+    fn total_bytes(&self) -> usize {
+        self.tree.total_bytes()
+    }
+}
+
+impl Serialize for Rmq {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.tree.serialize(w)
+    }
+}
+
+impl Deserialize for Rmq {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Rmq> {
+        let tree: BpTree = Deserialize::deserialize(r)?;
+        Ok(Rmq { tree })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::Rmq;
+    use super::super::collection::Collection;
+
+    #[quickcheck]
+    fn min_index_is_correct(v: Vec<u8>, i: usize, j: usize) -> TestResult {
+        if v.is_empty() {
+            return TestResult::discard();
+        }
+        let (i, j) = (i % v.len(), j % v.len());
+        let (i, j) = (if i < j { i } else { j }, if i < j { j } else { i });
+        let rmq = Rmq::new(v.as_slice());
+        let m = v[i..j + 1].iter().min().unwrap();
+        let expected = (i..j + 1).find(|k| v[*k] == *m).unwrap();
+        TestResult::from_bool(rmq.len() == v.len() && rmq.min_index(i, j) == expected)
+    }
+
+    #[test]
+    fn test_rmq() {
+        let rmq = Rmq::new(&[3usize, 1, 4, 1, 5, 9, 2, 6]);
+        assert_eq!(rmq.min_index(0, 7), 1);
+        assert_eq!(rmq.min_index(2, 7), 3);
+        assert_eq!(rmq.min_index(4, 7), 6);
+        assert_eq!(rmq.min_index(4, 5), 4);
+        assert_eq!(rmq.min_index(7, 7), 7);
+    }
+}
@@ -0,0 +1,663 @@
+//! Reading and writing the serialization of sdsl-lite
+//
+// sdsl-lite writes each member of a structure in turn in the byte
+// order of the machine, little-endian on those it supports:
+//
+// * `int_vector<w>` is its length in bits as a 64-bit word, then for
+//   `w = 0` alone its width as a byte, then the words of its bits,
+//   packed least significant bit first as in `IntVector`.
+// * `bit_vector` is `int_vector<1>`.
+// * `rank_support_v` is an `int_vector<64>` of two words per 512-bit
+//   block: the number of ones before the block, and the number of
+//   ones before each of its words but the first as 9-bit fields, the
+//   second word's count being the most significant. This is the
+//   `Rank9` directory with the fields in the opposite order.
+// * `select_support_mcl` is its number of matching bits, then, if
+//   there are any, the `int_vector<0>` of the position of the first of
+//   every 4096, a bit_vector marking which groups of 4096 are stored as
+//   mini blocks, empty if all are, and for each group an
+//   `int_vector<0>`: the offsets from its first of every 64th for a
+//   mini block, the positions of all of them for a long one. A group
+//   is long when its matching bits span more than `log^4` of the
+//   length, `log` being the bit width of the length.
+// * `wt_int` is its length and alphabet size as 64-bit words, then the
+//   bit_vector of its levels concatenated, the rank support of that
+//   bit_vector, `select_support_mcl` of its ones and zeros, and its
+//   number of levels as a 32-bit word. Its levels are laid out as
+//   those of `FlatWavelet`.
+// * `wt_huff` is its length and alphabet size, then the bit_vector of
+//   its inner nodes concatenated with its rank and two select
+//   supports as for `wt_int`, then its tree: the number of nodes as a
+//   64-bit word and for each its offset, the number of ones before it,
+//   its parent and its two children as 64-bit words, a missing node
+//   being `0xFFFF`, then the leaf of each of the 256 bytes and the path
+//   to it, its length in the top byte and its first step in the lowest
+//   bit. Its nodes are laid out as those of `HuffmanWavelet`.
+//
+// Rank and select supports are rebuilt rather than imported, as
+// `Rank9` answers both. Those written are built as sdsl-lite's
+// `init_slow` builds them.
+//
+// See https://github.com/simongog/sdsl-lite.
+
+use std::io::{self, Read, Write};
+use std::iter::repeat_n;
+use super::bit_vector::BitVector;
+use super::collection::Collection;
+use super::dictionary::BitRank;
+use super::int_vector::{IntVector, read_field, write_field, low_mask};
+use super::rank9::Rank9;
+use super::serialize::{write_u64, read_u64, read_uint, invalid_data};
+use super::utils::div_ceil;
+use super::wavelet::FlatWavelet;
+use super::wavelet::huffman::{HuffmanWavelet, Shape};
+
+/// The matching bits in each group of a `select_support_mcl`
+const SUPER_BLOCK: usize = 4096;
+
+/// The node of a `wt_huff` tree that is missing
+const UNDEF: u64 = 0xFFFF;
+
+/// The longest path to a leaf of a `wt_huff`
+const MAX_PATH: usize = 56;
+
+/// Read the words of `bits` bits
+fn read_words<R: Read>(r: &mut R, bits: usize) -> io::Result<Vec<u64>> {
+    let mut words = Vec::new();
+    for _ in 0..div_ceil(bits, 64) {
+        words.push(read_u64(r)?);
+    }
+    Ok(words)
+}
+
+/// Write the first `bits` bits of `words`, clearing those past them in
+/// the last word
+fn write_words<W: Write>(w: &mut W, words: &[u64], bits: usize) -> io::Result<()> {
+    let n = div_ceil(bits, 64);
+    for (i, word) in words[..n].iter().enumerate() {
+        let word = match i + 1 == n && !bits.is_multiple_of(64) {
+            true => *word & low_mask(bits % 64),
+            false => *word,
+        };
+        write_u64(w, word)?;
+    }
+    Ok(())
+}
+
+/// Write the first `bits` bits of `words` as a `bit_vector`
+fn write_bits<W: Write>(w: &mut W, words: &[u64], bits: usize) -> io::Result<()> {
+    write_u64(w, bits as u64)?;
+    write_words(w, words, bits)
+}
+
+/// Write a `bit_vector`
+pub fn write_bit_vector<W: Write>(w: &mut W, bv: &BitVector) -> io::Result<()> {
+    write_bits(w, bv.words(), bv.len())
+}
+
+/// Read a `bit_vector`
+pub fn read_bit_vector<R: Read>(r: &mut R) -> io::Result<BitVector> {
+    let bits = read_uint(r)?;
+    let words = read_words(r, bits)?;
+    Ok(BitVector::from_vec(&words, bits)?)
+}
+
+/// Write an `int_vector<0>`, of width at least one bit
+pub fn write_int_vector<W: Write>(w: &mut W, v: &IntVector) -> io::Result<()> {
+    let width = match v.width() {
+        0 => 1,
+        width => width,
+    };
+    let bits = width * v.len();
+    let mut words: Vec<u64> = repeat_n(0, div_ceil(bits, 64)).collect();
+    for (i, x) in v.iter().enumerate() {
+        write_field(words.as_mut_slice(), i * width, width, x);
+    }
+    write_u64(w, bits as u64)?;
+    w.write_all(&[width as u8])?;
+    write_words(w, words.as_slice(), bits)
+}
+
+/// Read an `int_vector<0>`
+pub fn read_int_vector<R: Read>(r: &mut R) -> io::Result<IntVector> {
+    let bits = read_uint(r)?;
+    let mut width = [0u8; 1];
+    r.read_exact(&mut width)?;
+    let width = width[0] as usize;
+    if width == 0 || width > 64 || bits % width != 0 {
+        return Err(invalid_data("int_vector width inconsistent with its length"));
+    }
+    let words = read_words(r, bits)?;
+    let mut v = IntVector::new(width, bits / width);
+    for i in 0..bits / width {
+        v.set(i, read_field(words.as_slice(), i * width, width));
+    }
+    Ok(v)
+}
+
+/// The entries of the `rank_support_v` of the first `bits` bits of
+/// `words`
+fn basic_blocks(words: &[u64], bits: usize) -> Vec<u64> {
+    let n = div_ceil(bits, 64);
+    let ones = |i: usize| -> u64 {
+        match i {
+            _ if i >= n => 0,
+            _ if i + 1 == n && !bits.is_multiple_of(64) => (words[i] & low_mask(bits % 64)).count_ones() as u64,
+            _ => words[i].count_ones() as u64,
+        }
+    };
+    let mut blocks: Vec<u64> = repeat_n(0, (((64 * n) >> 9) + 2) << 1).collect();
+    let mut j = 0;
+    let mut sum = ones(0);
+    let mut fields = 0;
+    let mut i = 1;
+    while i < n {
+        if i % 8 == 0 {
+            j += 2;
+            blocks[j - 1] = fields;
+            blocks[j] = blocks[j - 2] + sum;
+            fields = 0;
+            sum = 0;
+        } else {
+            fields |= sum << (63 - 9 * (i % 8));
+        }
+        sum += ones(i);
+        i += 1;
+    }
+    if i % 8 != 0 {
+        fields |= sum << (63 - 9 * (i % 8));
+        blocks[j + 1] = fields;
+    } else {
+        j += 2;
+        blocks[j - 1] = fields;
+        blocks[j] = blocks[j - 2] + sum;
+        blocks[j + 1] = 0;
+    }
+    blocks
+}
+
+/// Write the `rank_support_v` of the bits of `bv`, to be read with
+/// the `bit_vector` of the same bits
+pub fn write_rank_support_v<W: Write>(w: &mut W, bv: &Rank9) -> io::Result<()> {
+    let blocks = basic_blocks(bv.words(), bv.len());
+    write_u64(w, 64 * blocks.len() as u64)?;
+    for x in blocks.iter() {
+        write_u64(w, *x)?;
+    }
+    Ok(())
+}
+
+/// Read a `rank_support_v` of `bv`, giving `bv` with its `Rank9`
+/// directory
+pub fn read_rank_support_v<R: Read>(r: &mut R, bv: BitVector) -> io::Result<Rank9> {
+    let bits = read_uint(r)?;
+    if bits % 64 != 0 {
+        return Err(invalid_data("rank_support_v entries are not words"));
+    }
+    let entries = read_words(r, bits)?;
+    // check the counts before each block, which any version agrees on
+    let expected = basic_blocks(bv.words(), bv.len());
+    let blocks = div_ceil(bv.len(), 512);
+    if entries.len() < 2 * blocks || (0..blocks).any(|b| entries[2 * b] != expected[2 * b]) {
+        return Err(invalid_data("rank_support_v inconsistent with its bit_vector"));
+    }
+    let words = bv.words()[..div_ceil(bv.len(), 64)].to_vec();
+    Ok(Rank9::from_vec(&words, bv.len())?)
+}
+
+/// Skip a `select_support_mcl`
+fn skip_select_support_mcl<R: Read>(r: &mut R) -> io::Result<()> {
+    let ones = read_u64(r)?;
+    if ones == 0 {
+        return Ok(());
+    }
+    // the superblocks, which long and mini blocks follow
+    read_int_vector(r)?;
+    read_bit_vector(r)?;
+    for _ in 0..(ones + 4095) >> 12 {
+        read_int_vector(r)?;
+    }
+    Ok(())
+}
+
+/// The position of the highest set bit of `x`, which must be positive
+fn hi(x: usize) -> usize {
+    63 - (x as u64).leading_zeros() as usize
+}
+
+/// An `int_vector<0>` of `len` entries of `width` bits, at least one,
+/// holding `xs` followed by zeros
+fn int_vector(width: usize, len: usize, xs: &[usize]) -> IntVector {
+    let mut v = IntVector::new(width.max(1), len);
+    for (i, x) in xs.iter().enumerate() {
+        v.set(i, *x as u64);
+    }
+    v
+}
+
+/// Write the `select_support_mcl` of the `bit`s of `bv`
+fn write_select_support_mcl<W: Write>(w: &mut W, bv: &Rank9, bit: bool) -> io::Result<()> {
+    let len = bv.len();
+    let count = match bit {
+        true => bv.rank1(len),
+        false => bv.rank0(len),
+    };
+    write_u64(w, count as u64)?;
+    if count == 0 {
+        return Ok(());
+    }
+    let log = hi(len) + 1;
+    let log4 = (log * log).saturating_mul(log * log);
+
+    // the positions of the matching bits a group at a time
+    let mut firsts = Vec::new();
+    let mut blocks = Vec::new();
+    let mut group = Vec::with_capacity(SUPER_BLOCK);
+    for (i, b) in bv.iter().enumerate() {
+        if b != bit {
+            continue;
+        }
+        group.push(i);
+        if group.len() == SUPER_BLOCK || firsts.len() * SUPER_BLOCK + group.len() == count {
+            let (first, last) = (group[0], group[group.len() - 1]);
+            firsts.push(first);
+            blocks.push(match last - first > log4 {
+                true => (false, int_vector(hi(last) + 1, SUPER_BLOCK, group.as_slice())),
+                false => {
+                    let offsets: Vec<usize> = group.iter().step_by(64).map(|p| p - first).collect();
+                    let width = match last - first {
+                        0 => 1,
+                        diff => hi(diff) + 1,
+                    };
+                    (true, int_vector(width, 64, offsets.as_slice()))
+                },
+            });
+            group.clear();
+        }
+    }
+
+    write_int_vector(w, &int_vector(log, firsts.len(), firsts.as_slice()))?;
+    let mini = match blocks.iter().all(|&(mini, _)| mini) {
+        true => BitVector::zero(0),
+        false => BitVector::from_fn(blocks.len(), |i| blocks[i].0),
+    };
+    write_bit_vector(w, &mini)?;
+    for (_, block) in blocks.iter() {
+        write_int_vector(w, block)?;
+    }
+    Ok(())
+}
+
+/// Write a bitvector with its rank and select supports
+fn write_supported<W: Write>(w: &mut W, bv: &Rank9) -> io::Result<()> {
+    write_bits(w, bv.words(), bv.len())?;
+    write_rank_support_v(w, bv)?;
+    write_select_support_mcl(w, bv, true)?;
+    write_select_support_mcl(w, bv, false)
+}
+
+/// Read a bitvector with its rank and select supports
+fn read_supported<R: Read>(r: &mut R) -> io::Result<Rank9> {
+    let bv = read_bit_vector(r)?;
+    let bv = read_rank_support_v(r, bv)?;
+    skip_select_support_mcl(r)?;
+    skip_select_support_mcl(r)?;
+    Ok(bv)
+}
+
+/// The number of distinct symbols of a `FlatWavelet`, its leaves
+/// holding any
+fn flat_sigma<Sym>(wt: &FlatWavelet<Rank9, Sym>) -> usize {
+    let len = wt.len();
+    let bits = wt.bits();
+    let mut nodes: Vec<(usize, usize)> = match len {
+        0 => vec!(),
+        _ => vec!((0, len)),
+    };
+    for level in 0..wt.levels() {
+        let offset = level * len;
+        let mut next = Vec::with_capacity(2 * nodes.len());
+        for &(start, end) in nodes.iter() {
+            let zeros = bits.rank0(offset + end) - bits.rank0(offset + start);
+            for &(s, e) in [(start, start + zeros), (start + zeros, end)].iter() {
+                if s < e {
+                    next.push((s, e));
+                }
+            }
+        }
+        nodes = next;
+    }
+    nodes.len()
+}
+
+/// Write a `wt_int`
+pub fn write_wt_int<W: Write, Sym>(w: &mut W, wt: &FlatWavelet<Rank9, Sym>) -> io::Result<()> {
+    write_u64(w, wt.len() as u64)?;
+    write_u64(w, flat_sigma(wt) as u64)?;
+    write_supported(w, wt.bits())?;
+    w.write_all(&(wt.levels() as u32).to_le_bytes())
+}
+
+/// Read a `wt_int`
+pub fn read_wt_int<R: Read, Sym>(r: &mut R) -> io::Result<FlatWavelet<Rank9, Sym>> {
+    let len = read_uint(r)?;
+    let _sigma = read_u64(r)?;
+    let tree = read_supported(r)?;
+    let mut levels = [0u8; 4];
+    r.read_exact(&mut levels)?;
+    let levels = u32::from_le_bytes(levels) as usize;
+    if len.checked_mul(levels) != Some(tree.len()) {
+        return Err(invalid_data("wt_int levels inconsistent with its length"));
+    }
+    Ok(FlatWavelet::from_levels(tree, len, levels))
+}
+
+/// The parent and children of each node of `shapes`, as sdsl-lite
+/// writes them
+fn huff_links(shapes: &[Shape]) -> Vec<(u64, u64, u64)> {
+    let mut links: Vec<(u64, u64, u64)> = shapes.iter().map(|_| (UNDEF, UNDEF, UNDEF)).collect();
+    for (v, shape) in shapes.iter().enumerate() {
+        if let Shape::Inner(zero, one) = *shape {
+            links[v].1 = zero as u64;
+            links[v].2 = one as u64;
+            links[zero].0 = v as u64;
+            links[one].0 = v as u64;
+        }
+    }
+    links
+}
+
+/// Write a `wt_huff`, failing if a path is longer than sdsl-lite allows
+pub fn write_wt_huff<W: Write>(w: &mut W, wt: &HuffmanWavelet<Rank9>) -> io::Result<()> {
+    let shapes = wt.shapes();
+    let mut leaves: Vec<u64> = (0..256).map(|_| UNDEF).collect();
+    let mut paths: Vec<u64> = (0..256).map(|_| 0).collect();
+    for (v, shape) in shapes.iter().enumerate() {
+        if let Shape::Leaf(b) = *shape {
+            let path = wt.path(b).unwrap();
+            if path.len() > MAX_PATH {
+                return Err(invalid_data("wt_huff path longer than 56 bits"));
+            }
+            leaves[b as usize] = v as u64;
+            paths[b as usize] = path.iter().enumerate()
+                .fold((path.len() as u64) << 56, |x, (i, bit)| x | ((*bit as u64) << i));
+        }
+    }
+    let sigma = shapes.iter().filter(|s| matches!(s, Shape::Leaf(_))).count();
+
+    write_u64(w, wt.len() as u64)?;
+    write_u64(w, sigma as u64)?;
+    write_supported(w, wt.bits())?;
+    write_u64(w, shapes.len() as u64)?;
+    for (v, (parent, zero, one)) in huff_links(shapes.as_slice()).into_iter().enumerate() {
+        let (offset, ones) = wt.node_offset(v);
+        let ones = match shapes[v] {
+            Shape::Inner(..) => ones,
+            Shape::Leaf(_) => 0,
+        };
+        for x in [offset as u64, ones as u64, parent, zero, one].iter() {
+            write_u64(w, *x)?;
+        }
+    }
+    for x in leaves.iter().chain(paths.iter()) {
+        write_u64(w, *x)?;
+    }
+    Ok(())
+}
+
+/// Read a `wt_huff`
+pub fn read_wt_huff<R: Read>(r: &mut R) -> io::Result<HuffmanWavelet<Rank9>> {
+    let len = read_uint(r)?;
+    let _sigma = read_u64(r)?;
+    let bits = read_supported(r)?;
+    let n = read_uint(r)?;
+    if n > 511 {
+        return Err(invalid_data("wt_huff has more nodes than bytes allow"));
+    }
+    let mut nodes = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut node = [0u64; 5];
+        for x in node.iter_mut() {
+            *x = read_u64(r)?;
+        }
+        nodes.push(node);
+    }
+    let mut leaves = [0u64; 256];
+    for x in leaves.iter_mut() {
+        *x = read_u64(r)?;
+    }
+    // the paths follow from the tree
+    for _ in 0..256 {
+        read_u64(r)?;
+    }
+
+    // the byte of each leaf
+    let mut syms: Vec<Option<u8>> = (0..n).map(|_| None).collect();
+    if n > 0 {
+        for (b, v) in leaves.iter().enumerate() {
+            if *v != UNDEF {
+                match syms.get_mut(*v as usize) {
+                    Some(sym @ &mut None) => *sym = Some(b as u8),
+                    _ => return Err(invalid_data("wt_huff leaves inconsistent with its nodes")),
+                }
+            }
+        }
+    }
+    let mut shapes = Vec::with_capacity(n);
+    for (v, node) in nodes.iter().enumerate() {
+        shapes.push(match (node[3], node[4], syms[v]) {
+            (UNDEF, UNDEF, Some(b)) => Shape::Leaf(b),
+            (zero, one, None) if zero != UNDEF && one != UNDEF => Shape::Inner(zero as usize, one as usize),
+            _ => return Err(invalid_data("wt_huff leaves inconsistent with its nodes")),
+        });
+    }
+    let wt = HuffmanWavelet::from_parts(bits, len, shapes)?;
+
+    // check the offsets and parents, which the queries of sdsl-lite
+    // rely on, against those implied by the shape
+    let links = huff_links(wt.shapes().as_slice());
+    for (v, node) in nodes.iter().enumerate() {
+        let (offset, ones) = wt.node_offset(v);
+        let inner = node[3] != UNDEF;
+        if (inner && (node[0] != offset as u64 || node[1] != ones as u64)) || node[2] != links[v].0 {
+            return Err(invalid_data("wt_huff node offsets inconsistent with its bits"));
+        }
+    }
+    Ok(wt)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+    use super::super::bit_vector::BitVector;
+    use super::super::build::Builder;
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, BitRank, Rank, Select};
+    use super::super::int_vector::{self, IntVector};
+    use super::super::rank9::Rank9;
+    use super::super::serialize::{read_u64, write_u64};
+    use super::super::wavelet::FlatWavelet;
+    use super::super::wavelet::huffman::HuffmanWavelet;
+
+    #[quickcheck]
+    fn vectors_round_trip(v: Vec<u64>, bits: Vec<bool>) -> bool {
+        let mut iv = int_vector::WideningBuilder::new(1).from_iter(v.clone().into_iter());
+        let mut buf: Vec<u8> = Vec::new();
+        super::write_int_vector(&mut buf, &iv).unwrap();
+        iv = super::read_int_vector(&mut Cursor::new(buf)).unwrap();
+
+        let mut words: Vec<u64> = Vec::new();
+        for (i, bit) in bits.iter().enumerate() {
+            if i % 64 == 0 {
+                words.push(0);
+            }
+            words[i / 64] |= (*bit as u64) << (i % 64);
+        }
+        let bv = BitVector::from_vec(&words, bits.len()).unwrap();
+        let rank9 = Rank9::from_vec(&words, bits.len()).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        super::write_bit_vector(&mut buf, &bv).unwrap();
+        super::write_rank_support_v(&mut buf, &rank9).unwrap();
+        let mut r = Cursor::new(buf);
+        let read = super::read_bit_vector(&mut r).unwrap();
+        let read = super::read_rank_support_v(&mut r, read).unwrap();
+
+        (0..v.len()).all(|i| iv.get(i) == v[i])
+            && (0..bits.len()).all(|i| read.get(i) == bits[i])
+            && (0..bits.len() + 1).all(|i| read.rank1(i) == rank9.rank1(i))
+    }
+
+    #[test]
+    fn test_wt_int() {
+        // the levels of the wt_int of 4 6 2 7 5 1 6 2, most significant
+        // bit first
+        let levels = [true, true, false, true, true, false, true, false,
+                      true, false, true, false, true, true, false, true,
+                      true, false, false, false, true, false, true, false];
+        let mut word = 0;
+        for (i, bit) in levels.iter().enumerate() {
+            word |= (*bit as u64) << i;
+        }
+        let tree = Rank9::from_vec(&[word], 24).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        write_u64(&mut buf, 8).unwrap();
+        write_u64(&mut buf, 6).unwrap();
+        super::write_bit_vector(&mut buf, &BitVector::from_vec(&[word], 24).unwrap()).unwrap();
+        super::write_rank_support_v(&mut buf, &tree).unwrap();
+        // select supports over no ones
+        write_u64(&mut buf, 0).unwrap();
+        write_u64(&mut buf, 0).unwrap();
+        buf.extend_from_slice(&[3, 0, 0, 0]);
+
+        let wt: super::FlatWavelet<Rank9, u8> = super::read_wt_int(&mut Cursor::new(buf.clone())).unwrap();
+        let v: Vec<u8> = (0..8).map(|i| wt.get(i)).collect();
+        assert_eq!(v, vec!(4, 6, 2, 7, 5, 1, 6, 2));
+
+        buf.truncate(buf.len() - 4);
+        buf.extend_from_slice(&[2, 0, 0, 0]);
+        assert!(super::read_wt_int::<_, u8>(&mut Cursor::new(buf)).is_err());
+
+        // a bit_vector of the bits 101
+        let bytes = vec!(3u8, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0);
+        let bv = super::read_bit_vector(&mut Cursor::new(bytes)).unwrap();
+        assert!(bv.get(0) && !bv.get(1) && bv.get(2));
+    }
+
+    /// The position of the `n`th `bit`, counting from zero, as the
+    /// queries of sdsl-lite find it in a `select_support_mcl`
+    fn mcl_select<R: Read>(r: &mut R, bv: &Rank9, bit: bool, n: usize) -> usize {
+        let count = read_u64(r).unwrap() as usize;
+        assert!(n < count);
+        let firsts = super::read_int_vector(r).unwrap();
+        let mini = super::read_bit_vector(r).unwrap();
+        let blocks: Vec<IntVector> = (0..firsts.len()).map(|_| super::read_int_vector(r).unwrap()).collect();
+        let (group, i) = (n / 4096, n % 4096);
+        if mini.len() > 0 && !mini.get(group) {
+            return blocks[group].get(i) as usize;
+        }
+        // scan from the nearest sampled position
+        let mut pos = (firsts.get(group) + blocks[group].get(i / 64)) as usize;
+        let mut left = i % 64;
+        loop {
+            if bv.get(pos) == bit {
+                if left == 0 {
+                    return pos;
+                }
+                left -= 1;
+            }
+            pos += 1;
+        }
+    }
+
+    #[test]
+    fn test_select_support_mcl() {
+        // dense ones with a sparse tail, to have both mini and long
+        // blocks
+        let len = 4096 * 5 + (1 << 17);
+        let bits = Rank9::from_fn(len, |i| i < 4096 * 5 && i % 5 != 2 || i % 9999 == 0);
+        for &bit in [true, false].iter() {
+            let mut buf: Vec<u8> = Vec::new();
+            super::write_select_support_mcl(&mut buf, &bits, bit).unwrap();
+            let count = if bit { bits.rank1(len) } else { bits.rank0(len) };
+            for n in (0..count).step_by(97).chain(Some(count - 1)) {
+                let pos = mcl_select(&mut Cursor::new(buf.as_slice()), &bits, bit, n);
+                assert_eq!(pos + 1, bits.select(bit, n + 1));
+            }
+            let mut r = Cursor::new(buf.as_slice());
+            super::skip_select_support_mcl(&mut r).unwrap();
+            assert_eq!(r.position() as usize, buf.len());
+        }
+    }
+
+    #[quickcheck]
+    fn wt_int_round_trips(v: Vec<u16>) -> bool {
+        let wt = FlatWavelet::<Rank9, u16>::from_slice(&v);
+        let mut buf: Vec<u8> = Vec::new();
+        super::write_wt_int(&mut buf, &wt).unwrap();
+        let read: FlatWavelet<Rank9, u16> = super::read_wt_int(&mut Cursor::new(buf)).unwrap();
+        let mut distinct = v.clone();
+        distinct.sort();
+        distinct.dedup();
+        read.levels() == wt.levels()
+            && (0..v.len()).all(|i| read.get(i) == v[i])
+            && super::flat_sigma(&wt) == distinct.len()
+    }
+
+    #[quickcheck]
+    fn wt_huff_round_trips(v: Vec<u8>) -> bool {
+        let wt = HuffmanWavelet::from_slice(&v);
+        let mut buf: Vec<u8> = Vec::new();
+        super::write_wt_huff(&mut buf, &wt).unwrap();
+        let read = super::read_wt_huff(&mut Cursor::new(buf)).unwrap();
+        read.shapes() == wt.shapes()
+            && (0..v.len()).all(|i| read.get(i) == v[i])
+            && (0..=255u8).all(|b| read.rank(b, v.len()) == wt.rank(b, v.len()))
+    }
+
+    #[test]
+    fn test_wt_huff() {
+        // the wt_huff of "abb": a root whose zero child is the leaf of
+        // a and whose one child is that of b
+        let root = Rank9::from_fn(3, |i| i > 0);
+        let mut buf: Vec<u8> = Vec::new();
+        write_u64(&mut buf, 3).unwrap();
+        write_u64(&mut buf, 2).unwrap();
+        super::write_bit_vector(&mut buf, &BitVector::from_fn(3, |i| i > 0)).unwrap();
+        super::write_rank_support_v(&mut buf, &root).unwrap();
+        // select supports of the ones at 1, 2 and the zero at 0, both in
+        // one mini block
+        write_u64(&mut buf, 2).unwrap();
+        super::write_int_vector(&mut buf, &super::int_vector(2, 1, &[1])).unwrap();
+        super::write_bit_vector(&mut buf, &BitVector::zero(0)).unwrap();
+        super::write_int_vector(&mut buf, &super::int_vector(1, 64, &[0])).unwrap();
+        write_u64(&mut buf, 1).unwrap();
+        super::write_int_vector(&mut buf, &super::int_vector(2, 1, &[0])).unwrap();
+        super::write_bit_vector(&mut buf, &BitVector::zero(0)).unwrap();
+        super::write_int_vector(&mut buf, &super::int_vector(1, 64, &[0])).unwrap();
+        // offset, ones before, parent and children of each node
+        write_u64(&mut buf, 3).unwrap();
+        for x in [0, 0, 0xFFFF, 1, 2, 3, 0, 0, 0xFFFF, 0xFFFF, 3, 0, 0, 0xFFFF, 0xFFFF].iter() {
+            write_u64(&mut buf, *x).unwrap();
+        }
+        let leaf = |b: usize| match b { 97 => 1, 98 => 2, _ => 0xFFFF };
+        let path = |b: usize| match b { 97 => 1 << 56, 98 => (1 << 56) | 1, _ => 0 };
+        for b in 0..256 {
+            write_u64(&mut buf, leaf(b)).unwrap();
+        }
+        for b in 0..256 {
+            write_u64(&mut buf, path(b)).unwrap();
+        }
+
+        let wt = super::read_wt_huff(&mut Cursor::new(buf.clone())).unwrap();
+        let v: Vec<u8> = (0..3).map(|i| wt.get(i)).collect();
+        assert_eq!(v, b"abb".to_vec());
+        let mut written: Vec<u8> = Vec::new();
+        super::write_wt_huff(&mut written, &wt).unwrap();
+        assert_eq!(written, buf);
+
+        // a parent that is not the root
+        let node = buf.len() - 512 * 8 - 8 * 8;
+        buf[node] = 2;
+        assert!(super::read_wt_huff(&mut Cursor::new(buf)).is_err());
+    }
+}
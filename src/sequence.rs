@@ -0,0 +1,114 @@
+//! A sequence supporting efficient rank and select queries
+//
+// `RankedSequence` hides the choice of wavelet tree and bitvector
+// behind the interface of a plain sequence. It is a drop-in
+// replacement for the `Rank` and `Select` implementations of `Vec`,
+// which scan the whole vector for each query.
+
+use std::iter::FromIterator;
+use super::dictionary::{Access, Rank, Select};
+use super::collection::Collection;
+use super::build::Builder;
+use super::rank9::{self, Rank9};
+use super::wavelet::{alphabetic, AlphabeticWavelet};
+
+/// An immutable sequence of `T` supporting `rank`, `select`, `get`
+/// and `range_count` in time logarithmic in the number of distinct
+/// elements.
+pub struct RankedSequence<T> {
+    wavelet: AlphabeticWavelet<Rank9, T>,
+}
+
+fn new_bitvector() -> rank9::Builder {
+    rank9::Builder::with_capacity(128)
+}
+
+impl<T: Ord + Clone> RankedSequence<T> {
+    /// Index the elements of a vector
+    pub fn new(v: Vec<T>) -> RankedSequence<T> {
+        v.into_iter().collect()
+    }
+
+    /// The distinct elements of the sequence in increasing order
+    pub fn alphabet(&self) -> &[T] {
+        self.wavelet.alphabet()
+    }
+
+    /// The number of positions in `[i, j)` holding an element `x`
+    /// with `lo <= x < hi`
+    pub fn range_count(&self, i: usize, j: usize, lo: T, hi: T) -> usize {
+        self.wavelet.range_count(i, j, lo, hi)
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for RankedSequence<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> RankedSequence<T> {
+        let builder = alphabetic::Builder::new(new_bitvector);
+        RankedSequence { wavelet: builder.from_iter(iter.into_iter()) }
+    }
+}
+
+impl<T> Collection for RankedSequence<T> {
+    fn len(&self) -> usize {
+        self.wavelet.len()
+    }
+}
+
+impl<T: Ord + Clone> Access<T> for RankedSequence<T> {
+    fn get(&self, n: usize) -> T {
+        self.wavelet.get(n)
+    }
+}
+
+impl<T: Ord> Rank<T> for RankedSequence<T> {
+    fn rank(&self, el: T, n: usize) -> usize {
+        self.wavelet.rank(el, n)
+    }
+}
+
+impl<T: Ord> Select<T> for RankedSequence<T> {
+    fn select(&self, el: T, n: usize) -> usize {
+        self.wavelet.select(el, n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::RankedSequence;
+    use super::super::dictionary::{Access, Rank, Select};
+    use super::super::naive;
+
+    #[quickcheck]
+    fn matches_vec(el: i16, v: Vec<i16>, n: usize) -> TestResult {
+        if n > v.len() {
+            return TestResult::discard()
+        }
+        let seq = RankedSequence::new(v.clone());
+        let accessed = v.iter().enumerate().all(|(i, x)| seq.get(i) == *x);
+        let k = v.rank(el, n);
+        TestResult::from_bool(accessed
+                              && seq.rank(el, n) == k
+                              && (k == 0 || seq.select(el, k) == v.select(el, k)))
+    }
+
+    #[quickcheck]
+    fn range_count_is_correct(v: Vec<i16>, i: usize, j: usize, lo: i16, hi: i16) -> TestResult {
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+        let seq: RankedSequence<i16> = v.iter().copied().collect();
+        let expected = naive::Sequence::new(v).range_count(i, j, lo, hi);
+        TestResult::from_bool(seq.range_count(i, j, lo, hi) == expected)
+    }
+
+    #[test]
+    fn test_strings() {
+        let words = ["to", "be", "or", "not", "to", "be"];
+        let seq = RankedSequence::new(words.iter().map(|w| w.to_string()).collect());
+        assert_eq!(seq.rank("be".to_string(), 6), 2);
+        assert_eq!(seq.select("to".to_string(), 2), 5);
+        assert_eq!(seq.get(3), "not");
+        assert_eq!(seq.alphabet().len(), 4);
+    }
+}
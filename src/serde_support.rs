@@ -0,0 +1,201 @@
+//! Serde support, enabled by the `serde` feature
+//
+// Each structure is handed to serde as a byte string holding its
+// native serialization, so that every serde format carries the bytes
+// written by `serialize::Serialize` and reading them back goes through
+// the checks of `serialize::Deserialize`. Formats without byte strings,
+// such as JSON, give them as sequences of bytes, which are accepted
+// too.
+//
+// The functions `serialize` and `deserialize` can be named with
+// `#[serde(with = "succinct::serde_support")]` for a field of any type
+// implementing the native traits.
+
+use std::fmt;
+use std::io::Cursor;
+use std::marker::PhantomData;
+use serde;
+use serde::de::{Error, SeqAccess, Visitor};
+use super::serialize::{Serialize, Deserialize};
+
+use super::bit_vector::BitVector;
+use super::bits::BitIter;
+use super::collection::Collection;
+use super::coded::CodedSequence;
+use super::dac::Dac;
+use super::dictionary::{Rank, Access};
+use super::either::Either;
+use super::elias_fano::EliasFano;
+use super::graph::elias_fano::EliasFanoGraph;
+use super::graph::sequence::SequenceGraph;
+use super::graph::webgraph::BvGraph;
+use super::int_vector::IntVector;
+use super::k2raster::K2Raster;
+use super::k2tree::K2Tree;
+use super::multiset::Multiset;
+use super::pfor::PForArray;
+use super::postings::GapList;
+use super::prefix_sums::PrefixSums;
+use super::rank9::{Rank9, Counts};
+use super::relation::BinaryRelation;
+use super::rle::RleColumn;
+use super::rmq::Rmq;
+use super::sparse_array::SparseArray;
+use super::tree::binary::Tree;
+use super::tree::bp::BpTree;
+use super::tree::cardinal::CardinalTree;
+use super::tree::dfuds::Dfuds;
+use super::tree::louds::Louds;
+use super::tree::parens::BalancedParens;
+use super::tree::rose::SuccinctRose;
+use super::trie::Trie;
+use super::vbyte::VByteSequence;
+use super::wavelet::{Wavelet, FlatWavelet, AlphabeticWavelet};
+
+/// Serialize `x` as the bytes of its native serialization
+pub fn serialize<T: Serialize, S: serde::Serializer>(x: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    match x.serialize(&mut buf) {
+        Ok(()) => serializer.serialize_bytes(buf.as_slice()),
+        Err(e) => Err(serde::ser::Error::custom(e)),
+    }
+}
+
+/// Deserialize a value written by `serialize`
+pub fn deserialize<'de, T: Deserialize, D: serde::Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    deserializer.deserialize_bytes(NativeVisitor(PhantomData))
+}
+
+/// Read a value from the bytes of its native serialization
+struct NativeVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize> Visitor<'de> for NativeVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the bytes of a serialized succinct structure")
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<T, E> {
+        Deserialize::deserialize(&mut Cursor::new(v)).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            match seq.next_element() {
+                Ok(Some(b)) => buf.push(b),
+                Ok(None) => return self.visit_bytes(buf.as_slice()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Implement the serde traits of structures by their native ones
+macro_rules! via_native {
+    ($($t:ident),*) => {$(
+        impl serde::Serialize for $t {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize(self, serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                deserialize(deserializer)
+            }
+        }
+    )*};
+}
+
+/// Implement the serde traits of generic structures by their native
+/// ones, given those of their parameters
+macro_rules! via_native_generic {
+    ($($t:ident<$($p:ident),+>),*) => {$(
+        impl<$($p: Serialize),+> serde::Serialize for $t<$($p),+> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize(self, serializer)
+            }
+        }
+
+        impl<'de, $($p: Deserialize),+> serde::Deserialize<'de> for $t<$($p),+> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<$t<$($p),+>, D::Error> {
+                deserialize(deserializer)
+            }
+        }
+    )*};
+}
+
+via_native!(BitVector, Rank9, Counts, IntVector, Dac, EliasFano, CodedSequence, VByteSequence,
+            PForArray, PrefixSums, GapList, RleColumn, Multiset, Trie, Rmq,
+            BalancedParens, BpTree, Louds, Dfuds, CardinalTree, K2Tree, K2Raster,
+            BinaryRelation, EliasFanoGraph, BvGraph, SequenceGraph);
+
+via_native_generic!(AlphabeticWavelet<BitV, Sym>, Tree<T>, SuccinctRose<T>, SparseArray<T>,
+                    Either<A, B>);
+
+// The symbols of these wavelet trees are not serialized
+impl<BitV: Serialize, Sym> serde::Serialize for Wavelet<BitV, Sym> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de, BitV, Sym> serde::Deserialize<'de> for Wavelet<BitV, Sym>
+    where BitV: Deserialize + Rank<bool> + Access<bool> + Collection, Sym: BitIter
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Wavelet<BitV, Sym>, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+impl<BitV: Serialize, Sym> serde::Serialize for FlatWavelet<BitV, Sym> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de, BitV: Deserialize + Collection, Sym> serde::Deserialize<'de> for FlatWavelet<BitV, Sym> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<FlatWavelet<BitV, Sym>, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json;
+    use super::super::dictionary::{Access, BitRank};
+    use super::super::elias_fano::EliasFano;
+    use super::super::rank9::{self, Rank9};
+    use super::super::wavelet::Wavelet;
+
+    #[quickcheck]
+    fn rank9_round_trips(v: Vec<bool>) -> bool {
+        use super::super::build::Builder;
+        let bits: Rank9 = rank9::Builder::with_capacity(v.len()).from_iter(v.clone().into_iter());
+        let json = serde_json::to_string(&bits).unwrap();
+        let read: Rank9 = serde_json::from_str(&json).unwrap();
+        (0..v.len()).all(|i| read.get(i) == v[i])
+            && read.rank1(v.len()) == bits.rank1(v.len())
+    }
+
+    #[quickcheck]
+    fn wavelet_round_trips(v: Vec<u8>) -> bool {
+        let wavelet = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(128));
+        let json = serde_json::to_string(&wavelet).unwrap();
+        let read: Wavelet<Rank9, u8> = serde_json::from_str(&json).unwrap();
+        (0..v.len()).all(|i| read.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_invalid() {
+        let ef = EliasFano::from_slice(&[1, 5, 9]);
+        let mut json = serde_json::to_string(&ef).unwrap();
+        // drop the last byte of the native serialization
+        let end = json.rfind(',').unwrap();
+        json.truncate(end);
+        json.push(']');
+        assert!(serde_json::from_str::<EliasFano>(&json).is_err());
+    }
+}
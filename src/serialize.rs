@@ -0,0 +1,185 @@
+//! Serialization of succinct structures
+//
+// Structures are written as a sequence of little-endian 64-bit words,
+// so indexes can be built once and loaded at startup.
+
+use std::io::{self, Read, Write};
+use std::char;
+use std::cmp::min;
+use std::mem::size_of;
+
+/// The most bytes a vector read from a stream reserves before its
+/// elements are read; its length is only as good as the stream, so any
+/// more is left to `push`
+const MAX_RESERVE: usize = 1 << 20;
+
+/// A structure which can be written to a byte stream
+pub trait Serialize {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()>;
+}
+
+/// A structure which can be read back from a byte stream written by
+/// its `Serialize` implementation
+pub trait Deserialize: Sized {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+/// The error produced when a stream doesn't hold a valid structure
+pub fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Write a word in little-endian byte order
+pub fn write_u64<W: Write>(w: &mut W, x: u64) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+/// Read a word written by `write_u64`
+pub fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Write a length or position
+pub fn write_uint<W: Write>(w: &mut W, x: usize) -> io::Result<()> {
+    write_u64(w, x as u64)
+}
+
+/// Read a length or position written by `write_uint`
+pub fn read_uint<R: Read>(r: &mut R) -> io::Result<usize> {
+    let x = read_u64(r)?;
+    if x > (usize::MAX as u64) {
+        return Err(invalid_data("length exceeds address space"));
+    }
+    Ok(x as usize)
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.len())?;
+        for x in self.iter() {
+            x.serialize(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Deserialize> Deserialize for Vec<T> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Vec<T>> {
+        let len = read_uint(r)?;
+        let mut v = Vec::with_capacity(min(len, MAX_RESERVE / size_of::<T>().max(1)));
+        for _ in 0..len {
+            v.push(Deserialize::deserialize(r)?);
+        }
+        Ok(v)
+    }
+}
+
+impl Serialize for u64 {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, *self)
+    }
+}
+
+impl Deserialize for u64 {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<u64> {
+        read_u64(r)
+    }
+}
+
+impl Serialize for u32 {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, *self as u64)
+    }
+}
+
+impl Deserialize for u32 {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<u32> {
+        let x = read_u64(r)?;
+        if x > (u32::MAX as u64) { return Err(invalid_data("u32 out of range")); }
+        Ok(x as u32)
+    }
+}
+
+impl Serialize for u16 {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, *self as u64)
+    }
+}
+
+impl Deserialize for u16 {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<u16> {
+        let x = read_u64(r)?;
+        if x > (u16::MAX as u64) { return Err(invalid_data("u16 out of range")); }
+        Ok(x as u16)
+    }
+}
+
+impl Serialize for u8 {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, *self as u64)
+    }
+}
+
+impl Deserialize for u8 {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<u8> {
+        let x = read_u64(r)?;
+        if x > (u8::MAX as u64) { return Err(invalid_data("u8 out of range")); }
+        Ok(x as u8)
+    }
+}
+
+impl Serialize for char {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_u64(w, *self as u64)
+    }
+}
+
+impl Deserialize for char {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<char> {
+        let x = read_u64(r)?;
+        if x > (u32::MAX as u64) { return Err(invalid_data("invalid code point")); }
+        match char::from_u32(x as u32) {
+            Some(c) => Ok(c),
+            None => Err(invalid_data("invalid code point")),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::io::{self, Cursor};
+    use super::{Serialize, Deserialize};
+
+    /// Serialize a value and read it back
+    pub fn round_trip<T: Serialize + Deserialize>(x: &T) -> T {
+        let mut buf: Vec<u8> = Vec::new();
+        x.serialize(&mut buf).unwrap();
+        let mut reader = Cursor::new(buf);
+        Deserialize::deserialize(&mut reader).unwrap()
+    }
+
+    #[quickcheck]
+    fn vec_round_trips(v: Vec<u64>) -> bool {
+        round_trip(&v) == v
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buf: Vec<u8> = Vec::new();
+        vec!(1u64, 2, 3).serialize(&mut buf).unwrap();
+        buf.pop();
+        let res: Result<Vec<u64>, _> = Deserialize::deserialize(&mut Cursor::new(buf));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_huge_length() {
+        let mut buf: Vec<u8> = Vec::new();
+        (u32::MAX as u64).serialize(&mut buf).unwrap();
+        1u64.serialize(&mut buf).unwrap();
+        let res: Result<Vec<u64>, _> = Deserialize::deserialize(&mut Cursor::new(buf));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,16 @@
+//! Accounting of the memory used by structures
+
+use std::mem::size_of;
+
+/// A structure which can report the memory it occupies
+pub trait SpaceUsage {
+    /// The number of bytes occupied by the structure, including
+    /// those it owns on the heap
+    fn total_bytes(&self) -> usize;
+}
+
+/// The bytes owned by a vector of elements owning no heap memory of
+/// their own
+pub fn vec_bytes<T>(v: &Vec<T>) -> usize {
+    size_of::<Vec<T>>() + v.capacity() * size_of::<T>()
+}
@@ -0,0 +1,190 @@
+//! An array most of whose slots are empty
+//
+// The occupied slots are marked in a `Rank9` bitvector and their
+// values kept densely in a vector, so the value of a slot is found at
+// the rank of its bit. An empty slot costs little more than a bit.
+
+use std::io::{self, Read, Write};
+use std::iter::FromIterator;
+use super::collection::Collection;
+use super::dictionary::{Access, BitRank, Select};
+use super::rank9::Rank9;
+use super::serialize::{Serialize, Deserialize, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+
+pub use crate::sparse_array::build::Builder;
+
+/// An array of optional values
+pub struct SparseArray<T> {
+    /// whether each slot holds a value
+    occupied: Rank9,
+    /// the values of the occupied slots in order
+    values: Vec<T>,
+}
+
+impl<T> SparseArray<T> {
+    /// The value of slot `n`, if any
+    pub fn get(&self, n: usize) -> Option<&T> {
+        assert!(n < self.len(), "SparseArray: index {} out of bounds", n);
+        match self.occupied.get(n) {
+            true => Some(&self.values[self.occupied.rank1(n)]),
+            false => None,
+        }
+    }
+
+    /// Whether slot `n` holds a value
+    pub fn contains(&self, n: usize) -> bool {
+        self.occupied.get(n)
+    }
+
+    /// The number of occupied slots before slot `n`
+    pub fn rank(&self, n: usize) -> usize {
+        self.occupied.rank1(n)
+    }
+
+    /// The number of occupied slots
+    pub fn count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The values of the occupied slots in order
+    pub fn values(&self) -> &[T] {
+        self.values.as_slice()
+    }
+
+    /// An iterator over the occupied slots and their values
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter { array: self, n: 0 }
+    }
+}
+
+impl<T> Collection for SparseArray<T> {
+    fn len(&self) -> usize {
+        self.occupied.len()
+    }
+}
+
+impl<T> FromIterator<Option<T>> for SparseArray<T> {
+    fn from_iter<I: IntoIterator<Item=Option<T>>>(iter: I) -> SparseArray<T> {
+        use super::build::Builder;
+        build::Builder::new().from_iter(iter.into_iter())
+    }
+}
+
+impl<T> SpaceUsage for SparseArray<T> {
+    fn total_bytes(&self) -> usize {
+        self.occupied.total_bytes() + vec_bytes(&self.values)
+    }
+}
+
+impl<T: Serialize> Serialize for SparseArray<T> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.occupied.serialize(w)?;
+        self.values.serialize(w)
+    }
+}
+
+impl<T: Deserialize> Deserialize for SparseArray<T> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<SparseArray<T>> {
+        let occupied: Rank9 = Deserialize::deserialize(r)?;
+        let values: Vec<T> = Deserialize::deserialize(r)?;
+        if occupied.rank1(occupied.len()) != values.len() {
+            return Err(invalid_data("SparseArray values inconsistent with occupancy"));
+        }
+        Ok(SparseArray { occupied, values })
+    }
+}
+
+/// An iterator over the occupied slots of a `SparseArray` and their
+/// values
+pub struct Iter<'a, T: 'a> {
+    array: &'a SparseArray<T>,
+    /// the index of the next value
+    n: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (usize, &'a T);
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        if self.n == self.array.values.len() {
+            return None;
+        }
+        self.n += 1;
+        let pos = self.array.occupied.select(true, self.n) - 1;
+        Some((pos, &self.array.values[self.n - 1]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.array.values.len() - self.n;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+mod build {
+    use super::super::build;
+    use super::super::rank9;
+    use super::SparseArray;
+
+    /// Build a `SparseArray` from its slots
+    pub struct Builder<T> {
+        occupied: rank9::Builder,
+        values: Vec<T>,
+    }
+
+    impl<T> Builder<T> {
+        pub fn new() -> Builder<T> {
+            Builder { occupied: rank9::Builder::with_capacity(128), values: Vec::new() }
+        }
+    }
+
+    impl<T> Default for Builder<T> {
+        fn default() -> Builder<T> {
+            Builder::new()
+        }
+    }
+
+    impl<T> build::Builder<Option<T>, SparseArray<T>> for Builder<T> {
+        fn push(&mut self, slot: Option<T>) {
+            self.occupied.push(slot.is_some());
+            for x in slot.into_iter() {
+                self.values.push(x);
+            }
+        }
+
+        fn finish(self) -> SparseArray<T> {
+            SparseArray { occupied: self.occupied.finish(), values: self.values }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SparseArray;
+    use super::super::collection::Collection;
+
+    #[quickcheck]
+    fn get_is_correct(v: Vec<Option<u32>>) -> bool {
+        let array: SparseArray<u32> = v.iter().copied().collect();
+        array.len() == v.len()
+            && (0..v.len()).all(|i| array.get(i).copied() == v[i])
+    }
+
+    #[quickcheck]
+    fn iter_is_correct(v: Vec<Option<u32>>) -> bool {
+        let array: SparseArray<u32> = v.iter().copied().collect();
+        let expected: Vec<(usize, u32)> = v.iter().enumerate()
+            .filter_map(|(i, x)| x.map(|x| (i, x)))
+            .collect();
+        array.iter().map(|(i, x)| (i, *x)).collect::<Vec<(usize, u32)>>() == expected
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<Option<u32>>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let array: SparseArray<u32> = v.iter().copied().collect();
+        let array: SparseArray<u32> = round_trip(&array);
+        (0..v.len()).all(|i| array.get(i).copied() == v[i])
+    }
+}
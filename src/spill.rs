@@ -0,0 +1,197 @@
+//! Spilling finished values out of memory during construction
+//
+// A long build holds the values it has finished, such as the node
+// bitvectors of a wavelet tree, until it is done. A `SpillBuilder`
+// instead writes the serialization of its value to a file shared by
+// the builders of a structure as soon as it is finished, keeping only
+// a `Spilled` handle giving where it lies. A handle loads its value
+// back on demand, and serializes as the value itself by copying its
+// bytes from the file, so that a structure built of handles is written
+// out in the form of the structure built of the values without these
+// ever being held in memory together.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::rc::Rc;
+use super::build::Builder;
+use super::serialize::{Serialize, Deserialize, invalid_data};
+
+struct Inner<F> {
+    file: F,
+    /// the end of the values spilled
+    end: u64,
+    /// the first error met spilling a value
+    error: Option<io::Error>,
+}
+
+/// A file holding spilled values, shared by their builders and
+/// handles
+pub struct SpillFile<F> {
+    inner: Rc<RefCell<Inner<F>>>,
+}
+
+impl<F> Clone for SpillFile<F> {
+    fn clone(&self) -> SpillFile<F> {
+        SpillFile { inner: self.inner.clone() }
+    }
+}
+
+impl<F: Read + Write + Seek> SpillFile<F> {
+    /// Spill values to `file`, from its start
+    pub fn new(file: F) -> SpillFile<F> {
+        SpillFile { inner: Rc::new(RefCell::new(Inner { file, end: 0, error: None })) }
+    }
+
+    /// A builder spilling the value of `builder` once it is finished
+    pub fn spill<B>(&self, builder: B) -> SpillBuilder<B, F> {
+        SpillBuilder { builder, file: self.clone() }
+    }
+
+    /// The number of bytes spilled
+    pub fn len(&self) -> u64 {
+        self.inner.borrow().end
+    }
+
+    /// Whether nothing was spilled
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether all values were spilled, giving the first error met
+    /// otherwise
+    pub fn check(&self) -> io::Result<()> {
+        match self.inner.borrow_mut().error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the serialization of `value` after the values spilled,
+    /// giving its offset and length
+    fn write<T: Serialize>(&self, value: &T) -> io::Result<(u64, u64)> {
+        let mut inner = self.inner.borrow_mut();
+        let start = inner.end;
+        inner.file.seek(SeekFrom::Start(start))?;
+        value.serialize(&mut inner.file)?;
+        let end = inner.file.stream_position()?;
+        inner.end = end;
+        Ok((start, end - start))
+    }
+}
+
+/// Build a value and spill it when finished, as made by
+/// `SpillFile::spill`
+pub struct SpillBuilder<B, F> {
+    builder: B,
+    file: SpillFile<F>,
+}
+
+impl<E, T, B, F> Builder<E, Spilled<T, F>> for SpillBuilder<B, F>
+    where T: Serialize, B: Builder<E, T>, F: Read + Write + Seek
+{
+    fn push(&mut self, e: E) {
+        self.builder.push(e);
+    }
+
+    /// Errors are recorded in the file, to be found by
+    /// `SpillFile::check`, and by the handle when it is used
+    fn finish(self) -> Spilled<T, F> {
+        let value = self.builder.finish();
+        let extent = match self.file.write(&value) {
+            Ok(extent) => Some(extent),
+            Err(e) => {
+                let mut inner = self.file.inner.borrow_mut();
+                if inner.error.is_none() {
+                    inner.error = Some(e);
+                }
+                None
+            },
+        };
+        Spilled { file: self.file, extent, phantom: PhantomData }
+    }
+}
+
+/// A value spilled to a `SpillFile`
+pub struct Spilled<T, F> {
+    file: SpillFile<F>,
+    /// the offset and length of the serialization, unless spilling
+    /// failed
+    extent: Option<(u64, u64)>,
+    phantom: PhantomData<T>,
+}
+
+impl<T, F: Read + Seek> Spilled<T, F> {
+    /// The number of bytes of the serialization of the value
+    pub fn len(&self) -> u64 {
+        self.extent.map(|(_, len)| len).unwrap_or(0)
+    }
+
+    /// Whether the serialization of the value is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the spilled value back
+    pub fn load(&self) -> io::Result<T> where T: Deserialize {
+        let (start, len) = self.extent()?;
+        let mut inner = self.file.inner.borrow_mut();
+        inner.file.seek(SeekFrom::Start(start))?;
+        Deserialize::deserialize(&mut (&mut inner.file).take(len))
+    }
+
+    fn extent(&self) -> io::Result<(u64, u64)> {
+        self.extent.ok_or(invalid_data("Spilled: value was not spilled"))
+    }
+}
+
+/// Written as the value itself, copied from the file
+impl<T, F: Read + Seek> Serialize for Spilled<T, F> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (start, len) = self.extent()?;
+        let mut inner = self.file.inner.borrow_mut();
+        inner.file.seek(SeekFrom::Start(start))?;
+        let copied = io::copy(&mut (&mut inner.file).take(len), w)?;
+        match copied == len {
+            true => Ok(()),
+            false => Err(invalid_data("Spilled: spill file truncated")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::convert::From;
+    use super::SpillFile;
+    use super::super::build::Builder;
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, Rank};
+    use super::super::rank9::{self, Rank9};
+    use super::super::serialize::{Serialize, Deserialize};
+    use super::super::serialize::test::round_trip;
+    use super::super::wavelet::{self, Wavelet};
+
+    #[quickcheck]
+    fn spilled_values_load(v: Vec<bool>, w: Vec<bool>) -> bool {
+        let file = SpillFile::new(Cursor::new(Vec::new()));
+        let a = file.spill(rank9::Builder::with_capacity(64)).from_iter(v.clone().into_iter());
+        let b = file.spill(rank9::Builder::with_capacity(64)).from_iter(w.clone().into_iter());
+        let (a, b): (Rank9, Rank9) = (a.load().unwrap(), b.load().unwrap());
+        file.check().is_ok() && a == Rank9::from(v) && b == Rank9::from(w)
+    }
+
+    #[quickcheck]
+    fn spilled_wavelet_serializes(v: Vec<u8>) -> bool {
+        let file = SpillFile::new(Cursor::new(Vec::new()));
+        let spiller = file.clone();
+        let new_bitvector = move || spiller.spill(rank9::Builder::with_capacity(64));
+        let spilled = wavelet::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let mut bytes = Vec::new();
+        spilled.serialize(&mut bytes).unwrap();
+        let wavelet: Wavelet<Rank9, u8> = Deserialize::deserialize(&mut Cursor::new(bytes)).unwrap();
+        let read: Wavelet<Rank9, u8> = round_trip(&wavelet);
+        file.check().is_ok() && read.len() == v.len()
+            && v.iter().enumerate().all(|(i, x)| read.get(i) == *x && read.rank(*x, i) == v.rank(*x, i))
+    }
+}
@@ -0,0 +1,179 @@
+//! Streaming serialization in bounded chunks
+//
+// `Serialize` writes a structure word by word, so it can be streamed
+// to any `Write` without first being gathered in memory. Stores taking
+// data in parts of a fixed size, such as the multipart uploads of
+// object stores, are served by `ChunkWriter`, which hands its writer
+// the stream in chunks of exactly the size asked for, the last alone
+// being shorter. `ChunkReader` likewise asks its reader for chunks of
+// a fixed size, as ranged reads of an object would give.
+//
+// The stream builders of `bit_vector` and `rank9` write the
+// serialization of a bitvector as it is built, holding none of its
+// words in memory.
+
+use std::cmp::min;
+use std::io::{self, Read, Write};
+
+/// Write to a writer in chunks of a fixed number of bytes
+pub struct ChunkWriter<W> {
+    writer: W,
+    chunk: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Write to `writer` in chunks of `chunk` bytes
+    pub fn new(writer: W, chunk: usize) -> ChunkWriter<W> {
+        assert!(chunk > 0, "ChunkWriter: chunks must be nonempty");
+        ChunkWriter { writer, chunk, buf: Vec::with_capacity(chunk) }
+    }
+
+    /// Write the final, possibly short, chunk and give back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buf.is_empty() {
+            self.writer.write_all(self.buf.as_slice())?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = min(buf.len(), self.chunk - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == self.chunk {
+            self.writer.write_all(self.buf.as_slice())?;
+            self.buf.clear();
+        }
+        Ok(n)
+    }
+
+    /// Flushes the writer alone, as a partial chunk may only be
+    /// written last by `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Read from a reader in chunks of a fixed number of bytes
+pub struct ChunkReader<R> {
+    reader: R,
+    chunk: usize,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Read from `reader` in chunks of `chunk` bytes
+    pub fn new(reader: R, chunk: usize) -> ChunkReader<R> {
+        assert!(chunk > 0, "ChunkReader: chunks must be nonempty");
+        ChunkReader { reader, chunk, buf: Vec::with_capacity(chunk), pos: 0 }
+    }
+
+    /// Give back the reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next chunk, short only at the end of the stream
+    fn fill(&mut self) -> io::Result<()> {
+        self.buf.clear();
+        self.pos = 0;
+        self.reader.by_ref().take(self.chunk as u64).read_to_end(&mut self.buf)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            self.fill()?;
+        }
+        let n = min(buf.len(), self.buf.len() - self.pos);
+        for (dst, src) in buf.iter_mut().zip(self.buf[self.pos..self.pos + n].iter()) {
+            *dst = *src;
+        }
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Cursor, Read, Write};
+    use super::{ChunkWriter, ChunkReader};
+    use super::super::bit_vector::{self, BitVector};
+    use super::super::build::Builder;
+    use super::super::dictionary::BitRank;
+    use super::super::rank9::{self, Rank9};
+    use super::super::serialize::{Serialize, Deserialize};
+
+    /// A writer recording the size of each write
+    struct Parts {
+        bytes: Vec<u8>,
+        sizes: Vec<usize>,
+    }
+
+    impl Write for Parts {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.extend_from_slice(buf);
+            self.sizes.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[quickcheck]
+    fn chunks_are_bounded(bits: Vec<bool>, chunk: u8) -> bool {
+        let chunk = chunk as usize % 50 + 1;
+        let rank9: Rank9 = rank9::Builder::with_capacity(bits.len()).from_iter(bits.clone().into_iter());
+        let mut expected: Vec<u8> = Vec::new();
+        rank9.serialize(&mut expected).unwrap();
+
+        let mut w = ChunkWriter::new(Parts { bytes: Vec::new(), sizes: Vec::new() }, chunk);
+        rank9.serialize(&mut w).unwrap();
+        let parts = w.finish().unwrap();
+        let n = parts.sizes.len();
+        let mut r = ChunkReader::new(Cursor::new(parts.bytes.clone()), chunk);
+        let read: Rank9 = Deserialize::deserialize(&mut r).unwrap();
+        parts.bytes == expected
+            && parts.sizes.iter().take(n.saturating_sub(1)).all(|s| *s == chunk)
+            && read.rank1(bits.len()) == rank9.rank1(bits.len())
+    }
+
+    #[quickcheck]
+    fn stream_builders_serialize(bits: Vec<bool>) -> bool {
+        let bv: BitVector = bit_vector::Builder::with_capacity(bits.len()).from_iter(bits.clone().into_iter());
+        let rank9: Rank9 = rank9::Builder::with_capacity(bits.len()).from_iter(bits.clone().into_iter());
+        let (mut bv_bytes, mut rank9_bytes): (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+        bv.serialize(&mut bv_bytes).unwrap();
+        rank9.serialize(&mut rank9_bytes).unwrap();
+
+        let streamed = bit_vector::StreamBuilder::new(Vec::new(), bits.len()).unwrap()
+            .from_iter(bits.clone().into_iter()).unwrap();
+        let streamed9 = rank9::StreamBuilder::new(Vec::new(), bits.len()).unwrap()
+            .from_iter(bits.clone().into_iter()).unwrap();
+        streamed == bv_bytes && streamed9 == rank9_bytes
+    }
+
+    #[test]
+    fn test_stream_builder_length() {
+        let mut b = bit_vector::StreamBuilder::new(Vec::new(), 3).unwrap();
+        b.push(true);
+        b.push(false);
+        assert!(b.finish().is_err());
+
+        let mut r = ChunkReader::new(Cursor::new(vec!(1u8, 2, 3, 4, 5)), 2);
+        let mut buf = [0u8; 8];
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(r.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 5);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+}
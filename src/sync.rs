@@ -0,0 +1,125 @@
+// Thread safety of the structures
+//
+// Built structures own their words in vectors, or borrow them as
+// views and thawed regions do, and are never changed by queries, so
+// they are all `Send` and `Sync`. The assertions below fail the build
+// should a structure gain a field that isn't, such as an `Rc`, a
+// `Cell` or a raw pointer.
+
+use super::bit_vector::{BitVector, BitVectorRef};
+use super::bwt::Bwt;
+use super::coded::CodedSequence;
+use super::csa::Csa;
+use super::dac::Dac;
+use super::dna::DnaIndex;
+use super::docs::DocumentIndex;
+use super::either::Either;
+use super::elias_fano::EliasFano;
+use super::fm::FmIndex;
+use super::graph::elias_fano::EliasFanoGraph;
+use super::graph::sequence::SequenceGraph;
+use super::graph::webgraph::BvGraph;
+use super::grid::Grid;
+use super::int_vector::IntVector;
+use super::k2raster::K2Raster;
+use super::k2tree::K2Tree;
+use super::multiset::Multiset;
+use super::persistent::PersistentBitVector;
+use super::pfor::PForArray;
+use super::postings::GapList;
+use super::prefix_sums::PrefixSums;
+use super::rank9::{Rank9, Rank9Ref};
+use super::relation::BinaryRelation;
+use super::rindex::RIndex;
+use super::rle::RleColumn;
+use super::rmq::Rmq;
+use super::sequence::RankedSequence;
+use super::sparse_array::SparseArray;
+use super::tree::binary::{Tree, Cursor};
+use super::tree::binary::arena::ArenaTree;
+use super::tree::bp::BpTree;
+use super::tree::cardinal::CardinalTree;
+use super::tree::dfuds::Dfuds;
+use super::tree::louds::Louds;
+use super::tree::parens::BalancedParens;
+use super::tree::rose::SuccinctRose;
+use super::trie::Trie;
+use super::vbyte::VByteSequence;
+use super::wavelet::{Wavelet, FlatWavelet, AlphabeticWavelet, HuffmanWavelet};
+
+const fn send_sync<T: Send + Sync>() {}
+
+const _: () = {
+    send_sync::<BitVector>();
+    send_sync::<BitVectorRef<'static>>();
+    send_sync::<Rank9>();
+    send_sync::<Rank9Ref<'static>>();
+    send_sync::<PersistentBitVector>();
+    send_sync::<IntVector>();
+    send_sync::<Dac>();
+    send_sync::<CodedSequence>();
+    send_sync::<EliasFano>();
+    send_sync::<VByteSequence>();
+    send_sync::<PrefixSums>();
+    send_sync::<RleColumn>();
+    send_sync::<GapList>();
+    send_sync::<PForArray>();
+    send_sync::<Multiset>();
+    send_sync::<SparseArray<u64>>();
+    send_sync::<RankedSequence<u64>>();
+    send_sync::<Trie>();
+    send_sync::<Rmq>();
+    send_sync::<Bwt>();
+    send_sync::<FmIndex>();
+    send_sync::<Csa>();
+    send_sync::<RIndex>();
+    send_sync::<DocumentIndex>();
+    send_sync::<DnaIndex>();
+    send_sync::<Wavelet<Rank9, u8>>();
+    send_sync::<Wavelet<Rank9Ref<'static>, u64>>();
+    send_sync::<FlatWavelet<Rank9, u8>>();
+    send_sync::<AlphabeticWavelet<Rank9, u8>>();
+    send_sync::<HuffmanWavelet<Rank9>>();
+    send_sync::<Either<BitVector, Rank9>>();
+    send_sync::<Tree<u64>>();
+    send_sync::<Cursor<'static, u64>>();
+    send_sync::<ArenaTree<u64>>();
+    send_sync::<BalancedParens>();
+    send_sync::<BpTree>();
+    send_sync::<CardinalTree>();
+    send_sync::<Dfuds>();
+    send_sync::<Louds>();
+    send_sync::<SuccinctRose<u64>>();
+    send_sync::<Grid>();
+    send_sync::<K2Tree>();
+    send_sync::<K2Raster>();
+    send_sync::<BinaryRelation>();
+    send_sync::<BvGraph>();
+    send_sync::<EliasFanoGraph>();
+    send_sync::<SequenceGraph>();
+};
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use super::super::dictionary::{Access, Rank};
+    use super::super::rank9;
+    use super::super::wavelet::Wavelet;
+    use super::super::rank9::Rank9;
+
+    #[quickcheck]
+    fn shared_wavelet_is_correct(v: Vec<u8>) -> bool {
+        let wavelet: Wavelet<Rank9, u8> = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(16));
+        let wavelet = &wavelet;
+        let v = &v;
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|t| scope.spawn(move || {
+                (0..v.len()).filter(|i| i % 4 == t).all(|i| {
+                    wavelet.get(i) == v[i]
+                        && wavelet.rank(v[i], i) == v[..i].iter().filter(|x| **x == v[i]).count()
+                })
+            })).collect();
+            handles.into_iter().all(|h| h.join().unwrap())
+        })
+    }
+}
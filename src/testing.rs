@@ -0,0 +1,433 @@
+//! `quickcheck` support for property tests of the structures
+//
+// Uniformly random bits exercise little of a succinct structure: its
+// counts are all near half full and its runs short. The bits generated
+// here are instead drawn from one of several shapes, all zeros, all
+// ones, long runs, sparse or dense ones, or uniform, with lengths
+// crossing the block boundaries of the structures. Each structure is
+// generated from such bits or from symbols drawn likewise, and shrunk
+// by shrinking them. The integer sequences are generated from such
+// symbols too, with arbitrary parameters kept as they are shrunk.
+//
+// Enabled by the `testing` feature, for crates wrapping these types to
+// write their own property tests.
+
+use std::convert::From;
+use std::fmt;
+use quickcheck::{Arbitrary, Gen};
+use super::bit_vector::BitVector;
+use super::bits::BitIter;
+use super::build::{Builder, Buildable};
+use super::coded::{self, Code, CodedSequence};
+use super::collection::Collection;
+use super::dac::{self, Dac};
+use super::dictionary::Access;
+use super::elias_fano::EliasFano;
+use super::int_vector::IntVector;
+use super::multiset::Multiset;
+use super::pfor::PForArray;
+use super::postings::{GapCode, GapList};
+use super::prefix_sums::PrefixSums;
+use super::rank9::{self, Rank9};
+use super::rle::{self, RleColumn};
+use super::vbyte::{self, VByteSequence};
+use super::wavelet::Wavelet;
+
+/// The shapes of the bits generated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Zeros,
+    Ones,
+    /// alternating runs of zeros and ones of random lengths
+    Runs,
+    /// ones with probability 1/64
+    Sparse,
+    /// zeros with probability 1/64
+    Dense,
+    Uniform,
+}
+
+impl Arbitrary for Shape {
+    fn arbitrary(g: &mut Gen) -> Shape {
+        match gen_range(g, 0, 6) {
+            0 => Shape::Zeros,
+            1 => Shape::Ones,
+            2 => Shape::Runs,
+            3 => Shape::Sparse,
+            4 => Shape::Dense,
+            _ => Shape::Uniform,
+        }
+    }
+}
+
+/// A number in `[lo, hi)`
+fn gen_range(g: &mut Gen, lo: usize, hi: usize) -> usize {
+    lo + usize::arbitrary(g) % (hi - lo)
+}
+
+/// A length of up to a few blocks of 512 bits, scaled by the size of
+/// `g`, often landing on a word or block boundary
+fn arbitrary_len(g: &mut Gen) -> usize {
+    let max = 8 * g.size() + 1;
+    let len = gen_range(g, 0, max);
+    match gen_range(g, 0, 4) {
+        0 => len - len % 64,
+        1 => len - len % 512,
+        _ => len,
+    }
+}
+
+/// `len` bits of the given shape
+pub fn shaped_bits(g: &mut Gen, shape: Shape, len: usize) -> Vec<bool> {
+    match shape {
+        Shape::Zeros => (0..len).map(|_| false).collect(),
+        Shape::Ones => (0..len).map(|_| true).collect(),
+        Shape::Runs => {
+            let mut bits = Vec::with_capacity(len);
+            let mut bit = bool::arbitrary(g);
+            while bits.len() < len {
+                let run = gen_range(g, 1, 200);
+                bits.extend((0..run).map(|_| bit).take(len - bits.len()));
+                bit = !bit;
+            }
+            bits
+        },
+        Shape::Sparse => (0..len).map(|_| gen_range(g, 0, 64) == 0).collect(),
+        Shape::Dense => (0..len).map(|_| gen_range(g, 0, 64) != 0).collect(),
+        Shape::Uniform => (0..len).map(|_| bool::arbitrary(g)).collect(),
+    }
+}
+
+/// Bits of an arbitrary shape and length
+pub fn arbitrary_bits(g: &mut Gen) -> Vec<bool> {
+    let shape = Arbitrary::arbitrary(g);
+    let len = arbitrary_len(g);
+    shaped_bits(g, shape, len)
+}
+
+fn bits_of<T: Access<bool> + Collection>(bv: &T) -> Vec<bool> {
+    (0..bv.len()).map(|i| bv.get(i)).collect()
+}
+
+impl Arbitrary for BitVector {
+    fn arbitrary(g: &mut Gen) -> BitVector {
+        BitVector::from(arbitrary_bits(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=BitVector>> {
+        Box::new(bits_of(self).shrink().map(BitVector::from))
+    }
+}
+
+impl Arbitrary for Rank9 {
+    fn arbitrary(g: &mut Gen) -> Rank9 {
+        Rank9::from(arbitrary_bits(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=Rank9>> {
+        Box::new(bits_of(self).shrink().map(Rank9::from))
+    }
+}
+
+/// Symbols drawn from a small alphabet, runs of one symbol, or
+/// arbitrary symbols
+fn arbitrary_symbols<Sym: Arbitrary>(g: &mut Gen) -> Vec<Sym> {
+    let len = arbitrary_len(g);
+    match gen_range(g, 0, 3) {
+        0 => {
+            let alphabet: Vec<Sym> = (0..gen_range(g, 1, 5)).map(|_| Arbitrary::arbitrary(g)).collect();
+            (0..len).map(|_| g.choose(&alphabet).unwrap().clone()).collect()
+        },
+        1 => {
+            let mut symbols = Vec::with_capacity(len);
+            while symbols.len() < len {
+                let sym: Sym = Arbitrary::arbitrary(g);
+                let run = gen_range(g, 1, 100);
+                symbols.extend((0..run).map(|_| sym.clone()).take(len - symbols.len()));
+            }
+            symbols
+        },
+        _ => (0..len).map(|_| Arbitrary::arbitrary(g)).collect(),
+    }
+}
+
+/// Wavelet trees over `Rank9`, paired with their symbols, which are
+/// shrunk in their place
+#[derive(Clone)]
+pub struct ArbitraryWavelet<Sym> {
+    pub symbols: Vec<Sym>,
+    pub wavelet: Wavelet<Rank9, Sym>,
+}
+
+/// Shown as the symbols
+impl<Sym: fmt::Debug> fmt::Debug for ArbitraryWavelet<Sym> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.symbols.fmt(f)
+    }
+}
+
+fn wavelet<Sym: BitIter + Clone>(symbols: Vec<Sym>) -> ArbitraryWavelet<Sym> {
+    let wavelet = Wavelet::from_slice(symbols.as_slice(), || rank9::Builder::with_capacity(64));
+    ArbitraryWavelet { symbols, wavelet }
+}
+
+impl<Sym: Arbitrary + BitIter> Arbitrary for ArbitraryWavelet<Sym> {
+    fn arbitrary(g: &mut Gen) -> ArbitraryWavelet<Sym> {
+        wavelet(arbitrary_symbols(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=ArbitraryWavelet<Sym>>> {
+        Box::new(self.symbols.shrink().map(|symbols| wavelet(symbols)))
+    }
+}
+
+/// Trees over `Rank9`, shrunk by shrinking their symbols
+impl<Sym: Arbitrary + BitIter + Buildable<bool>> Arbitrary for Wavelet<Rank9, Sym> {
+    fn arbitrary(g: &mut Gen) -> Wavelet<Rank9, Sym> {
+        wavelet(arbitrary_symbols(g)).wavelet
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=Wavelet<Rank9, Sym>>> {
+        Box::new(self.iter().collect::<Vec<Sym>>().shrink().map(|symbols| wavelet(symbols).wavelet))
+    }
+}
+
+impl Arbitrary for IntVector {
+    fn arbitrary(g: &mut Gen) -> IntVector {
+        let width = gen_range(g, 0, 65);
+        let values: Vec<u64> = arbitrary_symbols(g);
+        int_vector(width, values)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=IntVector>> {
+        let width = self.width();
+        Box::new(self.iter().collect::<Vec<u64>>().shrink().map(move |values| int_vector(width, values)))
+    }
+}
+
+/// The values truncated to `width` bits
+fn int_vector(width: usize, values: Vec<u64>) -> IntVector {
+    let mut v = IntVector::new(width, values.len());
+    for (i, x) in values.into_iter().enumerate() {
+        v.set(i, x & super::int_vector::low_mask(width));
+    }
+    v
+}
+
+impl Arbitrary for EliasFano {
+    fn arbitrary(g: &mut Gen) -> EliasFano {
+        // the gaps between values, mostly small with some jumps
+        let gaps: Vec<u64> = arbitrary_symbols::<u16>(g).into_iter().map(|x| x as u64).collect();
+        elias_fano(gaps)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=EliasFano>> {
+        let values: Vec<u64> = self.iter().collect();
+        let gaps: Vec<u64> = (0..values.len()).map(|i| match i {
+            0 => values[0],
+            _ => values[i] - values[i - 1],
+        }).collect();
+        Box::new(gaps.shrink().map(elias_fano))
+    }
+}
+
+/// The sequence of prefix sums of `gaps`
+fn elias_fano(gaps: Vec<u64>) -> EliasFano {
+    let mut sum = 0;
+    let values: Vec<u64> = gaps.into_iter().map(|gap| { sum += gap; sum }).collect();
+    EliasFano::from_slice(values.as_slice())
+}
+
+/// The values of an integer sequence, to be shrunk
+fn values_of<T: Access<u64> + Collection>(seq: &T) -> Vec<u64> {
+    (0..seq.len()).map(|i| seq.get(i)).collect()
+}
+
+impl Arbitrary for Dac {
+    fn arbitrary(g: &mut Gen) -> Dac {
+        let chunk = gen_range(g, 1, 65);
+        dac::Builder::new(chunk).from_iter(arbitrary_symbols::<u64>(g).into_iter())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=Dac>> {
+        let chunk = self.chunk_width();
+        Box::new(values_of(self).shrink().map(move |values| dac::Builder::new(chunk).from_iter(values.into_iter())))
+    }
+}
+
+impl Arbitrary for VByteSequence {
+    fn arbitrary(g: &mut Gen) -> VByteSequence {
+        let block = gen_range(g, 1, 9);
+        vbyte::Builder::new(block).from_iter(arbitrary_symbols::<u64>(g).into_iter())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=VByteSequence>> {
+        let block = self.block();
+        Box::new(values_of(self).shrink().map(move |values| vbyte::Builder::new(block).from_iter(values.into_iter())))
+    }
+}
+
+impl Arbitrary for PForArray {
+    fn arbitrary(g: &mut Gen) -> PForArray {
+        let block = gen_range(g, 1, 9);
+        PForArray::with_block(block, arbitrary_symbols::<u64>(g).as_slice())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=PForArray>> {
+        let block = self.block();
+        Box::new(values_of(self).shrink().map(move |values| PForArray::with_block(block, values.as_slice())))
+    }
+}
+
+impl Arbitrary for Code {
+    fn arbitrary(g: &mut Gen) -> Code {
+        *g.choose(&[Code::Gamma, Code::Delta]).unwrap()
+    }
+}
+
+/// The values, the largest capped to be codable, in `code`
+fn coded_sequence(code: Code, rate: usize, values: Vec<u64>) -> CodedSequence {
+    let values = values.into_iter().map(|x| x.min(coded::MAX_VALUE));
+    coded::Builder::with_sample_rate(code, rate).from_iter(values)
+}
+
+impl Arbitrary for CodedSequence {
+    fn arbitrary(g: &mut Gen) -> CodedSequence {
+        let code = Arbitrary::arbitrary(g);
+        let rate = gen_range(g, 1, 9);
+        coded_sequence(code, rate, arbitrary_symbols(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=CodedSequence>> {
+        let (code, rate) = (self.code(), self.sample_rate());
+        Box::new(values_of(self).shrink().map(move |values| coded_sequence(code, rate, values)))
+    }
+}
+
+/// Values small enough for their sums to fit in a word
+fn arbitrary_small(g: &mut Gen) -> Vec<u64> {
+    arbitrary_symbols::<u16>(g).into_iter().map(|x| x as u64).collect()
+}
+
+impl Arbitrary for PrefixSums {
+    fn arbitrary(g: &mut Gen) -> PrefixSums {
+        PrefixSums::from_slice(arbitrary_small(g).as_slice())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=PrefixSums>> {
+        Box::new(values_of(self).shrink().map(|values| PrefixSums::from_slice(values.as_slice())))
+    }
+}
+
+impl Arbitrary for GapCode {
+    fn arbitrary(g: &mut Gen) -> GapCode {
+        *g.choose(&[GapCode::Gamma, GapCode::Rice]).unwrap()
+    }
+}
+
+/// The list of the prefix sums of `gaps`
+fn gap_list(code: GapCode, block: usize, gaps: Vec<u64>) -> GapList {
+    let mut sum = 0;
+    let values: Vec<u64> = gaps.into_iter().map(|gap| { sum += gap; sum }).collect();
+    GapList::with_block(code, block, values.as_slice())
+}
+
+impl Arbitrary for GapList {
+    fn arbitrary(g: &mut Gen) -> GapList {
+        let code = Arbitrary::arbitrary(g);
+        let block = gen_range(g, 1, 9);
+        gap_list(code, block, arbitrary_small(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=GapList>> {
+        let (code, block) = (self.code(), self.block());
+        let values = values_of(self);
+        let gaps: Vec<u64> = (0..values.len()).map(|i| match i {
+            0 => values[0],
+            _ => values[i] - values[i - 1],
+        }).collect();
+        Box::new(gaps.shrink().map(move |gaps| gap_list(code, block, gaps)))
+    }
+}
+
+impl Arbitrary for RleColumn {
+    fn arbitrary(g: &mut Gen) -> RleColumn {
+        rle::Builder::new().from_iter(arbitrary_symbols::<u64>(g).into_iter())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=RleColumn>> {
+        Box::new(values_of(self).shrink().map(|values| rle::Builder::new().from_iter(values.into_iter())))
+    }
+}
+
+impl Arbitrary for Multiset {
+    fn arbitrary(g: &mut Gen) -> Multiset {
+        Multiset::new(arbitrary_symbols(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=Multiset>> {
+        Box::new(self.iter().collect::<Vec<u64>>().shrink().map(Multiset::new))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::From;
+    use super::ArbitraryWavelet;
+    use super::super::bit_vector::BitVector;
+    use super::super::collection::Collection;
+    use super::super::dictionary::{Access, BitRank, Rank};
+    use super::super::elias_fano::EliasFano;
+    use super::super::int_vector::IntVector;
+    use super::super::rank9::Rank9;
+    use super::super::coded::CodedSequence;
+    use super::super::dac::Dac;
+    use super::super::multiset::Multiset;
+    use super::super::pfor::PForArray;
+    use super::super::postings::GapList;
+    use super::super::prefix_sums::PrefixSums;
+    use super::super::rle::RleColumn;
+    use super::super::vbyte::VByteSequence;
+    use super::super::wavelet::Wavelet;
+
+    #[quickcheck]
+    fn bitvectors_are_valid(bv: BitVector, rank9: Rank9) -> bool {
+        let bits: Vec<bool> = (0..rank9.len()).map(|i| rank9.get(i)).collect();
+        let ones = bits.iter().filter(|b| **b).count();
+        BitVector::from((0..bv.len()).map(|i| bv.get(i)).collect::<Vec<bool>>()) == bv
+            && rank9.rank1(rank9.len()) == ones
+    }
+
+    #[quickcheck]
+    fn sequences_are_valid(w: ArbitraryWavelet<u8>, iv: IntVector, ef: EliasFano) -> bool {
+        let values: Vec<u64> = ef.iter().collect();
+        w.wavelet.len() == w.symbols.len()
+            && w.symbols.iter().enumerate().all(|(i, x)| w.wavelet.get(i) == *x && w.wavelet.rank(*x, i) == w.symbols.rank(*x, i))
+            && iv.iter().count() == iv.len()
+            && values.windows(2).all(|p| p[0] <= p[1])
+    }
+
+    #[quickcheck]
+    fn coded_sequences_are_valid(dac: Dac, vbyte: VByteSequence, pfor: PForArray,
+                                 coded: CodedSequence) -> bool {
+        dac.iter().count() == dac.len() && vbyte.iter().count() == vbyte.len()
+            && pfor.iter().count() == pfor.len() && coded.iter().count() == coded.len()
+    }
+
+    #[quickcheck]
+    fn summed_sequences_are_valid(sums: PrefixSums, gaps: GapList, rle: RleColumn,
+                                  multiset: Multiset) -> bool {
+        let gap_values: Vec<u64> = gaps.iter().collect();
+        let sorted: Vec<u64> = multiset.iter().collect();
+        (0..sums.len()).map(|i| sums.get(i)).sum::<u64>() == sums.total()
+            && gap_values.windows(2).all(|p| p[0] <= p[1])
+            && (0..rle.runs()).map(|r| rle.run(r).2).sum::<usize>() == rle.len()
+            && sorted.windows(2).all(|p| p[0] <= p[1])
+    }
+
+    #[quickcheck]
+    fn wavelets_are_valid(w: Wavelet<Rank9, u16>) -> bool {
+        let symbols: Vec<u16> = w.iter().collect();
+        symbols.len() == w.len() && symbols.iter().enumerate().all(|(i, x)| w.rank(*x, i) == symbols.rank(*x, i))
+    }
+}
@@ -0,0 +1,374 @@
+//! Binary trees with nodes stored in a single vector
+//
+// The nodes refer to their children and parent by `u32` index into
+// the vector, so a tree takes a single allocation and its nodes lie
+// close together in the order they were added. Cursors are indices
+// and need no unsafe code.
+
+use std::ops::{Deref, DerefMut};
+use super::{Tree, Branch};
+use super::Branch::{Left, Right};
+
+/// The index of a node of an `ArenaTree`
+pub type NodeId = u32;
+
+/// The index standing for a missing node
+const NONE: NodeId = u32::MAX;
+
+struct Node<T> {
+    value: T,
+    left: NodeId,
+    right: NodeId,
+    parent: NodeId,
+}
+
+/// A binary tree with nodes labelled with `T`, the root having index
+/// zero
+pub struct ArenaTree<T> {
+    nodes: Vec<Node<T>>,
+}
+
+fn to_option(id: NodeId) -> Option<NodeId> {
+    match id {
+        NONE => None,
+        _ => Some(id),
+    }
+}
+
+impl<T> ArenaTree<T> {
+    pub fn singleton(value: T) -> ArenaTree<T> {
+        ArenaTree { nodes: vec!(Node { value, left: NONE, right: NONE, parent: NONE }) }
+    }
+
+    /// Copy the shape and values of a boxed `Tree`, numbering its
+    /// nodes in preorder
+    pub fn from_tree(tree: Tree<T>) -> ArenaTree<T> {
+        fn go<T>(arena: &mut ArenaTree<T>, parent: NodeId, branch: Branch, tree: Tree<T>) {
+            let Tree { value, left, right } = tree;
+            let id = arena.insert(parent, branch, value);
+            for child in left.into_iter() {
+                go(arena, id, Left, *child);
+            }
+            for child in right.into_iter() {
+                go(arena, id, Right, *child);
+            }
+        }
+        let Tree { value, left, right } = tree;
+        let mut arena = ArenaTree::singleton(value);
+        for child in left.into_iter() {
+            go(&mut arena, 0, Left, *child);
+        }
+        for child in right.into_iter() {
+            go(&mut arena, 0, Right, *child);
+        }
+        arena
+    }
+
+    /// The root node
+    pub fn root(&self) -> NodeId {
+        0
+    }
+
+    /// The number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether there are no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn value(&self, id: NodeId) -> &T {
+        &self.nodes[id as usize].value
+    }
+
+    pub fn value_mut(&mut self, id: NodeId) -> &mut T {
+        &mut self.nodes[id as usize].value
+    }
+
+    /// The child of `id` down `branch`, if any
+    pub fn child(&self, id: NodeId, branch: Branch) -> Option<NodeId> {
+        let node = &self.nodes[id as usize];
+        to_option(match branch {
+            Left => node.left,
+            Right => node.right,
+        })
+    }
+
+    /// The parent of `id` and the branch leading from it to `id`,
+    /// unless `id` is the root
+    pub fn parent(&self, id: NodeId) -> Option<(NodeId, Branch)> {
+        to_option(self.nodes[id as usize].parent).map(|p| {
+            match self.nodes[p as usize].left == id {
+                true => (p, Left),
+                false => (p, Right),
+            }
+        })
+    }
+
+    /// Add a child labelled `value` to `parent` down `branch`,
+    /// returning its index. Panics if the branch is occupied.
+    pub fn insert(&mut self, parent: NodeId, branch: Branch, value: T) -> NodeId {
+        let id = self.nodes.len();
+        assert!(id < NONE as usize, "ArenaTree: too many nodes");
+        let id = id as NodeId;
+        {
+            let node = &mut self.nodes[parent as usize];
+            let slot = match branch {
+                Left => &mut node.left,
+                Right => &mut node.right,
+            };
+            assert!(*slot == NONE, "ArenaTree: attempted to insert {:?} into occupied branch", branch);
+            *slot = id;
+        }
+        self.nodes.push(Node { value, left: NONE, right: NONE, parent });
+        id
+    }
+
+    pub fn map<F, V>(&self, f: F) -> ArenaTree<V>
+        where F: Fn(&T) -> V {
+        ArenaTree {
+            nodes: self.nodes.iter().map(|n| {
+                Node { value: f(&n.value), left: n.left, right: n.right, parent: n.parent }
+            }).collect(),
+        }
+    }
+
+    /// Convert to a boxed `Tree`
+    pub fn into_tree(self) -> Tree<T> {
+        let mut slots: Vec<Option<Node<T>>> = self.nodes.into_iter().map(Some).collect();
+        fn go<T>(slots: &mut Vec<Option<Node<T>>>, id: NodeId) -> Tree<T> {
+            let Node { value, left, right, .. } = slots[id as usize].take().unwrap();
+            Tree {
+                value,
+                left: to_option(left).map(|c| Box::new(go(slots, c))),
+                right: to_option(right).map(|c| Box::new(go(slots, c))),
+            }
+        }
+        go(&mut slots, 0)
+    }
+
+    /// A cursor at the root
+    pub fn cursor<'a>(&'a self) -> Cursor<'a, T> {
+        Cursor::new(self)
+    }
+
+    /// A mutable cursor at the root
+    pub fn cursor_mut<'a>(&'a mut self) -> MutCursor<'a, T> {
+        MutCursor::new(self)
+    }
+}
+
+/// A cursor over the nodes of an `ArenaTree`
+pub struct Cursor<'a, T: 'a> {
+    tree: &'a ArenaTree<T>,
+    node: NodeId,
+    depth: usize,
+}
+
+impl<'a, T> Clone for Cursor<'a, T> {
+    fn clone(&self) -> Cursor<'a, T> {
+        Cursor { tree: self.tree, node: self.node, depth: self.depth }
+    }
+}
+
+impl<'a, T> Cursor<'a, T> {
+    /// Create a new `Cursor` pointing to the root of the given tree
+    pub fn new(tree: &'a ArenaTree<T>) -> Cursor<'a, T> {
+        Cursor { tree, node: 0, depth: 0 }
+    }
+
+    /// The node pointed to
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    /// The number of steps from the root
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Whether the node has a child down `branch`
+    pub fn has_child(&self, branch: Branch) -> bool {
+        self.tree.child(self.node, branch).is_some()
+    }
+
+    /// Whether the node has no children
+    pub fn is_leaf(&self) -> bool {
+        !self.has_child(Left) && !self.has_child(Right)
+    }
+
+    /// Step the cursor back to the root
+    pub fn back_to_root(&mut self) {
+        self.node = 0;
+        self.depth = 0;
+    }
+
+    /// Descend down one of the branches
+    pub fn step(&mut self, branch: Branch) {
+        match self.tree.child(self.node, branch) {
+            None => panic!("Attempted to step {:?} into empty branch", branch),
+            Some(child) => {
+                self.node = child;
+                self.depth += 1;
+            }
+        }
+    }
+
+    /// Step the cursor back to the parent, returning the branch it
+    /// was reached by, unless at the root
+    pub fn up(&mut self) -> Option<Branch> {
+        self.tree.parent(self.node).map(|(parent, branch)| {
+            self.node = parent;
+            self.depth -= 1;
+            branch
+        })
+    }
+}
+
+impl<'a, T> Deref for Cursor<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.tree.value(self.node)
+    }
+}
+
+/// A cursor allowing navigation and mutation of an `ArenaTree`
+pub struct MutCursor<'a, T: 'a> {
+    tree: &'a mut ArenaTree<T>,
+    node: NodeId,
+    depth: usize,
+}
+
+impl<'a, T> MutCursor<'a, T> {
+    /// Create a new `MutCursor` pointing to the root of the given tree
+    pub fn new(tree: &'a mut ArenaTree<T>) -> MutCursor<'a, T> {
+        MutCursor { tree, node: 0, depth: 0 }
+    }
+
+    /// The node pointed to
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    /// The number of steps from the root
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Whether the node has a child down `branch`
+    pub fn has_child(&self, branch: Branch) -> bool {
+        self.tree.child(self.node, branch).is_some()
+    }
+
+    /// Step the cursor back to the root
+    pub fn back_to_root(&mut self) {
+        self.node = 0;
+        self.depth = 0;
+    }
+
+    /// Descend down one of the branches
+    pub fn step(&mut self, branch: Branch) {
+        match self.tree.child(self.node, branch) {
+            None => panic!("Attempted to step {:?} into empty branch", branch),
+            Some(child) => {
+                self.node = child;
+                self.depth += 1;
+            }
+        }
+    }
+
+    /// Descend down one of the branches, first adding a child labelled
+    /// by `f` if the branch is empty
+    pub fn step_or_insert_with<F: FnOnce() -> T>(&mut self, branch: Branch, f: F) {
+        if !self.has_child(branch) {
+            self.tree.insert(self.node, branch, f());
+        }
+        self.step(branch);
+    }
+
+    /// Step the cursor back to the parent, returning the branch it
+    /// was reached by, unless at the root
+    pub fn up(&mut self) -> Option<Branch> {
+        self.tree.parent(self.node).map(|(parent, branch)| {
+            self.node = parent;
+            self.depth -= 1;
+            branch
+        })
+    }
+
+    /// Reclaim the tree
+    pub fn finish(self) -> &'a mut ArenaTree<T> {
+        self.tree
+    }
+}
+
+impl<'a, T> Deref for MutCursor<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.tree.value(self.node)
+    }
+}
+
+impl<'a, T> DerefMut for MutCursor<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.tree.value_mut(self.node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ArenaTree;
+    use super::super::Tree;
+    use super::super::Branch::{Left, Right};
+
+    #[quickcheck]
+    fn round_trips_through_tree(paths: Vec<Vec<bool>>) -> bool {
+        // grow a tree along each path, counting visits to each node
+        let mut arena = ArenaTree::singleton(0usize);
+        for path in paths.iter() {
+            let mut cursor = arena.cursor_mut();
+            *cursor += 1;
+            for bit in path.iter().take(8) {
+                let branch = if *bit { Right } else { Left };
+                cursor.step_or_insert_with(branch, || 0);
+                *cursor += 1;
+            }
+        }
+        let doubled = arena.map(|x| 2 * x);
+        let tree: Tree<usize> = doubled.into_tree();
+        let arena = ArenaTree::from_tree(tree);
+        paths.iter().all(|path| {
+            let mut cursor = arena.cursor();
+            let mut ok = *cursor == 2 * paths.len();
+            for bit in path.iter().take(8) {
+                cursor.step(if *bit { Right } else { Left });
+                let visits = paths.iter()
+                    .filter(|p| p.len() >= cursor.depth() && p[..cursor.depth()] == path[..cursor.depth()])
+                    .count();
+                ok = ok && *cursor == 2 * visits;
+            }
+            while cursor.up().is_some() {}
+            ok && cursor.node() == arena.root()
+        })
+    }
+
+    #[test]
+    fn test_arena() {
+        let mut arena = ArenaTree::singleton("root");
+        let l = arena.insert(0, Left, "l");
+        let lr = arena.insert(l, Right, "lr");
+        assert_eq!(arena.len(), 3);
+        assert_eq!(arena.child(0, Left), Some(l));
+        assert_eq!(arena.child(0, Right), None);
+        assert_eq!(arena.parent(lr), Some((l, Right)));
+        let mut cursor = arena.cursor();
+        cursor.step(Left);
+        cursor.step(Right);
+        assert_eq!(*cursor, "lr");
+        assert_eq!(cursor.depth(), 2);
+        assert_eq!(cursor.up(), Some(Right));
+        assert_eq!(*cursor, "l");
+    }
+}
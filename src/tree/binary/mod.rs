@@ -1,12 +1,19 @@
-pub use tree::binary::cursor::Cursor;
-pub use tree::binary::mut_cursor::MutCursor;
+pub use crate::tree::binary::cursor::Cursor;
+pub use crate::tree::binary::mut_cursor::MutCursor;
+pub use crate::tree::binary::trail::Path;
 use std::fmt;
+use std::io::{self, Read, Write};
+use super::events::{Event, ParseError};
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+
+pub mod arena;
 
 /// A child branch of a `Tree`
-#[derive(Show, Copy)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Branch {Left, Right}
 
 /// A binary tree with nodes labelled with `T`
+#[derive(Clone)]
 pub struct Tree<T> {
     pub value: T,
     pub left: Option<Box<Tree<T>>>,
@@ -16,25 +23,25 @@ pub struct Tree<T> {
 impl<T: fmt::Debug> fmt::Debug for Tree<T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use std::fmt::Debug;
-        fn indent(level: uint, fmt: &mut fmt::Formatter) -> fmt::Result {
-            for i in range(0, level) {try!(fmt.write_str(" "))};
+        fn indent(level: usize, fmt: &mut fmt::Formatter) -> fmt::Result {
+            for _ in 0..level {fmt.write_str(" ")?};
             Ok(())
         }
 
-        fn go<T: Debug>(tree: &Tree<T>, fmt: &mut fmt::Formatter, level: uint) -> fmt::Result {
-            try!(indent(2*level, fmt));
-            try!(write!(fmt, "+ node {:p}    ", tree));
-            try!(tree.value.fmt(fmt));
-            try!(fmt.write_str("\n"));
+        fn go<T: Debug>(tree: &Tree<T>, fmt: &mut fmt::Formatter, level: usize) -> fmt::Result {
+            indent(2*level, fmt)?;
+            (write!(fmt, "+ node {:p}    ", tree))?;
+            tree.value.fmt(fmt)?;
+            fmt.write_str("\n")?;
 
-            try!(indent(2*level, fmt));
-            try!(fmt.write_str("| left:\n"));
-            for subtree in tree.left.iter() {try!(go(&**subtree, fmt, level+1)); }
+            indent(2*level, fmt)?;
+            fmt.write_str("| left:\n")?;
+            for subtree in tree.left.iter() {go(&**subtree, fmt, level+1)?; }
 
-            try!(indent(2*level, fmt));
-            try!(fmt.write_str("| right:\n"));
-            for subtree in tree.right.iter() {try!(go(&**subtree, fmt, level+1)); }
-            try!(fmt.write_str("\n"));
+            indent(2*level, fmt)?;
+            fmt.write_str("| right:\n")?;
+            for subtree in tree.right.iter() {go(&**subtree, fmt, level+1)?; }
+            fmt.write_str("\n")?;
             Ok(())
         }
         go(self, fmt, 0)
@@ -43,26 +50,107 @@ impl<T: fmt::Debug> fmt::Debug for Tree<T> {
 
 impl<T> Tree<T> {
     pub fn singleton(value: T) -> Tree<T> {
-        Tree {value: value, left: None, right: None}
+        Tree {value, left: None, right: None}
+    }
+
+    /// The tree of a depth-first traversal, the first child of each
+    /// node being its left and the second its right
+    pub fn from_events<I: Iterator<Item=Event<T>>>(events: I) -> Result<Tree<T>, ParseError> {
+        // the nodes entered and not yet left, with the index of the
+        // event entering each
+        let mut stack: Vec<(usize, Tree<T>)> = Vec::new();
+        let mut root = None;
+        for (i, event) in events.enumerate() {
+            match event {
+                Event::Enter(x) => {
+                    if root.is_some() {
+                        return Err(ParseError::NotSingleRoot);
+                    }
+                    stack.push((i, Tree::singleton(x)));
+                },
+                Event::Leave => {
+                    let node = match stack.pop() {
+                        None => return Err(ParseError::UnmatchedLeave(i)),
+                        Some((_, node)) => node,
+                    };
+                    match stack.last_mut() {
+                        None => root = Some(node),
+                        Some(&mut (j, ref mut parent)) => {
+                            if parent.left.is_none() {
+                                parent.left = Some(Box::new(node));
+                            } else if parent.right.is_none() {
+                                parent.right = Some(Box::new(node));
+                            } else {
+                                return Err(ParseError::TooManyChildren(j));
+                            }
+                        },
+                    }
+                },
+            }
+        }
+        match stack.is_empty() {
+            true => root.ok_or(ParseError::NotSingleRoot),
+            false => Err(ParseError::Unclosed),
+        }
     }
 
     pub fn map<F, V>(&self, f: F) -> Tree<V>
         where F : Fn(&T) -> V {
-        Tree {
-            left: self.left.as_ref().map(|x| box x.map(|y| f(y))),
-            right: self.right.as_ref().map(|x| box x.map(|y| f(y))),
-            value: f(&self.value),
+        fn go<T, V, F: Fn(&T) -> V>(tree: &Tree<T>, f: &F) -> Tree<V> {
+            Tree {
+                left: tree.left.as_ref().map(|x| Box::new(go(x, f))),
+                right: tree.right.as_ref().map(|x| Box::new(go(x, f))),
+                value: f(&tree.value),
+            }
         }
+        go(self, &f)
     }
 
-    pub fn map_step<V>(self, f: &mut FnMut(T) -> V) -> Tree<V> {
+    pub fn map_step<V>(self, f: &mut dyn FnMut(T) -> V) -> Tree<V> {
         Tree {
-            left: self.left.map(|x| box x.map_step(f)),
-            right: self.right.map(|x| box x.map_step(f)),
+            left: self.left.map(|x| Box::new(x.map_step(f))),
+            right: self.right.map(|x| Box::new(x.map_step(f))),
             value: f(self.value),
         }
     }
 
+    /// Combine the values of the nodes present in both trees with `f`,
+    /// in preorder, keeping the shape they have in common
+    pub fn merge_with<U, V, F>(self, other: Tree<U>, mut f: F) -> Tree<V>
+        where F: FnMut(T, U) -> V {
+        fn go<T, U, V, F: FnMut(T, U) -> V>(a: Tree<T>, b: Tree<U>, f: &mut F) -> Tree<V> {
+            let value = (*f)(a.value, b.value);
+            let left = match (a.left, b.left) {
+                (Some(l), Some(r)) => Some(Box::new(go(*l, *r, f))),
+                _ => None,
+            };
+            let right = match (a.right, b.right) {
+                (Some(l), Some(r)) => Some(Box::new(go(*l, *r, f))),
+                _ => None,
+            };
+            Tree { value, left, right }
+        }
+        go(self, other, &mut f)
+    }
+
+    /// Pair the values of the nodes present in both trees, keeping the
+    /// shape they have in common
+    pub fn zip<U>(self, other: Tree<U>) -> Tree<(T, U)> {
+        self.merge_with(other, |a, b| (a, b))
+    }
+
+    /// Whether the trees have the same shape, whatever their values
+    pub fn same_shape<U>(&self, other: &Tree<U>) -> bool {
+        fn same<T, U>(a: &Option<Box<Tree<T>>>, b: &Option<Box<Tree<U>>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a.same_shape(&**b),
+                (&None, &None) => true,
+                _ => false,
+            }
+        }
+        same(&self.left, &other.left) && same(&self.right, &other.right)
+    }
+
     pub fn branch(&self, branch: Branch) -> &Option<Box<Tree<T>>> {
         match branch {
             Branch::Left  => &self.left,
@@ -78,63 +166,315 @@ impl<T> Tree<T> {
     }
 }
 
+impl<T: fmt::Display> Tree<T> {
+    /// The tree in the DOT language of Graphviz, each node labelled by
+    /// its value and numbered in preorder, and each edge by its branch
+    pub fn to_dot(&self) -> String {
+        fn go<T: fmt::Display>(tree: &Tree<T>, out: &mut String, next: &mut usize) -> usize {
+            let id = *next;
+            *next += 1;
+            let label = format!("{}", tree.value).replace("\\", "\\\\").replace("\"", "\\\"");
+            out.push_str(&format!("  n{} [label=\"{}\"];\n", id, label));
+            for &(child, name) in [(&tree.left, "L"), (&tree.right, "R")].iter() {
+                for subtree in child.iter() {
+                    let child_id = go(&**subtree, out, next);
+                    out.push_str(&format!("  n{} -> n{} [label=\"{}\"];\n", id, child_id, name));
+                }
+            }
+            id
+        }
+        let mut out = String::from("digraph tree {\n");
+        go(self, &mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// The tree is written as its number of nodes, its shape as two bits
+/// per node in preorder, set for a left and a right child, packed in
+/// words, and the values of its nodes in preorder.
+impl<T: Serialize> Serialize for Tree<T> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut shape: Vec<u64> = Vec::new();
+        let mut nodes: Vec<&Tree<T>> = Vec::new();
+        let mut stack = vec!(self);
+        while let Some(node) = stack.pop() {
+            let i = 2 * nodes.len();
+            if i.is_multiple_of(64) {
+                shape.push(0);
+            }
+            let children = (node.left.is_some() as u64) | ((node.right.is_some() as u64) << 1);
+            shape[i / 64] |= children << (i % 64);
+            nodes.push(node);
+            for child in node.right.iter().chain(node.left.iter()) {
+                stack.push(&**child);
+            }
+        }
+        write_uint(w, nodes.len())?;
+        shape.serialize(w)?;
+        for node in nodes.iter() {
+            node.value.serialize(w)?;
+        }
+        Ok(())
+    }
+}
+
+/// The tree is read iteratively, as it is written, so that deep trees
+/// don't overflow the stack.
+impl<T: Deserialize> Deserialize for Tree<T> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Tree<T>> {
+        let len = read_uint(r)?;
+        let shape: Vec<u64> = Deserialize::deserialize(r)?;
+        if len == 0 || shape.len() != (2 * len).div_ceil(64) {
+            return Err(invalid_data("Tree shape inconsistent with its size"));
+        }
+        // read the nodes in preorder, counting the children promised
+        // but not yet read
+        let mut nodes: Vec<(T, u64)> = Vec::with_capacity(len);
+        let mut pending = 1;
+        for k in 0..len {
+            if pending == 0 {
+                return Err(invalid_data("Tree shape has unreachable nodes"));
+            }
+            let children = (shape[2 * k / 64] >> (2 * k % 64)) & 3;
+            pending = pending - 1 + children.count_ones() as usize;
+            nodes.push((Deserialize::deserialize(r)?, children));
+        }
+        if pending > 0 {
+            return Err(invalid_data("Tree shape refers to missing nodes"));
+        }
+        // assemble the subtrees from the last node back, the left
+        // subtree of a node being the one following it in preorder
+        let mut subtrees: Vec<Tree<T>> = Vec::new();
+        while let Some((value, children)) = nodes.pop() {
+            let left = match children & 1 {
+                0 => None,
+                _ => subtrees.pop().map(Box::new),
+            };
+            let right = match children & 2 {
+                0 => None,
+                _ => subtrees.pop().map(Box::new),
+            };
+            subtrees.push(Tree { value, left, right });
+        }
+        Ok(subtrees.pop().unwrap())
+    }
+}
+
+mod trail {
+    use super::Branch;
+
+    /// The number of steps recorded without allocating
+    pub const INLINE_DEPTH: usize = 32;
+
+    /// The nodes left and the branches taken on the way down from the
+    /// root of a tree, kept inline for the first `INLINE_DEPTH` steps
+    pub struct Trail<P> {
+        len: usize,
+        nodes: [P; INLINE_DEPTH],
+        branches: [Branch; INLINE_DEPTH],
+        spilled_nodes: Vec<P>,
+        spilled_branches: Vec<Branch>,
+    }
+
+    impl<P: Copy> Trail<P> {
+        /// An empty trail, `root` filling the unused slots
+        pub fn new(root: P) -> Trail<P> {
+            Trail {
+                len: 0,
+                nodes: [root; INLINE_DEPTH],
+                branches: [Branch::Left; INLINE_DEPTH],
+                spilled_nodes: Vec::new(),
+                spilled_branches: Vec::new(),
+            }
+        }
+
+        /// The number of steps taken
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        /// Record a step from `node` down `branch`
+        pub fn push(&mut self, node: P, branch: Branch) {
+            if self.len < INLINE_DEPTH {
+                self.nodes[self.len] = node;
+                self.branches[self.len] = branch;
+            } else {
+                self.spilled_nodes.push(node);
+                self.spilled_branches.push(branch);
+            }
+            self.len += 1;
+        }
+
+        /// Forget the last step, returning the node it was taken from
+        /// and its branch
+        pub fn pop(&mut self) -> Option<(P, Branch)> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            match self.len < INLINE_DEPTH {
+                true => Some((self.nodes[self.len], self.branches[self.len])),
+                false => Some((self.spilled_nodes.pop().unwrap(), self.spilled_branches.pop().unwrap())),
+            }
+        }
+
+        /// Forget all steps
+        pub fn clear(&mut self) {
+            self.len = 0;
+            self.spilled_nodes.clear();
+            self.spilled_branches.clear();
+        }
+
+        /// The branches taken from the root
+        pub fn path<'a>(&'a self) -> Path<'a> {
+            let inline = if self.len < INLINE_DEPTH { self.len } else { INLINE_DEPTH };
+            Path {
+                inline: &self.branches[..inline],
+                spilled: self.spilled_branches.as_slice(),
+                n: 0,
+            }
+        }
+    }
+
+    impl<P: Copy> Clone for Trail<P> {
+        fn clone(&self) -> Trail<P> {
+            Trail {
+                len: self.len,
+                nodes: self.nodes,
+                branches: self.branches,
+                spilled_nodes: self.spilled_nodes.to_vec(),
+                spilled_branches: self.spilled_branches.to_vec(),
+            }
+        }
+    }
+
+    /// An iterator over the branches taken from the root of a tree to
+    /// a cursor
+    pub struct Path<'a> {
+        inline: &'a [Branch],
+        spilled: &'a [Branch],
+        /// the index of the next step
+        n: usize,
+    }
+
+    impl<'a> Iterator for Path<'a> {
+        type Item = Branch;
+        fn next(&mut self) -> Option<Branch> {
+            let n = self.n;
+            self.n += 1;
+            match n < self.inline.len() {
+                true => Some(self.inline[n]),
+                false => self.spilled.get(n - self.inline.len()).copied(),
+            }
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let remaining = self.inline.len() + self.spilled.len() - self.n;
+            (remaining, Some(remaining))
+        }
+    }
+
+    impl<'a> ExactSizeIterator for Path<'a> {}
+}
+
 mod mut_cursor {
     use std::ops::{Deref, DerefMut};
     use super::{Tree, Branch};
+    use super::trail::{Trail, Path};
 
     /// A cursor allowing safe navigation and mutation of `Trees`
+    //
+    // The cursor is a zipper: on stepping down a branch the child is
+    // detached from its parent and kept by the cursor, which thereby
+    // owns the node it points to. The children are attached again on
+    // stepping up, at the latest when the cursor is dropped.
     pub struct MutCursor<'a, T: 'a> {
-        root: &'a mut Tree<T>,
-        node: *mut Tree<T>,
+        /// the root, until the tree is reclaimed by `finish`
+        root: Option<&'a mut Tree<T>>,
+        /// the nodes detached on the way down from the root, the
+        /// current node last
+        detached: Vec<Box<Tree<T>>>,
+        trail: Trail<()>,
     }
 
     impl<'a, T> MutCursor<'a, T> {
         /// Create a new `Cursor` pointing to the root of the given `Tree`
         pub fn new(tree: &'a mut Tree<T>) -> MutCursor<'a, T> {
             MutCursor {
-                root: tree,
-                node: tree,
+                root: Some(tree),
+                detached: Vec::new(),
+                trail: Trail::new(()),
             }
         }
 
         /// Step the cursor back to the root
         pub fn back_to_root(&mut self) {
-            self.node = self.root as *mut Tree<T>;
+            while self.up().is_some() {}
+        }
+
+        /// Step the cursor back to the parent, returning the branch it
+        /// was reached by, unless at the root
+        pub fn up(&mut self) -> Option<Branch> {
+            self.trail.pop().map(|((), branch)| {
+                let child = self.detached.pop().unwrap();
+                *self.branch_mut(branch) = Some(child);
+                branch
+            })
+        }
+
+        /// The number of steps from the root
+        pub fn depth(&self) -> usize {
+            self.trail.len()
+        }
+
+        /// The branches taken from the root
+        pub fn path<'b>(&'b self) -> Path<'b> {
+            self.trail.path()
         }
 
         /// Descend down one of the branches
         pub fn step(&mut self, branch: Branch) {
-            unsafe {
-                use super::Branch::{Left, Right};
-                let b: &mut Option<Box<Tree<T>>> = match branch {
-                    Left => &mut (*self.node).left,
-                    Right => &mut (*self.node).right,
-                };
-                match b {
-                    &mut None => panic!("Attempted to step {:?} into empty branch", branch),
-                    &mut Some(ref mut child) => {
-                        self.node = &mut **child as *mut Tree<T>;
-                    }
+            match self.branch_mut(branch).take() {
+                None => panic!("Attempted to step {:?} into empty branch", branch),
+                Some(child) => {
+                    self.detached.push(child);
+                    self.trail.push((), branch);
                 }
             }
         }
 
         /// Reclaim the tree
-        pub fn finish(self) -> &'a mut Tree<T> {
-            self.root
+        pub fn finish(mut self) -> &'a mut Tree<T> {
+            self.back_to_root();
+            self.root.take().unwrap()
+        }
+    }
+
+    impl<'a, T> Drop for MutCursor<'a, T> {
+        fn drop(&mut self) {
+            if self.root.is_some() {
+                self.back_to_root();
+            }
         }
     }
 
     impl<'a, T> Deref for MutCursor<'a, T> {
         type Target = Tree<T>;
-        fn deref<'b>(&'b self) -> &'b Tree<T> {
-            unsafe{ &*self.node }
+        fn deref(&self) -> &Tree<T> {
+            match self.detached.last() {
+                Some(node) => node,
+                None => self.root.as_ref().unwrap(),
+            }
         }
     }
 
     impl<'a, T> DerefMut for MutCursor<'a, T> {
-        fn deref_mut<'b>(&'b mut self) -> &'b mut Tree<T> {
-            unsafe{ &mut *self.node }
+        fn deref_mut(&mut self) -> &mut Tree<T> {
+            match self.detached.last_mut() {
+                Some(node) => node,
+                None => self.root.as_mut().unwrap(),
+            }
         }
     }
 }
@@ -142,11 +482,17 @@ mod mut_cursor {
 mod cursor {
     use std::ops::Deref;
     use super::{Tree, Branch};
+    use super::trail::{Trail, Path};
 
     /// A cursor allowing safe navigation of `Trees`
+    //
+    // The cursor only borrows the tree, keeping the nodes left on the
+    // way down to step back up to, so it is `Send` and `Sync` whenever
+    // the tree is `Sync`.
     pub struct Cursor<'a, T: 'a> {
         root: &'a Tree<T>,
-        node: *const Tree<T>,
+        node: &'a Tree<T>,
+        trail: Trail<&'a Tree<T>>,
     }
 
     impl<'a, T> Clone for Cursor<'a, T> {
@@ -154,6 +500,7 @@ mod cursor {
             Cursor {
                 root: self.root,
                 node: self.node,
+                trail: self.trail.clone(),
             }
         }
     }
@@ -164,27 +511,48 @@ mod cursor {
             Cursor {
                 root: tree,
                 node: tree,
+                trail: Trail::new(tree),
             }
         }
 
         /// Step the cursor back to the root
         pub fn back_to_root(&mut self) {
-            self.node = self.root as *const Tree<T>;
+            self.node = self.root;
+            self.trail.clear();
+        }
+
+        /// Step the cursor back to the parent, returning the branch it
+        /// was reached by, unless at the root
+        pub fn up(&mut self) -> Option<Branch> {
+            self.trail.pop().map(|(parent, branch)| {
+                self.node = parent;
+                branch
+            })
+        }
+
+        /// The number of steps from the root
+        pub fn depth(&self) -> usize {
+            self.trail.len()
+        }
+
+        /// The branches taken from the root
+        pub fn path<'b>(&'b self) -> Path<'b> {
+            self.trail.path()
         }
 
         /// Descend down one of the branches
         pub fn step(&mut self, branch: Branch) {
-            unsafe {
-                use super::Branch::{Left, Right};
-                let b: &Option<Box<Tree<T>>> = match branch {
-                    Left => &(*self.node).left,
-                    Right => &(*self.node).right,
-                };
-                match b {
-                    &None => panic!("Attempted to step {:?} into empty branch", branch),
-                    &Some(ref child) => {
-                        self.node = &**child as *const Tree<T>;
-                    }
+            use super::Branch::{Left, Right};
+            let node = self.node;
+            let b = match branch {
+                Left => &node.left,
+                Right => &node.right,
+            };
+            match b {
+                None => panic!("Attempted to step {:?} into empty branch", branch),
+                Some(child) => {
+                    self.trail.push(node, branch);
+                    self.node = child;
                 }
             }
         }
@@ -192,8 +560,199 @@ mod cursor {
 
     impl<'a, T> Deref for Cursor<'a, T> {
         type Target = Tree<T>;
-        fn deref<'b>(&'b self) -> &'b Tree<T> {
-            unsafe{ &*self.node }
+        fn deref(&self) -> &Tree<T> {
+            self.node
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Tree, Branch, Cursor, MutCursor};
+    use super::Branch::{Left, Right};
+    use super::super::events::ParseError;
+    use super::super::events::Event::{Enter, Leave};
+
+    /// A path of `depth` nodes leaning alternately left and right,
+    /// labelled by depth
+    fn zigzag(depth: usize) -> Tree<usize> {
+        let mut tree = Tree::singleton(depth - 1);
+        for d in (0..depth - 1).rev() {
+            let child = Some(Box::new(tree));
+            tree = match d % 2 {
+                0 => Tree { value: d, left: child, right: None },
+                _ => Tree { value: d, left: None, right: child },
+            };
+        }
+        tree
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let tree = Tree {
+            value: "a\"",
+            left: None,
+            right: Some(Box::new(Tree::singleton("b"))),
+        };
+        assert_eq!(tree.to_dot(),
+                   "digraph tree {\n  n0 [label=\"a\\\"\"];\n  n1 [label=\"b\"];\n  n0 -> n1 [label=\"R\"];\n}\n");
+    }
+
+    #[test]
+    fn test_zip() {
+        // a path leaning left beside a node with a left and a right
+        // child, sharing the root and its left child
+        let a = zigzag(3);
+        let b = Tree {
+            value: 'a',
+            left: Some(Box::new(Tree::singleton('b'))),
+            right: Some(Box::new(Tree::singleton('c'))),
+        };
+        assert!(!a.same_shape(&b));
+        let offsets = a.map(|x| *x).merge_with(b.map(|x| *x), |x, c| x + c as usize);
+        assert_eq!(offsets.value, 'a' as usize);
+        assert_eq!(offsets.left.as_ref().unwrap().value, 1 + 'b' as usize);
+        assert!(offsets.right.is_none());
+        let zipped = a.zip(b);
+        assert_eq!(zipped.left.as_ref().unwrap().value, (1, 'b'));
+        assert!(zipped.left.as_ref().unwrap().right.is_none());
+        assert!(zipped.same_shape(&zigzag(2)));
+    }
+
+    #[test]
+    fn test_from_events() {
+        let events = vec!(Enter(0usize), Enter(1), Leave, Enter(2), Enter(3), Leave, Leave, Leave);
+        let tree = Tree::from_events(events.into_iter()).unwrap();
+        let mut cursor = Cursor::new(&tree);
+        cursor.step(Right);
+        assert_eq!(cursor.value, 2);
+        cursor.step(Left);
+        assert_eq!(cursor.value, 3);
+        assert!(tree.left.as_ref().unwrap().left.is_none());
+
+        let events = vec!(Enter(0usize), Enter(1), Leave, Enter(2), Leave, Enter(3), Leave, Leave);
+        assert_eq!(Tree::from_events(events.into_iter()).err(), Some(ParseError::TooManyChildren(0)));
+        let events = vec!(Enter(0usize), Leave, Enter(1), Leave);
+        assert_eq!(Tree::from_events(events.into_iter()).err(), Some(ParseError::NotSingleRoot));
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<bool>) -> bool {
+        use super::super::events::Event;
+        use super::super::super::serialize::test::round_trip;
+        // a traversal of a binary tree shaped by `v`
+        let mut events = vec!(Enter(0u64));
+        let mut degrees = vec!(0usize);
+        for (i, b) in v.iter().enumerate() {
+            if *b && *degrees.last().unwrap() < 2 {
+                *degrees.last_mut().unwrap() += 1;
+                events.push(Enter(i as u64 + 1));
+                degrees.push(0);
+            } else if degrees.len() > 1 {
+                events.push(Leave);
+                degrees.pop();
+            }
+        }
+        for _ in 0..degrees.len() {
+            events.push(Leave);
+        }
+        let tree = Tree::from_events(events.clone().into_iter()).unwrap();
+        let tree: Tree<u64> = round_trip(&tree);
+        // read back the traversal
+        fn traverse(tree: &Tree<u64>, out: &mut Vec<Event<u64>>) {
+            out.push(Enter(tree.value));
+            for child in tree.left.iter().chain(tree.right.iter()) {
+                traverse(child, out);
+            }
+            out.push(Leave);
+        }
+        let mut out = Vec::new();
+        traverse(&tree, &mut out);
+        out == events
+    }
+
+    #[test]
+    fn test_deserialize_deep() {
+        use super::super::super::serialize::{Serialize, Deserialize};
+        // deep enough to overflow the stack if read recursively
+        let mut tree = Tree::singleton(0u64);
+        for d in 1..1 << 20 {
+            let child = Some(Box::new(tree));
+            tree = match d % 2 {
+                0 => Tree { value: d, left: child, right: None },
+                _ => Tree { value: d, left: None, right: child },
+            };
+        }
+        let mut buf = Vec::new();
+        tree.serialize(&mut buf).unwrap();
+        let read: Tree<u64> = Deserialize::deserialize(&mut buf.as_slice()).unwrap();
+        let mut again = Vec::new();
+        read.serialize(&mut again).unwrap();
+        assert!(again == buf);
+        // dropping the trees would recurse as deep
+        std::mem::forget(tree);
+        std::mem::forget(read);
+    }
+
+    #[test]
+    fn test_cursor_up() {
+        // deep enough to spill the inline trail
+        let tree = zigzag(40);
+        let branches: Vec<Branch> = (0..39).map(|d| if d % 2 == 0 { Left } else { Right }).collect();
+        let mut cursor = Cursor::new(&tree);
+        for b in branches.iter() {
+            cursor.step(*b);
+        }
+        assert_eq!(cursor.depth(), 39);
+        assert_eq!(cursor.value, 39);
+        assert_eq!(cursor.path().collect::<Vec<Branch>>(), branches);
+        let saved = cursor.clone();
+        for d in (0..39).rev() {
+            assert_eq!(cursor.up(), Some(branches[d]));
+            assert_eq!(cursor.value, d);
+        }
+        assert_eq!(cursor.up(), None);
+        assert_eq!(saved.depth(), 39);
+        assert_eq!(saved.path().len(), 39);
+    }
+
+    #[test]
+    fn test_mut_cursor_up() {
+        let mut tree = zigzag(3);
+        {
+            let mut cursor = MutCursor::new(&mut tree);
+            cursor.step(Left);
+            cursor.step(Right);
+            cursor.value = 10;
+            assert_eq!(cursor.path().collect::<Vec<Branch>>(), vec!(Left, Right));
+            assert_eq!(cursor.up(), Some(Right));
+            cursor.value = 20;
+            assert_eq!(cursor.depth(), 1);
+            cursor.back_to_root();
+            assert_eq!(cursor.depth(), 0);
+        }
+        assert_eq!(tree.left.as_ref().unwrap().value, 20);
+        assert_eq!(tree.left.as_ref().unwrap().right.as_ref().unwrap().value, 10);
+    }
+
+    #[test]
+    fn test_mut_cursor_drop() {
+        // dropping a cursor away from the root leaves the tree whole
+        let mut tree = zigzag(4);
+        {
+            let mut cursor = MutCursor::new(&mut tree);
+            cursor.step(Left);
+            cursor.step(Right);
+            cursor.step(Left);
+            cursor.value = 30;
+        }
+        {
+            let mut cursor = Cursor::new(&tree);
+            for b in [Left, Right, Left].iter() {
+                cursor.step(*b);
+            }
+            assert_eq!(cursor.value, 30);
         }
+        assert_eq!(MutCursor::new(&mut tree).finish().value, 0);
     }
 }
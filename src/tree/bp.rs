@@ -0,0 +1,347 @@
+//! Ordinal trees in balanced parentheses
+//
+// A tree is written as its depth-first traversal, with an opening
+// parenthesis on entering a node and a closing one on leaving it. A
+// node is identified by the position of its opening parenthesis, so
+// that its depth is the excess there and its subtree spans up to the
+// matching parenthesis. The navigation operations are searches in the
+// range min-max tree of the parentheses.
+//
+// See Navarro and Sadakane 2014.
+
+use std::io::{self, Read, Write};
+use super::super::collection::Collection;
+use super::super::serialize::{Serialize, Deserialize, invalid_data};
+use super::super::space::SpaceUsage;
+use super::events::{self, Event, ParseError};
+use super::parens::BalancedParens;
+
+/// A node of a `BpTree`, the position of its opening parenthesis
+pub type Node = usize;
+
+/// A static ordinal tree
+pub struct BpTree {
+    parens: BalancedParens,
+}
+
+impl BpTree {
+    /// The tree written by `parens`, `true` being an opening
+    /// parenthesis. Panics unless the parentheses are balanced and
+    /// enclose a single root.
+    pub fn new(parens: &[bool]) -> BpTree {
+        BpTree::from_parens(BalancedParens::new(parens))
+    }
+
+    /// The tree written by the parentheses of `parens`
+    pub fn from_parens(parens: BalancedParens) -> BpTree {
+        let len = parens.len();
+        assert!(len > 0 && len.is_multiple_of(2) && parens.excess(len) == 0,
+                "BpTree: unbalanced parentheses");
+        assert!(parens.is_open(0) && parens.find_close(0) == len - 1,
+                "BpTree: parentheses must enclose a single root");
+        BpTree { parens }
+    }
+
+    /// The tree written by a string of parentheses such as `"(()(()))"`
+    pub fn parse(s: &str) -> Result<BpTree, ParseError> {
+        let events = events::parse_parens(s)?;
+        BpTree::from_events(events.into_iter()).map(|(tree, _)| tree)
+    }
+
+    /// The tree of a depth-first traversal, and the values of its
+    /// nodes in preorder
+    pub fn from_events<T, I: Iterator<Item=Event<T>>>(events: I) -> Result<(BpTree, Vec<T>), ParseError> {
+        let (parens, values) = events::flatten(events)?;
+        Ok((BpTree::new(parens.as_slice()), values))
+    }
+
+    /// The parentheses of the tree
+    pub fn parens(&self) -> &BalancedParens {
+        &self.parens
+    }
+
+    /// The root node
+    pub fn root(&self) -> Node {
+        0
+    }
+
+    /// Whether `v` has no children
+    pub fn is_leaf(&self, v: Node) -> bool {
+        !self.parens.is_open(v + 1)
+    }
+
+    /// The parent of `v`, unless it is the root
+    pub fn parent(&self, v: Node) -> Option<Node> {
+        self.parens.enclose(v)
+    }
+
+    /// The first child of `v`, unless it is a leaf
+    pub fn first_child(&self, v: Node) -> Option<Node> {
+        match self.is_leaf(v) {
+            true => None,
+            false => Some(v + 1),
+        }
+    }
+
+    /// The next sibling of `v`, unless it is the last child of its
+    /// parent
+    pub fn next_sibling(&self, v: Node) -> Option<Node> {
+        let next = self.parens.find_close(v) + 1;
+        match next < self.parens.len() && self.parens.is_open(next) {
+            true => Some(next),
+            false => None,
+        }
+    }
+
+    /// The number of children of `v`
+    pub fn degree(&self, v: Node) -> usize {
+        // the excess returns to its minimum inside `v` at the start of
+        // each child and at the closing parenthesis
+        self.parens.min_count(v + 1, self.parens.find_close(v)) - 1
+    }
+
+    /// The index of `v` among the children of its parent
+    pub fn child_rank(&self, v: Node) -> usize {
+        match self.parent(v) {
+            None => 0,
+            Some(p) => self.parens.min_count(p + 1, v) - 1,
+        }
+    }
+
+    /// The children of `v` in order
+    pub fn children<'a>(&'a self, v: Node) -> Children<'a> {
+        Children { tree: self, next: self.first_child(v) }
+    }
+
+    /// The depth of `v`, the root having depth zero
+    pub fn depth(&self, v: Node) -> usize {
+        self.parens.excess(v + 1) as usize - 1
+    }
+
+    /// The number of nodes in the subtree rooted at `v`, including `v`
+    pub fn subtree_size(&self, v: Node) -> usize {
+        (self.parens.find_close(v) - v).div_ceil(2)
+    }
+
+    /// Whether `u` is an ancestor of `v` or `v` itself
+    pub fn is_ancestor(&self, u: Node, v: Node) -> bool {
+        u <= v && v <= self.parens.find_close(u)
+    }
+
+    /// The ancestor of `v` at depth `d`, or `v` itself at its own
+    /// depth
+    pub fn level_ancestor(&self, v: Node, d: usize) -> Option<Node> {
+        let depth = self.depth(v);
+        match d {
+            _ if d > depth => None,
+            _ if d == depth => Some(v),
+            // the excess before the ancestor is its depth and stays
+            // above until it closes
+            _ => self.parens.bwd_search(v, d as isize - depth as isize),
+        }
+    }
+
+    /// The lowest common ancestor of `u` and `v`
+    pub fn lca(&self, u: Node, v: Node) -> Node {
+        let (u, v) = if u <= v { (u, v) } else { (v, u) };
+        if self.is_ancestor(u, v) {
+            return u;
+        }
+        // the excess first drops to the depth of the ancestor's
+        // children on leaving the child containing `u`
+        let (q, _) = self.parens.min_excess(u + 1, v);
+        self.parent(q).unwrap()
+    }
+
+    /// The number of nodes preceding `v` in preorder
+    pub fn preorder(&self, v: Node) -> usize {
+        self.parens.rank_open(v)
+    }
+
+    /// The `n`th node in preorder, counting from zero
+    pub fn node(&self, n: usize) -> Node {
+        self.parens.select_open(n)
+    }
+}
+
+impl Collection for BpTree {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.parens.len() / 2
+    }
+}
+
+impl SpaceUsage for BpTree {
+    fn total_bytes(&self) -> usize {
+        self.parens.total_bytes()
+    }
+}
+
+impl Serialize for BpTree {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.parens.serialize(w)
+    }
+}
+
+impl Deserialize for BpTree {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BpTree> {
+        let parens: BalancedParens = Deserialize::deserialize(r)?;
+        let len = parens.len();
+        // the excess must stay positive until the root is closed
+        if len < 2 || parens.excess(len) != 0 || parens.min_excess(1, len - 1).1 < 1 {
+            return Err(invalid_data("BpTree parentheses not those of a single tree"));
+        }
+        Ok(BpTree { parens })
+    }
+}
+
+/// An iterator over the children of a node of a `BpTree`
+pub struct Children<'a> {
+    tree: &'a BpTree,
+    next: Option<Node>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Node;
+    fn next(&mut self) -> Option<Node> {
+        let v = self.next;
+        self.next = v.and_then(|v| self.tree.next_sibling(v));
+        v
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BpTree;
+    use super::super::parens::test::parens;
+    use super::super::super::collection::Collection;
+
+    /// The parent of each node and the position of its closing
+    /// parenthesis, by the position of its opening one
+    fn naive(v: &[bool]) -> (Vec<Option<usize>>, Vec<usize>) {
+        let mut parents: Vec<Option<usize>> = v.iter().map(|_| None).collect();
+        let mut closes: Vec<usize> = v.iter().map(|_| 0).collect();
+        let mut stack: Vec<usize> = Vec::new();
+        for (i, b) in v.iter().enumerate() {
+            if *b {
+                parents[i] = stack.last().copied();
+                stack.push(i);
+            } else {
+                closes[stack.pop().unwrap()] = i;
+            }
+        }
+        (parents, closes)
+    }
+
+    #[quickcheck]
+    fn navigation_is_correct(v: Vec<bool>) -> bool {
+        let v = parens(&v);
+        let (parents, closes) = naive(&v);
+        let tree = BpTree::new(v.as_slice());
+        let nodes: Vec<usize> = (0..v.len()).filter(|i| v[*i]).collect();
+        let depth = |mut u: usize| {
+            let mut d = 0;
+            while let Some(p) = parents[u] {
+                u = p;
+                d += 1;
+            }
+            d
+        };
+        tree.len() == nodes.len() && nodes.iter().enumerate().all(|(n, u)| {
+            let u = *u;
+            let first = nodes.iter().find(|w| parents[**w] == Some(u)).copied();
+            let next = nodes.iter().find(|w| **w > u && parents[**w] == parents[u]).copied();
+            let children: Vec<usize> = nodes.iter().filter(|w| parents[**w] == Some(u)).copied().collect();
+            let rank = nodes.iter().filter(|w| **w < u && parents[**w] == parents[u]).count();
+            tree.parent(u) == parents[u]
+                && tree.first_child(u) == first
+                && tree.degree(u) == children.len()
+                && tree.child_rank(u) == rank
+                && tree.next_sibling(u) == next
+                && tree.depth(u) == depth(u)
+                && tree.subtree_size(u) == nodes.iter().filter(|w| u <= **w && **w < closes[u]).count()
+                && tree.preorder(u) == n
+                && tree.node(n) == u
+        })
+    }
+
+    #[quickcheck]
+    fn ancestry_is_correct(v: Vec<bool>, a: usize, b: usize) -> bool {
+        let v = parens(&v);
+        let (parents, _) = naive(&v);
+        let tree = BpTree::new(v.as_slice());
+        let (u, w) = (tree.node(a % tree.len()), tree.node(b % tree.len()));
+        let ancestors = |mut x: usize| {
+            let mut path = vec!(x);
+            while let Some(p) = parents[x] {
+                path.push(p);
+                x = p;
+            }
+            path
+        };
+        let (au, aw) = (ancestors(u), ancestors(w));
+        let lca = *au.iter().find(|x| aw.contains(*x)).unwrap();
+        tree.is_ancestor(u, w) == aw.contains(&u) && tree.lca(u, w) == lca
+            && (0..aw.len() + 1).all(|d| {
+                let expected = if d < aw.len() { Some(aw[aw.len() - 1 - d]) } else { None };
+                tree.level_ancestor(w, d) == expected
+            })
+    }
+
+    #[test]
+    fn test_tree() {
+        // ((()())())
+        let tree = BpTree::new(&[true, true, true, false, true, false, false, true, false, false]);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.children(0).collect::<Vec<usize>>(), vec!(1, 7));
+        assert_eq!(tree.children(1).collect::<Vec<usize>>(), vec!(2, 4));
+        assert_eq!(tree.parent(4), Some(1));
+        assert_eq!(tree.depth(4), 2);
+        assert_eq!(tree.subtree_size(1), 3);
+        assert_eq!(tree.degree(1), 2);
+        assert_eq!(tree.degree(7), 0);
+        assert_eq!(tree.child_rank(4), 1);
+        assert_eq!(tree.child_rank(7), 1);
+        assert_eq!(tree.lca(4, 7), 0);
+        assert_eq!(tree.level_ancestor(4, 1), Some(1));
+        assert_eq!(tree.level_ancestor(4, 0), Some(0));
+        assert_eq!(tree.level_ancestor(4, 3), None);
+        assert!(tree.is_leaf(7));
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<bool>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let v = parens(&v);
+        let tree: BpTree = round_trip(&BpTree::new(v.as_slice()));
+        tree.len() == v.len() / 2
+            && (0..tree.len()).all(|n| tree.subtree_size(tree.node(n)) == {
+                let u = tree.node(n);
+                (tree.parens().find_close(u) - u).div_ceil(2)
+            })
+            && (0..v.len()).all(|i| tree.parens().is_open(i) == v[i])
+    }
+
+    #[test]
+    fn test_invalid() {
+        use std::io::Cursor;
+        use super::super::super::serialize::{Serialize, Deserialize};
+        use super::super::parens::BalancedParens;
+        let mut buf = Vec::new();
+        BalancedParens::new(&[true, false, true, false]).serialize(&mut buf).unwrap();
+        let res: Result<BpTree, _> = Deserialize::deserialize(&mut Cursor::new(buf));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        use super::super::events::Event::{Enter, Leave};
+        let tree = BpTree::parse("((()())())").unwrap();
+        assert_eq!(tree.children(1).collect::<Vec<usize>>(), vec!(2, 4));
+        let events = vec!(Enter('a'), Enter('b'), Leave, Enter('c'), Leave, Leave);
+        let (tree, values) = BpTree::from_events(events.into_iter()).unwrap();
+        assert_eq!(tree.children(0).collect::<Vec<usize>>(), vec!(1, 3));
+        assert_eq!(values[tree.preorder(3)], 'c');
+        assert!(BpTree::parse("(()").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Cardinal trees of fixed arity
+//
+// Each node of a tree of arity `k` has `k` slots, one per label, each
+// holding a child or not. The nodes are numbered in breadth-first
+// order and the slots of node `v` are bits `v*k` to `v*k + k - 1` of a
+// bitvector, set when holding a child. The children then appear in
+// the order of the set bits, so the child in a slot is one more than
+// the rank of the slot, and the parent of a node is found by a select.
+//
+// See Benoit et al. 2005.
+
+use std::io::{self, Read, Write};
+use super::super::collection::Collection;
+use super::super::dictionary::{Access, BitRank, Select};
+use super::super::rank9::Rank9;
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::super::space::SpaceUsage;
+
+/// A node of a `CardinalTree`, its breadth-first index
+pub type Node = usize;
+
+/// A static tree in which each child is labelled by one of `arity`
+/// slots of its parent
+pub struct CardinalTree {
+    arity: usize,
+    /// whether each slot of each node holds a child
+    slots: Rank9,
+}
+
+impl CardinalTree {
+    /// The tree whose nodes have children in the given slots, the
+    /// nodes being in breadth-first order
+    pub fn from_children<I: Iterator<Item=Vec<usize>>>(arity: usize, nodes: I) -> CardinalTree {
+        use super::super::build::Builder;
+        assert!(arity > 0, "CardinalTree: arity must be positive");
+        let mut builder = super::super::rank9::Builder::with_capacity(128);
+        let mut node = Vec::with_capacity(arity);
+        for labels in nodes {
+            node.clear();
+            node.extend((0..arity).map(|_| false));
+            for l in labels.into_iter() {
+                assert!(l < arity, "CardinalTree: label {} exceeds arity {}", l, arity);
+                node[l] = true;
+            }
+            for b in node.iter() {
+                builder.push(*b);
+            }
+        }
+        CardinalTree::from_slots(arity, builder.finish())
+    }
+
+    /// The tree whose slots are given by `slots`
+    pub fn from_slots(arity: usize, slots: Rank9) -> CardinalTree {
+        let len = slots.len();
+        assert!(arity > 0 && len > 0 && len.is_multiple_of(arity),
+                "CardinalTree: slots inconsistent with arity");
+        assert!(slots.rank1(len) + 1 == len / arity,
+                "CardinalTree: children inconsistent with the number of nodes");
+        CardinalTree { arity, slots }
+    }
+
+    /// The number of slots of each node
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// The root node
+    pub fn root(&self) -> Node {
+        0
+    }
+
+    /// The child of `v` labelled `label`, if any
+    pub fn child(&self, v: Node, label: usize) -> Option<Node> {
+        let p = self.slot(v, label);
+        match self.slots.get(p) {
+            true => Some(self.slots.rank1(p) + 1),
+            false => None,
+        }
+    }
+
+    /// Whether `v` has a child labelled `label`
+    pub fn has_child(&self, v: Node, label: usize) -> bool {
+        self.slots.get(self.slot(v, label))
+    }
+
+    /// The number of children of `v`
+    pub fn degree(&self, v: Node) -> usize {
+        let start = self.slot(v, 0);
+        self.slots.rank1(start + self.arity) - self.slots.rank1(start)
+    }
+
+    /// Whether `v` has no children
+    pub fn is_leaf(&self, v: Node) -> bool {
+        self.degree(v) == 0
+    }
+
+    /// The children of `v` and their labels in order of label
+    pub fn children<'a>(&'a self, v: Node) -> Children<'a> {
+        Children { tree: self, node: v, label: 0 }
+    }
+
+    /// The parent of `v` and the label of `v`, unless it is the root
+    pub fn parent(&self, v: Node) -> Option<(Node, usize)> {
+        match v {
+            0 => None,
+            _ => {
+                assert!(v < self.len(), "CardinalTree: node {} out of bounds", v);
+                let p = self.slots.select(true, v) - 1;
+                Some((p / self.arity, p % self.arity))
+            },
+        }
+    }
+
+    /// The position of slot `label` of `v`
+    fn slot(&self, v: Node, label: usize) -> usize {
+        assert!(v < self.len(), "CardinalTree: node {} out of bounds", v);
+        assert!(label < self.arity, "CardinalTree: label {} exceeds arity {}", label, self.arity);
+        v * self.arity + label
+    }
+}
+
+impl Collection for CardinalTree {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.slots.len() / self.arity
+    }
+}
+
+impl SpaceUsage for CardinalTree {
+    fn total_bytes(&self) -> usize {
+        self.slots.total_bytes()
+    }
+}
+
+impl Serialize for CardinalTree {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.arity)?;
+        self.slots.serialize(w)
+    }
+}
+
+impl Deserialize for CardinalTree {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<CardinalTree> {
+        let arity = read_uint(r)?;
+        let slots: Rank9 = Deserialize::deserialize(r)?;
+        let len = slots.len();
+        if arity == 0 || len == 0 || !len.is_multiple_of(arity) {
+            return Err(invalid_data("CardinalTree slots inconsistent with arity"));
+        }
+        if slots.rank1(len) + 1 != len / arity {
+            return Err(invalid_data("CardinalTree children inconsistent with the number of nodes"));
+        }
+        Ok(CardinalTree { arity, slots })
+    }
+}
+
+/// An iterator over the labels and children of a node of a
+/// `CardinalTree`
+pub struct Children<'a> {
+    tree: &'a CardinalTree,
+    node: Node,
+    /// the next label to try
+    label: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = (usize, Node);
+    fn next(&mut self) -> Option<(usize, Node)> {
+        while self.label < self.tree.arity {
+            self.label += 1;
+            if let Some(c) = self.tree.child(self.node, self.label - 1) { return Some((self.label - 1, c)) }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CardinalTree;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn navigation_is_correct(masks: Vec<u8>) -> bool {
+        // truncate the slots to those of a tree of arity 4
+        let mut nodes: Vec<Vec<usize>> = Vec::new();
+        let mut open = 1usize;
+        for m in masks.iter() {
+            if open == 0 {
+                break;
+            }
+            let labels: Vec<usize> = (0..4).filter(|l| *m & (1u8 << *l) != 0).collect();
+            open = open + labels.len() - 1;
+            nodes.push(labels);
+        }
+        for _ in 0..open {
+            nodes.push(Vec::new());
+        }
+        // the parent and label of each node
+        let mut parents: Vec<Option<(usize, usize)>> = vec!(None);
+        for (v, labels) in nodes.iter().enumerate() {
+            for l in labels.iter() {
+                parents.push(Some((v, *l)));
+            }
+        }
+
+        let tree = CardinalTree::from_children(4, nodes.iter().cloned());
+        tree.len() == nodes.len() && (0..nodes.len()).all(|v| {
+            tree.parent(v) == parents[v]
+                && tree.degree(v) == nodes[v].len()
+                && (0..4).all(|l| {
+                    tree.child(v, l) == (0..nodes.len()).find(|w| parents[*w] == Some((v, l)))
+                })
+        })
+    }
+
+    #[test]
+    fn test_serialize() {
+        use super::super::super::serialize::test::round_trip;
+        let tree = CardinalTree::from_children(4, vec!(vec!(1usize, 3), vec!(), vec!(0), vec!()).into_iter());
+        let tree: CardinalTree = round_trip(&tree);
+        assert_eq!(tree.arity(), 4);
+        assert_eq!(tree.child(2, 0), Some(3));
+        assert_eq!(tree.parent(2), Some((0, 3)));
+    }
+
+    #[test]
+    fn test_cardinal() {
+        let tree = CardinalTree::from_children(4, vec!(vec!(1usize, 3), vec!(), vec!(0), vec!()).into_iter());
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.child(0, 1), Some(1));
+        assert_eq!(tree.child(0, 2), None);
+        assert_eq!(tree.child(0, 3), Some(2));
+        assert_eq!(tree.child(2, 0), Some(3));
+        assert_eq!(tree.parent(3), Some((2, 0)));
+        assert_eq!(tree.children(0).collect::<Vec<(usize, usize)>>(), vec!((1, 1), (3, 2)));
+        assert!(tree.is_leaf(1));
+    }
+}
@@ -0,0 +1,92 @@
+//! Cartesian trees of sequences in balanced parentheses
+//
+// The tree of a sequence has a virtual root and a node for each
+// element, the parent of an element being the nearest earlier element
+// no greater than it, or the root if there is none. The elements are
+// then the nodes in preorder after the root, and the subtree of an
+// element spans the following elements up to the first smaller one.
+// The parentheses are written left to right with a stack of the
+// elements whose subtrees are still open: an element closes those
+// greater than it before opening its own node.
+//
+// See Fischer and Heun 2011.
+
+use super::bp::BpTree;
+
+pub use crate::tree::cartesian::build::Builder;
+
+/// The Cartesian tree of `xs`, element `i` being node `i + 1` in
+/// preorder
+pub fn cartesian_tree<T: Ord + Clone>(xs: &[T]) -> BpTree {
+    use super::super::build::Builder;
+    build::Builder::with_capacity(xs.len()).from_iter(xs.iter().cloned())
+}
+
+mod build {
+    use super::super::super::build;
+    use super::super::super::rank9;
+    use super::super::bp::BpTree;
+    use super::super::parens::BalancedParens;
+
+    /// Build the Cartesian tree of a sequence from its elements
+    pub struct Builder<T> {
+        parens: rank9::Builder,
+        /// the elements whose subtrees are still open, increasing
+        stack: Vec<T>,
+    }
+
+    impl<T: Ord> Builder<T> {
+        /// Build a tree with capacity for `cap` elements
+        pub fn with_capacity(cap: usize) -> Builder<T> {
+            let mut parens = rank9::Builder::with_capacity(2 * (cap + 1));
+            // open the root
+            build::Builder::push(&mut parens, true);
+            Builder { parens, stack: Vec::new() }
+        }
+    }
+
+    impl<T: Ord> build::Builder<T, BpTree> for Builder<T> {
+        fn push(&mut self, x: T) {
+            while self.stack.last().is_some_and(|top| *top > x) {
+                self.stack.pop();
+                self.parens.push(false);
+            }
+            self.stack.push(x);
+            self.parens.push(true);
+        }
+
+        fn finish(mut self) -> BpTree {
+            for _ in 0..self.stack.len() + 1 {
+                self.parens.push(false);
+            }
+            BpTree::from_parens(BalancedParens::from_bits(self.parens.finish()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::cartesian_tree;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn parents_are_previous_no_greater(v: Vec<u8>) -> bool {
+        let tree = cartesian_tree(v.as_slice());
+        (0..v.len()).all(|i| {
+            let parent = tree.parent(tree.node(i + 1)).map(|p| tree.preorder(p));
+            let expected = (0..i).rev().find(|j| v[*j] <= v[i]).map(|j| j + 1).unwrap_or(0);
+            parent == Some(expected)
+        })
+    }
+
+    #[test]
+    fn test_cartesian() {
+        // 3 1 4 1 5: 3 and the first 1 hang from the root, 4 and the
+        // second 1 from the first 1, and 5 from the second 1
+        let tree = cartesian_tree(&[3usize, 1, 4, 1, 5]);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.children(0).map(|v| tree.preorder(v)).collect::<Vec<usize>>(), vec!(1, 2));
+        assert_eq!(tree.children(tree.node(2)).map(|v| tree.preorder(v)).collect::<Vec<usize>>(), vec!(3, 4));
+        assert_eq!(tree.subtree_size(tree.node(4)), 2);
+    }
+}
@@ -0,0 +1,281 @@
+//! Ordinal trees in depth-first unary degree sequence
+//
+// The nodes are written in preorder, each as its degree in opening
+// parentheses followed by a closing one, after an opening parenthesis
+// standing for a virtual parent of the root. The sequence is then
+// balanced, each opening parenthesis of a node matching the closing
+// one just before the description of one of its children, last child
+// first, and the description of a subtree ends where the excess first
+// drops below its start. A node is identified by the position of its
+// description, which follows the `n`th closing parenthesis for the
+// `n`th node in preorder.
+//
+// See Benoit et al. 2005.
+
+use std::io::{self, Read, Write};
+use std::iter::repeat_n;
+use super::super::collection::Collection;
+use super::super::serialize::{Serialize, Deserialize, invalid_data};
+use super::super::space::SpaceUsage;
+use super::events::{self, Event, ParseError};
+use super::parens::BalancedParens;
+
+/// A node of a `Dfuds` tree, the position of its description
+pub type Node = usize;
+
+/// A static ordinal tree
+pub struct Dfuds {
+    parens: BalancedParens,
+}
+
+impl Dfuds {
+    /// The tree whose nodes have the given degrees in preorder
+    pub fn from_degrees<I: Iterator<Item=usize>>(degrees: I) -> Dfuds {
+        let mut parens = vec!(true);
+        for d in degrees {
+            parens.extend(repeat_n(true, d));
+            parens.push(false);
+        }
+        let parens = BalancedParens::new(parens.as_slice());
+        let len = parens.len();
+        assert!(len > 1 && parens.excess(len) == 0 && parens.fwd_search(1, -1) == Some(len),
+                "Dfuds: degrees inconsistent with a single tree");
+        Dfuds { parens }
+    }
+
+    /// The tree written by a string of parentheses such as `"(()(()))"`
+    pub fn parse(s: &str) -> Result<Dfuds, ParseError> {
+        let events = events::parse_parens(s)?;
+        Dfuds::from_events(events.into_iter()).map(|(tree, _)| tree)
+    }
+
+    /// The tree of a depth-first traversal, and the values of its
+    /// nodes in preorder
+    pub fn from_events<T, I: Iterator<Item=Event<T>>>(events: I) -> Result<(Dfuds, Vec<T>), ParseError> {
+        let (parens, values) = events::flatten(events)?;
+        // the degree of a node is the number of nodes entered at one
+        // more than its depth before it is left
+        let mut degrees: Vec<usize> = Vec::with_capacity(values.len());
+        let mut stack: Vec<usize> = Vec::new();
+        for p in parens.iter() {
+            if *p {
+                for parent in stack.last().iter() {
+                    degrees[**parent] += 1;
+                }
+                stack.push(degrees.len());
+                degrees.push(0);
+            } else {
+                stack.pop();
+            }
+        }
+        Ok((Dfuds::from_degrees(degrees.into_iter()), values))
+    }
+
+    /// The parentheses of the tree
+    pub fn parens(&self) -> &BalancedParens {
+        &self.parens
+    }
+
+    /// The root node
+    pub fn root(&self) -> Node {
+        1
+    }
+
+    /// The number of children of `v`
+    pub fn degree(&self, v: Node) -> usize {
+        self.end(v) - v
+    }
+
+    /// Whether `v` has no children
+    pub fn is_leaf(&self, v: Node) -> bool {
+        !self.parens.is_open(v)
+    }
+
+    /// The `n`th child of `v`, counting from zero
+    pub fn child(&self, v: Node, n: usize) -> Option<Node> {
+        let end = self.end(v);
+        match v + n < end {
+            true => Some(self.parens.find_close(end - 1 - n) + 1),
+            false => None,
+        }
+    }
+
+    /// The children of `v` in order
+    pub fn children<'a>(&'a self, v: Node) -> Children<'a> {
+        Children { tree: self, node: v, n: 0, degree: self.degree(v) }
+    }
+
+    /// The parent of `v`, unless it is the root
+    pub fn parent(&self, v: Node) -> Option<Node> {
+        match v {
+            1 => None,
+            _ => Some(self.start(self.parens.find_open(v - 1))),
+        }
+    }
+
+    /// The index of `v` among the children of its parent
+    pub fn child_rank(&self, v: Node) -> usize {
+        match v {
+            1 => 0,
+            _ => {
+                let p = self.parens.find_open(v - 1);
+                self.end(self.start(p)) - 1 - p
+            },
+        }
+    }
+
+    /// The number of nodes in the subtree rooted at `v`, including `v`
+    pub fn subtree_size(&self, v: Node) -> usize {
+        (self.parens.fwd_search(v, -1).unwrap() - v).div_ceil(2)
+    }
+
+    /// Whether `u` is an ancestor of `v` or `v` itself
+    pub fn is_ancestor(&self, u: Node, v: Node) -> bool {
+        u <= v && v < self.parens.fwd_search(u, -1).unwrap()
+    }
+
+    /// The number of nodes preceding `v` in preorder
+    pub fn preorder(&self, v: Node) -> usize {
+        v - self.parens.rank_open(v)
+    }
+
+    /// The `n`th node in preorder, counting from zero
+    pub fn node(&self, n: usize) -> Node {
+        self.start_after_close(n)
+    }
+
+    /// The position of the closing parenthesis ending the description
+    /// of `v`
+    fn end(&self, v: Node) -> usize {
+        self.parens.select_close(self.preorder(v))
+    }
+
+    /// The start of the description containing position `p`
+    fn start(&self, p: usize) -> Node {
+        self.start_after_close(p - self.parens.rank_open(p))
+    }
+
+    /// The position following the `n`th closing parenthesis, or the
+    /// root if `n` is zero
+    fn start_after_close(&self, n: usize) -> usize {
+        match n {
+            0 => 1,
+            _ => self.parens.select_close(n - 1) + 1,
+        }
+    }
+}
+
+impl Collection for Dfuds {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.parens.len() / 2
+    }
+}
+
+impl SpaceUsage for Dfuds {
+    fn total_bytes(&self) -> usize {
+        self.parens.total_bytes()
+    }
+}
+
+impl Serialize for Dfuds {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.parens.serialize(w)
+    }
+}
+
+impl Deserialize for Dfuds {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Dfuds> {
+        let parens: BalancedParens = Deserialize::deserialize(r)?;
+        let len = parens.len();
+        // the excess must stay positive until the root's subtree ends
+        if len < 2 || parens.excess(len) != 0 || parens.min_excess(1, len - 1).1 < 1 {
+            return Err(invalid_data("Dfuds parentheses not those of a single tree"));
+        }
+        Ok(Dfuds { parens })
+    }
+}
+
+/// An iterator over the children of a node of a `Dfuds` tree
+pub struct Children<'a> {
+    tree: &'a Dfuds,
+    node: Node,
+    /// the index of the next child
+    n: usize,
+    degree: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Node;
+    fn next(&mut self) -> Option<Node> {
+        if self.n == self.degree {
+            return None;
+        }
+        self.n += 1;
+        self.tree.child(self.node, self.n - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.degree - self.n, Some(self.degree - self.n))
+    }
+}
+
+impl<'a> ExactSizeIterator for Children<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::Dfuds;
+    use super::super::bp::BpTree;
+    use super::super::events::Event::{Enter, Leave};
+    use super::super::parens::test::parens;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn navigation_agrees_with_bp(v: Vec<bool>) -> bool {
+        // compare node by node in preorder with the same tree in
+        // balanced parentheses
+        let v = parens(&v);
+        let bp = BpTree::new(v.as_slice());
+        let events = v.iter().map(|b| if *b { Enter(()) } else { Leave });
+        let (tree, _) = Dfuds::from_events(events).unwrap();
+        let to_bp = |u: usize| bp.node(tree.preorder(u));
+        tree.len() == bp.len() && (0..tree.len()).all(|n| {
+            let u = tree.node(n);
+            let w = bp.node(n);
+            let children: Vec<usize> = bp.children(w).collect();
+            tree.preorder(u) == n
+                && tree.parent(u).map(&to_bp) == bp.parent(w)
+                && tree.degree(u) == children.len()
+                && tree.children(u).map(&to_bp).collect::<Vec<usize>>() == children
+                && tree.children(u).enumerate().all(|(i, c)| tree.child_rank(c) == i)
+                && tree.subtree_size(u) == bp.subtree_size(w)
+                && tree.is_leaf(u) == bp.is_leaf(w)
+        })
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<bool>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let v = parens(&v);
+        let events = v.iter().map(|b| if *b { Enter(()) } else { Leave });
+        let (tree, _) = Dfuds::from_events(events).unwrap();
+        let read: Dfuds = round_trip(&tree);
+        (0..tree.len()).all(|n| {
+            let u = tree.node(n);
+            read.degree(u) == tree.degree(u) && read.subtree_size(u) == tree.subtree_size(u)
+        })
+    }
+
+    #[test]
+    fn test_dfuds() {
+        let tree = Dfuds::from_degrees(vec!(2usize, 1, 0, 0).into_iter());
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.children(1).collect::<Vec<usize>>(), vec!(4, 7));
+        assert_eq!(tree.child(4, 0), Some(6));
+        assert_eq!(tree.parent(6), Some(4));
+        assert_eq!(tree.child_rank(7), 1);
+        assert_eq!(tree.subtree_size(4), 2);
+        assert_eq!(tree.node(3), 7);
+        assert!(tree.is_ancestor(1, 6) && !tree.is_ancestor(4, 7));
+    }
+}
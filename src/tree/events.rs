@@ -0,0 +1,137 @@
+//! Trees given as depth-first traversals
+//
+// A traversal is a sequence of events, entering a node with its value
+// and leaving it once its subtree has been traversed. A string of
+// parentheses is a traversal without values, `(` entering and `)`
+// leaving a node.
+
+/// A step of a depth-first traversal
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event<T> {
+    /// Enter a node labelled with the value
+    Enter(T),
+    /// Leave the last node entered and not yet left
+    Leave,
+}
+
+/// The ways in which a traversal may fail to describe a tree
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The event at the given index leaves a node never entered
+    UnmatchedLeave(usize),
+    /// The traversal ends with nodes not yet left
+    Unclosed,
+    /// The traversal has no root or several
+    NotSingleRoot,
+    /// The character at the given index is not a parenthesis
+    InvalidChar(usize),
+    /// The node entered at the given index has a third child, in a
+    /// binary tree
+    TooManyChildren(usize),
+}
+
+/// The traversal written by a string of parentheses, whitespace being
+/// ignored
+pub fn parse_parens(s: &str) -> Result<Vec<Event<()>>, ParseError> {
+    let mut events = Vec::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '(' => events.push(Event::Enter(())),
+            ')' => events.push(Event::Leave),
+            _ if c.is_whitespace() => {},
+            _ => return Err(ParseError::InvalidChar(i)),
+        }
+    }
+    Ok(events)
+}
+
+/// The balanced parentheses of a traversal of a single tree, `true`
+/// being an opening one, and the values of its nodes in preorder
+pub fn flatten<T, I: Iterator<Item=Event<T>>>(events: I) -> Result<(Vec<bool>, Vec<T>), ParseError> {
+    let mut parens = Vec::new();
+    let mut values = Vec::new();
+    let mut depth = 0usize;
+    let mut roots = 0usize;
+    for (i, event) in events.enumerate() {
+        match event {
+            Event::Enter(x) => {
+                if depth == 0 {
+                    roots += 1;
+                }
+                depth += 1;
+                parens.push(true);
+                values.push(x);
+            },
+            Event::Leave => {
+                if depth == 0 {
+                    return Err(ParseError::UnmatchedLeave(i));
+                }
+                depth -= 1;
+                parens.push(false);
+            },
+        }
+    }
+    match (depth, roots) {
+        (0, 1) => Ok((parens, values)),
+        (0, _) => Err(ParseError::NotSingleRoot),
+        _ => Err(ParseError::Unclosed),
+    }
+}
+
+/// The degrees of the nodes of a tree given by balanced parentheses
+/// in breadth-first order, and the preorder index of each
+pub fn breadth_first(parens: &[bool]) -> (Vec<usize>, Vec<usize>) {
+    // the children of each node, by preorder index
+    let mut children: Vec<Vec<usize>> = Vec::with_capacity(parens.len() / 2);
+    let mut stack: Vec<usize> = Vec::new();
+    for p in parens.iter() {
+        if *p {
+            let v = children.len();
+            for parent in stack.last().iter() {
+                children[**parent].push(v);
+            }
+            children.push(Vec::new());
+            stack.push(v);
+        } else {
+            stack.pop();
+        }
+    }
+
+    let mut order = Vec::with_capacity(children.len());
+    if !children.is_empty() {
+        order.push(0);
+    }
+    let mut i = 0;
+    while i < order.len() {
+        let v = order[i];
+        order.extend_from_slice(children[v].as_slice());
+        i += 1;
+    }
+    (order.iter().map(|v| children[*v].len()).collect(), order)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParseError, parse_parens, flatten, breadth_first};
+    use super::Event::{Enter, Leave};
+
+    #[test]
+    fn test_flatten() {
+        let events = vec!(Enter('a'), Enter('b'), Leave, Enter('c'), Enter('d'), Leave, Leave, Leave);
+        let (parens, values) = flatten(events.into_iter()).unwrap();
+        assert_eq!(parens, vec!(true, true, false, true, true, false, false, false));
+        assert_eq!(values, vec!('a', 'b', 'c', 'd'));
+        assert_eq!(breadth_first(parens.as_slice()), (vec!(2, 0, 1, 0), vec!(0, 1, 2, 3)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let parse = |s: &str| parse_parens(s).and_then(|e| flatten(e.into_iter()).map(|_| ()));
+        assert_eq!(parse("(()\n())"), Ok(()));
+        assert_eq!(parse("())"), Err(ParseError::UnmatchedLeave(2)));
+        assert_eq!(parse("(()"), Err(ParseError::Unclosed));
+        assert_eq!(parse("()()"), Err(ParseError::NotSingleRoot));
+        assert_eq!(parse(""), Err(ParseError::NotSingleRoot));
+        assert_eq!(parse("(x)"), Err(ParseError::InvalidChar(1)));
+    }
+}
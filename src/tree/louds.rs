@@ -0,0 +1,268 @@
+//! Ordinal trees in level-order unary degree sequence
+//
+// The nodes are numbered in breadth-first order and each is written as
+// its degree in unary, ones closed by a zero, after a virtual parent
+// of the root of degree one. The `i`th one then stands for node `i`
+// and the unary code of node `i` follows the `i+1`th zero, so that the
+// children of a node are consecutive and navigation is a rank or a
+// select.
+//
+// See Jacobson 1989.
+
+use std::io::{self, Read, Write};
+use super::super::collection::Collection;
+use super::super::dictionary::{Access, BitRank, Select};
+use super::super::rank9::Rank9;
+use super::super::serialize::{Serialize, Deserialize, invalid_data};
+use super::super::space::SpaceUsage;
+use super::events::{self, Event, ParseError};
+
+/// A node of a `Louds` tree, its breadth-first index
+pub type Node = usize;
+
+/// A static ordinal tree
+pub struct Louds {
+    bits: Rank9,
+}
+
+impl Louds {
+    /// The tree whose nodes have the given degrees in breadth-first
+    /// order
+    pub fn from_degrees<I: Iterator<Item=usize>>(degrees: I) -> Louds {
+        use super::super::build::Builder;
+        let mut builder = super::super::rank9::Builder::with_capacity(128);
+        builder.push(true);
+        builder.push(false);
+        for d in degrees {
+            for _ in 0..d {
+                builder.push(true);
+            }
+            builder.push(false);
+        }
+        Louds::from_bits(builder.finish())
+    }
+
+    /// The tree written by the bits of `bits`
+    pub fn from_bits(bits: Rank9) -> Louds {
+        let len = bits.len();
+        assert!(len > 1 && bits.rank1(len) + 1 == bits.rank0(len),
+                "Louds: degrees inconsistent with the number of nodes");
+        Louds { bits }
+    }
+
+    /// The tree written by a string of parentheses such as `"(()(()))"`
+    pub fn parse(s: &str) -> Result<Louds, ParseError> {
+        let events = events::parse_parens(s)?;
+        Louds::from_events(events.into_iter()).map(|(tree, _)| tree)
+    }
+
+    /// The tree of a depth-first traversal, and the values of its
+    /// nodes in breadth-first order
+    pub fn from_events<T, I: Iterator<Item=Event<T>>>(events: I) -> Result<(Louds, Vec<T>), ParseError> {
+        let (parens, values) = events::flatten(events)?;
+        let (degrees, order) = events::breadth_first(parens.as_slice());
+        let mut values: Vec<Option<T>> = values.into_iter().map(|x| Some(x)).collect();
+        let values = order.iter().map(|v| values[*v].take().unwrap()).collect();
+        Ok((Louds::from_degrees(degrees.into_iter()), values))
+    }
+
+    /// The bits of the tree
+    pub fn bits(&self) -> &Rank9 {
+        &self.bits
+    }
+
+    /// The root node
+    pub fn root(&self) -> Node {
+        0
+    }
+
+    /// The number of children of `v`
+    pub fn degree(&self, v: Node) -> usize {
+        self.end(v) - self.start(v)
+    }
+
+    /// Whether `v` has no children
+    pub fn is_leaf(&self, v: Node) -> bool {
+        !self.bits.get(self.start(v))
+    }
+
+    /// The `n`th child of `v`, counting from zero
+    pub fn child(&self, v: Node, n: usize) -> Option<Node> {
+        let p = self.start(v) + n;
+        match p < self.end(v) {
+            true => Some(self.bits.rank1(p)),
+            false => None,
+        }
+    }
+
+    /// The first child of `v`, unless it is a leaf
+    pub fn first_child(&self, v: Node) -> Option<Node> {
+        self.child(v, 0)
+    }
+
+    /// The children of `v`, which are numbered consecutively
+    pub fn children(&self, v: Node) -> ::std::ops::Range<Node> {
+        let start = self.start(v);
+        let first = self.bits.rank1(start);
+        first..first + self.end(v) - start
+    }
+
+    /// The next sibling of `v`, unless it is the last child of its
+    /// parent
+    pub fn next_sibling(&self, v: Node) -> Option<Node> {
+        match v > 0 && self.bits.get(self.position(v) + 1) {
+            true => Some(v + 1),
+            false => None,
+        }
+    }
+
+    /// The parent of `v`, unless it is the root
+    pub fn parent(&self, v: Node) -> Option<Node> {
+        match v {
+            0 => None,
+            _ => Some(self.bits.rank0(self.position(v)) - 1),
+        }
+    }
+
+    /// The index of `v` among the children of its parent
+    pub fn child_rank(&self, v: Node) -> usize {
+        match self.parent(v) {
+            None => 0,
+            Some(p) => v - self.children(p).start,
+        }
+    }
+
+    /// The position of the one standing for `v`
+    fn position(&self, v: Node) -> usize {
+        self.bits.select(true, v + 1) - 1
+    }
+
+    /// The position of the unary code of `v`
+    fn start(&self, v: Node) -> usize {
+        assert!(v < self.len(), "Louds: node {} out of bounds", v);
+        self.bits.select(false, v + 1)
+    }
+
+    /// The position of the zero closing the unary code of `v`
+    fn end(&self, v: Node) -> usize {
+        self.bits.select(false, v + 2) - 1
+    }
+}
+
+impl Collection for Louds {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.bits.len() / 2
+    }
+}
+
+impl SpaceUsage for Louds {
+    fn total_bytes(&self) -> usize {
+        self.bits.total_bytes()
+    }
+}
+
+impl Serialize for Louds {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.bits.serialize(w)
+    }
+}
+
+impl Deserialize for Louds {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Louds> {
+        let bits: Rank9 = Deserialize::deserialize(r)?;
+        if bits.len() < 2 || !bits.get(0) || bits.get(1) {
+            return Err(invalid_data("Louds bits do not start with the root"));
+        }
+        // each node must be a child of an earlier one, that is the
+        // `k`th one must precede the `k`th zero, but for the last zero
+        let (mut ones, mut zeros) = (0usize, 0usize);
+        for i in 0..bits.len() {
+            if zeros > ones {
+                return Err(invalid_data("Louds node precedes its parent"));
+            }
+            match bits.get(i) {
+                true => ones += 1,
+                false => zeros += 1,
+            }
+        }
+        if zeros != ones + 1 {
+            return Err(invalid_data("Louds degrees inconsistent with the number of nodes"));
+        }
+        Ok(Louds { bits })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::repeat_n;
+    use super::Louds;
+    use super::super::super::collection::Collection;
+
+    #[quickcheck]
+    fn navigation_is_correct(degrees: Vec<u8>) -> bool {
+        // truncate the degrees to those of a tree
+        let mut ds: Vec<usize> = Vec::new();
+        let mut open = 1usize;
+        for d in degrees.iter() {
+            if open == 0 {
+                break;
+            }
+            ds.push(*d as usize % 4);
+            open = open + *d as usize % 4 - 1;
+        }
+        ds.extend(repeat_n(0, open));
+        let mut parents: Vec<Option<usize>> = vec!(None);
+        for (v, d) in ds.iter().enumerate() {
+            for _ in 0..*d {
+                parents.push(Some(v));
+            }
+        }
+
+        let tree = Louds::from_degrees(ds.iter().copied());
+        tree.len() == ds.len() && (0..ds.len()).all(|v| {
+            let children: Vec<usize> = (0..ds.len()).filter(|w| parents[*w] == Some(v)).collect();
+            tree.degree(v) == ds[v]
+                && tree.parent(v) == parents[v]
+                && tree.children(v).collect::<Vec<usize>>() == children
+                && (0..ds[v]).all(|n| tree.child(v, n) == Some(children[n]))
+                && tree.child(v, ds[v]).is_none()
+                && (v == 0 || tree.child(parents[v].unwrap(), tree.child_rank(v)) == Some(v))
+                && tree.next_sibling(v) == (v + 1..ds.len()).next()
+                    .and_then(|w| if v > 0 && parents[w] == parents[v] { Some(w) } else { None })
+        })
+    }
+
+    #[test]
+    fn test_serialize() {
+        use super::super::super::serialize::test::round_trip;
+        let tree: Louds = round_trip(&Louds::from_degrees(vec!(2usize, 1, 0, 0).into_iter()));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.children(0), 1..3);
+        assert_eq!(tree.parent(3), Some(1));
+    }
+
+    #[test]
+    fn test_louds() {
+        let tree = Louds::from_degrees(vec!(2usize, 1, 0, 0).into_iter());
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.children(0), 1..3);
+        assert_eq!(tree.child(1, 0), Some(3));
+        assert_eq!(tree.parent(3), Some(1));
+        assert_eq!(tree.next_sibling(1), Some(2));
+        assert_eq!(tree.next_sibling(2), None);
+        assert!(tree.is_leaf(2));
+    }
+
+    #[test]
+    fn test_parse() {
+        use super::super::events::Event::{Enter, Leave};
+        let tree = Louds::parse("((())())").unwrap();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.children(0), 1..3);
+        assert_eq!(tree.children(1), 3..4);
+        let events = vec!(Enter('a'), Enter('b'), Enter('d'), Leave, Leave, Enter('c'), Leave, Leave);
+        let (_, values) = Louds::from_events(events.into_iter()).unwrap();
+        assert_eq!(values, vec!('a', 'b', 'c', 'd'));
+    }
+}
@@ -1,7 +1,17 @@
 //! Various trees
 
 pub mod binary;
+pub mod events;
+pub mod parens;
+pub mod bp;
+pub mod cartesian;
+pub mod louds;
+pub mod dfuds;
+pub mod cardinal;
+pub mod rose;
 
+/// A tree with values at its leaves and nodes of any arity
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Rose<T> {
     Leaves(Vec<T>),
     Nodes(Vec<Rose<T>>),
@@ -0,0 +1,477 @@
+//! Balanced parentheses with a range min-max tree
+//
+// A sequence of parentheses is stored as bits, one for an opening
+// parenthesis and zero for a closing one. The excess `E(p)` at
+// position `p` is the number of opening parentheses among the first
+// `p` bits less the number of closing ones; it is computed by a rank.
+// Navigation reduces to searching for the nearest position reaching a
+// given excess. As the excess moves by one at each step, a block of
+// bits reaches a target excess exactly when the target lies between
+// the minimum and maximum excess within the block. These extremes are
+// kept for each block of `BLOCK` bits and for each node of a complete
+// binary tree over the blocks, so that a search scans at most two
+// blocks and descends the tree once. Each node also counts the
+// positions attaining its minimum, so that the children of a node,
+// which start where the excess returns to its minimum, can be counted
+// without visiting them.
+//
+// See Navarro and Sadakane 2014.
+
+use std::cmp::{min, max};
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use super::super::collection::Collection;
+use super::super::dictionary::{Access, BitRank, Select};
+use super::super::rank9::Rank9;
+use super::super::serialize::{Serialize, Deserialize};
+use super::super::space::{SpaceUsage, vec_bytes};
+
+/// The number of bits per leaf of the range min-max tree
+pub const BLOCK: usize = 256;
+
+/// A sequence of parentheses supporting excess searches
+pub struct BalancedParens {
+    bits: Rank9,
+    /// the number of leaves of the range min-max tree, a power of two
+    leaves: usize,
+    /// the minimum and maximum excess reached after each bit of each
+    /// node of the range min-max tree, relative to the excess at its
+    /// start, in heap order from index 1
+    mins: Vec<i64>,
+    maxs: Vec<i64>,
+    /// the number of positions of each node attaining its minimum
+    counts: Vec<usize>,
+}
+
+impl BalancedParens {
+    /// Index a sequence of parentheses, `true` being an opening one
+    pub fn new(parens: &[bool]) -> BalancedParens {
+        use super::super::build::Builder;
+        let mut builder = super::super::rank9::Builder::with_capacity(parens.len());
+        for p in parens.iter() {
+            builder.push(*p);
+        }
+        BalancedParens::from_bits(builder.finish())
+    }
+
+    /// Index the parentheses of a bitvector
+    pub fn from_bits(bits: Rank9) -> BalancedParens {
+        let len = bits.len();
+        let blocks = len.div_ceil(BLOCK);
+        let leaves = max(blocks, 1).next_power_of_two();
+        let mut mins: Vec<i64> = (0..2 * leaves).map(|_| i64::MAX).collect();
+        let mut maxs: Vec<i64> = (0..2 * leaves).map(|_| i64::MIN).collect();
+        let mut sums: Vec<i64> = (0..2 * leaves).map(|_| 0).collect();
+        let mut counts: Vec<usize> = (0..2 * leaves).map(|_| 0).collect();
+        for c in 0..blocks {
+            let (mut e, mut lo, mut hi, mut count) = (0, i64::MAX, i64::MIN, 0);
+            for i in c * BLOCK..min(len, (c + 1) * BLOCK) {
+                e += if bits.get(i) { 1 } else { -1 };
+                if e < lo {
+                    lo = e;
+                    count = 0;
+                }
+                if e == lo {
+                    count += 1;
+                }
+                hi = max(hi, e);
+            }
+            mins[leaves + c] = lo;
+            maxs[leaves + c] = hi;
+            sums[leaves + c] = e;
+            counts[leaves + c] = count;
+        }
+        for node in (1..leaves).rev() {
+            let (l, r) = (2 * node, 2 * node + 1);
+            sums[node] = sums[l] + sums[r];
+            counts[node] = match mins[r] {
+                i64::MAX => counts[l],
+                m if sums[l] + m < mins[l] => counts[r],
+                m if sums[l] + m > mins[l] => counts[l],
+                _ => counts[l] + counts[r],
+            };
+            mins[node] = match mins[r] {
+                i64::MAX => mins[l],
+                m => min(mins[l], sums[l] + m),
+            };
+            maxs[node] = match maxs[r] {
+                i64::MIN => maxs[l],
+                m => max(maxs[l], sums[l] + m),
+            };
+        }
+        BalancedParens { bits, leaves, mins, maxs, counts }
+    }
+
+    /// Whether the `i`th parenthesis is an opening one
+    pub fn is_open(&self, i: usize) -> bool {
+        self.bits.get(i)
+    }
+
+    /// The excess at position `p`, that is after the first `p`
+    /// parentheses
+    pub fn excess(&self, p: usize) -> isize {
+        2 * self.bits.rank1(p) as isize - p as isize
+    }
+
+    /// The number of opening parentheses among the first `p`
+    pub fn rank_open(&self, p: usize) -> usize {
+        self.bits.rank1(p)
+    }
+
+    /// The index of the `n`th opening parenthesis, counting from zero
+    pub fn select_open(&self, n: usize) -> usize {
+        self.bits.select(true, n + 1) - 1
+    }
+
+    /// The index of the `n`th closing parenthesis, counting from zero
+    pub fn select_close(&self, n: usize) -> usize {
+        self.bits.select(false, n + 1) - 1
+    }
+
+    /// The smallest position `q > p` with `E(q) = E(p) + d`
+    pub fn fwd_search(&self, p: usize, d: isize) -> Option<usize> {
+        let len = self.len();
+        let target = self.excess(p) + d;
+        let end = min(len, (p / BLOCK + 1) * BLOCK);
+        if let Some(q) = self.scan_fwd(p, end, target) { return Some(q) }
+        let from = p / BLOCK + 1;
+        self.fwd_block(1, 0, self.leaves, from, target).map(|c| {
+            let start = c * BLOCK;
+            self.scan_fwd(start, min(len, start + BLOCK), target).unwrap()
+        })
+    }
+
+    /// The largest position `q < p` with `E(q) = E(p) + d`
+    pub fn bwd_search(&self, p: usize, d: isize) -> Option<usize> {
+        if p == 0 {
+            return None;
+        }
+        let target = self.excess(p) + d;
+        let block = (p - 1) / BLOCK;
+        if let Some(q) = self.scan_bwd(p - 1, block * BLOCK, target) { return Some(q) }
+        match self.bwd_block(1, 0, self.leaves, block, target) {
+            Some(c) => self.scan_bwd(min(self.len(), (c + 1) * BLOCK), c * BLOCK, target),
+            // the blocks cover every position but the first
+            None if target == 0 => Some(0),
+            None => None,
+        }
+    }
+
+    /// The closing parenthesis matching the opening one at `i`
+    pub fn find_close(&self, i: usize) -> usize {
+        debug_assert!(self.is_open(i));
+        self.fwd_search(i + 1, -1).expect("unbalanced parentheses") - 1
+    }
+
+    /// The opening parenthesis matching the closing one at `i`
+    pub fn find_open(&self, i: usize) -> usize {
+        debug_assert!(!self.is_open(i));
+        self.bwd_search(i, -1).expect("unbalanced parentheses")
+    }
+
+    /// The opening parenthesis of the nearest pair enclosing the one
+    /// opened at `i`, if any
+    pub fn enclose(&self, i: usize) -> Option<usize> {
+        self.bwd_search(i, -1)
+    }
+
+    /// The leftmost position `q` with `p <= q <= r` of minimum excess,
+    /// and its excess
+    pub fn min_excess(&self, p: usize, r: usize) -> (usize, isize) {
+        assert!(p <= r && r <= self.len(), "BalancedParens: invalid range [{}, {}]", p, r);
+        let mut best = (p, self.excess(p));
+        let head_end = min(r, (p / BLOCK + 1) * BLOCK);
+        self.scan_min(p, head_end, &mut best);
+        if head_end < r {
+            let (first, last) = (head_end / BLOCK, r / BLOCK);
+            if first < last {
+                match self.min_block(1, 0, self.leaves, first, last) {
+                    Some((m, c)) if m < best.1 => {
+                        let start = c * BLOCK;
+                        let mut block_best = (start, i64::MAX as isize);
+                        self.scan_min(start, min(self.len(), start + BLOCK), &mut block_best);
+                        best = block_best;
+                    },
+                    _ => {},
+                }
+            }
+            let tail = max(head_end, last * BLOCK);
+            let mut tail_best = (tail, self.excess(tail));
+            self.scan_min(tail, r, &mut tail_best);
+            if tail_best.1 < best.1 {
+                best = tail_best;
+            }
+        }
+        best
+    }
+
+    /// The number of positions `q` with `p <= q <= r` of minimum
+    /// excess
+    pub fn min_count(&self, p: usize, r: usize) -> usize {
+        let (_, m) = self.min_excess(p, r);
+        let mut count = if self.excess(p) == m { 1 } else { 0 };
+        // the positions after each bit of the head, the whole blocks
+        // and the tail
+        let head_end = min(r, (p / BLOCK + 1) * BLOCK);
+        count += self.scan_count(p, head_end, m);
+        if head_end < r {
+            let (first, last) = (head_end / BLOCK, r / BLOCK);
+            if first < last {
+                count += self.count_block(1, 0, self.leaves, first, last, m);
+            }
+            count += self.scan_count(max(head_end, last * BLOCK), r, m);
+        }
+        count
+    }
+
+    /// The step in excess of the `i`th parenthesis
+    fn step(&self, i: usize) -> isize {
+        if self.bits.get(i) { 1 } else { -1 }
+    }
+
+    /// The first position after bits `[p, end)` reaching `target`
+    fn scan_fwd(&self, p: usize, end: usize, target: isize) -> Option<usize> {
+        let mut e = self.excess(p);
+        for i in p..end {
+            e += self.step(i);
+            if e == target {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    /// The last position `q` with `start <= q <= last` reaching `target`
+    fn scan_bwd(&self, last: usize, start: usize, target: isize) -> Option<usize> {
+        let mut q = last;
+        let mut e = self.excess(q);
+        loop {
+            if e == target {
+                return Some(q);
+            }
+            if q == start {
+                return None;
+            }
+            q -= 1;
+            e -= self.step(q);
+        }
+    }
+
+    /// Update `best` with the leftmost minimum after bits `[p, end)`
+    fn scan_min(&self, p: usize, end: usize, best: &mut (usize, isize)) {
+        let mut e = self.excess(p);
+        for i in p..end {
+            e += self.step(i);
+            if e < best.1 {
+                *best = (i + 1, e);
+            }
+        }
+    }
+
+    /// The number of positions after bits `[p, end)` reaching `target`
+    fn scan_count(&self, p: usize, end: usize, target: isize) -> usize {
+        let mut e = self.excess(p);
+        let mut count = 0;
+        for i in p..end {
+            e += self.step(i);
+            if e == target {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Whether the blocks of `node`, starting at block `lo`, reach
+    /// `target`
+    fn reaches(&self, node: usize, lo: usize, target: isize) -> bool {
+        if self.mins[node] == i64::MAX {
+            return false;
+        }
+        let start = self.excess(lo * BLOCK);
+        start + self.mins[node] as isize <= target && target <= start + self.maxs[node] as isize
+    }
+
+    /// The first block no earlier than `from` reaching `target`, within
+    /// the blocks `[lo, hi)` of `node`
+    fn fwd_block(&self, node: usize, lo: usize, hi: usize, from: usize, target: isize) -> Option<usize> {
+        if hi <= from {
+            return None;
+        }
+        if lo >= from && !self.reaches(node, lo, target) {
+            return None;
+        }
+        if hi - lo == 1 {
+            return Some(lo);
+        }
+        let mid = (lo + hi) / 2;
+        self.fwd_block(2 * node, lo, mid, from, target)
+            .or_else(|| self.fwd_block(2 * node + 1, mid, hi, from, target))
+    }
+
+    /// The last block before `to` reaching `target`, within the blocks
+    /// `[lo, hi)` of `node`
+    fn bwd_block(&self, node: usize, lo: usize, hi: usize, to: usize, target: isize) -> Option<usize> {
+        if lo >= to {
+            return None;
+        }
+        if hi <= to && !self.reaches(node, lo, target) {
+            return None;
+        }
+        if hi - lo == 1 {
+            return Some(lo);
+        }
+        let mid = (lo + hi) / 2;
+        self.bwd_block(2 * node + 1, mid, hi, to, target)
+            .or_else(|| self.bwd_block(2 * node, lo, mid, to, target))
+    }
+
+    /// The minimum excess reached within blocks `[from, to)` and the
+    /// leftmost block reaching it, within the blocks `[lo, hi)` of
+    /// `node`
+    fn min_block(&self, node: usize, lo: usize, hi: usize, from: usize, to: usize) -> Option<(isize, usize)> {
+        if hi <= from || lo >= to || self.mins[node] == i64::MAX {
+            return None;
+        }
+        if hi - lo == 1 {
+            return Some((self.excess(lo * BLOCK) + self.mins[node] as isize, lo));
+        }
+        if from <= lo && hi <= to {
+            // descend towards the leftmost block attaining the minimum
+            let m = self.excess(lo * BLOCK) + self.mins[node] as isize;
+            let mid = (lo + hi) / 2;
+            return match self.min_block(2 * node, lo, mid, from, to) {
+                Some((lm, c)) if lm == m => Some((lm, c)),
+                _ => self.min_block(2 * node + 1, mid, hi, from, to),
+            };
+        }
+        let mid = (lo + hi) / 2;
+        match (self.min_block(2 * node, lo, mid, from, to),
+               self.min_block(2 * node + 1, mid, hi, from, to)) {
+            (Some(l), Some(r)) => Some(if r.0 < l.0 { r } else { l }),
+            (l, None) => l,
+            (None, r) => r,
+        }
+    }
+
+    /// The number of positions within blocks `[from, to)` reaching
+    /// `m`, which no position there falls below, within the blocks
+    /// `[lo, hi)` of `node`
+    fn count_block(&self, node: usize, lo: usize, hi: usize, from: usize, to: usize, m: isize) -> usize {
+        if hi <= from || lo >= to || self.mins[node] == i64::MAX {
+            return 0;
+        }
+        if from <= lo && hi <= to {
+            return match self.excess(lo * BLOCK) + self.mins[node] as isize == m {
+                true => self.counts[node],
+                false => 0,
+            };
+        }
+        let mid = (lo + hi) / 2;
+        self.count_block(2 * node, lo, mid, from, to, m)
+            + self.count_block(2 * node + 1, mid, hi, from, to, m)
+    }
+}
+
+impl Collection for BalancedParens {
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+}
+
+impl SpaceUsage for BalancedParens {
+    fn total_bytes(&self) -> usize {
+        size_of::<usize>() + self.bits.total_bytes() + vec_bytes(&self.mins) + vec_bytes(&self.maxs)
+            + vec_bytes(&self.counts)
+    }
+}
+
+/// Only the parentheses are written, the range min-max tree being
+/// rebuilt on reading.
+impl Serialize for BalancedParens {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.bits.serialize(w)
+    }
+}
+
+impl Deserialize for BalancedParens {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<BalancedParens> {
+        let bits: Rank9 = Deserialize::deserialize(r)?;
+        Ok(BalancedParens::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::iter::repeat_n;
+    use quickcheck::TestResult;
+    use super::BalancedParens;
+
+    /// A balanced sequence of parentheses enclosed in a single pair,
+    /// shaped by `v`
+    pub fn parens(v: &[bool]) -> Vec<bool> {
+        let mut out = vec!(true);
+        let mut depth = 0usize;
+        for b in v.iter() {
+            if *b || depth == 0 {
+                out.push(true);
+                depth += 1;
+            } else {
+                out.push(false);
+                depth -= 1;
+            }
+        }
+        out.extend(repeat_n(false, depth + 1));
+        out
+    }
+
+    fn excess(v: &[bool], p: usize) -> isize {
+        v.iter().take(p).map(|b| if *b { 1 } else { -1 }).sum()
+    }
+
+    #[quickcheck]
+    fn searches_are_correct(v: Vec<bool>, p: usize, d: i8) -> TestResult {
+        let v = parens(&v);
+        let p = p % (v.len() + 1);
+        let d = (d % 4) as isize;
+        let bp = BalancedParens::new(v.as_slice());
+        let target = excess(&v, p) + d;
+        let fwd = (p + 1..v.len() + 1).find(|q| excess(&v, *q) == target);
+        let bwd = (0..p).rev().find(|q| excess(&v, *q) == target);
+        TestResult::from_bool(bp.fwd_search(p, d) == fwd && bp.bwd_search(p, d) == bwd)
+    }
+
+    #[quickcheck]
+    fn min_excess_is_correct(v: Vec<bool>, p: usize, r: usize) -> bool {
+        let v = parens(&v);
+        let (p, r) = (p % (v.len() + 1), r % (v.len() + 1));
+        let (p, r) = (if p < r { p } else { r }, if p < r { r } else { p });
+        let bp = BalancedParens::new(v.as_slice());
+        let m = (p..r + 1).map(|q| excess(&v, q)).min().unwrap();
+        let q = (p..r + 1).find(|q| excess(&v, *q) == m).unwrap();
+        bp.min_excess(p, r) == (q, m)
+            && bp.min_count(p, r) == (p..r + 1).filter(|q| excess(&v, *q) == m).count()
+    }
+
+    #[test]
+    fn test_long() {
+        // a path deep enough to span many blocks
+        let mut v: Vec<bool> = (0..1000).map(|_| true).collect();
+        v.extend((0..1000).map(|_| false));
+        let bp = BalancedParens::new(v.as_slice());
+        assert_eq!(bp.find_close(0), 1999);
+        assert_eq!(bp.find_close(10), 1989);
+        assert_eq!(bp.find_open(1500), 499);
+        assert_eq!(bp.enclose(700), Some(699));
+        assert_eq!(bp.min_excess(300, 1900), (1900, 100));
+        assert_eq!(bp.min_count(300, 1900), 1);
+        // a root with many leaves spanning several blocks
+        let mut v = vec!(true);
+        for _ in 0..1000 {
+            v.extend_from_slice(&[true, false]);
+        }
+        v.push(false);
+        let bp = BalancedParens::new(v.as_slice());
+        assert_eq!(bp.min_count(1, 2001), 1001);
+        assert_eq!(bp.min_count(1, 601), 301);
+    }
+}
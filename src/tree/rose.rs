@@ -0,0 +1,200 @@
+//! Succinct rose trees
+//
+// A `Rose` becomes an ordinal tree with a node for each `Leaves` and
+// `Nodes` constructor and a leaf node for each value, kept in DFUDS.
+// The values are stored apart in preorder, with a bitvector marking
+// the nodes holding one. An empty `Nodes` cannot be told from an empty
+// `Leaves` and is decoded as the latter.
+
+use std::io::{self, Read, Write};
+use super::Rose;
+use super::dfuds::{self, Dfuds, Node};
+use super::super::collection::Collection;
+use super::super::sparse_array::{self, SparseArray};
+use super::super::serialize::{Serialize, Deserialize, invalid_data};
+use super::super::space::SpaceUsage;
+
+/// A static rose tree
+pub struct SuccinctRose<T> {
+    tree: Dfuds,
+    /// the value of each node in preorder, if any
+    values: SparseArray<T>,
+}
+
+impl<T> SuccinctRose<T> {
+    /// Encode `rose`
+    pub fn new(rose: Rose<T>) -> SuccinctRose<T> {
+        use super::super::build::Builder;
+        fn go<T>(rose: Rose<T>, degrees: &mut Vec<usize>, values: &mut sparse_array::Builder<T>) {
+            values.push(None);
+            match rose {
+                Rose::Leaves(xs) => {
+                    degrees.push(xs.len());
+                    for x in xs.into_iter() {
+                        degrees.push(0);
+                        values.push(Some(x));
+                    }
+                },
+                Rose::Nodes(children) => {
+                    degrees.push(children.len());
+                    for child in children.into_iter() {
+                        go(child, degrees, values);
+                    }
+                },
+            }
+        }
+        let mut degrees = Vec::new();
+        let mut values = sparse_array::Builder::new();
+        go(rose, &mut degrees, &mut values);
+        SuccinctRose {
+            tree: Dfuds::from_degrees(degrees.into_iter()),
+            values: values.finish(),
+        }
+    }
+
+    /// The shape of the tree
+    pub fn tree(&self) -> &Dfuds {
+        &self.tree
+    }
+
+    /// The root node
+    pub fn root(&self) -> Node {
+        self.tree.root()
+    }
+
+    /// The value of `v`, if it stands for one
+    pub fn value(&self, v: Node) -> Option<&T> {
+        self.values.get(self.tree.preorder(v))
+    }
+
+    /// Whether `v` stands for a `Leaves` constructor
+    pub fn is_leaves(&self, v: Node) -> bool {
+        match self.tree.child(v, 0) {
+            Some(c) => self.values.contains(self.tree.preorder(c)),
+            // an empty constructor is taken to be `Leaves`, unless
+            // it stands for a value
+            None => !self.values.contains(self.tree.preorder(v)),
+        }
+    }
+
+    /// The parent of `v`, unless it is the root
+    pub fn parent(&self, v: Node) -> Option<Node> {
+        self.tree.parent(v)
+    }
+
+    /// The children of `v` in order
+    pub fn children<'a>(&'a self, v: Node) -> dfuds::Children<'a> {
+        self.tree.children(v)
+    }
+
+    /// The number of nodes in the subtree rooted at `v`, including `v`
+    pub fn subtree_size(&self, v: Node) -> usize {
+        self.tree.subtree_size(v)
+    }
+
+    /// The number of values in the subtree rooted at `v`
+    pub fn subtree_values(&self, v: Node) -> usize {
+        let start = self.tree.preorder(v);
+        let end = start + self.tree.subtree_size(v);
+        self.values.rank(end) - self.values.rank(start)
+    }
+
+    /// The values in preorder
+    pub fn values(&self) -> &[T] {
+        self.values.values()
+    }
+}
+
+impl<T: Clone> SuccinctRose<T> {
+    /// Decode the subtree rooted at `v`
+    pub fn to_rose(&self, v: Node) -> Rose<T> {
+        match self.is_leaves(v) {
+            true => Rose::Leaves(self.children(v).map(|c| self.value(c).unwrap().clone()).collect()),
+            false => Rose::Nodes(self.children(v).map(|c| self.to_rose(c)).collect()),
+        }
+    }
+}
+
+impl<T> Collection for SuccinctRose<T> {
+    /// The number of nodes
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+}
+
+impl<T> SpaceUsage for SuccinctRose<T> {
+    fn total_bytes(&self) -> usize {
+        self.tree.total_bytes() + self.values.total_bytes()
+    }
+}
+
+impl<T: Serialize> Serialize for SuccinctRose<T> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.tree.serialize(w)?;
+        self.values.serialize(w)
+    }
+}
+
+impl<T: Deserialize> Deserialize for SuccinctRose<T> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<SuccinctRose<T>> {
+        let tree: Dfuds = Deserialize::deserialize(r)?;
+        let values: SparseArray<T> = Deserialize::deserialize(r)?;
+        if values.len() != tree.len() {
+            return Err(invalid_data("SuccinctRose values inconsistent with its shape"));
+        }
+        Ok(SuccinctRose { tree, values })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SuccinctRose;
+    use super::super::Rose;
+    use super::super::super::collection::Collection;
+
+    /// A rose tree with no empty `Nodes`, shaped by `v`
+    fn rose(v: &[u8], depth: usize) -> Rose<u8> {
+        match v.first() {
+            Some(x) if depth > 0 && v.len() > 1 && *x % 2 == 0 => {
+                Rose::Nodes(v[1..].chunks(3).map(|c| rose(c, depth - 1)).collect())
+            },
+            _ => Rose::Leaves(v.to_vec()),
+        }
+    }
+
+    #[quickcheck]
+    fn round_trips(v: Vec<u8>) -> bool {
+        let r = rose(v.as_slice(), 4);
+        let tree = SuccinctRose::new(r.clone());
+        let root = tree.root();
+        tree.to_rose(root) == r
+            && tree.children(root).all(|c| tree.parent(c) == Some(root))
+            && tree.subtree_size(root) == tree.len()
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u8>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let r = rose(v.as_slice(), 4);
+        let tree: SuccinctRose<u8> = round_trip(&SuccinctRose::new(r.clone()));
+        tree.to_rose(tree.root()) == r
+    }
+
+    #[test]
+    fn test_rose() {
+        let r = Rose::Nodes(vec!(Rose::Leaves(vec!(1usize, 2)), Rose::Nodes(vec!(Rose::Leaves(vec!(3))))));
+        let tree = SuccinctRose::new(r.clone());
+        // the constructors and the values
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.values(), [1, 2, 3].as_slice());
+        let children: Vec<usize> = tree.children(tree.root()).collect();
+        assert_eq!(children.len(), 2);
+        assert!(tree.is_leaves(children[0]));
+        assert!(!tree.is_leaves(children[1]));
+        assert_eq!(tree.subtree_values(children[0]), 2);
+        assert_eq!(tree.subtree_size(children[1]), 3);
+        let leaf = tree.children(children[0]).nth(1).unwrap();
+        assert_eq!(tree.value(leaf), Some(&2));
+        assert_eq!(tree.to_rose(tree.root()), r);
+    }
+}
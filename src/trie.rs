@@ -0,0 +1,273 @@
+//! A static trie of byte strings
+//
+// The shape of the trie is kept in LOUDS, with the label of the edge
+// into each node packed in an `IntVector` in breadth-first order and a
+// bitvector marking the nodes at which keys end. As the keys are
+// sorted before building, the children of each node appear in
+// increasing order of label, so a lookup takes a binary search among
+// the children at each step. Keys are identified by the rank of their
+// node among those marked.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::iter::FromIterator;
+use super::collection::Collection;
+use super::dictionary::{Access, BitRank, Select};
+use super::int_vector::IntVector;
+use super::rank9::Rank9;
+use super::serialize::{Serialize, Deserialize, invalid_data};
+use super::space::SpaceUsage;
+use super::tree::louds::{Louds, Node};
+
+/// A set of byte strings
+pub struct Trie {
+    tree: Louds,
+    /// the label of the edge into each node but the root
+    labels: IntVector,
+    /// whether a key ends at each node
+    terminals: Rank9,
+}
+
+impl Trie {
+    /// The trie of `keys`
+    pub fn new(mut keys: Vec<Vec<u8>>) -> Trie {
+        use super::build::Builder;
+        use super::{int_vector, rank9};
+        keys.sort();
+        keys.dedup();
+
+        // the nodes in breadth-first order, each being the range of
+        // keys starting with its path
+        let mut degrees = Vec::new();
+        let mut labels = int_vector::Builder::with_capacity(8, keys.len());
+        let mut terminals = rank9::Builder::with_capacity(keys.len());
+        let mut queue = VecDeque::new();
+        queue.push_back((0, keys.len(), 0));
+        while let Some((lo, hi, depth)) = queue.pop_front() {
+            let mut i = lo;
+            let terminal = i < hi && keys[i].len() == depth;
+            terminals.push(terminal);
+            if terminal {
+                i += 1;
+            }
+            let mut degree = 0usize;
+            while i < hi {
+                let label = keys[i][depth];
+                let mut j = i + 1;
+                while j < hi && keys[j][depth] == label {
+                    j += 1;
+                }
+                labels.push(label as u64);
+                queue.push_back((i, j, depth + 1));
+                degree += 1;
+                i = j;
+            }
+            degrees.push(degree);
+        }
+
+        Trie {
+            tree: Louds::from_degrees(degrees.into_iter()),
+            labels: labels.finish(),
+            terminals: terminals.finish(),
+        }
+    }
+
+    /// Whether `key` is in the set
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.id(key).is_some()
+    }
+
+    /// The identifier of `key`, if it is in the set. Identifiers are
+    /// consecutive from zero.
+    pub fn id(&self, key: &[u8]) -> Option<usize> {
+        match self.find(key) {
+            Some(v) if self.terminals.get(v) => Some(self.terminals.rank1(v)),
+            _ => None,
+        }
+    }
+
+    /// The key with identifier `id`
+    pub fn key(&self, id: usize) -> Vec<u8> {
+        assert!(id < self.len(), "Trie: key id {} out of bounds", id);
+        let mut v = self.terminals.select(true, id + 1) - 1;
+        let mut key = Vec::new();
+        while let Some(parent) = self.tree.parent(v) {
+            key.push(self.label(v));
+            v = parent;
+        }
+        key.reverse();
+        key
+    }
+
+    /// The keys starting with `prefix` in increasing order
+    pub fn with_prefix<'a>(&'a self, prefix: &[u8]) -> Keys<'a> {
+        let stack = match self.find(prefix) {
+            Some(v) => vec!((v, prefix.len())),
+            None => Vec::new(),
+        };
+        Keys { trie: self, stack, base: prefix.len(), key: prefix.to_vec() }
+    }
+
+    /// The keys in increasing order
+    pub fn iter<'a>(&'a self) -> Keys<'a> {
+        self.with_prefix(&[])
+    }
+
+    /// The label of the edge into `v`
+    fn label(&self, v: Node) -> u8 {
+        self.labels.get(v - 1) as u8
+    }
+
+    /// The node reached by following `path` from the root
+    fn find(&self, path: &[u8]) -> Option<Node> {
+        let mut v = self.tree.root();
+        for c in path.iter() {
+            let children = self.tree.children(v);
+            let (mut lo, mut hi) = (children.start, children.end);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if self.label(mid) < *c {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            if lo == children.end || self.label(lo) != *c {
+                return None;
+            }
+            v = lo;
+        }
+        Some(v)
+    }
+}
+
+impl Collection for Trie {
+    /// The number of keys
+    fn len(&self) -> usize {
+        self.terminals.rank1(self.terminals.len())
+    }
+}
+
+impl FromIterator<Vec<u8>> for Trie {
+    fn from_iter<I: IntoIterator<Item=Vec<u8>>>(iter: I) -> Trie {
+        Trie::new(iter.into_iter().collect())
+    }
+}
+
+impl SpaceUsage for Trie {
+    fn total_bytes(&self) -> usize {
+        self.tree.total_bytes() + self.labels.total_bytes() + self.terminals.total_bytes()
+    }
+}
+
+impl Serialize for Trie {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.tree.serialize(w)?;
+        self.labels.serialize(w)?;
+        self.terminals.serialize(w)
+    }
+}
+
+impl Deserialize for Trie {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Trie> {
+        let tree: Louds = Deserialize::deserialize(r)?;
+        let labels: IntVector = Deserialize::deserialize(r)?;
+        let terminals: Rank9 = Deserialize::deserialize(r)?;
+        if labels.len() + 1 != tree.len() || terminals.len() != tree.len() {
+            return Err(invalid_data("Trie labels inconsistent with its shape"));
+        }
+        if labels.width() > 8 {
+            return Err(invalid_data("Trie labels wider than a byte"));
+        }
+        Ok(Trie { tree, labels, terminals })
+    }
+}
+
+/// An iterator over the keys of a `Trie` in increasing order
+pub struct Keys<'a> {
+    trie: &'a Trie,
+    /// the nodes left to visit and their depths, the next on top
+    stack: Vec<(Node, usize)>,
+    /// the depth of the node of the prefix
+    base: usize,
+    /// the path to the last node visited
+    key: Vec<u8>,
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = Vec<u8>;
+    fn next(&mut self) -> Option<Vec<u8>> {
+        while let Some((v, depth)) = self.stack.pop() {
+            if depth > self.base {
+                self.key.truncate(depth - 1);
+                self.key.push(self.trie.label(v));
+            }
+            for child in self.trie.tree.children(v).rev() {
+                self.stack.push((child, depth + 1));
+            }
+            if self.trie.terminals.get(v) {
+                return Some(self.key.clone());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use super::Trie;
+    use super::super::collection::Collection;
+
+    /// Keys over a small alphabet, so that they share prefixes
+    fn keys(v: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        v.into_iter().map(|k| k.into_iter().map(|c| c % 4).collect()).collect()
+    }
+
+    #[quickcheck]
+    fn lookup_is_correct(v: Vec<Vec<u8>>, probe: Vec<u8>) -> bool {
+        let v = keys(v);
+        let probe: Vec<u8> = probe.into_iter().map(|c| c % 4).collect();
+        let set: BTreeSet<Vec<u8>> = v.iter().cloned().collect();
+        let trie: Trie = v.into_iter().collect();
+        trie.len() == set.len()
+            && trie.contains(probe.as_slice()) == set.contains(&probe)
+            && set.iter().all(|k| trie.id(k.as_slice()).map(|id| trie.key(id)) == Some(k.clone()))
+    }
+
+    #[quickcheck]
+    fn prefixes_are_correct(v: Vec<Vec<u8>>, prefix: Vec<u8>) -> bool {
+        let v = keys(v);
+        let prefix: Vec<u8> = prefix.into_iter().take(2).map(|c| c % 4).collect();
+        let set: BTreeSet<Vec<u8>> = v.iter().cloned().collect();
+        let trie = Trie::new(v);
+        let expected: Vec<Vec<u8>> = set.iter()
+            .filter(|k| k.starts_with(prefix.as_slice())).cloned()
+            .collect();
+        trie.with_prefix(prefix.as_slice()).collect::<Vec<Vec<u8>>>() == expected
+            && trie.iter().collect::<Vec<Vec<u8>>>() == set.into_iter().collect::<Vec<Vec<u8>>>()
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<Vec<u8>>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let v = keys(v);
+        let set: BTreeSet<Vec<u8>> = v.iter().cloned().collect();
+        let trie: Trie = round_trip(&Trie::new(v));
+        trie.iter().collect::<Vec<Vec<u8>>>() == set.into_iter().collect::<Vec<Vec<u8>>>()
+    }
+
+    #[test]
+    fn test_trie() {
+        let words = ["tea", "ten", "to", "inn", "in", "tea"];
+        let trie: Trie = words.iter().map(|w| w.as_bytes().to_vec()).collect();
+        assert_eq!(trie.len(), 5);
+        assert!(trie.contains(b"ten"));
+        assert!(!trie.contains(b"te"));
+        assert!(!trie.contains(b""));
+        let found: Vec<Vec<u8>> = trie.with_prefix(b"te").collect();
+        assert_eq!(found, vec!(b"tea".to_vec(), b"ten".to_vec()));
+        let id = trie.id(b"inn").unwrap();
+        assert_eq!(trie.key(id), b"inn".to_vec());
+    }
+}
@@ -1,11 +1,24 @@
 //! Utilities
 
-use std::num::{Int};
-
-pub fn div_ceil<T: Int>(a: T, b: T) -> T {
-    if a % b != Int::zero() {
-        a / b + Int::one()
+pub fn div_ceil(a: usize, b: usize) -> usize {
+    if !a.is_multiple_of(b) {
+        a / b + 1
     } else {
         a / b
     }
 }
+
+/// The `i`th of the words holding a bitvector of `bits` bits, with
+/// its bits past the end cleared
+pub fn bit_word(words: &[u64], bits: usize, i: usize) -> u64 {
+    match bits - 64 * i {
+        n if n < 64 => words[i] & ((1 << n) - 1),
+        _ => words[i],
+    }
+}
+
+/// Whether the `bits`-bit vectors held by `a` and `b` are the same,
+/// ignoring the bits past their end
+pub fn same_bits(a: &[u64], b: &[u64], bits: usize) -> bool {
+    (0..div_ceil(bits, 64)).all(|i| bit_word(a, bits, i) == bit_word(b, bits, i))
+}
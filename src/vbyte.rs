@@ -0,0 +1,304 @@
+//! A sequence of integers in variable-byte (LEB128) code
+//
+// Each value is written in groups of seven bits, least significant
+// first, one group per byte. The high bit of a byte is set when
+// another byte of the same value follows. The offset of every
+// `block`th value is sampled, so access decodes at most `block`
+// values while sequential scans decode whole bytes at a time.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use super::bits::bit_width;
+use super::collection::Collection;
+use super::dictionary::Access;
+use super::int_vector::{self, IntVector};
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use super::space::{SpaceUsage, vec_bytes};
+use super::utils::div_ceil;
+
+pub use crate::vbyte::build::Builder;
+
+/// Append the code of `x` to `bytes`
+pub fn write_vbyte(bytes: &mut Vec<u8>, x: u64) {
+    let mut rest = x;
+    while rest >= 0x80 {
+        bytes.push((rest as u8 & 0x7f) | 0x80);
+        rest >>= 7;
+    }
+    bytes.push(rest as u8);
+}
+
+/// Read the code starting at `*pos` of `bytes`, advancing `*pos` past
+/// it. Returns `None` if the code is truncated or exceeds 64 bits.
+pub fn read_vbyte(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut x = 0;
+    let mut shift = 0;
+    loop {
+        if *pos >= bytes.len() || shift >= 64 {
+            return None;
+        }
+        let byte = bytes[*pos];
+        *pos += 1;
+        let group = (byte & 0x7f) as u64;
+        if shift > 0 && group >> (64 - shift) != 0 {
+            return None;
+        }
+        x |= group << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(x);
+        }
+    }
+}
+
+/// A sequence of variable-byte coded integers with sampled offsets
+#[derive(Clone, Debug)]
+pub struct VByteSequence {
+    /// the number of values per sample
+    block: usize,
+    len: usize,
+    bytes: Vec<u8>,
+    /// the offset of every `block`th value
+    samples: IntVector,
+}
+
+/// Sample the offset of every `block`th code of `bytes`, failing if
+/// the stream doesn't consist of whole codes
+fn sample(bytes: &[u8], block: usize) -> Option<(usize, IntVector)> {
+    use super::build::Builder;
+    let mut offsets = Vec::new();
+    let mut pos = 0;
+    let mut len = 0;
+    while pos < bytes.len() {
+        if len % block == 0 {
+            offsets.push(pos as u64);
+        }
+        read_vbyte(bytes, &mut pos)?;
+        len += 1;
+    }
+    let builder = int_vector::Builder::with_capacity(bit_width(bytes.len() as u64), offsets.len());
+    Some((len, builder.from_iter(offsets.into_iter())))
+}
+
+impl VByteSequence {
+    /// Index an existing stream of codes, sampling every `block`th
+    /// value. Returns `None` if the stream ends within a code or holds
+    /// a value exceeding 64 bits.
+    pub fn from_bytes(bytes: Vec<u8>, block: usize) -> Option<VByteSequence> {
+        assert!(block > 0, "VByteSequence: block size must be positive");
+        sample(bytes.as_slice(), block).map(|(len, samples)| {
+            VByteSequence { block, len, bytes, samples }
+        })
+    }
+
+    /// The codes of the values
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_slice()
+    }
+
+    /// The number of values per sample
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    /// An iterator over the values
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { seq: self, pos: 0, n: 0 }
+    }
+
+    /// An iterator over the values from the `n`th on
+    pub fn iter_from<'a>(&'a self, n: usize) -> Iter<'a> {
+        assert!(n <= self.len, "VByteSequence: index {} out of bounds", n);
+        let sample = n / self.block;
+        if sample >= self.samples.len() {
+            return Iter { seq: self, pos: self.bytes.len(), n: self.len };
+        }
+        let mut iter = Iter {
+            seq: self,
+            pos: self.samples.get(sample) as usize,
+            n: sample * self.block,
+        };
+        for _ in iter.n..n {
+            iter.next();
+        }
+        iter
+    }
+}
+
+impl Collection for VByteSequence {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Access<u64> for VByteSequence {
+    fn get(&self, n: usize) -> u64 {
+        assert!(n < self.len, "VByteSequence: index {} out of bounds", n);
+        self.iter_from(n).next().unwrap()
+    }
+}
+
+impl SpaceUsage for VByteSequence {
+    fn total_bytes(&self) -> usize {
+        2 * size_of::<usize>() + vec_bytes(&self.bytes) + self.samples.total_bytes()
+    }
+}
+
+/// The bytes are packed eight to a word
+impl Serialize for VByteSequence {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.block)?;
+        write_uint(w, self.len)?;
+        write_uint(w, self.bytes.len())?;
+        for chunk in self.bytes.chunks(8) {
+            let mut word = 0;
+            for (i, b) in chunk.iter().enumerate() {
+                word |= (*b as u64) << (8 * i);
+            }
+            write_u64(w, word)?;
+        }
+        self.samples.serialize(w)
+    }
+}
+
+impl Deserialize for VByteSequence {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<VByteSequence> {
+        let block = read_uint(r)?;
+        let len = read_uint(r)?;
+        let n_bytes = read_uint(r)?;
+        let mut bytes = Vec::with_capacity(n_bytes);
+        for _ in 0..div_ceil(n_bytes, 8) {
+            let word = read_u64(r)?;
+            for i in 0..8 {
+                if bytes.len() < n_bytes {
+                    bytes.push((word >> (8 * i)) as u8);
+                }
+            }
+        }
+        let samples: IntVector = Deserialize::deserialize(r)?;
+        if block == 0 {
+            return Err(invalid_data("VByteSequence block size is zero"));
+        }
+        if samples.len() != div_ceil(len, block) {
+            return Err(invalid_data("VByteSequence samples inconsistent with length"));
+        }
+        if samples.iter().any(|s| s >= n_bytes as u64) {
+            return Err(invalid_data("VByteSequence sample exceeds its codes"));
+        }
+        Ok(VByteSequence { block, len, bytes, samples })
+    }
+}
+
+/// An iterator over the values of a `VByteSequence`, decoding them in
+/// order
+pub struct Iter<'a> {
+    seq: &'a VByteSequence,
+    /// the offset of the next code
+    pos: usize,
+    /// the index of the next value
+    n: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        if self.n < self.seq.len {
+            self.n += 1;
+            read_vbyte(self.seq.bytes.as_slice(), &mut self.pos)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.seq.len - self.n;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+mod build {
+    use super::super::build;
+    use super::super::bits::bit_width;
+    use super::super::int_vector;
+    use super::{VByteSequence, write_vbyte};
+
+    /// Build a `VByteSequence` from its values
+    pub struct Builder {
+        block: usize,
+        len: usize,
+        bytes: Vec<u8>,
+        samples: Vec<u64>,
+    }
+
+    impl Builder {
+        /// Build a sequence sampling every `block`th value. Access to
+        /// a value decodes up to `block` codes.
+        pub fn new(block: usize) -> Builder {
+            assert!(block > 0, "VByteSequence: block size must be positive");
+            Builder { block, len: 0, bytes: Vec::new(), samples: Vec::new() }
+        }
+    }
+
+    impl build::Builder<u64, VByteSequence> for Builder {
+        fn push(&mut self, x: u64) {
+            if self.len.is_multiple_of(self.block) {
+                self.samples.push(self.bytes.len() as u64);
+            }
+            write_vbyte(&mut self.bytes, x);
+            self.len += 1;
+        }
+
+        fn finish(self) -> VByteSequence {
+            let width = bit_width(self.bytes.len() as u64);
+            let samples = int_vector::Builder::with_capacity(width, self.samples.len())
+                .from_iter(self.samples.into_iter());
+            VByteSequence { block: self.block, len: self.len, bytes: self.bytes, samples }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VByteSequence;
+    use super::super::dictionary::Access;
+    use super::super::build::Builder;
+
+    #[quickcheck]
+    fn access_is_correct(block: usize, v: Vec<u64>) -> bool {
+        let seq: VByteSequence = super::Builder::new(block % 8 + 1).from_iter(v.clone().into_iter());
+        v.iter().enumerate().all(|(i, x)| seq.get(i) == *x)
+            && seq.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[quickcheck]
+    fn from_bytes_agrees(v: Vec<u64>) -> bool {
+        let seq: VByteSequence = super::Builder::new(4).from_iter(v.clone().into_iter());
+        match VByteSequence::from_bytes(seq.as_bytes().to_vec(), 3) {
+            Some(seq2) => seq2.iter().collect::<Vec<u64>>() == v,
+            None => false,
+        }
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u64>) -> bool {
+        use super::super::serialize::test::round_trip;
+        let seq: VByteSequence = super::Builder::new(5).from_iter(v.clone().into_iter());
+        let seq2: VByteSequence = round_trip(&seq);
+        seq2.iter().collect::<Vec<u64>>() == v
+    }
+
+    #[test]
+    fn test_invalid_streams() {
+        // truncated
+        assert!(VByteSequence::from_bytes(vec!(0x80), 4).is_none());
+        // eleven groups exceed 64 bits
+        let mut bytes: Vec<u8> = (0..10).map(|_| 0xff).collect();
+        bytes.push(0x01);
+        assert!(VByteSequence::from_bytes(bytes, 4).is_none());
+        let mut bytes = Vec::new();
+        super::write_vbyte(&mut bytes, 300);
+        assert_eq!(bytes, vec!(0xac, 0x02));
+    }
+}
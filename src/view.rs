@@ -0,0 +1,147 @@
+//! Zero-copy views of serialized structures
+//
+// The serialization of a bitvector is a sequence of little-endian
+// words: its header fields followed by its word arrays, each preceded
+// by its length. Held in memory on a little-endian machine, as when a
+// file written by `Serialize` is mapped with `mmap`, these words are
+// already the arrays the structure queries, so a view can borrow them
+// in place rather than copying them to the heap. Loading a view checks
+// the headers alone and so takes constant time, leaving the operating
+// system to page in the parts of the arrays queries touch.
+//
+// A file must be mapped at a word-aligned address, which `mmap` always
+// gives.
+
+use std::io;
+use std::mem::{align_of, size_of};
+use std::slice;
+use super::serialize::invalid_data;
+
+/// A structure borrowing its words from its serialization in memory
+pub trait View<'a>: Sized {
+    /// View the structure serialized at the front of `words`, leaving
+    /// the words after it
+    fn view(words: &mut &'a [u64]) -> io::Result<Self>;
+}
+
+/// The words of a serialization held in memory, such as a mapped file
+pub fn words(bytes: &[u8]) -> io::Result<&[u64]> {
+    if cfg!(target_endian = "big") {
+        return Err(invalid_data("views require a little-endian machine"));
+    }
+    if !bytes.len().is_multiple_of(size_of::<u64>()) {
+        return Err(invalid_data("serialization is not a whole number of words"));
+    }
+    if !(bytes.as_ptr() as usize).is_multiple_of(align_of::<u64>()) {
+        return Err(invalid_data("serialization is not word-aligned"));
+    }
+    Ok(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const u64, bytes.len() / size_of::<u64>()) })
+}
+
+/// View the structure serialized in `bytes`, which must hold nothing
+/// else
+pub fn view<'a, T: View<'a>>(bytes: &'a [u8]) -> io::Result<T> {
+    let mut words = words(bytes)?;
+    let x = View::view(&mut words)?;
+    if !words.is_empty() {
+        return Err(invalid_data("trailing words after structure"));
+    }
+    Ok(x)
+}
+
+/// Take a word written by `write_u64`
+pub fn take_u64(words: &mut &[u64]) -> io::Result<u64> {
+    if words.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of words"));
+    }
+    let x = u64::from_le(words[0]);
+    *words = &words[1..];
+    Ok(x)
+}
+
+/// Take a length or position written by `write_uint`
+pub fn take_uint(words: &mut &[u64]) -> io::Result<usize> {
+    let x = take_u64(words)?;
+    if x > (usize::MAX as u64) {
+        return Err(invalid_data("length exceeds address space"));
+    }
+    Ok(x as usize)
+}
+
+/// Take a `Vec<u64>` as serialized, borrowing its words
+pub fn take_words<'a>(words: &mut &'a [u64]) -> io::Result<&'a [u64]> {
+    let len = take_uint(words)?;
+    if len > words.len() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of words"));
+    }
+    let (xs, rest) = words.split_at(len);
+    *words = rest;
+    Ok(xs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::View;
+    use super::super::bit_vector::{BitVector, BitVectorRef};
+    use super::super::dictionary::{Access, BitRank, Select};
+    use super::super::rank9::{Rank9, Rank9Ref};
+    use super::super::serialize::Serialize;
+    use super::super::wavelet::FlatWavelet;
+
+    /// The serialization of `x` as words, as a mapped file would be
+    fn serialized<T: Serialize>(x: &T) -> Vec<u64> {
+        use std::io::Cursor;
+        use super::super::serialize::read_u64;
+        let mut buf: Vec<u8> = Vec::new();
+        x.serialize(&mut buf).unwrap();
+        let len = buf.len() / 8;
+        let mut cursor = Cursor::new(buf);
+        (0..len).map(|_| read_u64(&mut cursor).unwrap()).collect()
+    }
+
+    #[quickcheck]
+    fn views_agree(v: Vec<u64>) -> bool {
+        let bits = 64 * v.len();
+        let rank9 = Rank9::from_vec(&v, bits).unwrap();
+        let bv = BitVector::from_vec(&v, bits).unwrap();
+        let words = serialized(&rank9);
+        let r: Rank9Ref = View::view(&mut words.as_slice()).unwrap();
+        let words2 = serialized(&bv);
+        let b: BitVectorRef = View::view(&mut words2.as_slice()).unwrap();
+        let ones = rank9.rank1(bits);
+        (0..bits).all(|i| r.get(i) == rank9.get(i) && b.get(i) == rank9.get(i))
+            && (0..bits + 1).all(|i| r.rank1(i) == rank9.rank1(i))
+            && (1..ones + 1).all(|n| r.select(true, n) == rank9.select(true, n)
+                                      && b.select(true, n) == rank9.select(true, n))
+    }
+
+    #[quickcheck]
+    fn flat_wavelet_views(v: Vec<u8>) -> bool {
+        let flat: FlatWavelet<Rank9, u8> = FlatWavelet::from_slice(v.as_slice());
+        let words = serialized(&flat);
+        let mut rest = words.as_slice();
+        let viewed: FlatWavelet<Rank9Ref, u8> = View::view(&mut rest).unwrap();
+        rest.is_empty() && (0..v.len()).all(|i| viewed.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_flat_wavelet_overflow() {
+        // a length and number of levels whose product wraps to that of
+        // no bits
+        let mut words = vec!(1 << 63, 2);
+        words.extend(serialized(&Rank9::from_fn(0, |_| false)));
+        assert!(<FlatWavelet<Rank9Ref, u8> as View>::view(&mut words.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_view_bytes() {
+        let rank9 = Rank9::from_vec(&[0b1011, 0, 1 << 63], 192).unwrap();
+        let words = serialized(&rank9);
+        let bytes = unsafe { ::std::slice::from_raw_parts(words.as_ptr() as *const u8, 8 * words.len()) };
+        let r: Rank9Ref = super::view(bytes).unwrap();
+        assert_eq!(r.rank1(192), 4);
+        assert_eq!(r.select(true, 4), 192);
+        assert!(super::view::<Rank9Ref>(&bytes[..bytes.len() - 8]).is_err());
+        assert!(super::view::<Rank9Ref>(&bytes[1..bytes.len() - 7]).is_err());
+    }
+}
@@ -0,0 +1,534 @@
+//! Wavelet trees with an alphabetic (Hu-Tucker) shape
+//
+// The shape of the tree is given by an optimal alphabetic code, whose
+// code lengths we compute with the Garsia-Wachs algorithm (these are
+// the same lengths Hu-Tucker produces). Like a Huffman-shaped tree,
+// frequent symbols sit close to the root. Unlike one, the code
+// preserves symbol order, so the leaves of the tree remain sorted and
+// queries over ranges of values (`range_count`, `quantile`) can still
+// be answered by descending the tree.
+
+use std::iter::repeat_n;
+use std::collections::BTreeMap;
+use super::super::dictionary::{Rank, Select, Access};
+use super::super::collection::Collection;
+use super::super::build;
+use super::super::tree::binary::{self, Tree, Branch};
+use super::bit_to_branch;
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use std::io::{self, Read, Write};
+
+/// A code word. The first bit of the code is taken at the root.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Code {
+    bits: u64,
+    len: usize,
+}
+
+impl Code {
+    fn empty() -> Code {
+        Code { bits: 0, len: 0 }
+    }
+
+    /// The `i`th bit of the code, counting from the root
+    fn bit(&self, i: usize) -> bool {
+        (self.bits >> (self.len - 1 - i)) & 1 == 1
+    }
+
+    /// The code extended by `bit`
+    fn child(&self, bit: bool) -> Code {
+        Code { bits: (self.bits << 1) | bit as u64, len: self.len + 1 }
+    }
+
+    /// The code left-aligned in a word. Comparing aligned codes
+    /// orders them as the leaves of the tree are ordered.
+    fn aligned(&self) -> u64 {
+        match self.len {
+            0 => 0,
+            _ => self.bits << (64 - self.len),
+        }
+    }
+
+    /// Whether this is the right-most code of its length
+    fn is_last(&self) -> bool {
+        self.len == 0 || self.bits == u64::MAX >> (64 - self.len)
+    }
+}
+
+/// Compute the code lengths of an optimal alphabetic code for
+/// symbols of the given weights using the Garsia-Wachs algorithm.
+pub fn code_lengths(weights: &[u64]) -> Vec<usize> {
+    let n = weights.len();
+    if n == 0 {
+        return vec!();
+    }
+
+    // Nodes of the combination tree; the first `n` are the leaves
+    let mut children: Vec<Option<(usize, usize)>> = repeat_n(None, n).collect();
+
+    // The working sequence of `(weight, node)` with a sentinel at either end
+    let mut seq: Vec<(u64, usize)> = Vec::with_capacity(n + 2);
+    seq.push((u64::MAX, 0));
+    for (i, w) in weights.iter().enumerate() {
+        seq.push((*w, i));
+    }
+    seq.push((u64::MAX, 0));
+
+    while seq.len() > 3 {
+        // find the left-most pair `(k-1, k)` with `w[k-1] <= w[k+1]`
+        let mut k = 2;
+        while seq[k-1].0 > seq[k+1].0 {
+            k += 1;
+        }
+
+        let weight = seq[k-1].0 + seq[k].0;
+        let node = children.len();
+        children.push(Some((seq[k-1].1, seq[k].1)));
+        seq.remove(k);
+        seq.remove(k-1);
+
+        // and move it left to follow the nearest weight no smaller than it
+        let mut j = k - 1;
+        while seq[j-1].0 < weight {
+            j -= 1;
+        }
+        seq.insert(j, (weight, node));
+    }
+
+    // The depths of the leaves in the combination tree are the code lengths
+    let mut lengths: Vec<usize> = repeat_n(0, n).collect();
+    let mut stack: Vec<(usize, usize)> = vec!((seq[1].1, 0));
+    while let Some((node, depth)) = stack.pop() {
+        match children[node] {
+            None => lengths[node] = depth,
+            Some((l, r)) => {
+                stack.push((l, depth + 1));
+                stack.push((r, depth + 1));
+            }
+        }
+    }
+    lengths
+}
+
+/// Assign codes to symbols in order given the lengths of an
+/// alphabetic code
+fn codes_from_lengths(lengths: &[usize]) -> Vec<Code> {
+    let mut codes: Vec<Code> = Vec::with_capacity(lengths.len());
+    for &len in lengths.iter() {
+        assert!(len <= 64, "Alphabetic code length exceeds 64 bits");
+        let code = match codes.last() {
+            None => Code { bits: 0, len },
+            Some(prev) => {
+                let next = prev.bits + 1;
+                let bits = if len >= prev.len {
+                    next << (len - prev.len)
+                } else {
+                    next >> (prev.len - len)
+                };
+                Code { bits, len }
+            }
+        };
+        codes.push(code);
+    }
+    codes
+}
+
+/// A wavelet tree shaped by an optimal alphabetic code over the
+/// symbols of type `Sym`
+pub struct AlphabeticWavelet<BitV, Sym> {
+    /// the distinct symbols in increasing order
+    symbols: Vec<Sym>,
+    /// the code of each symbol
+    codes: Vec<Code>,
+    tree: Tree<BitV>,
+    /// length of the sequence
+    len: usize,
+}
+
+impl<BitV, Sym: Ord> AlphabeticWavelet<BitV, Sym> {
+    /// The distinct symbols of the sequence in increasing order
+    pub fn alphabet(&self) -> &[Sym] {
+        self.symbols.as_slice()
+    }
+
+    fn code(&self, sym: &Sym) -> Option<Code> {
+        self.symbols.binary_search(sym).ok().map(|i| self.codes[i])
+    }
+
+    /// The number of symbols whose codes precede the left-aligned code `aligned`
+    fn codes_below(&self, aligned: u64) -> usize {
+        match self.codes.binary_search_by(|c| c.aligned().cmp(&aligned)) {
+            Ok(i) => i,
+            Err(i) => i,
+        }
+    }
+
+    /// The index of the symbol with the given code
+    fn symbol_index(&self, code: Code) -> usize {
+        self.codes_below(code.aligned())
+    }
+
+    /// The range of indices of the symbols whose codes begin with `prefix`
+    fn leaf_range(&self, prefix: Code) -> (usize, usize) {
+        let first = self.codes_below(prefix.aligned());
+        let last = if prefix.is_last() {
+            self.codes.len()
+        } else {
+            let next = Code { bits: prefix.bits + 1, len: prefix.len };
+            self.codes_below(next.aligned())
+        };
+        (first, last)
+    }
+
+    /// The number of symbols smaller than `sym`
+    fn symbols_below(&self, sym: &Sym) -> usize {
+        match self.symbols.binary_search(sym) {
+            Ok(i) => i,
+            Err(i) => i,
+        }
+    }
+}
+
+impl<BitV: Rank<bool>, Sym: Ord + Clone> AlphabeticWavelet<BitV, Sym> {
+    /// The number of positions in `[i, j)` holding a symbol `s` with
+    /// `lo <= s < hi`
+    pub fn range_count(&self, i: usize, j: usize, lo: Sym, hi: Sym) -> usize {
+        let lo = self.symbols_below(&lo);
+        let hi = self.symbols_below(&hi);
+        self.range_count_node(&self.tree, Code::empty(), i, j, lo, hi)
+    }
+
+    fn range_count_node(&self, node: &Tree<BitV>, prefix: Code,
+                        i: usize, j: usize, lo: usize, hi: usize) -> usize {
+        if i == j {
+            return 0;
+        }
+        let (first, last) = self.leaf_range(prefix);
+        if hi <= first || last <= lo {
+            return 0;
+        }
+        if lo <= first && last <= hi {
+            return j - i;
+        }
+
+        let mut count = 0;
+        for &bit in [false, true].iter() {
+            match node.branch(bit_to_branch(bit)) {
+                &None => {},
+                Some(child) => {
+                    let ci = node.value.rank(bit, i);
+                    let cj = node.value.rank(bit, j);
+                    count += self.range_count_node(&**child, prefix.child(bit), ci, cj, lo, hi);
+                }
+            }
+        }
+        count
+    }
+
+    /// The `k`th smallest (counting from zero) symbol occurring in
+    /// positions `[i, j)`
+    pub fn quantile(&self, mut i: usize, mut j: usize, mut k: usize) -> Sym {
+        assert!(k < j - i, "quantile: k={} out of range [{}, {})", k, i, j);
+        let mut node = &self.tree;
+        let mut code = Code::empty();
+        while node.left.is_some() || node.right.is_some() {
+            let zi = node.value.rank(false, i);
+            let zj = node.value.rank(false, j);
+            let zeros = zj - zi;
+            let bit = k >= zeros;
+            if bit {
+                k -= zeros;
+                i -= zi;
+                j -= zj;
+            } else {
+                i = zi;
+                j = zj;
+            }
+            node = match node.branch(bit_to_branch(bit)) {
+                Some(child) => &**child,
+                &None => panic!("quantile: missing branch"),
+            };
+            code = code.child(bit);
+        }
+        self.symbols[self.symbol_index(code)].clone()
+    }
+}
+
+impl<BitV: Serialize, Sym: Serialize> Serialize for AlphabeticWavelet<BitV, Sym> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.len)?;
+        write_uint(w, self.symbols.len())?;
+        for (sym, code) in self.symbols.iter().zip(self.codes.iter()) {
+            sym.serialize(w)?;
+            write_uint(w, code.len)?;
+        }
+        self.tree.serialize(w)
+    }
+}
+
+impl<BitV: Deserialize, Sym: Deserialize> Deserialize for AlphabeticWavelet<BitV, Sym> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<AlphabeticWavelet<BitV, Sym>> {
+        let len = read_uint(r)?;
+        let n_symbols = read_uint(r)?;
+        let mut symbols = Vec::with_capacity(n_symbols);
+        let mut lengths = Vec::with_capacity(n_symbols);
+        for _ in 0..n_symbols {
+            symbols.push(Deserialize::deserialize(r)?);
+            let len = read_uint(r)?;
+            if len > 64 {
+                return Err(invalid_data("alphabetic code too long"));
+            }
+            lengths.push(len);
+        }
+        let tree = Deserialize::deserialize(r)?;
+        Ok(AlphabeticWavelet {
+            symbols,
+            codes: codes_from_lengths(lengths.as_slice()),
+            tree,
+            len,
+        })
+    }
+}
+
+impl<BitV, Sym> Collection for AlphabeticWavelet<BitV, Sym> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<BitV: Rank<bool> + Access<bool>, Sym: Ord + Clone> Access<Sym> for AlphabeticWavelet<BitV, Sym> {
+    fn get(&self, mut n: usize) -> Sym {
+        let mut cursor = binary::Cursor::new(&self.tree);
+        let mut code = Code::empty();
+        while cursor.left.is_some() || cursor.right.is_some() {
+            let bit = cursor.value.get(n);
+            n = cursor.value.rank(bit, n);
+            cursor.step(bit_to_branch(bit));
+            code = code.child(bit);
+        }
+        self.symbols[self.symbol_index(code)].clone()
+    }
+}
+
+impl<BitV: Rank<bool>, Sym: Ord> Rank<Sym> for AlphabeticWavelet<BitV, Sym> {
+    fn rank(&self, sym: Sym, mut n: usize) -> usize {
+        let code = match self.code(&sym) {
+            None => return 0,
+            Some(code) => code,
+        };
+        let mut cursor = binary::Cursor::new(&self.tree);
+        for i in 0..code.len {
+            let bit = code.bit(i);
+            n = cursor.value.rank(bit, n);
+            cursor.step(bit_to_branch(bit));
+        }
+        n
+    }
+}
+
+impl<BitV: Select<bool>, Sym: Ord> Select<Sym> for AlphabeticWavelet<BitV, Sym> {
+    fn select(&self, sym: Sym, n: usize) -> usize {
+        if n == 0 { return 0; }
+        let code = match self.code(&sym) {
+            None => panic!("select: symbol does not occur"),
+            Some(code) => code,
+        };
+        let mut cursor = binary::Cursor::new(&self.tree);
+        for i in 0..code.len {
+            cursor.step(bit_to_branch(code.bit(i)));
+        }
+
+        let mut n = n;
+        while let Some(branch) = cursor.up() {
+            n = cursor.value.select(branch == Branch::Right, n);
+        }
+        n
+    }
+}
+
+/// Build up an alphabetic wavelet tree from a sequence of symbols.
+///
+/// The code can only be determined once the symbol frequencies are
+/// known so the symbols are buffered until `finish`.
+pub struct Builder<BitVBuilder, Sym> {
+    symbols: Vec<Sym>,
+    new_bitvector: fn() -> BitVBuilder,
+}
+
+impl<BitVBuilder, Sym> Builder<BitVBuilder, Sym> {
+    pub fn new(new_bitvector: fn() -> BitVBuilder) -> Builder<BitVBuilder, Sym> {
+        Builder {
+            symbols: Vec::new(),
+            new_bitvector,
+        }
+    }
+}
+
+impl<BitV, BitVBuilder: build::Builder<bool, BitV>, Sym: Ord + Clone>
+    build::Builder<Sym, AlphabeticWavelet<BitV, Sym>>
+    for Builder<BitVBuilder, Sym>
+{
+    fn push(&mut self, element: Sym) {
+        self.symbols.push(element);
+    }
+
+    fn finish(self) -> AlphabeticWavelet<BitV, Sym> {
+        let mut stats = build::StatsBuilder::new(true);
+        for sym in self.symbols.iter() {
+            stats.push(sym.clone());
+        }
+        let stats = stats.finish();
+        CodedBuilder::new(self.new_bitvector, &stats.frequencies).from_iter(self.symbols.into_iter())
+    }
+}
+
+/// Build up an alphabetic wavelet tree from a sequence of symbols
+/// whose frequencies are known in advance, pushing each down the tree
+/// as it comes rather than buffering them
+pub struct CodedBuilder<BitVBuilder, Sym> {
+    /// the symbols that may be pushed, in order
+    alphabet: Vec<Sym>,
+    codes: Vec<Code>,
+    tree: Tree<BitVBuilder>,
+    new_bitvector: fn() -> BitVBuilder,
+    len: usize,
+}
+
+impl<BitVBuilder, Sym: Ord + Clone> CodedBuilder<BitVBuilder, Sym> {
+    /// Build a tree shaped by the number of occurrences of each
+    /// symbol given by `frequencies`. Only these symbols may be
+    /// pushed.
+    pub fn new(new_bitvector: fn() -> BitVBuilder, frequencies: &BTreeMap<Sym, usize>)
+               -> CodedBuilder<BitVBuilder, Sym> {
+        let alphabet: Vec<Sym> = frequencies.keys().cloned().collect();
+        let weights: Vec<u64> = frequencies.values().map(|n| *n as u64).collect();
+        CodedBuilder {
+            alphabet,
+            codes: codes_from_lengths(code_lengths(weights.as_slice()).as_slice()),
+            tree: Tree::singleton(new_bitvector()),
+            new_bitvector,
+            len: 0,
+        }
+    }
+}
+
+impl<BitV, BitVBuilder: build::Builder<bool, BitV>, Sym: Ord + Clone>
+    build::Builder<Sym, AlphabeticWavelet<BitV, Sym>>
+    for CodedBuilder<BitVBuilder, Sym>
+{
+    fn push(&mut self, element: Sym) {
+        let code = match self.alphabet.binary_search(&element) {
+            Ok(i) => self.codes[i],
+            Err(_) => panic!("CodedBuilder: symbol not in the alphabet"),
+        };
+        let new_bitvector = self.new_bitvector;
+        let mut cursor = binary::MutCursor::new(&mut self.tree);
+        for i in 0..code.len {
+            let bit = code.bit(i);
+            cursor.value.push(bit);
+            let branch = bit_to_branch(bit);
+            match cursor.branch_mut(branch) {
+                &mut Some(_) => {},
+                n => *n = Some(Box::new(Tree::singleton(new_bitvector()))),
+            }
+            cursor.step(branch);
+        }
+        self.len += 1;
+    }
+
+    fn finish(self) -> AlphabeticWavelet<BitV, Sym> {
+        AlphabeticWavelet {
+            symbols: self.alphabet,
+            codes: self.codes,
+            tree: self.tree.map_step(&mut |b| b.finish()),
+            len: self.len,
+        }
+    }
+}
+
+impl<BitV, Sym: Ord + Clone> AlphabeticWavelet<BitV, Sym> {
+    /// Build a tree from the symbols of `iter` in two passes, the
+    /// first counting the symbols to shape the tree and the second
+    /// pushing them down it, so that they are never buffered
+    pub fn from_iter_two_pass<BitVBuilder, Iter>(iter: Iter, new_bitvector: fn() -> BitVBuilder)
+                                                 -> AlphabeticWavelet<BitV, Sym>
+        where BitVBuilder: build::Builder<bool, BitV>, Iter: Iterator<Item=Sym> + Clone
+    {
+        build::TwoPass::new(iter).with_frequencies()
+            .build(|stats| CodedBuilder::new(new_bitvector, &stats.frequencies))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::code_lengths;
+    use super::super::super::dictionary::{Rank, Select, Access};
+    use super::super::super::build::Builder;
+    use super::super::super::bit_vector;
+    use super::super::super::naive;
+
+    fn new_bitvector() -> bit_vector::Builder {
+        bit_vector::Builder::with_capacity(128)
+    }
+
+    #[test]
+    fn test_code_lengths() {
+        assert_eq!(code_lengths(&[1, 1, 1, 1]), vec!(2, 2, 2, 2));
+        assert_eq!(code_lengths(&[10, 1, 1]), vec!(1, 2, 2));
+        assert_eq!(code_lengths(&[1, 1, 10]), vec!(2, 2, 1));
+        assert_eq!(code_lengths(&[5]), vec!(0));
+    }
+
+    #[quickcheck]
+    fn rank_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        if n > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        TestResult::from_bool(wavelet.rank(el, n) == v.rank(el, n))
+    }
+
+    #[quickcheck]
+    fn select_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        if n == 0 || v.iter().filter(|x| *x == &el).count() < n {
+            return TestResult::discard()
+        }
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        TestResult::from_bool(wavelet.select(el, n) == v.select(el, n))
+    }
+
+    #[quickcheck]
+    fn access_is_correct(v: Vec<u8>) -> bool {
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        v.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x)
+    }
+
+    #[quickcheck]
+    fn two_pass_is_correct(v: Vec<u8>) -> bool {
+        use super::AlphabeticWavelet;
+        let wavelet: AlphabeticWavelet<bit_vector::BitVector, u8> =
+            AlphabeticWavelet::from_iter_two_pass(v.iter().copied(), new_bitvector);
+        v.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x)
+            && v.iter().all(|x| (0..v.len() + 1).all(|n| wavelet.rank(*x, n) == v.rank(*x, n)))
+    }
+
+    #[quickcheck]
+    fn quantile_is_correct(v: Vec<u8>, i: usize, j: usize, k: usize) -> TestResult {
+        if i >= j || j > v.len() || k >= j - i {
+            return TestResult::discard()
+        }
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        TestResult::from_bool(wavelet.quantile(i, j, k) == naive::Sequence::new(v).quantile(i, j, k))
+    }
+
+    #[quickcheck]
+    fn range_count_is_correct(v: Vec<u8>, i: usize, j: usize, lo: u8, hi: u8) -> TestResult {
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let expected = naive::Sequence::new(v).range_count(i, j, lo, hi);
+        TestResult::from_bool(wavelet.range_count(i, j, lo, hi) == expected)
+    }
+}
@@ -0,0 +1,211 @@
+//! Concatenation of wavelet trees
+//
+// The node of the concatenated tree reached by a path holds the bits
+// of the nodes reached by that path in each of the two trees, one
+// after the other. The trees are therefore merged node by node,
+// copying bitvectors rather than decoding symbols. Where the trees'
+// shapes differ, because a level collapsed by path compression in one
+// tree is not unary in the other, the collapsed level's bits are
+// materialized. Subtrees present in only one of the trees are moved
+// into the result untouched.
+
+use std::cmp::min;
+use std::marker::PhantomData;
+use super::super::dictionary::{Rank, Access};
+use super::super::build;
+use super::super::tree::binary::Tree;
+use super::{Wavelet, Node, digest_symbols};
+
+/// The remainder of a subtree taking part in a concatenation
+struct Part<BitV> {
+    tree: Tree<Node<BitV>>,
+    /// the number of bits of the root's skip already consumed
+    skipped: usize,
+    /// the number of positions passing through the subtree
+    len: usize,
+}
+
+/// The bits of the first level of a `Part`
+enum Level {
+    /// every position takes the same branch
+    Unary(bool),
+    /// the positions' branches are given by the root's bitvector
+    Bits,
+    /// the positions have reached their leaf
+    Leaf,
+}
+
+impl<BitV: Rank<bool> + Access<bool>> Part<BitV> {
+    fn level(&self) -> Level {
+        if self.skipped < self.tree.value.skip.len() {
+            Level::Unary(self.tree.value.skip[self.skipped])
+        } else if self.tree.value.bits.is_some() {
+            Level::Bits
+        } else {
+            Level::Leaf
+        }
+    }
+
+    /// Push the bits of the first level
+    fn push_bits<B: build::Builder<bool, BitV>>(&self, builder: &mut B) {
+        match (self.level(), &self.tree.value.bits) {
+            (Level::Unary(bit), _) => for _ in 0..self.len {
+                builder.push(bit);
+            },
+            (Level::Bits, Some(bv)) => for i in 0..self.len {
+                builder.push(bv.get(i));
+            },
+            _ => panic!("concat: leaf has no bits"),
+        }
+    }
+
+    /// The parts of the left and right subtrees below the first level
+    fn split(self) -> (Option<Part<BitV>>, Option<Part<BitV>>) {
+        match self.level() {
+            Level::Unary(bit) => {
+                let part = Part { skipped: self.skipped + 1, ..self };
+                match bit {
+                    false => (Some(part), None),
+                    true => (None, Some(part)),
+                }
+            },
+            Level::Bits => {
+                let Tree { value, left, right } = self.tree;
+                let zeros = match value.bits {
+                    Some(ref bv) => bv.rank(false, self.len),
+                    None => unreachable!(),
+                };
+                let left = left.map(|t| Part { tree: *t, skipped: 0, len: zeros });
+                let right = right.map(|t| Part { tree: *t, skipped: 0, len: self.len - zeros });
+                (left, right)
+            },
+            Level::Leaf => panic!("concat: leaf has no children"),
+        }
+    }
+
+    /// The subtree with its consumed skip removed and `skip` prepended
+    fn into_tree(self, mut skip: Vec<bool>) -> Tree<Node<BitV>> {
+        let mut tree = self.tree;
+        skip.extend_from_slice(&tree.value.skip[self.skipped..]);
+        tree.value.skip = skip;
+        tree
+    }
+}
+
+/// Concatenate two parts lying below the collapsed path `skip`
+fn merge<BitV, BitVBuilder, F>(a: Option<Part<BitV>>, b: Option<Part<BitV>>,
+                               mut skip: Vec<bool>, new_bitvector: &mut F) -> Tree<Node<BitV>>
+    where BitV: Rank<bool> + Access<bool>,
+          BitVBuilder: build::Builder<bool, BitV>,
+          F: FnMut() -> BitVBuilder
+{
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(part), None) | (None, Some(part)) => return part.into_tree(skip),
+        (None, None) => panic!("concat: empty subtree"),
+    };
+
+    match (a.level(), b.level()) {
+        (Level::Leaf, Level::Leaf) => return Tree::singleton(Node::leaf(skip)),
+        (Level::Unary(x), Level::Unary(y)) if x == y => {
+            skip.push(x);
+            let a = Part { skipped: a.skipped + 1, ..a };
+            let b = Part { skipped: b.skipped + 1, ..b };
+            return merge(Some(a), Some(b), skip, new_bitvector);
+        },
+        (Level::Leaf, _) | (_, Level::Leaf) => panic!("concat: trees of differing depth"),
+        _ => {},
+    }
+
+    let mut builder = (*new_bitvector)();
+    a.push_bits(&mut builder);
+    b.push_bits(&mut builder);
+    let (a_left, a_right) = a.split();
+    let (b_left, b_right) = b.split();
+    Tree {
+        value: Node { skip, bits: Some(builder.finish()) },
+        left: Some(Box::new(merge(a_left, b_left, Vec::new(), new_bitvector))),
+        right: Some(Box::new(merge(a_right, b_right, Vec::new(), new_bitvector))),
+    }
+}
+
+/// The number of levels of a path-compressed tree
+fn depth<BitV>(tree: &Tree<Node<BitV>>) -> usize {
+    match tree.left {
+        Some(ref left) => tree.value.skip.len() + 1 + depth(&**left),
+        None => tree.value.skip.len(),
+    }
+}
+
+impl<BitV: Rank<bool> + Access<bool>, Sym> Wavelet<BitV, Sym> {
+    /// The tree of the sequence of `self` followed by that of `other`.
+    ///
+    /// The trees are merged node by node, the new node bitvectors
+    /// being built with `new_bitvector`. Only the symbols of `other`
+    /// are decoded, to extend the content digest of `self`, so this is
+    /// suited to combining indexes of shards of a sequence built
+    /// independently. Both trees must have the same number of levels.
+    pub fn concat<BitVBuilder, F>(self, other: Wavelet<BitV, Sym>, mut new_bitvector: F)
+                                  -> Wavelet<BitV, Sym>
+        where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder
+    {
+        if other.len == 0 {
+            return self;
+        }
+        if self.len == 0 {
+            return other;
+        }
+        assert_eq!(depth(&self.tree), depth(&other.tree));
+
+        let len = self.len + other.len;
+        let width = min(self.width, other.width);
+        let mut digest = self.digest;
+        digest_symbols(&other.tree, other.len, &mut digest);
+        let a = Part { tree: self.tree, skipped: 0, len: self.len };
+        let b = Part { tree: other.tree, skipped: 0, len: other.len };
+        Wavelet {
+            tree: merge(Some(a), Some(b), Vec::new(), &mut new_bitvector),
+            width,
+            len,
+            digest,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::dictionary::{Access, Rank};
+    use super::super::super::build::Builder;
+    use super::super::super::rank9;
+
+    fn new_bitvector() -> rank9::Builder {
+        rank9::Builder::with_capacity(128)
+    }
+
+    #[quickcheck]
+    fn concat_is_correct(el: u8, a: Vec<u8>, b: Vec<u8>) -> bool {
+        let wa = super::super::Wavelet::from_slice(a.as_slice(), new_bitvector);
+        let wb = super::super::Builder::new(new_bitvector).from_iter(b.clone().into_iter());
+        let wavelet = wa.concat(wb, new_bitvector);
+        let mut v = a.clone();
+        v.extend_from_slice(b.as_slice());
+        (0..v.len()).all(|i| wavelet.get(i) == v[i])
+            && (0..v.len() + 1).all(|n| wavelet.rank(el, n) == v.rank(el, n))
+    }
+
+    #[test]
+    fn test_concat_shapes() {
+        // each tree alone collapses levels which are not unary in the other
+        let a: Vec<u8> = vec!(0x10, 0x10, 0x11);
+        let b: Vec<u8> = vec!(0x80, 0x11);
+        let wa = super::super::Wavelet::from_slice(a.as_slice(), new_bitvector);
+        let wb = super::super::Wavelet::from_slice(b.as_slice(), new_bitvector);
+        let wavelet = wa.concat(wb, new_bitvector);
+        let expected: Vec<u8> = vec!(0x10, 0x10, 0x11, 0x80, 0x11);
+        for (i, x) in expected.iter().enumerate() {
+            assert_eq!(wavelet.get(i), *x);
+        }
+        assert_eq!(wavelet.rank(0x11, 5), 2);
+    }
+}
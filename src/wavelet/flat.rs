@@ -0,0 +1,526 @@
+//! A pointerless wavelet tree
+
+use super::super::bits::BitIter;
+use super::super::dictionary::{Rank, Select, Access};
+use super::super::collection::Collection;
+use super::super::build::{Builder, Buildable};
+use super::super::int_vector::IntVector;
+use super::super::rank9::{self, Rank9};
+use super::super::tree::binary::Tree;
+use std::marker::PhantomData;
+use std::mem::swap;
+use super::{Wavelet, symbol_path, child};
+use super::super::serialize::{Serialize, Deserialize, write_uint, read_uint, invalid_data};
+use super::super::view::{View, take_uint};
+use super::super::frozen::{self, Freeze, Thaw, Region};
+use std::io::{self, Read, Write};
+
+/**
+A packed wavelet tree.
+
+Here the node bitvectors are packed into a single bitvector, removing
+the need for forwarding pointers. The tree is laid out level by level,
+each level holding one bit for every position of the sequence. Within
+a level the nodes appear in the order of their paths from the root
+and each node stores its bits in sequence order, so the extent of a
+node's children can be computed from ranks alone.
+*/
+pub struct FlatWavelet<BitV, Sym> {
+    /// the concatenated levels
+    bits: BitV,
+    /// length of the sequence
+    len: usize,
+    /// number of levels
+    levels: usize,
+    phantom: PhantomData<Sym>,
+}
+
+/// The extent `[start, end)` of a node within its level
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    level: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<Sym> FlatWavelet<Rank9, Sym> {
+    /// Flatten a pointer-based wavelet tree.
+    ///
+    /// The unary levels collapsed into the skips of the pointer-based
+    /// tree are expanded again as each level must hold a bit for
+    /// every position.
+    pub fn from_tree<BitV: Access<bool> + Rank<bool> + Collection>(wavelet: &Wavelet<BitV, Sym>)
+                                                                   -> FlatWavelet<Rank9, Sym> {
+        let len = wavelet.len;
+        let mut builder = rank9::Builder::with_capacity(len);
+        // each node along with the number of bits of its skip emitted
+        // and its number of positions
+        let mut level: Vec<(&Tree<super::Node<BitV>>, usize, usize)> = vec!((&wavelet.tree, 0, len));
+        let mut levels = 0;
+        // the final level consists of leaves with exhausted skips
+        while level.iter().any(|&(t, skipped, _)| skipped < t.value.skip.len() || t.value.bits.is_some()) {
+            let mut next = Vec::with_capacity(2 * level.len());
+            for &(node, skipped, n) in level.iter() {
+                if skipped < node.value.skip.len() {
+                    let bit = node.value.skip[skipped];
+                    for _ in 0..n {
+                        builder.push(bit);
+                    }
+                    next.push((node, skipped + 1, n));
+                    continue;
+                }
+                for bv in node.value.bits.iter() {
+                    for i in 0..n {
+                        builder.push(bv.get(i));
+                    }
+                    let zeros = bv.rank(false, n);
+                    next.push((child(node, false), 0, zeros));
+                    next.push((child(node, true), 0, n - zeros));
+                }
+            }
+            level = next;
+            levels += 1;
+        }
+
+        FlatWavelet {
+            bits: builder.finish(),
+            len,
+            levels,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Sym: BitIter + Clone> FlatWavelet<Rank9, Sym> {
+    /// Build a packed wavelet tree from a slice of symbols.
+    ///
+    /// The tree is written one level at a time directly into the
+    /// packed bitvector. Besides the output only two buffers of the
+    /// width of the tree per symbol are needed: one holding the
+    /// symbols in the order of the current level, into the other of
+    /// which they are stably partitioned to give the order of the
+    /// next level.
+    pub fn from_slice(symbols: &[Sym]) -> FlatWavelet<Rank9, Sym> {
+        let levels = match symbols.first() {
+            Some(sym) => sym.clone().bit_iter().count(),
+            None => 0,
+        };
+        FlatWavelet::from_slice_with_width(symbols, levels)
+    }
+
+    /// Build a packed wavelet tree of `width` levels encoding the
+    /// `width` least significant bits of each symbol. All symbols must
+    /// fit in this width.
+    pub fn from_slice_with_width(symbols: &[Sym], width: usize) -> FlatWavelet<Rank9, Sym> {
+        let len = symbols.len();
+        let levels = width;
+        assert!(levels <= 64, "from_slice: symbols wider than 64 bits");
+
+        let mut cur = IntVector::new(width, len);
+        for (i, sym) in symbols.iter().enumerate() {
+            match symbol_path(sym.clone(), width) {
+                None => panic!("from_slice: symbol exceeds width of {} bits", width),
+                Some(bits) => cur.set(i, pack_path(bits)),
+            }
+        }
+        let mut next = IntVector::new(width, len);
+        let mut builder = rank9::Builder::with_capacity(len * levels);
+        for level in 0..levels {
+            // the bits determining a symbol's node on this level
+            let path_mask: u64 = (1 << level) - 1;
+            let mut start = 0;
+            while start < len {
+                // the node's symbols are those sharing its path
+                let path = cur.get(start) & path_mask;
+                let mut end = start;
+                let mut zeros = 0;
+                while end < len && cur.get(end) & path_mask == path {
+                    let bit = (cur.get(end) >> level) & 1 == 1;
+                    builder.push(bit);
+                    if !bit {
+                        zeros += 1;
+                    }
+                    end += 1;
+                }
+
+                let mut left = start;
+                let mut right = start + zeros;
+                for i in start..end {
+                    let code = cur.get(i);
+                    if (code >> level) & 1 == 0 {
+                        next.set(left, code);
+                        left += 1;
+                    } else {
+                        next.set(right, code);
+                        right += 1;
+                    }
+                }
+                start = end;
+            }
+            swap(&mut cur, &mut next);
+        }
+
+        FlatWavelet {
+            bits: builder.finish(),
+            len,
+            levels,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<BitV: Collection, Sym> FlatWavelet<BitV, Sym> {
+    /// A tree of `levels` levels over `len` symbols from its levels
+    /// concatenated in `bits`, as laid out by `from_slice_with_width`
+    pub fn from_levels(bits: BitV, len: usize, levels: usize) -> FlatWavelet<BitV, Sym> {
+        assert!(bits.len() == len * levels, "from_levels: {} bits for {} levels of {} symbols",
+                bits.len(), levels, len);
+        FlatWavelet { bits, len, levels, phantom: PhantomData }
+    }
+}
+
+impl<BitV, Sym> FlatWavelet<BitV, Sym> {
+    /// The concatenated levels, each of `len()` bits
+    pub fn bits(&self) -> &BitV {
+        &self.bits
+    }
+}
+
+impl<BitV: Rank<bool>, Sym> FlatWavelet<BitV, Sym> {
+    /// The number of levels in the tree
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    fn root(&self) -> Node {
+        Node { level: 0, start: 0, end: self.len }
+    }
+
+    /// The position of the first bit of `node` in `bits`
+    fn node_offset(&self, node: &Node) -> usize {
+        node.level * self.len + node.start
+    }
+
+    /// The number of `bit`s preceding the `n`th bit of `node`
+    fn node_rank(&self, node: &Node, bit: bool, n: usize) -> usize {
+        let offset = self.node_offset(node);
+        self.bits.rank(bit, offset + n) - self.bits.rank(bit, offset)
+    }
+
+    /// The child of `node` reached by following `bit`
+    fn child(&self, node: &Node, bit: bool) -> Node {
+        let zeros = self.node_rank(node, false, node.end - node.start);
+        match bit {
+            false => Node { level: node.level + 1, start: node.start, end: node.start + zeros },
+            true => Node { level: node.level + 1, start: node.start + zeros, end: node.end },
+        }
+    }
+}
+
+/// The bits of a path packed into a word, the `i`th bit of the word
+/// being the bit taken at depth `i`
+fn pack_path<I: Iterator<Item=bool>>(bits: I) -> u64 {
+    let mut code = 0;
+    for (i, bit) in bits.enumerate() {
+        code |= (bit as u64) << i;
+    }
+    code
+}
+
+/// The codes of the leaves below a node at `level` of a tree with
+/// `levels` levels reached by `prefix`, as an inclusive range. A
+/// symbol's code is the value of its `levels` least significant bits.
+fn code_range(prefix: u64, level: usize, levels: usize) -> (u64, u64) {
+    let shift = levels - level;
+    let (min, mask) = match shift {
+        64 => (0, u64::MAX),
+        _ => (prefix << shift, (1 << shift) - 1),
+    };
+    (min, min | mask)
+}
+
+impl<BitV: Rank<bool>, Sym> FlatWavelet<BitV, Sym> {
+    /// The number of positions in `[i, j)` whose codes lie in
+    /// `[lo, hi)`, a symbol's code being the value of its `levels()`
+    /// least significant bits
+    pub fn range_count(&self, i: usize, j: usize, lo: u64, hi: u64) -> usize {
+        if hi == 0 {
+            return 0;
+        }
+        self.range_count_node(&self.root(), 0, i, j, lo, hi - 1)
+    }
+
+    fn range_count_node(&self, node: &Node, prefix: u64, i: usize, j: usize, lo: u64, hi: u64) -> usize {
+        if i == j {
+            return 0;
+        }
+        let (min, max) = code_range(prefix, node.level, self.levels);
+        if max < lo || hi < min {
+            return 0;
+        }
+        if lo <= min && max <= hi {
+            return j - i;
+        }
+
+        let mut count = 0;
+        for &bit in [false, true].iter() {
+            let ci = self.node_rank(node, bit, i);
+            let cj = self.node_rank(node, bit, j);
+            let child = self.child(node, bit);
+            count += self.range_count_node(&child, (prefix << 1) | bit as u64, ci, cj, lo, hi);
+        }
+        count
+    }
+}
+
+impl<BitV: Rank<bool> + Select<bool>, Sym> FlatWavelet<BitV, Sym> {
+    /// The positions in `[i, j)` whose codes lie in `[lo, hi)` along
+    /// with their codes, in increasing order of position
+    pub fn range_report(&self, i: usize, j: usize, lo: u64, hi: u64) -> Vec<(usize, u64)> {
+        let mut out = Vec::new();
+        if hi > 0 {
+            self.range_report_node(&mut Vec::new(), &self.root(), 0, i, j, lo, hi - 1, &mut out);
+        }
+        out.sort();
+        out
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn range_report_node(&self, path: &mut Vec<(bool, Node)>, node: &Node, prefix: u64,
+                         i: usize, j: usize, lo: u64, hi: u64, out: &mut Vec<(usize, u64)>) {
+        if i == j {
+            return;
+        }
+        let (min, max) = code_range(prefix, node.level, self.levels);
+        if max < lo || hi < min {
+            return;
+        }
+        if node.level == self.levels {
+            for k in i..j {
+                out.push((self.to_root(path.as_slice(), k), prefix));
+            }
+            return;
+        }
+
+        for &bit in [false, true].iter() {
+            let ci = self.node_rank(node, bit, i);
+            let cj = self.node_rank(node, bit, j);
+            let child = self.child(node, bit);
+            path.push((bit, *node));
+            self.range_report_node(path, &child, (prefix << 1) | bit as u64, ci, cj, lo, hi, out);
+            path.pop();
+        }
+    }
+
+    /// Map the `n`th position of the node below `path` to its
+    /// position in the sequence
+    fn to_root(&self, path: &[(bool, Node)], mut n: usize) -> usize {
+        for &(bit, ref node) in path.iter().rev() {
+            let offset = self.node_offset(node);
+            let preceding = self.bits.rank(bit, offset);
+            n = (self.bits.select(bit, preceding + n + 1) - offset) - 1;
+        }
+        n
+    }
+}
+
+impl<BitV: Serialize, Sym> Serialize for FlatWavelet<BitV, Sym> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.len)?;
+        write_uint(w, self.levels)?;
+        self.bits.serialize(w)
+    }
+}
+
+impl<BitV: Deserialize + Collection, Sym> Deserialize for FlatWavelet<BitV, Sym> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<FlatWavelet<BitV, Sym>> {
+        let len = read_uint(r)?;
+        let levels = read_uint(r)?;
+        let bits: BitV = Deserialize::deserialize(r)?;
+        if len.checked_mul(levels) != Some(bits.len()) {
+            return Err(invalid_data("FlatWavelet levels inconsistent with its length"));
+        }
+        Ok(FlatWavelet { bits, len, levels, phantom: PhantomData })
+    }
+}
+
+/// Viewing the levels in place, as `FlatWavelet<Rank9Ref, Sym>`
+impl<'a, BitV: View<'a> + Collection, Sym> View<'a> for FlatWavelet<BitV, Sym> {
+    fn view(words: &mut &'a [u64]) -> io::Result<FlatWavelet<BitV, Sym>> {
+        let len = take_uint(words)?;
+        let levels = take_uint(words)?;
+        let bits: BitV = View::view(words)?;
+        if len.checked_mul(levels) != Some(bits.len()) {
+            return Err(invalid_data("FlatWavelet levels inconsistent with its length"));
+        }
+        Ok(FlatWavelet { bits, len, levels, phantom: PhantomData })
+    }
+}
+
+/// The length and number of levels, then the levels
+impl<BitV: Freeze, Sym> Freeze for FlatWavelet<BitV, Sym> {
+    fn freeze(&self, out: &mut Vec<u64>) {
+        let mut region = Region::new(out, 3);
+        region.set(0, self.len as u64);
+        region.set(1, self.levels as u64);
+        region.child(2, &self.bits);
+        region.finish()
+    }
+}
+
+/// Querying the levels in place, as `FlatWavelet<Rank9Ref, Sym>`
+impl<'a, BitV: Thaw<'a> + Collection, Sym> Thaw<'a> for FlatWavelet<BitV, Sym> {
+    fn thaw(region: &'a [u64]) -> io::Result<FlatWavelet<BitV, Sym>> {
+        let len = frozen::uint_field(region, 0)?;
+        let levels = frozen::uint_field(region, 1)?;
+        let bits: BitV = frozen::child(region, 2)?;
+        if len.checked_mul(levels) != Some(bits.len()) {
+            return Err(invalid_data("FlatWavelet levels inconsistent with its length"));
+        }
+        Ok(FlatWavelet { bits, len, levels, phantom: PhantomData })
+    }
+}
+
+impl<BitV, Sym> Collection for FlatWavelet<BitV, Sym> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<BitV: Rank<bool> + Access<bool>, Sym: Buildable<bool>> Access<Sym> for FlatWavelet<BitV, Sym> {
+    fn get(&self, mut n: usize) -> Sym {
+        let mut builder = <Sym as Buildable<bool>>::new_builder();
+        let mut node = self.root();
+        for _ in 0..self.levels {
+            let bit = self.bits.get(self.node_offset(&node) + n);
+            builder.push(bit);
+            n = self.node_rank(&node, bit, n);
+            node = self.child(&node, bit);
+        }
+        builder.finish()
+    }
+}
+
+impl<BitV: Rank<bool>, Sym: BitIter> Rank<Sym> for FlatWavelet<BitV, Sym> {
+    fn rank(&self, sym: Sym, n: usize) -> usize {
+        let mut node = self.root();
+        let mut n = n;
+        let bits = match symbol_path(sym, self.levels) {
+            // symbols wider than the tree don't occur
+            None => return 0,
+            Some(bits) => bits,
+        };
+        for bit in bits {
+            n = self.node_rank(&node, bit, n);
+            node = self.child(&node, bit);
+        }
+        n
+    }
+}
+
+impl<BitV: Rank<bool> + Select<bool>, Sym: BitIter> Select<Sym> for FlatWavelet<BitV, Sym> {
+    fn select(&self, sym: Sym, n: usize) -> usize {
+        if n == 0 { return 0; }
+        let mut path: Vec<(bool, Node)> = Vec::with_capacity(self.levels);
+        let mut node = self.root();
+        let bits = match symbol_path(sym, self.levels) {
+            None => panic!("select: symbol does not occur"),
+            Some(bits) => bits,
+        };
+        for bit in bits {
+            path.push((bit, node));
+            node = self.child(&node, bit);
+        }
+
+        let mut n = n;
+        for &(bit, ref node) in path.iter().rev() {
+            let offset = self.node_offset(node);
+            let preceding = self.bits.rank(bit, offset);
+            n = self.bits.select(bit, preceding + n) - offset;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::FlatWavelet;
+    use super::super::super::dictionary::{Rank, Select, Access};
+    use super::super::super::build::Builder;
+    use super::super::super::bit_vector;
+
+    fn new_bitvector() -> bit_vector::Builder {
+        bit_vector::Builder::with_capacity(128)
+    }
+
+    #[quickcheck]
+    fn rank_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        if n > v.len() {
+            return TestResult::discard()
+        }
+
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let flat = FlatWavelet::from_tree(&wavelet);
+        TestResult::from_bool(flat.rank(el, n) == v.rank(el, n))
+    }
+
+    #[quickcheck]
+    fn select_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        if v.iter().filter(|x| *x == &el).count() < n {
+            return TestResult::discard()
+        }
+
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let flat = FlatWavelet::from_tree(&wavelet);
+        TestResult::from_bool(flat.select(el, n) == v.select(el, n))
+    }
+
+    #[quickcheck]
+    fn access_is_correct(v: Vec<u8>) -> bool {
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let flat: FlatWavelet<_, u8> = FlatWavelet::from_tree(&wavelet);
+        v.iter().enumerate().all(|(i, x)| flat.get(i) == *x)
+    }
+
+    #[quickcheck]
+    fn from_slice_matches_tree(v: Vec<u8>) -> bool {
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let flat: FlatWavelet<_, u8> = FlatWavelet::from_tree(&wavelet);
+        let direct: FlatWavelet<_, u8> = FlatWavelet::from_slice(v.as_slice());
+        (0..v.len()).all(|i| direct.get(i) == v[i])
+            && (0..256).all(|c| direct.rank(c as u8, v.len()) == flat.rank(c as u8, v.len()))
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u8>) -> bool {
+        use super::super::super::serialize::test::round_trip;
+        let flat: FlatWavelet<_, u8> = FlatWavelet::from_slice(v.as_slice());
+        let flat2: FlatWavelet<_, u8> = round_trip(&flat);
+        (0..v.len()).all(|i| flat2.get(i) == v[i])
+    }
+
+    #[test]
+    pub fn test_select() {
+        let v: Vec<u8> = vec!(4, 6, 2, 7, 5, 1, 6, 2);
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let flat = FlatWavelet::from_tree(&wavelet);
+        assert_eq!(flat.levels(), 8);
+        assert_eq!(flat.select(2, 2), 8);
+        assert_eq!(flat.rank(6, 7), 2);
+    }
+
+    #[test]
+    fn test_deserialize_overflow() {
+        use std::io::Cursor;
+        use super::super::super::rank9::Rank9;
+        use super::super::super::serialize::{Serialize, Deserialize, write_u64};
+        // a length and number of levels whose product wraps to that of
+        // no bits
+        let mut buf: Vec<u8> = Vec::new();
+        write_u64(&mut buf, 1 << 63).unwrap();
+        write_u64(&mut buf, 2).unwrap();
+        Rank9::from_fn(0, |_| false).serialize(&mut buf).unwrap();
+        assert!(<FlatWavelet<Rank9, u8> as Deserialize>::deserialize(&mut Cursor::new(buf)).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+//! Wavelet trees of bytes with a Huffman shape
+//
+// The shape of the tree is that of a Huffman code for the frequencies
+// of the bytes, so a byte occurring `f` times in a sequence of `n`
+// sits at depth about `log(n / f)` and the tree takes about `H0` bits
+// per byte. Unlike an alphabetic tree its leaves are not in byte
+// order, so only `get`, `rank` and `select` are answered.
+//
+// As in `FlatWavelet` the node bitvectors are packed into a single
+// bitvector, here in breadth-first order of the nodes. The `k`th inner
+// node in this order has the nodes `2k + 1` and `2k + 2` as its
+// children, and its bits start where those of the inner nodes before
+// it end. This is the layout of the `wt_huff` of sdsl-lite, which
+// `sdsl` reads and writes.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use super::super::dictionary::{Rank, Select, Access};
+use super::super::collection::Collection;
+use super::super::build::Builder;
+use super::super::bit_vector;
+use super::super::rank9::{self, Rank9};
+use super::super::serialize::invalid_data;
+use std::io;
+
+/// A node of the tree
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// An inner node with the children reached by a zero and a one
+    Inner(usize, usize),
+    /// The leaf of a byte
+    Leaf(u8),
+}
+
+/// Where the bits of a node lie
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    shape: Shape,
+    /// the position of its first bit
+    offset: usize,
+    /// the number of ones before its first bit
+    ones: usize,
+    /// the number of positions below it
+    len: usize,
+    parent: Option<usize>,
+}
+
+/// A Huffman-shaped wavelet tree of bytes
+pub struct HuffmanWavelet<BitV> {
+    /// the bits of the inner nodes in breadth-first order
+    bits: BitV,
+    len: usize,
+    nodes: Vec<Node>,
+    /// the leaf of each byte occurring
+    leaves: Vec<Option<usize>>,
+}
+
+impl HuffmanWavelet<Rank9> {
+    /// Build the tree of `bytes`
+    pub fn from_slice(bytes: &[u8]) -> HuffmanWavelet<Rank9> {
+        let mut freqs: Vec<usize> = (0..256).map(|_| 0).collect();
+        for b in bytes.iter() {
+            freqs[*b as usize] += 1;
+        }
+
+        // the Huffman tree, leaves first, merging the two least
+        // frequent nodes in turn
+        let mut children: Vec<Option<(usize, usize)>> = Vec::new();
+        let mut syms: Vec<u8> = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (b, f) in freqs.iter().enumerate() {
+            if *f > 0 {
+                heap.push(Reverse((*f, children.len())));
+                children.push(None);
+                syms.push(b as u8);
+            }
+        }
+        while heap.len() > 1 {
+            let Reverse((fa, a)) = heap.pop().unwrap();
+            let Reverse((fb, b)) = heap.pop().unwrap();
+            heap.push(Reverse((fa + fb, children.len())));
+            children.push(Some((a, b)));
+        }
+
+        // number the nodes breadth-first
+        let mut shapes: Vec<Shape> = Vec::with_capacity(children.len());
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        if let Some(root) = heap.pop() {
+            let Reverse((_, root)) = root;
+            queue.push_back(root);
+        }
+        let mut next = 1;
+        while let Some(v) = queue.pop_front() {
+            match children[v] {
+                None => shapes.push(Shape::Leaf(syms[v])),
+                Some((a, b)) => {
+                    shapes.push(Shape::Inner(next, next + 1));
+                    queue.push_back(a);
+                    queue.push_back(b);
+                    next += 2;
+                }
+            }
+        }
+
+        // the path of each byte, the first bit being taken at the root
+        let mut paths: Vec<Vec<bool>> = (0..256).map(|_| Vec::new()).collect();
+        let mut prefixes: Vec<Vec<bool>> = vec!(Vec::new());
+        for (v, shape) in shapes.iter().enumerate() {
+            let prefix = prefixes[v].clone();
+            match *shape {
+                Shape::Leaf(b) => paths[b as usize] = prefix,
+                Shape::Inner(..) => for bit in [false, true].iter() {
+                    let mut path = prefix.clone();
+                    path.push(*bit);
+                    prefixes.push(path);
+                },
+            }
+        }
+
+        // fill the bits of the inner nodes, the `k`th inner node being
+        // the parent of nodes `2k + 1` and `2k + 2`
+        let mut inner_index: Vec<usize> = Vec::with_capacity(shapes.len());
+        let mut inner = 0;
+        for shape in shapes.iter() {
+            inner_index.push(inner);
+            if let Shape::Inner(..) = *shape {
+                inner += 1;
+            }
+        }
+        let mut builders: Vec<bit_vector::Builder> = (0..inner).map(|_| bit_vector::Builder::with_capacity(0)).collect();
+        for b in bytes.iter() {
+            let mut k = 0;
+            for bit in paths[*b as usize].iter() {
+                builders[k].push(*bit);
+                k = inner_index[2 * k + 1 + *bit as usize];
+            }
+        }
+        let mut bits = rank9::Builder::with_capacity(0);
+        for builder in builders.into_iter() {
+            for bit in builder.finish().iter() {
+                bits.push(bit);
+            }
+        }
+        HuffmanWavelet::from_parts(bits.finish(), bytes.len(), shapes)
+            .expect("HuffmanWavelet: inconsistent shape")
+    }
+}
+
+impl<BitV: Rank<bool> + Collection> HuffmanWavelet<BitV> {
+    /// A tree of `len` bytes from the bits of its inner nodes and the
+    /// shapes of its nodes, both in breadth-first order, failing if
+    /// they are inconsistent
+    pub fn from_parts(bits: BitV, len: usize, shapes: Vec<Shape>) -> io::Result<HuffmanWavelet<BitV>> {
+        if shapes.is_empty() != (len == 0) {
+            return Err(invalid_data("HuffmanWavelet: nodes inconsistent with its length"));
+        }
+        let mut nodes: Vec<Node> = shapes.iter().map(|shape| {
+            Node { shape: *shape, offset: 0, ones: 0, len: 0, parent: None }
+        }).collect();
+        let mut leaves: Vec<Option<usize>> = (0..256).map(|_| None).collect();
+        let mut offset = 0;
+        let mut inner = 0;
+        for v in 0..nodes.len() {
+            let node_len = match v {
+                0 => len,
+                _ => match nodes[v].parent {
+                    None => return Err(invalid_data("HuffmanWavelet: node unreachable from the root")),
+                    Some(_) => nodes[v].len,
+                },
+            };
+            nodes[v].offset = offset;
+            nodes[v].len = node_len;
+            match nodes[v].shape {
+                Shape::Leaf(b) => {
+                    if leaves[b as usize].is_some() {
+                        return Err(invalid_data("HuffmanWavelet: byte with two leaves"));
+                    }
+                    leaves[b as usize] = Some(v);
+                },
+                Shape::Inner(zero, one) => {
+                    if zero != 2 * inner + 1 || one != 2 * inner + 2 || one >= nodes.len() {
+                        return Err(invalid_data("HuffmanWavelet: nodes not in breadth-first order"));
+                    }
+                    match offset.checked_add(node_len) {
+                        Some(end) if end <= bits.len() => {},
+                        _ => return Err(invalid_data("HuffmanWavelet: nodes exceed its bits")),
+                    }
+                    let ones_before = bits.rank(true, offset);
+                    let ones = bits.rank(true, offset + node_len) - ones_before;
+                    nodes[v].ones = ones_before;
+                    nodes[zero].parent = Some(v);
+                    nodes[zero].len = node_len - ones;
+                    nodes[one].parent = Some(v);
+                    nodes[one].len = ones;
+                    offset += node_len;
+                    inner += 1;
+                },
+            }
+        }
+        if offset != bits.len() {
+            return Err(invalid_data("HuffmanWavelet: bits exceed its nodes"));
+        }
+        Ok(HuffmanWavelet { bits, len, nodes, leaves })
+    }
+}
+
+impl<BitV> HuffmanWavelet<BitV> {
+    /// The bits of the inner nodes in breadth-first order
+    pub fn bits(&self) -> &BitV {
+        &self.bits
+    }
+
+    /// The shapes of the nodes in breadth-first order
+    pub fn shapes(&self) -> Vec<Shape> {
+        self.nodes.iter().map(|node| node.shape).collect()
+    }
+
+    /// The position of the first bit of node `v` among the bits and
+    /// the number of ones before it
+    pub fn node_offset(&self, v: usize) -> (usize, usize) {
+        (self.nodes[v].offset, self.nodes[v].ones)
+    }
+
+    /// The number of `bit`s before the bits of `node`
+    fn before(node: &Node, bit: bool) -> usize {
+        match bit {
+            true => node.ones,
+            false => node.offset - node.ones,
+        }
+    }
+
+    /// The path from the root to the leaf of `sym`, the first bit
+    /// being taken at the root
+    pub fn path(&self, sym: u8) -> Option<Vec<bool>> {
+        let mut v = self.leaves[sym as usize]?;
+        let mut path = Vec::new();
+        while let Some(p) = self.nodes[v].parent {
+            path.push(self.nodes[p].shape == Shape::Inner(v - 1, v));
+            v = p;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+impl<BitV> Collection for HuffmanWavelet<BitV> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<BitV: Rank<bool> + Access<bool>> Access<u8> for HuffmanWavelet<BitV> {
+    fn get(&self, mut n: usize) -> u8 {
+        assert!(n < self.len, "HuffmanWavelet: index {} out of bounds", n);
+        let mut node = &self.nodes[0];
+        loop {
+            match node.shape {
+                Shape::Leaf(b) => return b,
+                Shape::Inner(zero, one) => {
+                    let bit = self.bits.get(node.offset + n);
+                    n = self.bits.rank(bit, node.offset + n) - HuffmanWavelet::<BitV>::before(node, bit);
+                    node = &self.nodes[if bit { one } else { zero }];
+                },
+            }
+        }
+    }
+}
+
+impl<BitV: Rank<bool>> Rank<u8> for HuffmanWavelet<BitV> {
+    fn rank(&self, sym: u8, mut n: usize) -> usize {
+        let path = match self.path(sym) {
+            None => return 0,
+            Some(path) => path,
+        };
+        let mut node = &self.nodes[0];
+        for bit in path.into_iter() {
+            n = self.bits.rank(bit, node.offset + n) - HuffmanWavelet::<BitV>::before(node, bit);
+            node = match node.shape {
+                Shape::Inner(zero, one) => &self.nodes[if bit { one } else { zero }],
+                Shape::Leaf(_) => unreachable!(),
+            };
+        }
+        n
+    }
+}
+
+impl<BitV: Select<bool>> Select<u8> for HuffmanWavelet<BitV> {
+    fn select(&self, sym: u8, mut n: usize) -> usize {
+        if n == 0 { return 0; }
+        let mut v = match self.leaves[sym as usize] {
+            None => panic!("select: symbol does not occur"),
+            Some(v) => v,
+        };
+        while let Some(p) = self.nodes[v].parent {
+            let node = &self.nodes[p];
+            let bit = node.shape == Shape::Inner(v - 1, v);
+            n = self.bits.select(bit, HuffmanWavelet::<BitV>::before(node, bit) + n) - node.offset;
+            v = p;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HuffmanWavelet, Shape};
+    use super::super::super::dictionary::{Access, Rank, Select};
+    use super::super::super::collection::Collection;
+    use super::super::super::naive;
+    use super::super::super::rank9::Rank9;
+
+    #[quickcheck]
+    fn matches_naive(v: Vec<u8>) -> bool {
+        let wavelet = HuffmanWavelet::from_slice(v.as_slice());
+        let seq = naive::Sequence::new(v.clone());
+        wavelet.len() == v.len()
+            && (0..v.len()).all(|i| wavelet.get(i) == seq.get(i))
+            && v.iter().all(|&x| (0..v.len() + 1).all(|n| {
+                let k = seq.rank(x, n);
+                wavelet.rank(x, n) == k && wavelet.select(x, k) == seq.select(x, k)
+            }))
+    }
+
+    #[quickcheck]
+    fn from_parts_round_trips(v: Vec<u8>) -> bool {
+        let wavelet = HuffmanWavelet::from_slice(v.as_slice());
+        let parts = HuffmanWavelet::from_parts(wavelet.bits().clone(), v.len(), wavelet.shapes()).unwrap();
+        (0..v.len()).all(|i| parts.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_shape() {
+        // a occurs five times and sits below the root
+        let wavelet = HuffmanWavelet::from_slice(b"abracadabra");
+        assert_eq!(wavelet.path(b'a'), Some(vec!(false)));
+        assert_eq!(wavelet.path(b'c'), Some(vec!(true, false, false)));
+        assert_eq!(wavelet.path(b'z'), None);
+        assert_eq!(wavelet.rank(b'a', 11), 5);
+        assert_eq!(wavelet.select(b'r', 2), 10);
+        assert_eq!(wavelet.shapes().len(), 9);
+
+        let single = HuffmanWavelet::from_slice(b"zzz");
+        assert_eq!(single.shapes(), vec!(Shape::Leaf(b'z')));
+        assert_eq!(single.rank(b'z', 2), 2);
+        assert_eq!(single.select(b'z', 3), 3);
+
+        // children out of breadth-first order
+        let bits = Rank9::from_vec(&[0b10], 2).unwrap();
+        let shapes = vec!(Shape::Inner(2, 1), Shape::Leaf(b'a'), Shape::Leaf(b'b'));
+        assert!(HuffmanWavelet::from_parts(bits.clone(), 2, shapes).is_err());
+        let shapes = vec!(Shape::Inner(1, 2), Shape::Leaf(b'a'), Shape::Leaf(b'a'));
+        assert!(HuffmanWavelet::from_parts(bits, 2, shapes).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Iteration over the sequence stored in a wavelet tree
+
+use std::iter::IntoIterator;
+use std::cmp::min;
+use super::super::dictionary::{Rank, Select, Access};
+use super::super::bits::BitIter;
+use super::super::collection::Collection;
+use super::super::build::{Builder, Buildable};
+use super::super::tree::binary::Tree;
+use super::{Wavelet, Node, child, build_symbol, symbol_path, interior_path};
+
+/// The number of positions decoded at once by `Iter`
+const BATCH_SIZE: usize = 1024;
+
+/// An iterator over the symbols of a `Wavelet` in sequence order.
+///
+/// Rather than accessing each position separately the iterator
+/// decodes a batch of consecutive positions at a time, walking each
+/// node of the tree once per batch and reading its bits sequentially.
+pub struct Iter<'a, BitV: 'a, Sym> {
+    wavelet: &'a Wavelet<BitV, Sym>,
+    /// the position of the next batch to decode
+    pos: usize,
+    /// the remainder of the current batch, in reverse order
+    batch: Vec<Sym>,
+}
+
+impl<BitV: Collection, Sym> Wavelet<BitV, Sym> {
+    /// An iterator over the symbols of the sequence
+    pub fn iter<'a>(&'a self) -> Iter<'a, BitV, Sym> {
+        Iter {
+            wavelet: self,
+            pos: 0,
+            batch: Vec::new(),
+        }
+    }
+}
+
+/// Push the bits of the positions `[start, start + idxs.len())` of
+/// `node` and its descendents to the builders of the symbols
+/// `idxs`.
+fn decode_node<BitV, Sym>(node: &Tree<Node<BitV>>, start: usize, idxs: &[usize],
+                          builders: &mut Vec<<Sym as Buildable<bool>>::Builder>)
+    where BitV: Rank<bool> + Access<bool>, Sym: Buildable<bool>
+{
+    for &idx in idxs.iter() {
+        for bit in node.value.skip.iter() {
+            builders[idx].push(*bit);
+        }
+    }
+    let bv = match node.value.bits {
+        Some(ref bv) if !idxs.is_empty() => bv,
+        _ => return,
+    };
+
+    let mut left: Vec<usize> = Vec::with_capacity(idxs.len());
+    let mut right: Vec<usize> = Vec::with_capacity(idxs.len());
+    for (k, &idx) in idxs.iter().enumerate() {
+        let bit = bv.get(start + k);
+        builders[idx].push(bit);
+        if bit {
+            right.push(idx);
+        } else {
+            left.push(idx);
+        }
+    }
+
+    let left_start = bv.rank(false, start);
+    decode_node::<BitV, Sym>(child(node, false), left_start, left.as_slice(), builders);
+    let right_start = bv.rank(true, start);
+    decode_node::<BitV, Sym>(child(node, true), right_start, right.as_slice(), builders);
+}
+
+/// Decode the symbols at positions `[start, end)`
+pub fn decode_range<BitV, Sym>(wavelet: &Wavelet<BitV, Sym>, start: usize, end: usize) -> Vec<Sym>
+    where BitV: Rank<bool> + Access<bool>, Sym: Buildable<bool>
+{
+    let idxs: Vec<usize> = (0..end - start).collect();
+    let mut builders: Vec<<Sym as Buildable<bool>>::Builder> =
+        idxs.iter().map(|_| <Sym as Buildable<bool>>::new_builder()).collect();
+    decode_node::<BitV, Sym>(&wavelet.tree, start, idxs.as_slice(), &mut builders);
+    builders.into_iter().map(|b| b.finish()).collect()
+}
+
+impl<'a, BitV, Sym> Iterator for Iter<'a, BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool>
+{
+    type Item = Sym;
+    fn next(&mut self) -> Option<Sym> {
+        if self.batch.is_empty() {
+            let len = self.wavelet.len();
+            if self.pos >= len {
+                return None;
+            }
+            let end = if self.pos + BATCH_SIZE < len { self.pos + BATCH_SIZE } else { len };
+            self.batch = decode_range(self.wavelet, self.pos, end);
+            self.batch.reverse();
+            self.pos = end;
+        }
+        self.batch.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.wavelet.len() - self.pos + self.batch.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, BitV, Sym> ExactSizeIterator for Iter<'a, BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool> {}
+
+/// An iterator over the symbols of a range of positions of a
+/// `Wavelet` in non-decreasing order.
+///
+/// This performs an in-order traversal of the part of the tree
+/// through which the range passes, yielding each symbol as many times
+/// as it occurs in the range once its leaf is reached.
+#[allow(clippy::type_complexity)]
+pub struct SortedIter<'a, BitV: 'a, Sym> {
+    /// the nodes yet to be visited along with the range of positions
+    /// within them and their path from the root
+    stack: Vec<(&'a Tree<Node<BitV>>, usize, usize, Vec<bool>)>,
+    /// the symbol of the current leaf
+    current: Option<Sym>,
+    /// the number of occurrences of `current` yet to be produced
+    remaining: usize,
+}
+
+impl<BitV, Sym> Wavelet<BitV, Sym> {
+    /// An iterator over the symbols of positions `[i, j)` in
+    /// non-decreasing order
+    pub fn iter_sorted<'a>(&'a self, i: usize, j: usize) -> SortedIter<'a, BitV, Sym> {
+        SortedIter {
+            stack: vec!((&self.tree, i, j, Vec::new())),
+            current: None,
+            remaining: 0,
+        }
+    }
+}
+
+impl<'a, BitV, Sym> Iterator for SortedIter<'a, BitV, Sym>
+    where BitV: Rank<bool>, Sym: Buildable<bool> + Clone
+{
+    type Item = Sym;
+    fn next(&mut self) -> Option<Sym> {
+        loop {
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                return self.current.clone();
+            }
+
+            let (node, i, j, mut path) = self.stack.pop()?;
+            if i == j {
+                continue;
+            }
+            path.extend_from_slice(node.value.skip.as_slice());
+            let bv = match node.value.bits {
+                None => {
+                    self.current = Some(build_symbol(path.as_slice()));
+                    self.remaining = j - i;
+                    continue;
+                },
+                Some(ref bv) => bv,
+            };
+
+            // push the right child first so that the left is visited first
+            for &bit in [true, false].iter() {
+                let ci = bv.rank(bit, i);
+                let cj = bv.rank(bit, j);
+                let mut child_path = path.clone();
+                child_path.push(bit);
+                self.stack.push((child(node, bit), ci, cj, child_path));
+            }
+        }
+    }
+}
+
+/// The number of bits scanned for the next occurrence of a bit in a
+/// node before resorting to `select`
+const SCAN_LIMIT: usize = 64;
+
+/// An iterator over the positions of the occurrences of a symbol in
+/// increasing order.
+///
+/// The occurrences are the consecutive positions of the symbol's leaf.
+/// These are mapped up the tree a batch at a time, each level mapping
+/// the whole batch before the next. As consecutive occurrences are
+/// usually close together in each node, each is found by scanning
+/// forward from the previous one, falling back to `select` only when
+/// this fails.
+pub struct Occurrences<'a, BitV: 'a> {
+    /// the interior nodes on the symbol's path from the root and the
+    /// branch taken from each
+    path: Vec<(bool, &'a BitV)>,
+    /// the number of occurrences batched so far
+    batched: usize,
+    /// the number of occurrences of the symbol
+    count: usize,
+    /// the remainder of the current batch, in reverse order
+    batch: Vec<usize>,
+}
+
+impl<BitV: Rank<bool>, Sym: BitIter> Wavelet<BitV, Sym> {
+    /// An iterator over the positions of `sym` in increasing order
+    pub fn occurrences<'a>(&'a self, sym: Sym) -> Occurrences<'a, BitV> {
+        let path = symbol_path(sym, self.width).and_then(|bits| interior_path(&self.tree, bits));
+        let (path, count) = match path {
+            None => (Vec::new(), 0),
+            Some(path) => {
+                let count = path.iter().fold(self.len, |n, &(bit, bv)| bv.rank(bit, n));
+                (path, count)
+            },
+        };
+        Occurrences {
+            path,
+            batched: 0,
+            count,
+            batch: Vec::new(),
+        }
+    }
+}
+
+impl<'a, BitV: Access<bool> + Select<bool> + Collection> Occurrences<'a, BitV> {
+    /// Map the positions of consecutive occurrences in the leaf up to
+    /// the root
+    fn fill_batch(&mut self) {
+        let end = if self.batched + BATCH_SIZE < self.count { self.batched + BATCH_SIZE } else { self.count };
+        let mut positions: Vec<usize> = (self.batched..end).collect();
+        self.batched = end;
+        for &(bit, bv) in self.path.iter().rev() {
+            // the previous position in the child and its position here
+            let mut prev: Option<(usize, usize)> = None;
+            for pos in positions.iter_mut() {
+                let scanned = match prev {
+                    Some((child_pos, parent_pos)) if child_pos + 1 == *pos => {
+                        let limit = min(parent_pos + 1 + SCAN_LIMIT, bv.len());
+                        (parent_pos + 1..limit).find(|i| bv.get(*i) == bit)
+                    },
+                    _ => None,
+                };
+                let parent_pos = match scanned {
+                    Some(p) => p,
+                    None => bv.select(bit, *pos + 1) - 1,
+                };
+                prev = Some((*pos, parent_pos));
+                *pos = parent_pos;
+            }
+        }
+        positions.reverse();
+        self.batch = positions;
+    }
+}
+
+impl<'a, BitV: Access<bool> + Select<bool> + Collection> Iterator for Occurrences<'a, BitV> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.batch.is_empty() {
+            if self.batched >= self.count {
+                return None;
+            }
+            self.fill_batch();
+        }
+        self.batch.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.batched + self.batch.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, BitV: Access<bool> + Select<bool> + Collection> ExactSizeIterator for Occurrences<'a, BitV> {}
+
+impl<'a, BitV, Sym> IntoIterator for &'a Wavelet<BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool>
+{
+    type Item = Sym;
+    type IntoIter = Iter<'a, BitV, Sym>;
+    fn into_iter(self) -> Iter<'a, BitV, Sym> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::TestResult;
+    use super::super::super::build::Builder;
+    use super::super::super::rank9;
+
+    fn new_bitvector() -> rank9::Builder {
+        rank9::Builder::with_capacity(128)
+    }
+
+    #[quickcheck]
+    fn iter_round_trips(v: Vec<u8>) -> bool {
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        wavelet.iter().collect::<Vec<u8>>() == v
+    }
+
+    #[quickcheck]
+    fn iter_sorted_is_sorted(v: Vec<u8>, i: usize, j: usize) -> TestResult {
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let mut expected = v[i..j].to_vec();
+        expected.sort();
+        TestResult::from_bool(wavelet.iter_sorted(i, j).collect::<Vec<u8>>() == expected)
+    }
+
+    #[quickcheck]
+    fn occurrences_are_correct(el: u8, v: Vec<u8>) -> bool {
+        let wavelet = super::super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let expected: Vec<usize> = (0..v.len()).filter(|i| v[*i] == el).collect();
+        wavelet.occurrences(el).collect::<Vec<usize>>() == expected
+    }
+
+    #[test]
+    fn test_occurrences_batches() {
+        let v: Vec<u8> = (0..5000usize).map(|i| if i % 3 == 0 || i % 101 == 0 { 7 } else { (i % 13) as u8 }).collect();
+        let wavelet = super::super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let expected: Vec<usize> = (0..v.len()).filter(|i| v[*i] == 7).collect();
+        assert_eq!(wavelet.occurrences(7).len(), expected.len());
+        assert_eq!(wavelet.occurrences(7).collect::<Vec<usize>>(), expected);
+        assert_eq!(wavelet.occurrences(200).next(), None);
+    }
+
+    #[test]
+    fn test_iter_sorted_chars() {
+        let wavelet = super::super::Wavelet::from_str("wavelet", new_bitvector);
+        let sorted: String = wavelet.iter_sorted(1, 6).collect();
+        assert_eq!(sorted, "aeelv");
+    }
+
+    #[test]
+    fn test_iter_batches() {
+        let v: Vec<u16> = (0..3000).map(|i| ((i * 7919) % 1013) as u16).collect();
+        let wavelet = super::super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        assert_eq!(wavelet.iter().len(), v.len());
+        let mut n = 0;
+        for (a, b) in (&wavelet).into_iter().zip(v.iter()) {
+            assert_eq!(a, *b);
+            n += 1;
+        }
+        assert_eq!(n, v.len());
+    }
+}
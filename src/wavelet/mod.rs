@@ -1,12 +1,35 @@
 //! Wavelet trees
 
-use super::bits::{BitIter};
+use std::cmp::{max, min};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Index;
+use super::bits::{BitIter, bit_width};
 use super::dictionary::{Rank, Select, Access};
 use super::build;
+use super::build::{Buildable, TwoPass};
 use super::tree::binary;
 use super::tree::binary::{Tree};
 use super::tree::binary::Branch::{self, Left, Right};
 use super::collection::Collection;
+use super::digest::{ContentDigest, Digest};
+use std::hash::{Hash, Hasher};
+use super::serialize::{Serialize, Deserialize, write_u64, read_u64, write_uint, read_uint, invalid_data};
+use std::io::{self, Read, Write};
+
+pub use crate::wavelet::flat::FlatWavelet;
+pub use crate::wavelet::alphabetic::AlphabeticWavelet;
+pub use crate::wavelet::huffman::HuffmanWavelet;
+pub use crate::wavelet::iter::{Iter, SortedIter, Occurrences};
+pub use crate::wavelet::stats::{Stats, NodeStats};
+
+mod flat;
+mod iter;
+mod stats;
+mod concat;
+pub mod alphabetic;
+pub mod huffman;
 
 fn bit_to_branch(bit: bool) -> Branch {
     match bit {
@@ -15,9 +38,103 @@ fn bit_to_branch(bit: bool) -> Branch {
     }
 }
 
+/// The bits of `sym` giving its path from the root of a tree
+/// encoding `width` bits, or `None` if `sym` doesn't fit in `width`
+/// bits
+fn symbol_path<Sym: BitIter>(sym: Sym, width: usize) -> Option<<Sym as BitIter>::Iter> {
+    let mut bits = sym.bit_iter();
+    let extra = bits.len().saturating_sub(width);
+    if bits.by_ref().take(extra).any(|b| b) {
+        None
+    } else {
+        Some(bits)
+    }
+}
+
+/// The value of `sym`, its bits taken most significant first
+fn symbol_value<Sym: BitIter>(sym: Sym) -> u64 {
+    sym.bit_iter().fold(0, |value, bit| value << 1 | bit as u64)
+}
+
+/// A node of a path-compressed wavelet tree.
+///
+/// A node all of whose positions would take the same branch stores no
+/// bitvector. Instead such unary chains are collapsed into the `skip`
+/// of the node below them, so every node with a bitvector has both
+/// children and leaves have neither.
+#[derive(Clone)]
+struct Node<BitV> {
+    /// the path bits shared by all positions passing through the
+    /// node, taken before those of `bits`
+    skip: Vec<bool>,
+    /// the branch taken by each position passing through the node,
+    /// or `None` at leaves
+    bits: Option<BitV>,
+}
+
+impl<BitV> Node<BitV> {
+    fn leaf(skip: Vec<bool>) -> Node<BitV> {
+        Node { skip, bits: None }
+    }
+}
+
+/// The child of an interior node of a path-compressed tree
+fn child<T>(node: &Tree<T>, bit: bool) -> &Tree<T> {
+    match node.branch(bit_to_branch(bit)) {
+        Some(child) => child,
+        &None => panic!("wavelet tree interior node lacks a child"),
+    }
+}
+
+/// The value of the `n`th symbol of `tree`, read off its path
+fn path_value<BitV: Rank<bool> + Access<bool>>(tree: &Tree<Node<BitV>>, mut n: usize) -> u64 {
+    let mut value = 0;
+    let mut node = tree;
+    loop {
+        for bit in node.value.skip.iter() {
+            value = value << 1 | *bit as u64;
+        }
+        match node.value.bits {
+            None => return value,
+            Some(ref bv) => {
+                let bit = bv.get(n);
+                value = value << 1 | bit as u64;
+                n = bv.rank(bit, n);
+                node = child(node, bit);
+            }
+        }
+    }
+}
+
+/// Hash the values of the first `len` symbols of `tree`
+fn digest_symbols<BitV: Rank<bool> + Access<bool>>(tree: &Tree<Node<BitV>>, len: usize, digest: &mut Digest) {
+    for n in 0..len {
+        digest.update_u64(path_value(tree, n));
+    }
+}
+
 /// A wavelet tree over symbols of type `Sym`
+///
+/// Symbols are encoded most significant bit first so the leaves of
+/// the tree are in symbol order.
+#[derive(Clone)]
 pub struct Wavelet<BitV, Sym> {
-    tree: Tree<BitV>,
+    tree: Tree<Node<BitV>>,
+    /// the number of (least significant) bits of each symbol encoded
+    /// by the tree; any more significant bits must be zero
+    width: usize,
+    /// length of the sequence
+    len: usize,
+    /// the hash of the values of the symbols, see `content_digest`
+    digest: Digest,
+    phantom: PhantomData<Sym>,
+}
+
+impl<BitV, Sym> Wavelet<BitV, Sym> {
+    /// The number of bits of each symbol encoded by the tree
+    pub fn width(&self) -> usize {
+        self.width
+    }
 }
 
 impl<BitV: Rank<bool> + Access<bool>, Sym: BitIter> Wavelet<BitV, Sym> {
@@ -27,108 +144,769 @@ impl<BitV: Rank<bool> + Access<bool>, Sym: BitIter> Wavelet<BitV, Sym> {
     /// `wavelet.symbol_eq(sym, n)` is Functionally equivalent to
     /// `wavelet.access(n) == sym` but avoids traversing the entire
     /// depth of the tree in the unequal case.
-    pub fn symbol_eq(&self, sym: Sym, mut n: uint) -> bool {
-        let mut cursor = binary::Cursor::new(&self.tree);
-        for bit in sym.bit_iter() {
-            let branch = bit_to_branch(bit);
-            match cursor.branch(branch) {
-                &None => return false,
-                &Some(_) => if bit != cursor.value.get(n) {
+    pub fn symbol_eq(&self, sym: Sym, n: usize) -> bool {
+        match symbol_path(sym, self.width) {
+            None => false,
+            Some(bits) => self.path_has_prefix(bits, n),
+        }
+    }
+}
+
+/// The `len` bits of `prefix` from the most significant down
+fn prefix_path(prefix: u64, len: usize) -> Vec<bool> {
+    assert!(len <= 64, "prefix longer than 64 bits");
+    (0..len).rev().map(|k| (prefix >> k) & 1 == 1).collect()
+}
+
+impl<BitV: Rank<bool> + Access<bool>, Sym> Wavelet<BitV, Sym> {
+    /// Test whether the path of the symbol at the `n`th position
+    /// begins with the `len` least significant bits of `prefix`, most
+    /// significant first.
+    ///
+    /// The symbols sharing a prefix are those of a subtree, so this
+    /// tests membership of a whole class of symbols in one descent.
+    /// A prefix as long as the symbols themselves is equivalent to
+    /// `symbol_eq`.
+    pub fn symbol_has_prefix(&self, prefix: u64, len: usize, n: usize) -> bool {
+        self.path_has_prefix(prefix_path(prefix, len).into_iter(), n)
+    }
+
+    /// The number of positions before `i` whose symbols begin with
+    /// the `len` least significant bits of `prefix`, most significant
+    /// first
+    pub fn rank_prefix(&self, prefix: u64, len: usize, i: usize) -> usize {
+        self.rank_path(prefix_path(prefix, len).into_iter(), i)
+    }
+
+    /// Test whether the path of the `n`th position begins with `bits`
+    fn path_has_prefix<I: Iterator<Item=bool>>(&self, bits: I, mut n: usize) -> bool {
+        let mut node = &self.tree;
+        // the number of bits of the node's skip already matched
+        let mut skipped = 0;
+        for bit in bits {
+            if skipped < node.value.skip.len() {
+                if node.value.skip[skipped] != bit {
+                    return false;
+                }
+                skipped += 1;
+                continue;
+            }
+            match node.value.bits {
+                None => return false,
+                Some(ref bv) => if bit != bv.get(n) {
                     return false;
                 } else {
-                    n = cursor.value.rank(bit, n as int) as uint;
-                    cursor.step(branch);
+                    n = bv.rank(bit, n);
+                    node = child(node, bit);
+                    skipped = 0;
                 }
             }
         }
         true
     }
+
+    /// The number of positions before `i` whose paths begin with `bits`
+    fn rank_path<I: Iterator<Item=bool>>(&self, bits: I, mut i: usize) -> usize {
+        let mut node = &self.tree;
+        let mut skipped = 0;
+        for bit in bits {
+            if skipped < node.value.skip.len() {
+                if node.value.skip[skipped] != bit {
+                    return 0;
+                }
+                skipped += 1;
+                continue;
+            }
+            match node.value.bits {
+                None => return 0,
+                Some(ref bv) => {
+                    i = bv.rank(bit, i);
+                    node = child(node, bit);
+                    skipped = 0;
+                }
+            }
+        }
+        i
+    }
 }
 
 impl<BitV: Rank<bool> + Access<bool>, Sym: Ord> Wavelet<BitV, Sym> {
-    pub fn range_next_value(_i: uint, _j: uint, _sym: Sym) {
+    pub fn range_next_value(_i: usize, _j: usize, _sym: Sym) {
         // TODO: Implement me
     }
 }
 
-impl<BitV: Rank<bool> + Access<bool>, Sym> Wavelet<BitV, Sym> {
-    /// TODO: This needs to turn into an `Access` impl once
-    /// `Buildable` has an associated `Builder` type
-    pub fn access<SymBuilder: build::Builder<bool, Sym>>(&self, mut builder: SymBuilder, mut n: uint) -> Sym {
-        let mut cursor = binary::Cursor::new(&self.tree);
+impl<BitV: Rank<bool> + Access<bool>, Sym: Buildable<bool>> Access<Sym> for Wavelet<BitV, Sym> {
+    fn get(&self, mut n: usize) -> Sym {
+        use crate::build::Builder;
+        let mut builder = <Sym as Buildable<bool>>::new_builder();
+        let mut node = &self.tree;
         loop {
-            if cursor.branch(Left).is_none() {  // HACK: encode the leaf
-                break;
+            for bit in node.value.skip.iter() {
+                builder.push(*bit);
             }
-            let bit = cursor.value.get(n);
-            builder.push(bit);
-            let branch = bit_to_branch(bit);
-            println!("on node {:p}", &*cursor);
-            match cursor.branch(branch) {
-                &None => break,
-                &Some(_) => {
-                    n = cursor.value.rank(bit, n as int) as uint;
-                    cursor.step(branch)
-                },
+            match node.value.bits {
+                None => break,
+                Some(ref bv) => {
+                    let bit = bv.get(n);
+                    builder.push(bit);
+                    n = bv.rank(bit, n);
+                    node = child(node, bit);
+                }
             }
         }
         builder.finish()
     }
 }
 
+/// A node visited on the way from the root of a tree to the leaf of
+/// a position
+pub struct PathStep<'a, BitV: 'a> {
+    /// the bitvector of the node
+    pub node: &'a BitV,
+    /// the level of the node, counting the levels collapsed above it
+    pub level: usize,
+    /// the position within the node
+    pub pos: usize,
+    /// the branch taken, the bit of the node at `pos`
+    pub bit: bool,
+    /// the number of occurrences of `bit` in the node before `pos`,
+    /// which is the position within the child
+    pub rank: usize,
+}
+
+impl<BitV: Rank<bool> + Access<bool>, Sym: Buildable<bool>> Wavelet<BitV, Sym> {
+    /// The symbol at position `n` along with the rank computed at
+    /// each node on the way to its leaf.
+    ///
+    /// Algorithms which need these intermediate ranks, such as the
+    /// backward search of an FM-index, can thus get them from the
+    /// single traversal done by `get`. Nodes collapsed by path
+    /// compression have no bitvector and are not reported; at these
+    /// levels the position is unchanged.
+    pub fn access_path<'a>(&'a self, mut n: usize) -> (Sym, Vec<PathStep<'a, BitV>>) {
+        use crate::build::Builder;
+        let mut builder = <Sym as Buildable<bool>>::new_builder();
+        let mut steps = Vec::new();
+        let mut node = &self.tree;
+        let mut level = 0;
+        loop {
+            for bit in node.value.skip.iter() {
+                builder.push(*bit);
+            }
+            level += node.value.skip.len();
+            match node.value.bits {
+                None => break,
+                Some(ref bv) => {
+                    let bit = bv.get(n);
+                    let rank = bv.rank(bit, n);
+                    builder.push(bit);
+                    steps.push(PathStep { node: bv, level, pos: n, bit, rank });
+                    n = rank;
+                    level += 1;
+                    node = child(node, bit);
+                }
+            }
+        }
+        (builder.finish(), steps)
+    }
+
+    /// The symbols at each of `positions`.
+    ///
+    /// The positions are sorted and pushed down the tree together,
+    /// each node being visited once for the whole batch and its bits
+    /// read in increasing order. This amortizes the cache misses
+    /// incurred by accessing each position separately.
+    pub fn access_batch(&self, positions: &[usize]) -> Vec<Sym> {
+        use crate::build::Builder;
+        fn go<BitV: Rank<bool> + Access<bool>, B: Builder<bool, Sym>, Sym>(
+            node: &Tree<Node<BitV>>, queries: &[(usize, usize)], builders: &mut Vec<B>)
+        {
+            for &(_, idx) in queries.iter() {
+                for bit in node.value.skip.iter() {
+                    builders[idx].push(*bit);
+                }
+            }
+            let bv = match node.value.bits {
+                Some(ref bv) if !queries.is_empty() => bv,
+                _ => return,
+            };
+            let mut left: Vec<(usize, usize)> = Vec::with_capacity(queries.len());
+            let mut right: Vec<(usize, usize)> = Vec::with_capacity(queries.len());
+            for &(n, idx) in queries.iter() {
+                let bit = bv.get(n);
+                builders[idx].push(bit);
+                let child_n = bv.rank(bit, n);
+                if bit {
+                    right.push((child_n, idx));
+                } else {
+                    left.push((child_n, idx));
+                }
+            }
+            go(child(node, false), left.as_slice(), builders);
+            go(child(node, true), right.as_slice(), builders);
+        }
+
+        // (position, index of the query)
+        let mut queries: Vec<(usize, usize)> =
+            positions.iter().enumerate().map(|(idx, &n)| (n, idx)).collect();
+        queries.sort();
+        let mut builders: Vec<<Sym as Buildable<bool>>::Builder> =
+            positions.iter().map(|_| <Sym as Buildable<bool>>::new_builder()).collect();
+        go(&self.tree, queries.as_slice(), &mut builders);
+        builders.into_iter().map(|b| b.finish()).collect()
+    }
+}
+
+impl<BitV> Wavelet<BitV, char> {
+    /// Build a wavelet tree over the characters of a string
+    pub fn from_str<BitVBuilder, F>(s: &str, new_bitvector: F) -> Wavelet<BitV, char>
+        where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder
+    {
+        let builder: Builder<BitVBuilder, char, _> = Builder::new(new_bitvector);
+        build::Builder::from_iter(builder, s.chars())
+    }
+}
+
+/// Build a symbol from its bits
+fn build_symbol<Sym: Buildable<bool>>(bits: &[bool]) -> Sym {
+    use crate::build::Builder;
+    let mut builder = <Sym as Buildable<bool>>::new_builder();
+    for bit in bits.iter() {
+        builder.push(*bit);
+    }
+    builder.finish()
+}
+
+impl<BitV: Rank<bool>, Sym: Buildable<bool>> Wavelet<BitV, Sym> {
+    /// The distinct symbols occurring in positions `[i, j)` along with
+    /// their number of occurrences, in the order of the leaves of the
+    /// tree.
+    ///
+    /// Only the nodes through which some position of the range passes
+    /// are visited, so the cost is proportional to the number of
+    /// distinct symbols in the range rather than the size of the
+    /// alphabet.
+    pub fn histogram(&self, i: usize, j: usize) -> Vec<(Sym, usize)> {
+        fn go<BitV: Rank<bool>, Sym: Buildable<bool>>(node: &Tree<Node<BitV>>, i: usize, j: usize,
+                                                     path: &mut Vec<bool>,
+                                                     out: &mut Vec<(Sym, usize)>) {
+            if i == j {
+                return;
+            }
+            let depth = path.len();
+            path.extend_from_slice(node.value.skip.as_slice());
+            match node.value.bits {
+                None => out.push((build_symbol(path.as_slice()), j - i)),
+                Some(ref bv) => for &bit in [false, true].iter() {
+                    let ci = bv.rank(bit, i);
+                    let cj = bv.rank(bit, j);
+                    path.push(bit);
+                    go(child(node, bit), ci, cj, path, out);
+                    path.pop();
+                },
+            }
+            path.truncate(depth);
+        }
+
+        let mut out = Vec::new();
+        go(&self.tree, i, j, &mut Vec::new(), &mut out);
+        out
+    }
+
+    /// The `k` symbols occurring most often in positions `[i, j)`
+    /// along with their number of occurrences, most frequent first.
+    ///
+    /// Nodes are visited in decreasing order of the number of
+    /// positions of the range passing through them, so the first `k`
+    /// leaves reached are the most frequent symbols.
+    #[allow(clippy::type_complexity)]
+    pub fn top_k(&self, i: usize, j: usize, k: usize) -> Vec<(Sym, usize)> {
+        // the nodes reached, each with its range and path, and a heap
+        // of their indices by the size of their range
+        let mut nodes: Vec<(&Tree<Node<BitV>>, usize, usize, Vec<bool>)> = vec!((&self.tree, i, j, Vec::new()));
+        let mut heap = BinaryHeap::new();
+        let mut out = Vec::new();
+        if i < j {
+            heap.push((j - i, 0usize));
+        }
+        while out.len() < k {
+            let n = match heap.pop() {
+                None => break,
+                Some((_, n)) => n,
+            };
+            let (node, i, j, mut path) = nodes[n].clone();
+            path.extend_from_slice(node.value.skip.as_slice());
+            match node.value.bits {
+                None => out.push((build_symbol(path.as_slice()), j - i)),
+                Some(ref bv) => for &bit in [false, true].iter() {
+                    let ci = bv.rank(bit, i);
+                    let cj = bv.rank(bit, j);
+                    if ci < cj {
+                        let mut child_path = path.clone();
+                        child_path.push(bit);
+                        heap.push((cj - ci, nodes.len()));
+                        nodes.push((child(node, bit), ci, cj, child_path));
+                    }
+                },
+            }
+        }
+        out
+    }
+
+    /// The smallest symbol occurring in positions `[i, j)`, or `None`
+    /// if the range is empty
+    pub fn range_min(&self, i: usize, j: usize) -> Option<Sym> {
+        self.range_extreme(i, j, false)
+    }
+
+    /// The largest symbol occurring in positions `[i, j)`, or `None`
+    /// if the range is empty
+    pub fn range_max(&self, i: usize, j: usize) -> Option<Sym> {
+        self.range_extreme(i, j, true)
+    }
+
+    /// Descend to the leaf of the range `[i, j)` furthest in the
+    /// direction of `prefer`, following the other branch only where
+    /// no position of the range takes the preferred one
+    fn range_extreme(&self, mut i: usize, mut j: usize, prefer: bool) -> Option<Sym> {
+        use crate::build::Builder;
+        if i >= j {
+            return None;
+        }
+        let mut builder = <Sym as Buildable<bool>>::new_builder();
+        let mut node = &self.tree;
+        loop {
+            for bit in node.value.skip.iter() {
+                builder.push(*bit);
+            }
+            let bv = match node.value.bits {
+                None => break,
+                Some(ref bv) => bv,
+            };
+            let pi = bv.rank(prefer, i);
+            let pj = bv.rank(prefer, j);
+            let bit = if pj > pi {
+                i = pi;
+                j = pj;
+                prefer
+            } else {
+                i -= pi;
+                j -= pj;
+                !prefer
+            };
+            builder.push(bit);
+            node = child(node, bit);
+        }
+        Some(builder.finish())
+    }
+}
+
+/// The interior nodes along the path `bits` of a path-compressed
+/// tree, each with the branch taken from it, or `None` if no
+/// position has the path
+fn interior_path<BitV, I>(tree: &Tree<Node<BitV>>, bits: I) -> Option<Vec<(bool, &BitV)>>
+    where I: Iterator<Item=bool>
+{
+    let mut path = Vec::new();
+    let mut node = tree;
+    let mut skipped = 0;
+    for bit in bits {
+        if skipped < node.value.skip.len() {
+            if node.value.skip[skipped] != bit {
+                return None;
+            }
+            skipped += 1;
+            continue;
+        }
+        match node.value.bits {
+            None => return None,
+            Some(ref bv) => {
+                path.push((bit, bv));
+                node = child(node, bit);
+                skipped = 0;
+            }
+        }
+    }
+    Some(path)
+}
+
+impl<BitV: Collection + Rank<bool> + Select<bool>, Sym: BitIter> Wavelet<BitV, Sym> {
+    /// The position of the first occurrence of `sym` at or after
+    /// position `i`, if any.
+    ///
+    /// This is equivalent to `select(sym, rank(sym, i) + 1) - 1` but
+    /// performs the downward rank walk and upward select walk in a
+    /// single pass.
+    pub fn select_next_occurrence(&self, sym: Sym, i: usize) -> Option<usize> {
+        let path = match symbol_path(sym, self.width) {
+            None => return None,
+            Some(bits) => interior_path(&self.tree, bits)?,
+        };
+        let mut rank = i;
+        let mut count = self.len;
+        for &(bit, bv) in path.iter() {
+            rank = bv.rank(bit, rank);
+            count = bv.rank(bit, count);
+        }
+
+        if rank >= count {
+            return None;
+        }
+        let mut n = rank + 1;
+        for &(bit, bv) in path.iter().rev() {
+            n = bv.select(bit, n);
+        }
+        Some(n - 1)
+    }
+}
+
+/// The empty sequence, as built by `from_slice` from no symbols
+impl<BitV, Sym> Default for Wavelet<BitV, Sym> {
+    fn default() -> Wavelet<BitV, Sym> {
+        Wavelet {
+            tree: Tree::singleton(Node::leaf(Vec::new())),
+            width: 0,
+            len: 0,
+            digest: Digest::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Every byte, so that `Index` can give a byte by reference
+static BYTES: [u8; 256] = {
+    let mut bytes = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        bytes[i] = i as u8;
+        i += 1;
+    }
+    bytes
+};
+
+/// The `i`th byte, as given by `get`
+///
+/// This is only implemented for trees of bytes. `Index` must lend a
+/// reference that lives as long as the tree, but the tree keeps no
+/// symbols: each is decoded from the branch bits along its path, so
+/// there is nothing in the tree to point at. For bytes the reference
+/// is instead into `BYTES`, a static table of all 256 of them; a table
+/// of every `u16` would take 128 KiB and one of every `u32` or `u64`
+/// cannot exist, and keeping a symbol at each leaf would grow every
+/// tree for the sake of this one trait. Trees of wider symbols are
+/// read by value with `get`.
+impl<BitV: Rank<bool> + Access<bool>> Index<usize> for Wavelet<BitV, u8> {
+    type Output = u8;
+    fn index(&self, i: usize) -> &u8 {
+        &BYTES[self.get(i) as usize]
+    }
+}
+
+impl<BitV, Sym> Collection for Wavelet<BitV, Sym> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Shown as its symbols
+impl<BitV, Sym> fmt::Debug for Wavelet<BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool> + fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Wavelet trees are equal when they hold the same sequence, however
+/// their nodes are represented
+impl<BitV, Sym> PartialEq for Wavelet<BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool> + PartialEq
+{
+    fn eq(&self, other: &Wavelet<BitV, Sym>) -> bool {
+        self.len == other.len && self.digest == other.digest
+            && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl<BitV, Sym> Eq for Wavelet<BitV, Sym>
+    where BitV: Rank<bool> + Access<bool> + Collection, Sym: Buildable<bool> + Eq {}
+
+impl<BitV, Sym> Hash for Wavelet<BitV, Sym> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.content_digest().hash(state);
+    }
+}
+
+/// The digest of the symbols hashed as the tree was built, then the
+/// length
+impl<BitV, Sym> ContentDigest for Wavelet<BitV, Sym> {
+    fn content_digest(&self) -> u64 {
+        let mut digest = self.digest;
+        digest.update_u64(self.len as u64);
+        digest.finish()
+    }
+}
+
+impl<BitV: Serialize> Serialize for Node<BitV> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.skip.len())?;
+        for chunk in self.skip.chunks(64) {
+            let mut word = 0;
+            for (i, bit) in chunk.iter().enumerate() {
+                word |= (*bit as u64) << i;
+            }
+            write_u64(w, word)?;
+        }
+        match self.bits {
+            None => write_u64(w, 0),
+            Some(ref bv) => {
+                write_u64(w, 1)?;
+                bv.serialize(w)
+            }
+        }
+    }
+}
+
+impl<BitV: Deserialize> Deserialize for Node<BitV> {
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Node<BitV>> {
+        let skip_len = read_uint(r)?;
+        let mut skip = Vec::new();
+        while skip.len() < skip_len {
+            let word = read_u64(r)?;
+            for i in 0..64 {
+                if skip.len() == skip_len { break; }
+                skip.push((word >> i) & 1 == 1);
+            }
+        }
+        let bits = match read_u64(r)? {
+            0 => None,
+            1 => Some(Deserialize::deserialize(r)?),
+            _ => return Err(invalid_data("invalid wavelet tree node")),
+        };
+        Ok(Node { skip, bits })
+    }
+}
+
+/// Check that interior nodes and only interior nodes have children
+fn is_well_formed<BitV>(tree: &Tree<Node<BitV>>) -> bool {
+    match (&tree.value.bits, &tree.left, &tree.right) {
+        (&Some(_), Some(left), Some(right)) => is_well_formed(&**left) && is_well_formed(&**right),
+        (&None, &None, &None) => true,
+        _ => false,
+    }
+}
+
+impl<BitV: Serialize, Sym> Serialize for Wavelet<BitV, Sym> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.width)?;
+        write_uint(w, self.len)?;
+        self.tree.serialize(w)
+    }
+}
+
+/// Check that the bitvector of each interior node holds a bit for each
+/// of the `len` positions passing through it
+fn has_lengths<BitV: Rank<bool> + Collection>(tree: &Tree<Node<BitV>>, len: usize) -> bool {
+    match (&tree.value.bits, &tree.left, &tree.right) {
+        (Some(bv), Some(left), Some(right)) => bv.len() == len
+            && has_lengths(left, bv.rank(false, len)) && has_lengths(right, bv.rank(true, len)),
+        _ => true,
+    }
+}
+
+/// The digest of the symbols is computed afresh by decoding them
+impl<BitV, Sym> Deserialize for Wavelet<BitV, Sym>
+    where BitV: Deserialize + Rank<bool> + Access<bool> + Collection, Sym: BitIter
+{
+    fn deserialize<R: Read>(r: &mut R) -> io::Result<Wavelet<BitV, Sym>> {
+        let width = read_uint(r)?;
+        if width > Sym::BITS {
+            return Err(invalid_data("wavelet tree wider than its symbols"));
+        }
+        let len = read_uint(r)?;
+        let tree: Tree<Node<BitV>> = Deserialize::deserialize(r)?;
+        if !is_well_formed(&tree) {
+            return Err(invalid_data("invalid wavelet tree shape"));
+        }
+        if !has_lengths(&tree, len) {
+            return Err(invalid_data("wavelet tree nodes inconsistent with its length"));
+        }
+        let mut digest = Digest::new();
+        digest_symbols(&tree, len, &mut digest);
+        Ok(Wavelet { tree, width, len, digest, phantom: PhantomData })
+    }
+}
+
 /// Build up a wavelet tree from a sequence of symbols.
 ///
 /// We expect that the symbols are of homogenous bitwidth.
-pub struct Builder<BitVBuilder, Sym> {
-    tree: Wavelet<BitVBuilder, Sym>,
-    new_bitvector: fn() -> BitVBuilder,
+pub struct Builder<BitVBuilder, Sym, Factory> {
+    /// the uncompressed tree, compressed by `finish`
+    tree: Tree<BitVBuilder>,
+    width: usize,
+    len: usize,
+    /// the expected length of the sequence, used to size the
+    /// bitvectors of new nodes
+    expected_len: usize,
+    /// the hash of the values of the symbols pushed
+    digest: Digest,
+    factory: Factory,
+    phantom: PhantomData<Sym>,
+}
+
+/// Creates the bitvector builders of the nodes of a wavelet tree
+pub trait NodeFactory<BitVBuilder> {
+    /// A builder for the bitvector of a node at `level`, the root
+    /// being at level 0, which is expected to hold about `capacity`
+    /// bits
+    fn new_node(&mut self, level: usize, capacity: usize) -> BitVBuilder;
+}
+
+/// A `NodeFactory` creating every node's bitvector in the same way
+pub struct Uniform<F>(pub F);
+
+impl<BitVBuilder, F: FnMut() -> BitVBuilder> NodeFactory<BitVBuilder> for Uniform<F> {
+    fn new_node(&mut self, _level: usize, _capacity: usize) -> BitVBuilder {
+        (self.0)()
+    }
+}
+
+/// A `NodeFactory` creating each node's bitvector given its level
+pub struct PerLevel<F>(pub F);
+
+impl<BitVBuilder, F: FnMut(usize) -> BitVBuilder> NodeFactory<BitVBuilder> for PerLevel<F> {
+    fn new_node(&mut self, level: usize, _capacity: usize) -> BitVBuilder {
+        (self.0)(level)
+    }
+}
+
+/// A `NodeFactory` creating each node's bitvector given its expected
+/// capacity in bits
+pub struct Presized<F>(pub F);
+
+impl<BitVBuilder, F: FnMut(usize) -> BitVBuilder> NodeFactory<BitVBuilder> for Presized<F> {
+    fn new_node(&mut self, _level: usize, capacity: usize) -> BitVBuilder {
+        (self.0)(capacity)
+    }
+}
+
+/// The expected number of bits of a node at `level` of a tree over a
+/// sequence of `len` symbols, assuming the positions divide evenly
+/// among the nodes of each level
+fn node_capacity(len: usize, level: usize) -> usize {
+    if level >= usize::BITS as usize { 0 } else { len >> level }
 }
 
-impl<BitV, BitVBuilder: build::Builder<bool, BitV>, Sym: BitIter>
-    build::Builder<Sym, Wavelet<BitV, Sym>>
-    for Builder<BitVBuilder, Sym>
+/// Finish the bitvectors of a tree built by pushing symbols,
+/// collapsing its unary nodes. `skip` is the path of unary nodes
+/// collapsed above `tree`.
+///
+/// A node gains a child the first time a position takes its branch,
+/// so the nodes with a single child are exactly those whose positions
+/// all take the same branch.
+fn compress<BitV, BitVBuilder>(tree: Tree<BitVBuilder>, mut skip: Vec<bool>) -> Tree<Node<BitV>>
+    where BitVBuilder: build::Builder<bool, BitV>
+{
+    let Tree { value, left, right } = tree;
+    match (left, right) {
+        (Some(left), Some(right)) => Tree {
+            value: Node { skip, bits: Some(value.finish()) },
+            left: Some(Box::new(compress(*left, Vec::new()))),
+            right: Some(Box::new(compress(*right, Vec::new()))),
+        },
+        (Some(left), None) => {
+            skip.push(false);
+            compress(*left, skip)
+        },
+        (None, Some(right)) => {
+            skip.push(true);
+            compress(*right, skip)
+        },
+        (None, None) => Tree::singleton(Node::leaf(skip)),
+    }
+}
+
+impl<BitV, BitVBuilder, Sym, Factory> build::Builder<Sym, Wavelet<BitV, Sym>>
+    for Builder<BitVBuilder, Sym, Factory>
+    where BitVBuilder: build::Builder<bool, BitV>, Sym: BitIter, Factory: NodeFactory<BitVBuilder>
 {
 
         fn push(&mut self, element: Sym) {
-            let new_bitvector = &self.new_bitvector;
-            let mut cursor = binary::MutCursor::new(&mut self.tree.tree);
-            for bit in element.bit_iter() {
+            let factory = &mut self.factory;
+            let expected_len = max(self.expected_len, self.len + 1);
+            let mut cursor = binary::MutCursor::new(&mut self.tree);
+            let bits = match symbol_path(element, self.width) {
+                None => panic!("Symbol exceeds wavelet tree width of {} bits", self.width),
+                Some(bits) => bits,
+            };
+            let mut value = 0;
+            for (level, bit) in bits.enumerate() {
+                value = value << 1 | bit as u64;
                 cursor.value.push(bit);
                 let branch = bit_to_branch(bit);
                 match cursor.branch_mut(branch) {
                     &mut Some(_) => {},
-                    n => *n = Some(box Tree::singleton((*new_bitvector)())),
+                    n => {
+                        let capacity = node_capacity(expected_len, level + 1);
+                        *n = Some(Box::new(Tree::singleton(factory.new_node(level + 1, capacity))));
+                    },
                 }
                 cursor.step(branch);
             }
+            self.digest.update_u64(value);
+            self.len += 1;
         }
 
         fn finish(self) -> Wavelet<BitV, Sym> {
-            use build::Builder;
-            Wavelet { tree: self.tree.tree.map_step(&mut |&: b| b.finish()) }
+            Wavelet {
+                tree: compress(self.tree, Vec::new()),
+                width: min(self.width, Sym::BITS),
+                len: self.len,
+                digest: self.digest,
+                phantom: PhantomData,
+            }
+        }
+
+        fn from_iter<Iter: Iterator<Item=Sym>>(self, iter: Iter) -> Wavelet<BitV, Sym> {
+            let expected_len = self.len + iter.size_hint().0;
+            let mut builder = match expected_len > self.expected_len {
+                true => self.with_expected_len(expected_len),
+                false => self,
+            };
+            for sym in iter {
+                builder.push(sym);
+            }
+            builder.finish()
         }
 }
 
+/// A tree over the full width of its symbols, with node bitvectors
+/// built by their own `new_builder`
+impl<BitV: Buildable<bool>, Sym: BitIter> Buildable<Sym> for Wavelet<BitV, Sym> {
+    type Builder = Builder<<BitV as Buildable<bool>>::Builder, Sym,
+                           Uniform<fn() -> <BitV as Buildable<bool>>::Builder>>;
+    fn new_builder() -> <Wavelet<BitV, Sym> as Buildable<Sym>>::Builder {
+        Builder::new(<BitV as Buildable<bool>>::new_builder as fn() -> <BitV as Buildable<bool>>::Builder)
+    }
+}
+
 impl<BitV: Collection+Access<bool>+Select<bool>, Sym: BitIter>
     Select<Sym> for Wavelet<BitV, Sym>
 {
-    fn select(&self, sym: Sym, n: int) -> int {
+    fn select(&self, sym: Sym, n: usize) -> usize {
         if n == 0 { return 0; }
-        let mut stack: Vec<(bool, binary::Cursor<BitV>)> = Vec::new();
-        let mut cursor = binary::Cursor::new(&self.tree);
-        for bit in sym.bit_iter() {
-            match cursor.branch(bit_to_branch(bit)) {
-                &None    => panic!(),
-                &Some(_) => {
-                    stack.push((bit, cursor.clone()));
-                    cursor.step(bit_to_branch(bit))
-                },
-            }
-        }
+        let path = match symbol_path(sym, self.width) {
+            None => panic!(),
+            Some(bits) => match interior_path(&self.tree, bits) {
+                None => panic!(),
+                Some(path) => path,
+            },
+        };
 
         let mut n = n;
-        for (bit,cursor) in stack.into_iter().rev() {
-            n = cursor.value.select(bit, n);
+        for &(bit, bv) in path.iter().rev() {
+            n = bv.select(bit, n);
         }
         n
     }
@@ -137,54 +915,312 @@ impl<BitV: Collection+Access<bool>+Select<bool>, Sym: BitIter>
 impl<BitV: Collection+Access<bool>+Rank<bool>, Sym: BitIter>
     Rank<Sym> for Wavelet<BitV, Sym>
 {
-    fn rank(&self, sym: Sym, mut idx: int) -> int {
-        let mut cursor = binary::Cursor::new(&self.tree);
-        for bit in sym.bit_iter() {
-            idx = cursor.value.rank(bit, idx);
-            match cursor.branch(bit_to_branch(bit)) {
-                &None    => return 0,
-                &Some(_) => cursor.step(bit_to_branch(bit)),
-            }
+    fn rank(&self, sym: Sym, idx: usize) -> usize {
+        match symbol_path(sym, self.width) {
+            None => 0,
+            Some(bits) => self.rank_path(bits, idx),
         }
-        idx
     }
 }
 
-impl<BitVBuilder, Sym> Builder<BitVBuilder, Sym> {
-    pub fn new(new_bitvector: fn() -> BitVBuilder)
-               -> Builder<BitVBuilder, Sym> {
+impl<BitVBuilder, Sym, F: FnMut() -> BitVBuilder> Builder<BitVBuilder, Sym, Uniform<F>> {
+    pub fn new(new_bitvector: F) -> Builder<BitVBuilder, Sym, Uniform<F>> {
+        Builder::with_width(new_bitvector, usize::MAX)
+    }
+
+    /// Build a tree encoding only the `width` least significant
+    /// bits of each symbol. All symbols pushed must fit in this width.
+    pub fn with_width(new_bitvector: F, width: usize) -> Builder<BitVBuilder, Sym, Uniform<F>> {
+        Builder::with_factory(Uniform(new_bitvector), width)
+    }
+}
+
+impl<BitVBuilder, Sym, F: FnMut(usize) -> BitVBuilder> Builder<BitVBuilder, Sym, PerLevel<F>> {
+    /// Build a tree whose node bitvectors are created by
+    /// `new_bitvector` given the level of the node, the root being at
+    /// level 0.
+    ///
+    /// Together with `either::Builder` this allows different
+    /// bitvector representations to be used for the long bitvectors
+    /// near the root and the short ones near the leaves.
+    pub fn per_level(new_bitvector: F) -> Builder<BitVBuilder, Sym, PerLevel<F>> {
+        Builder::per_level_with_width(new_bitvector, usize::MAX)
+    }
+
+    /// Build a tree encoding only the `width` least significant bits
+    /// of each symbol with node bitvectors created per level
+    pub fn per_level_with_width(new_bitvector: F, width: usize)
+                                -> Builder<BitVBuilder, Sym, PerLevel<F>> {
+        Builder::with_factory(PerLevel(new_bitvector), width)
+    }
+}
+
+impl<BitVBuilder, Sym, Factory: NodeFactory<BitVBuilder>> Builder<BitVBuilder, Sym, Factory> {
+    /// Build a tree encoding the `width` least significant bits of
+    /// each symbol with node bitvectors created by `factory`
+    pub fn with_factory(mut factory: Factory, width: usize) -> Builder<BitVBuilder, Sym, Factory> {
         Builder {
-            tree: Wavelet {tree: Tree::singleton(new_bitvector())},
-            new_bitvector: new_bitvector,
+            tree: Tree::singleton(factory.new_node(0, 0)),
+            width,
+            len: 0,
+            expected_len: 0,
+            digest: Digest::new(),
+            factory,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The number of symbols pushed
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no symbols have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of symbols the node bitvectors are sized for
+    pub fn capacity(&self) -> usize {
+        max(self.expected_len, self.len)
+    }
+
+    /// Expect at least `additional` more symbols, sizing the node
+    /// bitvectors created from now on accordingly
+    pub fn reserve(&mut self, additional: usize) {
+        let len = max(self.expected_len, self.len + additional);
+        self.expect_len(len);
+    }
+
+    /// Expect a sequence of about `len` symbols, sizing the node
+    /// bitvectors accordingly.
+    ///
+    /// This is done automatically by `from_iter` using the iterator's
+    /// `size_hint`.
+    pub fn with_expected_len(mut self, len: usize) -> Builder<BitVBuilder, Sym, Factory> {
+        self.expect_len(len);
+        self
+    }
+
+    fn expect_len(&mut self, len: usize) {
+        self.expected_len = len;
+        if self.len == 0 {
+            // nothing has been pushed to the root yet
+            self.tree = Tree::singleton(self.factory.new_node(0, len));
         }
     }
 }
 
-/**
-A packed wavelet tree.
+/// Check that each node of a checkpointed tree `levels` deep holds a
+/// bit for each position passing through it, its children having been
+/// created as positions took their branches. The positions reaching
+/// the leaves are pushed to no bitvector, so the children of the last
+/// level of interior nodes are checked only to be empty leaves.
+fn holds_positions<B: Collection>(tree: &Tree<B>, levels: usize) -> bool {
+    let children = [&tree.left, &tree.right];
+    match levels {
+        0 => tree.value.is_empty() && tree.left.is_none() && tree.right.is_none(),
+        1 => children.iter().all(|c| c.as_ref().is_none_or(|c| holds_positions(c, 0)))
+            && (tree.value.is_empty() || tree.left.is_some() || tree.right.is_some()),
+        _ => children.iter().all(|c| {
+            c.as_ref().is_none_or(|c| !c.value.is_empty() && holds_positions(c, levels - 1))
+        }) && children.iter().map(|c| c.as_ref().map_or(0, |c| c.value.len())).sum::<usize>()
+            == tree.value.len(),
+    }
+}
+
+impl<BitVBuilder, Sym, Factory> Builder<BitVBuilder, Sym, Factory>
+    where BitVBuilder: Deserialize + Collection, Sym: BitIter, Factory: NodeFactory<BitVBuilder>
+{
+    /// Resume a build from a checkpoint written by `serialize`, the
+    /// node bitvectors created from then on by `factory`. The tree is
+    /// checked to encode symbols of the width of the checkpoint and to
+    /// hold its number of them.
+    pub fn resume<R: Read>(r: &mut R, factory: Factory) -> io::Result<Builder<BitVBuilder, Sym, Factory>> {
+        let width = read_uint(r)?;
+        let len = read_uint(r)?;
+        let expected_len = read_uint(r)?;
+        let digest: Digest = Deserialize::deserialize(r)?;
+        let tree: Tree<BitVBuilder> = Deserialize::deserialize(r)?;
+        let levels = min(width, Sym::BITS);
+        if (len as u64).checked_mul(8) != Some(digest.len()) || (levels > 0 && tree.value.len() != len)
+            || !holds_positions(&tree, levels) {
+            return Err(invalid_data("wavelet tree checkpoint inconsistent with its width and length"));
+        }
+        Ok(Builder { tree, width, len, expected_len, digest, factory, phantom: PhantomData })
+    }
+}
+
+/// A checkpoint of the symbols pushed so far, from which the build
+/// can be resumed by `resume`. The node bitvectors are checkpointed
+/// as they stand.
+impl<BitVBuilder: Serialize, Sym, Factory> Serialize for Builder<BitVBuilder, Sym, Factory> {
+    fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_uint(w, self.width)?;
+        write_uint(w, self.len)?;
+        write_uint(w, self.expected_len)?;
+        self.digest.serialize(w)?;
+        self.tree.serialize(w)
+    }
+}
+
+impl<BitVBuilder, Sym, F: FnMut(usize) -> BitVBuilder> Builder<BitVBuilder, Sym, Presized<F>> {
+    /// Build a tree whose node bitvectors are created by
+    /// `new_bitvector` given the number of bits they are expected to
+    /// hold
+    pub fn presized(new_bitvector: F) -> Builder<BitVBuilder, Sym, Presized<F>> {
+        Builder::with_factory(Presized(new_bitvector), usize::MAX)
+    }
+}
+
+impl<BitV, Sym: BitIter + Clone + Ord> Wavelet<BitV, Sym> {
+    /// Build a wavelet tree with only as many levels as are needed
+    /// to encode the largest symbol produced by `iter`.
+    ///
+    /// This makes two passes over `iter`, the first determining the
+    /// width of the symbols and the length of the sequence.
+    pub fn from_iter_narrow<BitVBuilder, F, Iter>(iter: Iter, new_bitvector: F) -> Wavelet<BitV, Sym>
+        where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder,
+              Iter: Iterator<Item=Sym> + Clone
+    {
+        TwoPass::new(iter).build(move |stats| {
+            let width = stats.max.clone().map(|sym| bit_width(sym)).unwrap_or(0);
+            let builder: Builder<BitVBuilder, Sym, _> = Builder::with_width(new_bitvector, max(width, 1));
+            builder.with_expected_len(stats.len)
+        })
+    }
+}
+
+impl<BitV, Sym: BitIter + Clone> Wavelet<BitV, Sym> {
+    /// Build a wavelet tree of `len` symbols whose `i`th is `f(i)`
+    pub fn from_fn<BitVBuilder, F, G>(len: usize, f: G, new_bitvector: F) -> Wavelet<BitV, Sym>
+        where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder, G: Fn(usize) -> Sym
+    {
+        let builder: Builder<BitVBuilder, Sym, _> = Builder::new(new_bitvector);
+        build::Builder::from_iter(builder, (0..len).map(f))
+    }
 
-Here the node bitvectors are packed into a single bitvector, removing
-the need for forwarding pointers.
-*/
-pub struct FlatWavelet<BitV, Sym> {
-    bits: BitV,
+    /// Build a wavelet tree from a slice of symbols.
+    ///
+    /// Rather than pushing each symbol down the tree this builds the
+    /// tree a level at a time, stably partitioning the symbols by
+    /// their bit at each level. This takes `O(n log σ)` time and
+    /// fills each node's bitvector in a single pass.
+    pub fn from_slice<BitVBuilder, F>(symbols: &[Sym], mut new_bitvector: F) -> Wavelet<BitV, Sym>
+        where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder
+    {
+        let width = match symbols.first() {
+            Some(sym) => sym.clone().bit_iter().count(),
+            None => 0,
+        };
+        assert!(width <= 64, "from_slice: symbols wider than 64 bits");
+        let mut digest = Digest::new();
+        let mut codes: Vec<u64> = symbols.iter().map(|sym| {
+            digest.update_u64(symbol_value(sym.clone()));
+            pack_bits(sym.clone())
+        }).collect();
+        let mut scratch: Vec<u64> = Vec::with_capacity(codes.len());
+        Wavelet {
+            tree: build_subtree(codes.as_mut_slice(), &mut scratch, 0, width, &mut new_bitvector),
+            width,
+            len: symbols.len(),
+            digest,
+            phantom: PhantomData,
+        }
+    }
 }
-/*
-impl FlatWavelet<BitV, Sym> {
-    fn from_tree(tree: Wavelet<BitV, Sym>) -> FlatWavelet<BitV, Sym> {
-        // TODO: flatten tree
+
+/// The bits of a symbol packed into a word, the `i`th bit of the word
+/// being the bit of the symbol's path taken at depth `i`
+fn pack_bits<Sym: BitIter>(sym: Sym) -> u64 {
+    let mut code = 0;
+    for (i, bit) in sym.bit_iter().enumerate() {
+        code |= (bit as u64) << i;
+    }
+    code
+}
+
+/// Build the subtree at depth `level` holding the symbols `codes`
+/// (packed by `pack_bits`). `codes` is permuted in the process and
+/// `scratch` is used to stably partition it.
+fn build_subtree<BitV, BitVBuilder, F>(codes: &mut [u64], scratch: &mut Vec<u64>,
+                                       mut level: usize, width: usize,
+                                       new_bitvector: &mut F) -> Tree<Node<BitV>>
+    where BitVBuilder: build::Builder<bool, BitV>, F: FnMut() -> BitVBuilder
+{
+    if codes.is_empty() {
+        return Tree::singleton(Node::leaf(Vec::new()));
+    }
+
+    // Collapse the levels on which all of the symbols agree
+    let mut skip = Vec::new();
+    while level < width {
+        let bit = (codes[0] >> level) & 1;
+        if codes.iter().any(|code| (code >> level) & 1 != bit) {
+            break;
+        }
+        skip.push(bit == 1);
+        level += 1;
+    }
+    if level == width {
+        return Tree::singleton(Node::leaf(skip));
+    }
+
+    // Push this level's bits while moving the symbols headed left to
+    // the front of `codes` and those headed right to `scratch`
+    let mut builder = (*new_bitvector)();
+    let mut zeros = 0;
+    scratch.clear();
+    for i in 0..codes.len() {
+        let code = codes[i];
+        let bit = (code >> level) & 1 == 1;
+        builder.push(bit);
+        if bit {
+            scratch.push(code);
+        } else {
+            codes[zeros] = code;
+            zeros += 1;
+        }
+    }
+    for (i, code) in scratch.iter().enumerate() {
+        codes[zeros + i] = *code;
+    }
+
+    let (left, right) = codes.split_at_mut(zeros);
+    Tree {
+        value: Node { skip, bits: Some(builder.finish()) },
+        left: Some(Box::new(build_subtree(left, scratch, level + 1, width, new_bitvector))),
+        right: Some(Box::new(build_subtree(right, scratch, level + 1, width, new_bitvector))),
     }
 }
-*/
 
 #[cfg(test)]
 mod test {
     use quickcheck::TestResult;
-    use super::super::dictionary::{Rank, Select};
+    use super::super::dictionary::{Rank, Select, Access};
     use super::super::build::Builder;
 
     #[quickcheck]
-    fn rank_is_correct(el: u8, v: Vec<u8>, n: uint) -> TestResult {
+    fn std_traits_agree(v: Vec<u8>, el: u8) -> bool {
+        use super::super::rank9::{self, Rank9};
+        use super::super::collection::Collection;
+        use super::super::serialize::Serialize;
+        use super::Wavelet;
+        let wavelet = Wavelet::from_slice(v.as_slice(), || rank9::Builder::with_capacity(128));
+        let empty: Wavelet<Rank9, u8> = Wavelet::default();
+        let built: Wavelet<Rank9, u8> = Wavelet::from_slice(&[], || rank9::Builder::with_capacity(0));
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        empty.serialize(&mut a).unwrap();
+        built.serialize(&mut b).unwrap();
+        (0..v.len()).all(|i| wavelet[i] == v[i])
+            && (&wavelet).into_iter().collect::<Vec<u8>>() == v
+            && wavelet.clone() == wavelet
+            && empty.len() == 0 && empty.rank(el, 0) == 0 && empty.iter().next().is_none()
+            && empty == built && empty.width() == built.width() && a == b
+    }
+
+    #[quickcheck]
+    fn rank_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
         use super::super::rank9;
         fn new_bitvector() -> rank9::Builder {
            rank9::Builder::with_capacity(128)
@@ -195,12 +1231,12 @@ mod test {
         }
 
         let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
-        let ans = wavelet.rank(el, n as int);
-        TestResult::from_bool(ans == v.rank(el, n as int))
+        let ans = wavelet.rank(el, n);
+        TestResult::from_bool(ans == v.rank(el, n))
     }
 
     #[quickcheck]
-    fn select_is_correct(el: u8, v: Vec<u8>, n: uint) -> TestResult {
+    fn select_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
         use super::super::bit_vector;
         fn new_bitvector() -> bit_vector::Builder {
            bit_vector::Builder::with_capacity(128)
@@ -211,8 +1247,177 @@ mod test {
         }
 
         let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
-        let ans = wavelet.select(el, n as int);
-        TestResult::from_bool(ans == v.select(el, n as int))
+        let ans = wavelet.select(el, n);
+        TestResult::from_bool(ans == v.select(el, n))
+    }
+
+    #[quickcheck]
+    fn access_is_correct(v: Vec<u8>) -> bool {
+        use super::super::bit_vector;
+        fn new_bitvector() -> bit_vector::Builder {
+           bit_vector::Builder::with_capacity(128)
+        }
+
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        v.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x)
+    }
+
+    #[quickcheck]
+    fn matches_naive(v: Vec<u8>) -> bool {
+        use super::super::rank9;
+        use super::super::naive;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let seq = naive::Sequence::new(v.clone());
+        (0..v.len()).all(|i| wavelet.get(i) == seq.get(i))
+            && v.iter().all(|&x| (0..v.len() + 1).all(|n| {
+                let k = seq.rank(x, n);
+                wavelet.rank(x, n) == k && wavelet.select(x, k) == seq.select(x, k)
+            }))
+    }
+
+    #[quickcheck]
+    fn resumed_build_is_correct(v: Vec<u8>, w: Vec<u8>) -> bool {
+        use std::io::Cursor;
+        use super::super::rank9;
+        use super::super::serialize::Serialize;
+        fn new_bitvector() -> rank9::Builder {
+            rank9::Builder::with_capacity(128)
+        }
+
+        let mut builder = super::Builder::new(new_bitvector);
+        for x in v.iter() {
+            builder.push(*x);
+        }
+        let mut checkpoint = Vec::new();
+        builder.serialize(&mut checkpoint).unwrap();
+        let mut resumed = super::Builder::resume(&mut Cursor::new(checkpoint), super::Uniform(new_bitvector)).unwrap();
+        for x in w.iter() {
+            resumed.push(*x);
+        }
+        let wavelet = resumed.finish();
+        let all: Vec<u8> = v.iter().chain(w.iter()).copied().collect();
+        all.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x && wavelet.rank(*x, i) == all.rank(*x, i))
+    }
+
+    #[test]
+    fn test_resume_checks() {
+        use std::io::Cursor;
+        use super::super::rank9;
+        use super::super::serialize::Serialize;
+        fn new_bitvector() -> rank9::Builder {
+            rank9::Builder::with_capacity(128)
+        }
+        let resume = |checkpoint: &[u8]| -> bool {
+            let builder: Result<super::Builder<_, u8, _>, _> =
+                super::Builder::resume(&mut Cursor::new(checkpoint), super::Uniform(new_bitvector as fn() -> _));
+            builder.is_ok()
+        };
+
+        let mut builder = super::Builder::new(new_bitvector);
+        for x in [3u8, 1, 4, 1, 5].iter() {
+            builder.push(*x);
+        }
+        let mut checkpoint = Vec::new();
+        builder.serialize(&mut checkpoint).unwrap();
+        assert!(resume(&checkpoint));
+
+        // a narrower tree than was built, the width being first
+        let mut narrow = checkpoint.clone();
+        narrow[..8].copy_from_slice(&3u64.to_le_bytes());
+        assert!(!resume(&narrow));
+
+        // more symbols than were pushed, the length following the width
+        let mut long = checkpoint.clone();
+        long[8..16].copy_from_slice(&6u64.to_le_bytes());
+        assert!(!resume(&long));
+    }
+
+    #[quickcheck]
+    fn from_fn_is_correct(v: Vec<u8>) -> bool {
+        use super::super::rank9;
+        use super::Wavelet;
+        let wavelet: Wavelet<rank9::Rank9, u8> =
+            Wavelet::from_fn(v.len(), |i| v[i], || rank9::Builder::with_capacity(128));
+        v.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x)
+    }
+
+    #[quickcheck]
+    fn build_from_iter_is_correct(v: Vec<u8>) -> bool {
+        use super::super::build::Buildable;
+        use super::super::rank9::Rank9;
+        use super::Wavelet;
+        let wavelet: Wavelet<Rank9, u8> = Buildable::build_from_iter(v.clone().into_iter());
+        v.iter().enumerate().all(|(i, x)| wavelet.get(i) == *x)
+            && (0..v.len() + 1).all(|n| wavelet.rank(0, n) == v.rank(0, n))
+    }
+
+    #[quickcheck]
+    fn histogram_is_correct(v: Vec<u8>, i: usize, j: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let mut hist = wavelet.histogram(i, j);
+        hist.sort();
+        let mut expected: Vec<(u8, usize)> = Vec::new();
+        let mut sorted = v[i..j].to_vec();
+        sorted.sort();
+        for x in sorted.into_iter() {
+            match expected.last_mut() {
+                Some(&mut (y, ref mut n)) if y == x => { *n += 1; continue; },
+                _ => {},
+            }
+            expected.push((x, 1));
+        }
+        TestResult::from_bool(hist == expected)
+    }
+
+    #[quickcheck]
+    fn top_k_is_correct(v: Vec<u8>, i: usize, j: usize, k: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+
+        let k = k % 5;
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.iter().map(|x| *x % 8));
+        let hist = wavelet.histogram(i, j);
+        let top = wavelet.top_k(i, j, k);
+        let mut counts: Vec<usize> = hist.iter().map(|&(_, n)| n).collect();
+        counts.sort_by(|a, b| b.cmp(a));
+        counts.truncate(k);
+        TestResult::from_bool(top.iter().map(|&(_, n)| n).collect::<Vec<usize>>() == counts
+                              && top.iter().all(|x| hist.contains(x)))
+    }
+
+    #[quickcheck]
+    fn select_next_occurrence_is_correct(el: u8, v: Vec<u8>, i: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if i > v.len() {
+            return TestResult::discard()
+        }
+
+        let wavelet = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let expected = (i..v.len()).find(|j| v[*j] == el);
+        TestResult::from_bool(wavelet.select_next_occurrence(el, i) == expected)
     }
 
     #[test]
@@ -227,6 +1432,248 @@ mod test {
         assert_eq!(wavelet.select(2, 2), 8);
     }
 
+    #[quickcheck]
+    fn narrow_is_correct(el: u64, v: Vec<u16>, n: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if n > v.len() {
+            return TestResult::discard()
+        }
+        let v: Vec<u64> = v.into_iter().map(|x| x as u64).collect();
+        let wavelet = super::Wavelet::from_iter_narrow(v.iter().copied(), new_bitvector);
+        if wavelet.width() > 16 {
+            return TestResult::failed()
+        }
+        let accessed = (0..v.len()).all(|i| wavelet.get(i) == v[i]);
+        TestResult::from_bool(accessed && wavelet.rank(el, n) == v.rank(el, n))
+    }
+
+    #[quickcheck]
+    fn from_slice_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if n > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let accessed = (0..v.len()).all(|i| wavelet.get(i) == v[i]);
+        TestResult::from_bool(accessed && wavelet.rank(el, n) == v.rank(el, n))
+    }
+
+    #[quickcheck]
+    fn serialize_round_trips(v: Vec<u8>) -> bool {
+        use super::super::rank9;
+        use super::super::serialize::test::round_trip;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let wavelet2: super::Wavelet<rank9::Rank9, u8> = round_trip(&wavelet);
+        (0..v.len()).all(|i| wavelet2.get(i) == v[i])
+    }
+
+    #[test]
+    fn test_deserialize_checks_width() {
+        use std::io::Cursor;
+        use super::super::rank9::{self, Rank9};
+        use super::super::serialize::{Serialize, Deserialize};
+        let wavelet: super::Wavelet<Rank9, u64> =
+            super::Builder::new(|| rank9::Builder::with_capacity(16)).from_iter(vec!(1u64, 300, 2).into_iter());
+        assert_eq!(wavelet.width(), 64);
+        let mut buf: Vec<u8> = Vec::new();
+        wavelet.serialize(&mut buf).unwrap();
+        let read: super::Wavelet<Rank9, u64> = Deserialize::deserialize(&mut Cursor::new(buf.clone())).unwrap();
+        assert_eq!(read.get(1), 300);
+        assert!(<super::Wavelet<Rank9, u8> as Deserialize>::deserialize(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[quickcheck]
+    fn range_min_max_are_correct(v: Vec<u8>, i: usize, j: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if i > j || j > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let min = v[i..j].iter().min().copied();
+        let max = v[i..j].iter().max().copied();
+        TestResult::from_bool(wavelet.range_min(i, j) == min && wavelet.range_max(i, j) == max)
+    }
+
+    #[quickcheck]
+    fn prefix_queries_are_correct(prefix: u8, len: usize, v: Vec<u8>, n: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        let len = len % 9;
+        if n >= v.len() {
+            return TestResult::discard()
+        }
+        let prefix = (prefix as u64) >> (8 - len);
+        let matches = |x: u8| (x as u64) >> (8 - len) == prefix;
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let count = v[..n].iter().filter(|x| matches(**x)).count();
+        TestResult::from_bool(wavelet.symbol_has_prefix(prefix, len, n) == matches(v[n])
+                              && wavelet.rank_prefix(prefix, len, n) == count)
+    }
+
+    #[quickcheck]
+    fn access_batch_is_correct(v: Vec<u8>, positions: Vec<usize>) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if v.is_empty() {
+            return TestResult::discard()
+        }
+        let positions: Vec<usize> = positions.into_iter().map(|n| n % v.len()).collect();
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let expected: Vec<u8> = positions.iter().map(|n| v[*n]).collect();
+        TestResult::from_bool(wavelet.access_batch(positions.as_slice()) == expected)
+    }
+
+    #[test]
+    pub fn test_path_compression() {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        fn interior_nodes<BitV>(tree: &super::Tree<super::Node<BitV>>) -> usize {
+            match (&tree.left, &tree.right) {
+                (Some(l), Some(r)) => 1 + interior_nodes(&**l) + interior_nodes(&**r),
+                _ => 0,
+            }
+        }
+
+        let v: Vec<u64> = vec!(3 << 40, 5, 3 << 40, 5, 5);
+        let pushed = super::Builder::new(new_bitvector).from_iter(v.clone().into_iter());
+        let sliced = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        for wavelet in [pushed, sliced].iter() {
+            assert_eq!(interior_nodes(&wavelet.tree), 1);
+            assert_eq!(wavelet.tree.value.skip.len(), 22);
+            for (i, x) in v.iter().enumerate() {
+                assert_eq!(wavelet.get(i), *x);
+            }
+            assert_eq!(wavelet.rank(5, 4), 2);
+            assert_eq!(wavelet.rank(4, 4), 0);
+            assert_eq!(wavelet.select(3 << 40, 2), 3);
+        }
+    }
+
+    #[quickcheck]
+    fn per_level_is_correct(el: u8, v: Vec<u8>, n: usize) -> TestResult {
+        use super::super::{rank9, bit_vector, either};
+        type BitVBuilder = either::Builder<rank9::Builder, bit_vector::Builder>;
+        fn new_bitvector(level: usize) -> BitVBuilder {
+            match level {
+                0..=3 => either::Builder::Left(rank9::Builder::with_capacity(128)),
+                _ => either::Builder::Right(bit_vector::Builder::with_capacity(128)),
+            }
+        }
+
+        if n > v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Builder::per_level(new_bitvector).from_iter(v.clone().into_iter());
+        let accessed = (0..v.len()).all(|i| wavelet.get(i) == v[i]);
+        let selected = match v.rank(el, n) {
+            0 => true,
+            k => wavelet.select(el, k) == v.select(el, k),
+        };
+        TestResult::from_bool(accessed && selected && wavelet.rank(el, n) == v.rank(el, n))
+    }
+
+    #[quickcheck]
+    fn closure_factory_is_correct(v: Vec<u8>, cap: usize) -> bool {
+        use super::super::rank9;
+        let cap = cap % 1024;
+        let mut nodes = 0;
+        let wavelet = {
+            let new_bitvector = || {
+                nodes += 1;
+                rank9::Builder::with_capacity(cap)
+            };
+            super::Builder::new(new_bitvector).from_iter(v.clone().into_iter())
+        };
+        // the root, again once sized by `from_iter`, and at most one new
+        // node per level for each symbol
+        (0..v.len()).all(|i| wavelet.get(i) == v[i]) && nodes >= 1 && nodes <= 2 + 8 * v.len()
+    }
+
+    #[quickcheck]
+    fn presized_is_correct(v: Vec<u8>) -> bool {
+        use super::super::rank9;
+        let mut capacities = Vec::new();
+        let wavelet = {
+            let new_bitvector = |cap: usize| {
+                capacities.push(cap);
+                rank9::Builder::with_capacity(cap)
+            };
+            super::Builder::presized(new_bitvector).from_iter(v.clone().into_iter())
+        };
+        // the root is resized for the whole sequence once its length
+        // is known
+        (0..v.len()).all(|i| wavelet.get(i) == v[i])
+            && (v.is_empty() || capacities[1] == v.len())
+    }
+
+    #[quickcheck]
+    fn access_path_is_consistent(v: Vec<u8>, n: usize) -> TestResult {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        if n >= v.len() {
+            return TestResult::discard()
+        }
+        let wavelet = super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let (sym, steps) = wavelet.access_path(n);
+        let mut pos = n;
+        for step in steps.iter() {
+            if step.pos != pos || step.node.get(pos) != step.bit
+                || step.node.rank(step.bit, pos) != step.rank
+                || (sym >> (7 - step.level)) & 1 != step.bit as u8 {
+                return TestResult::failed()
+            }
+            pos = step.rank;
+        }
+        TestResult::from_bool(sym == v[n])
+    }
+
+    #[test]
+    pub fn test_str() {
+        use super::super::rank9;
+        fn new_bitvector() -> rank9::Builder {
+           rank9::Builder::with_capacity(128)
+        }
+
+        let text = "mississippi ☃ mañana";
+        let wavelet = super::Wavelet::from_str(text, new_bitvector);
+        let chars: Vec<char> = text.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            assert_eq!(wavelet.get(i), *c);
+        }
+        assert_eq!(wavelet.rank('s', 7), 4);
+        assert_eq!(wavelet.rank('ñ', chars.len()), 1);
+        assert_eq!(wavelet.select('☃', 1), 13);
+        assert!(wavelet.symbol_eq('p', 8));
+    }
+
     #[test]
     pub fn test_symbol_eq() {
         use super::super::bit_vector;
@@ -0,0 +1,166 @@
+//! Statistics describing the shape of a wavelet tree
+
+use std::fmt;
+use std::mem::size_of;
+use super::super::dictionary::Rank;
+use super::super::collection::Collection;
+use super::super::space::{SpaceUsage, vec_bytes};
+use super::super::tree::binary::Tree;
+use super::{Wavelet, Node};
+
+/// A node of a wavelet tree as reported by `Wavelet::stats`
+#[derive(Debug)]
+pub struct NodeStats {
+    /// the number of levels above the node, including those
+    /// collapsed into the skips of its ancestors
+    pub depth: usize,
+    /// the number of unary levels collapsed into the node
+    pub skip: usize,
+    /// the number of positions passing through the node
+    pub positions: usize,
+    /// the number of bits of the node's bitvector, zero at leaves
+    pub bits: usize,
+    /// the number of ones of the node's bitvector
+    pub ones: usize,
+    /// the bytes occupied by the node, including its bitvector
+    pub bytes: usize,
+}
+
+impl NodeStats {
+    /// Whether the node is a leaf
+    pub fn is_leaf(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// The fraction of the node's bits which are ones
+    pub fn fill_ratio(&self) -> f64 {
+        match self.bits {
+            0 => 0.0,
+            n => self.ones as f64 / n as f64,
+        }
+    }
+}
+
+/// The shape and size of a wavelet tree
+pub struct Stats {
+    /// the number of levels of the tree
+    pub depth: usize,
+    /// the nodes of the tree in preorder
+    pub nodes: Vec<NodeStats>,
+    /// the total number of bits of the node bitvectors
+    pub bits: usize,
+    /// the total bytes occupied by the tree
+    pub bytes: usize,
+}
+
+impl Stats {
+    /// The number of nodes with bitvectors
+    pub fn interior_nodes(&self) -> usize {
+        self.nodes.iter().filter(|n| !n.is_leaf()).count()
+    }
+
+    /// The number of leaves, equal to the number of distinct symbols
+    /// of a non-empty sequence
+    pub fn leaves(&self) -> usize {
+        self.nodes.iter().filter(|n| n.is_leaf()).count()
+    }
+
+    /// The average number of bits per node bitvector
+    pub fn bits_per_node(&self) -> f64 {
+        match self.interior_nodes() {
+            0 => 0.0,
+            n => self.bits as f64 / n as f64,
+        }
+    }
+}
+
+/// A dump of the shape of the tree, one node per line indented by its
+/// depth
+impl fmt::Debug for Stats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        (writeln!(fmt, "depth {}, {} interior nodes, {} leaves, {} bits, {} bytes",
+                    self.depth, self.interior_nodes(), self.leaves(), self.bits, self.bytes))?;
+        for node in self.nodes.iter() {
+            for _ in 0..node.depth {
+                fmt.write_str(" ")?;
+            }
+            match node.is_leaf() {
+                true => (writeln!(fmt, "leaf: skip {}, {} positions",
+                                    node.skip, node.positions))?,
+                false => (writeln!(fmt, "node: skip {}, {} bits, fill {:.3}, {} bytes",
+                                     node.skip, node.bits, node.fill_ratio(), node.bytes))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<BitV: Rank<bool> + Collection + SpaceUsage, Sym> Wavelet<BitV, Sym> {
+    /// Statistics describing the shape and size of the tree
+    pub fn stats(&self) -> Stats {
+        fn go<BitV: Rank<bool> + Collection + SpaceUsage>(node: &Tree<Node<BitV>>, depth: usize,
+                                                          positions: usize, out: &mut Vec<NodeStats>) {
+            let mut stats = NodeStats {
+                depth,
+                skip: node.value.skip.len(),
+                positions,
+                bits: 0,
+                ones: 0,
+                bytes: size_of::<Tree<Node<BitV>>>() + vec_bytes(&node.value.skip),
+            };
+            let depth = depth + stats.skip;
+            match node.value.bits {
+                None => out.push(stats),
+                Some(ref bv) => {
+                    let ones = bv.rank(true, bv.len());
+                    stats.bits = bv.len();
+                    stats.ones = ones;
+                    stats.bytes += bv.total_bytes();
+                    out.push(stats);
+                    for child in node.left.iter() {
+                        go(&**child, depth + 1, positions - ones, out);
+                    }
+                    for child in node.right.iter() {
+                        go(&**child, depth + 1, ones, out);
+                    }
+                }
+            }
+        }
+
+        let mut nodes = Vec::new();
+        go(&self.tree, 0, self.len, &mut nodes);
+        Stats {
+            depth: nodes.iter().map(|n| n.depth + n.skip).max().unwrap_or(0),
+            bits: nodes.iter().map(|n| n.bits).sum(),
+            bytes: size_of::<Wavelet<BitV, Sym>>() + nodes.iter().map(|n| n.bytes).sum::<usize>(),
+            nodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::rank9;
+
+    fn new_bitvector() -> rank9::Builder {
+        rank9::Builder::with_capacity(128)
+    }
+
+    #[test]
+    fn test_stats() {
+        let v: Vec<u8> = vec!(4, 6, 2, 7, 5, 1, 6, 2);
+        let wavelet = super::super::Wavelet::from_slice(v.as_slice(), new_bitvector);
+        let stats = wavelet.stats();
+        assert_eq!(stats.depth, 8);
+        assert_eq!(stats.leaves(), 6);
+        assert_eq!(stats.interior_nodes(), 5);
+        // the top five levels are shared by every symbol
+        assert_eq!(stats.nodes[0].skip, 5);
+        assert_eq!(stats.nodes[0].bits, 8);
+        assert_eq!(stats.nodes[0].ones, 5);
+        assert_eq!(stats.bits, stats.nodes.iter().map(|n| n.bits).sum::<usize>());
+        assert!(stats.bytes > stats.bits / 8);
+        let dump = format!("{:?}", stats);
+        assert_eq!(dump.lines().count(), 1 + stats.nodes.len());
+    }
+}